@@ -1,13 +1,28 @@
-use crate::config::{ApiKeys};
-use crate::error::AppError;
-use reqwest::Client;
+use crate::audio;
+use crate::cache::{generate_cache_key, CacheKeyMode, CacheStats, Lookup, SpillJob, TtsCache};
+use crate::content;
+use crate::config::{ApiKeys, BaiduSettings, CacheSettings, NetworkSettings, ServerAuth};
+use crate::error::{AppError, BaiduErrorKind};
+use crate::logging;
+use crate::stream::StreamWriter;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, TRANSFER_ENCODING, USER_AGENT};
+use reqwest::{Certificate, Client, RequestBuilder, Response, StatusCode, Version};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Semaphore, SemaphorePermit};
 
 // --- DeepSeek Structures ---
 #[derive(Serialize)]
 struct DeepSeekRequest<'a> {
     model: &'a str,
     messages: Vec<Message<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
 }
 
 #[derive(Serialize)]
@@ -16,47 +31,453 @@ struct Message<'a> {
     content: &'a str,
 }
 
+/// One server-sent event of a streamed chat completion
 #[derive(Deserialize)]
-struct DeepSeekResponse {
-    choices: Vec<Choice>,
+struct DeepSeekChunk {
+    choices: Vec<ChunkChoice>,
 }
 
 #[derive(Deserialize)]
-struct Choice {
-    message: ResponseMessage,
+struct ChunkChoice {
+    #[serde(default)]
+    delta: Delta,
 }
 
-#[derive(Deserialize)]
-struct ResponseMessage {
-    content: String,
+#[derive(Deserialize, Default)]
+struct Delta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// Replies shorter than this (after trimming) are treated as unusable
+const MIN_RESPONSE_CHARS: usize = 2;
+/// Openings that mark a reply as a refusal rather than content to read aloud
+const REFUSAL_MARKERS: [&str; 6] = ["抱歉，我无法", "抱歉，我不能", "我无法提供", "我不能提供", "I'm sorry, but I can", "I cannot help"];
+/// Appended to the system prompt when retrying after an unusable reply
+const RETRY_INSTRUCTION: &str = "请直接输出可以朗读的完整内容，不要留空，也不要拒绝或解释。";
+
+/// Returns why a DeepSeek reply can't be spoken, or `None` if it looks usable
+fn unusable_reason(text: &str) -> Option<&'static str> {
+    let text = text.trim();
+    if text.is_empty() {
+        Some("返回内容为空")
+    } else if text.chars().count() < MIN_RESPONSE_CHARS {
+        Some("返回内容过短")
+    } else if REFUSAL_MARKERS.iter().any(|marker| text.starts_with(marker)) {
+        Some("拒绝回答")
+    } else {
+        None
+    }
 }
 
 // --- Baidu TTS Structures ---
+// Failed requests carry `error`/`error_description` instead of a token
 #[derive(Deserialize, Debug)]
 struct BaiduTokenResponse {
-    access_token: String,
+    access_token: Option<String>,
+    expires_in: Option<u64>,
+    // Space-separated permissions granted to the application
+    scope: Option<String>,
+    error_description: Option<String>,
+}
+
+struct IssuedToken {
+    token: String,
+    lifetime: Duration,
+    scopes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DeepSeekModels {
+    data: Vec<DeepSeekModel>,
+}
+
+#[derive(Deserialize)]
+struct DeepSeekModel {
+    id: String,
+}
+
+/// The chat model every DeepSeek request uses
+const DEEPSEEK_MODEL: &str = "deepseek-chat";
+/// Baidu scope required for short-text synthesis
+const BAIDU_TTS_SCOPE: &str = "audio_tts_post";
+
+/// What the services reported during the last connection test
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    /// Round-trip time of the Baidu token request
+    pub latency: Duration,
+    /// Permissions granted to the Baidu application; empty if Baidu didn't report them
+    pub baidu_scopes: Vec<String>,
+    /// Models visible to the DeepSeek key, or why they couldn't be listed; `None` without a key
+    pub deepseek_models: Option<Result<Vec<String>, String>>,
+}
+
+impl ServerInfo {
+    /// Mismatches between what the services offer and what this client uses
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if !self.baidu_scopes.is_empty() && !self.baidu_scopes.iter().any(|scope| scope == BAIDU_TTS_SCOPE) {
+            warnings.push(format!("百度应用未开通语音合成权限（{}），请在控制台为应用添加语音技术服务", BAIDU_TTS_SCOPE));
+        }
+        match &self.deepseek_models {
+            Some(Ok(models)) if !models.iter().any(|model| model == DEEPSEEK_MODEL) => {
+                warnings.push(format!("DeepSeek 未提供本程序使用的 {} 模型，可用模型: {}", DEEPSEEK_MODEL, models.join(", ")));
+            }
+            Some(Err(e)) => warnings.push(format!("无法获取 DeepSeek 模型列表: {}", e)),
+            _ => {}
+        }
+        warnings
+    }
+
+    /// One line for the status bar
+    pub fn summary(&self) -> String {
+        let deepseek = match &self.deepseek_models {
+            Some(Ok(_)) => format!(" · DeepSeek {}", DEEPSEEK_MODEL),
+            _ => String::new(),
+        };
+        format!("百度 TTS{} · {} ms", deepseek, self.latency.as_millis())
+    }
+}
+
+/// How long each phase of the most recent DeepSeek or Baidu operation took
+#[derive(Debug, Clone)]
+pub struct OperationTiming {
+    pub operation: &'static str,
+    /// Phases in the order they ran
+    pub phases: Vec<(&'static str, Duration)>,
+}
+
+impl OperationTiming {
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// One line for the log and the diagnostics panel
+    pub fn summary(&self) -> String {
+        let phases: Vec<String> = self.phases.iter().map(|(name, duration)| format!("{} {} ms", name, duration.as_millis())).collect();
+        format!("{}: {}（共 {} ms）", self.operation, phases.join("，"), self.total().as_millis())
+    }
+}
+
+/// Splits one operation into consecutive phases; each `lap` closes the phase that just ran
+struct PhaseTimer {
+    timing: OperationTiming,
+    lap_started: Instant,
+}
+
+impl PhaseTimer {
+    fn new(operation: &'static str) -> Self {
+        Self {
+            timing: OperationTiming { operation, phases: Vec::new() },
+            lap_started: Instant::now(),
+        }
+    }
+
+    fn lap(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.timing.phases.push((phase, now - self.lap_started));
+        self.lap_started = now;
+    }
+}
+
+// Tokens are valid for 30 days; refresh a little early so a request never races the expiry
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(10 * 60);
+// Used when the token response omits `expires_in`
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+// --- Baidu long-text synthesis ---
+// Long texts are synthesized as a task: `create` returns a task id, `query` is polled until
+// the task reports a download URL for the finished audio
+const LONG_TEXT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LONG_TEXT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Serialize)]
+struct LongTextRequest<'a> {
+    text: &'a str,
+    format: &'a str,
+    voice: i32,
+    lang: &'a str,
+    speed: i32,
+    pitch: i32,
+    volume: i32,
+    enable_subtitle: i32,
+}
+
+#[derive(Serialize)]
+struct LongTextQueryRequest<'a> {
+    task_ids: [&'a str; 1],
+}
+
+/// Both endpoints report failures as `{"error_code": 110, "error_msg": "..."}`
+#[derive(Deserialize)]
+struct LongTextError {
+    error_code: Option<i64>,
+    #[serde(default)]
+    error_msg: String,
+}
+
+#[derive(Deserialize)]
+struct LongTextCreated {
+    task_id: String,
+}
+
+#[derive(Deserialize)]
+struct LongTextQuery {
+    #[serde(default)]
+    tasks_info: Vec<LongTextTask>,
+}
+
+#[derive(Deserialize)]
+struct LongTextTask {
+    task_id: String,
+    // "Running", "Success" or "Failure"
+    task_status: String,
+    task_result: Option<LongTextResult>,
+}
+
+#[derive(Deserialize)]
+struct LongTextResult {
+    speech_url: Option<String>,
+    err_msg: Option<String>,
+}
+
+/// An access token together with the key pair it was issued for
+struct CachedToken {
+    api_key: String,
+    secret_key: String,
+    token: String,
+    expires_at: Instant,
+}
+
+// --- In-flight deduplication ---
+type SharedSynthesis = Result<Arc<Vec<u8>>, String>;
+type InFlightMap = Mutex<HashMap<String, broadcast::Sender<SharedSynthesis>>>;
+
+/// Owns an in-flight entry. Removing it on drop means waiters see the channel close
+/// instead of hanging forever if the leading request is cancelled.
+struct InFlightGuard<'a> {
+    map: &'a InFlightMap,
+    key: Option<String>,
+}
+
+impl InFlightGuard<'_> {
+    fn finish(mut self, result: &Result<Vec<u8>, AppError>) {
+        let Some(key) = self.key.take() else {
+            return;
+        };
+        if let Some(sender) = self.map.lock().unwrap().remove(&key) {
+            if sender.receiver_count() > 0 {
+                let shared = match result {
+                    Ok(audio) => Ok(Arc::new(audio.clone())),
+                    Err(e) => Err(e.to_string()),
+                };
+                let _ = sender.send(shared);
+            }
+        }
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.map.lock().unwrap().remove(&key);
+        }
+    }
 }
 
 // --- API Client ---
 pub struct ApiClient {
     client: Client,
+    // Limits simultaneous outbound requests so bursts queue instead of hitting the servers at once
+    request_limiter: Arc<Semaphore>,
+    // Identical syntheses that are already running; later callers wait for the first one
+    in_flight: InFlightMap,
+    cache: Mutex<TtsCache>,
+    cache_key_mode: Mutex<CacheKeyMode>,
+    cache_namespace: Mutex<String>,
+    // Reused across syntheses so each request doesn't pay for an extra OAuth round-trip
+    access_token: Mutex<Option<CachedToken>>,
+    baidu: BaiduSettings,
+    // Root of the DeepSeek API without a trailing slash
+    deepseek_url: String,
+    max_response_bytes: usize,
+    // Phase breakdown of the last operation that completed, for the diagnostics panel
+    last_timing: Mutex<Option<OperationTiming>>,
 }
 
 impl ApiClient {
-    pub fn new() -> Self {
-        Self {
-            client: Client::new(),
+    pub fn new(network: &NetworkSettings, cache: &CacheSettings, baidu: &BaiduSettings, deepseek_url: &str) -> Result<Self, AppError> {
+        Ok(Self {
+            client: build_http_client(network)?,
+            request_limiter: Arc::new(Semaphore::new(network.max_concurrent_requests.max(1))),
+            in_flight: Mutex::new(HashMap::new()),
+            cache: Mutex::new(TtsCache::new(cache.max_entries, cache.max_memory_mb * 1024 * 1024)),
+            cache_key_mode: Mutex::new(cache.key_mode),
+            cache_namespace: Mutex::new(cache.namespace.clone()),
+            access_token: Mutex::new(None),
+            baidu: baidu.clone(),
+            deepseek_url: deepseek_url.trim_end_matches('/').to_string(),
+            max_response_bytes: network.max_response_mb.max(1) * 1024 * 1024,
+            last_timing: Mutex::new(None),
+        })
+    }
+
+    /// Builds a client for new network settings. With `keep_cache` the synthesis cache moves
+    /// over, otherwise it starts empty; the access token is always fetched again. Requests
+    /// still running on the old client finish there.
+    pub fn reconnect(&self, network: &NetworkSettings, keep_cache: bool) -> Result<Self, AppError> {
+        let client = build_http_client(network)?;
+        let cache = {
+            let mut cache = self.cache.lock().unwrap();
+            if keep_cache {
+                cache.take()
+            } else {
+                TtsCache::new(cache.capacity(), cache.memory_budget())
+            }
+        };
+        Ok(Self {
+            client,
+            request_limiter: Arc::new(Semaphore::new(network.max_concurrent_requests.max(1))),
+            in_flight: Mutex::new(HashMap::new()),
+            cache: Mutex::new(cache),
+            cache_key_mode: Mutex::new(self.cache_key_mode()),
+            cache_namespace: Mutex::new(self.cache_namespace.lock().unwrap().clone()),
+            access_token: Mutex::new(None),
+            baidu: self.baidu.clone(),
+            deepseek_url: self.deepseek_url.clone(),
+            max_response_bytes: network.max_response_mb.max(1) * 1024 * 1024,
+            last_timing: Mutex::new(None),
+        })
+    }
+
+    /// Fetches a fresh Baidu access token (timing the round-trip and reading the granted
+    /// scopes) and, when a DeepSeek key is set, the list of models
+    pub async fn test_connection(&self, api_keys: &ApiKeys) -> Result<ServerInfo, AppError> {
+        let started = Instant::now();
+        let issued = self.get_baidu_access_token(&api_keys.baidu_api_key, &api_keys.baidu_secret_key).await?;
+        let latency = started.elapsed();
+        let baidu_scopes = issued.scopes.clone();
+        self.store_token(&api_keys.baidu_api_key, &api_keys.baidu_secret_key, issued);
+        let deepseek_key = api_keys.deepseek_api_key.trim();
+        let deepseek_models = if deepseek_key.is_empty() {
+            None
+        } else {
+            Some(self.list_deepseek_models(deepseek_key).await.map_err(|e| e.to_string()))
+        };
+        Ok(ServerInfo { latency, baidu_scopes, deepseek_models })
+    }
+
+    /// Phase breakdown of the most recently completed DeepSeek or Baidu operation
+    pub fn last_timing(&self) -> Option<OperationTiming> {
+        self.last_timing.lock().unwrap().clone()
+    }
+
+    fn record_timing(&self, timer: PhaseTimer) {
+        log::debug!("{}", timer.timing.summary());
+        *self.last_timing.lock().unwrap() = Some(timer.timing);
+    }
+
+    /// Number of cached syntheses and the current capacity
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats()
+    }
+
+    pub fn set_cache_capacity(&self, capacity: usize) {
+        self.cache.lock().unwrap().set_capacity(capacity);
+    }
+
+    pub async fn set_cache_memory_budget(&self, megabytes: usize) {
+        let jobs = self.cache.lock().unwrap().set_memory_budget(megabytes * 1024 * 1024);
+        self.write_spills(jobs).await;
+    }
+
+    /// Writes the cache entries chosen for spilling on the blocking pool, so the cache lock
+    /// is never held across disk I/O
+    async fn write_spills(&self, jobs: Vec<SpillJob>) {
+        if jobs.is_empty() {
+            return;
+        }
+        let written = tokio::task::spawn_blocking(move || {
+            jobs.into_iter()
+                .map(|job| {
+                    let result = job.write();
+                    (job, result)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await;
+        match written {
+            Ok(written) => {
+                let mut cache = self.cache.lock().unwrap();
+                for (job, result) in written {
+                    cache.finish_spill(job, result);
+                }
+            }
+            Err(e) => log::warn!("Cache spill task failed: {}", e),
+        }
+    }
+
+    /// Reads a spilled cache entry back on the blocking pool and returns it to memory
+    async fn reload_spilled(&self, key: &str, path: PathBuf) -> Option<Arc<Vec<u8>>> {
+        let read_path = path.clone();
+        let read = tokio::task::spawn_blocking(move || std::fs::read(read_path)).await;
+        let audio = match read {
+            Ok(Ok(audio)) => Some(Arc::new(audio)),
+            Ok(Err(e)) => {
+                log::warn!("Dropping spilled cache entry {}: {}", path.display(), e);
+                None
+            }
+            Err(e) => {
+                log::warn!("Cache reload task failed: {}", e);
+                None
+            }
+        };
+        let jobs = self.cache.lock().unwrap().restore(key, &path, audio.clone());
+        self.write_spills(jobs).await;
+        audio
+    }
+
+    pub fn cache_key_mode(&self) -> CacheKeyMode {
+        *self.cache_key_mode.lock().unwrap()
+    }
+
+    pub fn set_cache_key_mode(&self, mode: CacheKeyMode) {
+        *self.cache_key_mode.lock().unwrap() = mode;
+    }
+
+    /// Entries cached under the previous namespace stop matching and age out of the LRU
+    pub fn set_cache_namespace(&self, namespace: &str) {
+        *self.cache_namespace.lock().unwrap() = namespace.to_string();
+    }
+
+    async fn acquire_permit(&self) -> SemaphorePermit<'_> {
+        // The semaphore is never closed, so acquire cannot fail
+        self.request_limiter.acquire().await.expect("request limiter closed")
+    }
+
+    /// Adds the credentials for an auth proxy in front of the Baidu-compatible server. Only
+    /// the configured endpoints get them, not the audio URLs that long-text tasks hand back
+    fn baidu_request(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.baidu.auth {
+            ServerAuth::None => request,
+            ServerAuth::Bearer { token } => request.bearer_auth(token),
+            ServerAuth::Basic { username, password } => request.basic_auth(username, Some(password)),
         }
     }
 
+    /// Streams the completion, calling `on_progress` with the text received so far after
+    /// each batch of tokens. Only the returned text is complete.
     pub async fn call_deepseek_api(
         &self,
         api_key: &str,
         system_prompt: &str,
         user_prompt: &str,
+        temperature: Option<f32>,
+        on_progress: &mut (impl FnMut(&str) + Send),
     ) -> Result<String, AppError> {
         let request_payload = DeepSeekRequest {
-            model: "deepseek-chat",
+            model: DEEPSEEK_MODEL,
             messages: vec![
                 Message {
                     role: "system",
@@ -67,45 +488,179 @@ impl ApiClient {
                     content: user_prompt,
                 },
             ],
+            stream: true,
+            temperature,
         };
 
-        let response: DeepSeekResponse = self
+        let mut timer = PhaseTimer::new("DeepSeek 生成");
+        let _permit = self.acquire_permit().await;
+        timer.lap("排队");
+        let mut response = self
             .client
-            .post("https://api.deepseek.com/chat/completions")
+            .post(format!("{}/chat/completions", self.deepseek_url))
             .bearer_auth(api_key)
             .json(&request_payload)
             .send()
-            .await?
-            .json()
             .await?;
+        timer.lap("等待响应");
+        let status = response.status();
+        if !status.is_success() {
+            let body = self.read_body(&mut response).await?;
+            return Err(AppError::DeepSeekApi(format!("HTTP {}: {}", status, String::from_utf8_lossy(&body).trim())));
+        }
+
+        let mut text = String::new();
+        let mut pending = Vec::new();
+        let mut received = 0;
+        let mut done = false;
+        while !done {
+            let Some(chunk) = response.chunk().await? else {
+                break;
+            };
+            received += chunk.len();
+            if received > self.max_response_bytes {
+                return Err(AppError::ResponseTooLarge(self.max_response_bytes));
+            }
+            pending.extend_from_slice(&chunk);
+            let before = text.len();
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                match sse_event(&line)? {
+                    SseEvent::Delta(delta) => text.push_str(&delta),
+                    SseEvent::Done => {
+                        done = true;
+                        break;
+                    }
+                    SseEvent::Other => {}
+                }
+            }
+            // The last batch often arrives in the same chunk as [DONE] and still needs reporting
+            if text.len() > before {
+                on_progress(&text);
+            }
+        }
+        timer.lap("接收文本");
+        self.record_timing(timer);
+        Ok(text)
+    }
+
+    /// Calls DeepSeek and retries up to `max_retries` times with a firmer instruction
+    /// when the reply is empty, too short or a refusal. `temperature` overrides the
+    /// server default.
+    pub async fn generate_text(
+        &self,
+        api_key: &str,
+        system_prompt: &str,
+        user_prompt: &str,
+        max_retries: u32,
+        temperature: Option<f32>,
+        mut on_progress: impl FnMut(&str) + Send,
+    ) -> Result<String, AppError> {
+        let retry_prompt = format!("{}\n{}", system_prompt, RETRY_INSTRUCTION);
+        let mut attempt = 0;
+        loop {
+            let prompt = if attempt == 0 { system_prompt } else { retry_prompt.as_str() };
+            let text = self.call_deepseek_api(api_key, prompt, user_prompt, temperature, &mut on_progress).await?;
+            let Some(reason) = unusable_reason(&text) else {
+                return Ok(text);
+            };
+            if attempt >= max_retries {
+                return Err(AppError::DeepSeekApi(format!("{}，已重试 {} 次", reason, max_retries)));
+            }
+            attempt += 1;
+            log::warn!("DeepSeek {}，第 {} 次重试", reason, attempt);
+        }
+    }
 
-        Ok(response.choices[0].message.content.clone())
+    /// Forgets the cached access token; the next synthesis fetches a new one
+    pub fn clear_token_cache(&self) {
+        *self.access_token.lock().unwrap() = None;
+    }
+
+    /// Returns the cached token for this key pair, fetching a new one on a miss or near expiry
+    async fn baidu_access_token(&self, api_key: &str, secret_key: &str) -> Result<String, AppError> {
+        if let Some(cached) = self.access_token.lock().unwrap().as_ref() {
+            let fresh = Instant::now() + TOKEN_REFRESH_MARGIN < cached.expires_at;
+            if fresh && cached.api_key == api_key && cached.secret_key == secret_key {
+                log::debug!("Baidu access token cache hit");
+                return Ok(cached.token.clone());
+            }
+        }
+        let issued = self.get_baidu_access_token(api_key, secret_key).await?;
+        let token = issued.token.clone();
+        self.store_token(api_key, secret_key, issued);
+        Ok(token)
+    }
+
+    fn store_token(&self, api_key: &str, secret_key: &str, issued: IssuedToken) {
+        *self.access_token.lock().unwrap() = Some(CachedToken {
+            api_key: api_key.to_string(),
+            secret_key: secret_key.to_string(),
+            token: issued.token,
+            expires_at: Instant::now() + issued.lifetime,
+        });
     }
 
     async fn get_baidu_access_token(
         &self,
         api_key: &str,
         secret_key: &str,
-    ) -> Result<String, AppError> {
-        let url = "https://aip.baidubce.com/oauth/2.0/token";
+    ) -> Result<IssuedToken, AppError> {
         let params = [
             ("grant_type", "client_credentials"),
             ("client_id", api_key),
             ("client_secret", secret_key),
         ];
 
+        let _permit = self.acquire_permit().await;
         let response: BaiduTokenResponse = self
-            .client
-            .post(url)
+            .baidu_request(self.client.post(&self.baidu.token_url))
             .form(&params)
             .send()
             .await?
             .json()
             .await?;
 
-        Ok(response.access_token)
+        let lifetime = response.expires_in.map_or(DEFAULT_TOKEN_LIFETIME, Duration::from_secs);
+        let scopes = response.scope.as_deref().unwrap_or_default().split_whitespace().map(str::to_string).collect();
+        let token = response.access_token.ok_or_else(|| {
+            let reason = response.error_description.unwrap_or_else(|| "未知错误".to_string());
+            AppError::BaiduApi(format!("获取 access token 失败，请检查 API Key 和 Secret Key: {}", reason))
+        })?;
+        // The token goes into request URLs (long-text tasks), so reqwest errors may carry it
+        logging::add_issued_secret(&token);
+        Ok(IssuedToken { token, lifetime, scopes })
+    }
+
+    /// Listing models is the cheapest authenticated DeepSeek request, so checking a key
+    /// does not spend any tokens
+    pub async fn verify_deepseek_key(&self, api_key: &str) -> Result<(), AppError> {
+        self.list_deepseek_models(api_key).await.map(|_| ())
+    }
+
+    async fn list_deepseek_models(&self, api_key: &str) -> Result<Vec<String>, AppError> {
+        let _permit = self.acquire_permit().await;
+        let mut response = self.client.get(format!("{}/models", self.deepseek_url)).bearer_auth(api_key).send().await?;
+        match response.status() {
+            status if status.is_success() => {
+                let body = self.read_body(&mut response).await?;
+                let models: DeepSeekModels =
+                    serde_json::from_slice(&body).map_err(|e| AppError::DeepSeekApi(format!("无法解析模型列表: {}", e)))?;
+                Ok(models.data.into_iter().map(|model| model.id).collect())
+            }
+            StatusCode::UNAUTHORIZED => Err(AppError::DeepSeekApi("API 密钥无效".to_string())),
+            status => Err(AppError::DeepSeekApi(format!("验证失败 (HTTP {})", status))),
+        }
     }
 
+    /// Baidu keys are only usable as a pair, so both are checked by requesting an access token
+    pub async fn verify_baidu_keys(&self, api_key: &str, secret_key: &str) -> Result<(), AppError> {
+        self.get_baidu_access_token(api_key, secret_key).await.map(|_| ())
+    }
+
+    /// With `use_cache` false the cache is neither read nor written and no in-flight request
+    /// is joined, so every call reaches Baidu; useful for A/B testing parameter changes.
+    #[allow(clippy::too_many_arguments)]
     pub async fn call_baidu_tts_api(
         &self,
         api_keys: &ApiKeys,
@@ -114,45 +669,1299 @@ impl ApiClient {
         pitch: i32,
         volume: i32,
         person: i32,
+        use_cache: bool,
+    ) -> Result<Vec<u8>, AppError> {
+        self.synthesize(api_keys, text, speed, pitch, volume, person, use_cache, None).await
+    }
+
+    /// Tries the `fallbacks` voices in order after `person` failed with `error`, skipping
+    /// `person` itself. Returns the first successful result with the voice that produced it,
+    /// or the last error when every voice fails.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn call_baidu_tts_fallbacks(
+        &self,
+        api_keys: &ApiKeys,
+        text: &str,
+        speed: i32,
+        pitch: i32,
+        volume: i32,
+        person: i32,
+        fallbacks: &[i32],
+        use_cache: bool,
+        error: AppError,
+    ) -> (Result<Vec<u8>, AppError>, i32) {
+        let mut result = Err(error);
+        let mut used_person = person;
+        for &fallback in fallbacks.iter().filter(|&&p| p != person) {
+            let Err(e) = &result else {
+                break;
+            };
+            log::warn!("Baidu voice {} failed ({}), trying {}", used_person, e, fallback);
+            used_person = fallback;
+            result = self.call_baidu_tts_api(api_keys, text, speed, pitch, volume, fallback, use_cache).await;
+        }
+        (result, used_person)
+    }
+
+    /// Same as `call_baidu_tts_api`, but also feeds the audio into `writer` as it downloads
+    /// so playback can start before the whole MP3 has arrived. Cached or shared results are
+    /// pushed in one piece; the writer is always finished or failed before this returns.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn call_baidu_tts_streaming(
+        &self,
+        api_keys: &ApiKeys,
+        text: &str,
+        speed: i32,
+        pitch: i32,
+        volume: i32,
+        person: i32,
+        use_cache: bool,
+        writer: &StreamWriter,
+    ) -> Result<Vec<u8>, AppError> {
+        let result = self.synthesize(api_keys, text, speed, pitch, volume, person, use_cache, Some(writer)).await;
+        match &result {
+            Ok(_) => writer.finish(),
+            Err(e) => writer.fail(e.to_string()),
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn synthesize(
+        &self,
+        api_keys: &ApiKeys,
+        text: &str,
+        speed: i32,
+        pitch: i32,
+        volume: i32,
+        person: i32,
+        use_cache: bool,
+        writer: Option<&StreamWriter>,
+    ) -> Result<Vec<u8>, AppError> {
+        let mode = self.cache_key_mode();
+        let mut timer = PhaseTimer::new("百度合成");
+        if !use_cache {
+            log::debug!("Baidu TTS cache bypassed");
+            let result = self
+                .perform_baidu_tts(api_keys, text, speed, pitch, mode.request_volume(volume), person, writer, &mut timer)
+                .await
+                .and_then(checked_audio);
+            timer.lap("解码校验");
+            self.record_timing(timer);
+            return result;
+        }
+        let namespace = self.cache_namespace.lock().unwrap().clone();
+        let key = generate_cache_key(mode, &namespace, text, speed, pitch, volume, person);
+        let lookup = self.cache.lock().unwrap().get(&key);
+        let cached = match lookup {
+            Lookup::Hit(audio) => Some(audio),
+            Lookup::OnDisk(path) => self.reload_spilled(&key, path).await,
+            Lookup::Miss => None,
+        };
+        if let Some(audio) = cached {
+            log::debug!("Baidu TTS cache hit");
+            if let Some(writer) = writer {
+                writer.push(&audio);
+            }
+            timer.lap("读取缓存");
+            self.record_timing(timer);
+            return Ok(audio.to_vec());
+        }
+
+        let waiting = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(sender) => Some(sender.subscribe()),
+                None => {
+                    in_flight.insert(key.clone(), broadcast::channel(1).0);
+                    None
+                }
+            }
+        };
+
+        if let Some(mut receiver) = waiting {
+            log::debug!("Joining in-flight Baidu TTS request");
+            let shared = receiver.recv().await;
+            timer.lap("等待相同的请求");
+            self.record_timing(timer);
+            return match shared {
+                Ok(Ok(audio)) => {
+                    if let Some(writer) = writer {
+                        writer.push(&audio);
+                    }
+                    Ok(audio.to_vec())
+                }
+                Ok(Err(e)) => Err(AppError::BaiduApi(e)),
+                Err(_) => Err(AppError::BaiduApi("合成请求已取消".to_string())),
+            };
+        }
+
+        let guard = InFlightGuard {
+            map: &self.in_flight,
+            key: Some(key.clone()),
+        };
+        // Undecodable audio is reported as an error so it is neither cached nor shared
+        let result = self
+            .perform_baidu_tts(api_keys, text, speed, pitch, mode.request_volume(volume), person, writer, &mut timer)
+            .await
+            .and_then(checked_audio);
+        timer.lap("解码校验");
+        self.record_timing(timer);
+        let spills = match &result {
+            Ok(audio) => self.cache.lock().unwrap().insert(key, Arc::new(audio.clone())),
+            Err(_) => Vec::new(),
+        };
+        guard.finish(&result);
+        self.write_spills(spills).await;
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn perform_baidu_tts(
+        &self,
+        api_keys: &ApiKeys,
+        text: &str,
+        speed: i32,
+        pitch: i32,
+        volume: i32,
+        person: i32,
+        writer: Option<&StreamWriter>,
+        timer: &mut PhaseTimer,
     ) -> Result<Vec<u8>, AppError> {
+        self.baidu.validate().map_err(AppError::Config)?;
         let access_token = self
-            .get_baidu_access_token(&api_keys.baidu_api_key, &api_keys.baidu_secret_key)
+            .baidu_access_token(&api_keys.baidu_api_key, &api_keys.baidu_secret_key)
             .await?;
+        timer.lap("获取令牌");
+        if !self.fits_single_request(text) {
+            let audio_data = self.perform_long_text(&access_token, text, speed, pitch, volume, person).await?;
+            timer.lap("长文本合成");
+            if let Some(writer) = writer {
+                writer.push(&audio_data);
+            }
+            return Ok(audio_data);
+        }
 
-        let url = "https://tsn.baidu.com/text2audio";
-        
         let spd = &speed.to_string();
         let pit = &pitch.to_string();
         let vol = &volume.to_string();
         let per = &person.to_string();
 
-        let params = [
+        let mut params = vec![
             ("tex", text),
             ("tok", &access_token),
-            ("cuid", "ttsmate_rust_client"),
-            ("ctp", "1"),
-            ("lan", "zh"),
+            ("cuid", &self.baidu.cuid),
+            ("ctp", &self.baidu.ctp),
+            ("lan", &self.baidu.lan),
             ("spd", spd),
             ("pit", pit),
             ("vol", vol),
             ("per", per),
             ("aue", "3"), // aue=3 for mp3 format
         ];
+        // Extra parameters from the config replace built-in ones of the same name
+        for (name, value) in &self.baidu.extra_params {
+            params.retain(|(existing, _)| existing != name);
+            params.push((name, value));
+        }
 
-        let response = self.client.post(url).form(&params).send().await?;
+        let _permit = self.acquire_permit().await;
+        timer.lap("排队");
+        let mut response = self.baidu_request(self.client.post(&self.baidu.tts_url)).form(&params).send().await?;
+        timer.lap("等待响应");
         
         // Check if the response is an error JSON or audio data
-        let content_type = response.headers().get("Content-Type").cloned();
-        let audio_data = response.bytes().await?;
+        let is_json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .is_some_and(|ct| ct.to_str().unwrap_or("").contains("application/json"));
 
-        if let Some(ct) = content_type {
-            if ct.to_str().unwrap_or("").contains("application/json") {
-                let error_text = String::from_utf8_lossy(&audio_data).to_string();
-                log::error!("Baidu TTS Error: {}", error_text);
-                return Err(AppError::BaiduApi(error_text));
+        let writer = match writer {
+            Some(writer) if !is_json && supports_chunked_delivery(&response) => writer,
+            _ => {
+                let audio_data = self.read_body(&mut response).await?;
+                if is_json || looks_like_json(&audio_data) {
+                    return Err(self.synthesis_error(&audio_data));
+                }
+                timer.lap("下载");
+                if let Some(writer) = writer {
+                    writer.push(&audio_data);
+                }
+                return Ok(audio_data.to_vec());
             }
+        };
+
+        self.check_content_length(&response)?;
+        let mut audio_data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if audio_data.len() + chunk.len() > self.max_response_bytes {
+                return Err(AppError::ResponseTooLarge(self.max_response_bytes));
+            }
+            // An error body sent with an audio content type is only recognisable by its first bytes
+            if audio_data.is_empty() && looks_like_json(&chunk) {
+                let mut body = chunk.to_vec();
+                body.extend(self.read_body(&mut response).await?);
+                return Err(self.synthesis_error(&body));
+            }
+            writer.push(&chunk);
+            audio_data.extend_from_slice(&chunk);
         }
+        timer.lap("下载");
 
-        Ok(audio_data.to_vec())
+        Ok(audio_data)
     }
-} 
\ No newline at end of file
+
+    /// Whether Baidu accepts `text` in a single short-text request
+    fn fits_single_request(&self, text: &str) -> bool {
+        content::baidu_text_len(text) <= content::BAIDU_MAX_BYTES
+    }
+
+    /// Whether `text` can be synthesized at all: short enough for one request, or long-text
+    /// synthesis is enabled
+    pub fn accepts_text(&self, text: &str) -> bool {
+        self.baidu.long_text || self.fits_single_request(text)
+    }
+
+    /// Submits `text` as a long-text task, polls until it finishes and downloads the MP3.
+    /// Only the individual requests hold a concurrency permit, not the wait in between.
+    async fn perform_long_text(
+        &self,
+        access_token: &str,
+        text: &str,
+        speed: i32,
+        pitch: i32,
+        volume: i32,
+        person: i32,
+    ) -> Result<Vec<u8>, AppError> {
+        let request = LongTextRequest {
+            text,
+            format: "mp3-16k",
+            voice: person,
+            lang: &self.baidu.lan,
+            speed,
+            pitch,
+            volume,
+            enable_subtitle: 0,
+        };
+        let created: LongTextCreated = self.post_long_text("create", access_token, &request).await?;
+        let task_id = created.task_id;
+        log::info!("Baidu long-text task {} created", task_id);
+
+        let deadline = Instant::now() + LONG_TEXT_TIMEOUT;
+        let speech_url = loop {
+            tokio::time::sleep(LONG_TEXT_POLL_INTERVAL).await;
+            let query = LongTextQueryRequest { task_ids: [&task_id] };
+            let response: LongTextQuery = self.post_long_text("query", access_token, &query).await?;
+            let task = response
+                .tasks_info
+                .into_iter()
+                .find(|task| task.task_id == task_id)
+                .ok_or_else(|| AppError::BaiduApi(format!("长文本合成任务 {} 不存在", task_id)))?;
+            match task.task_status.as_str() {
+                "Success" => match task.task_result.and_then(|result| result.speech_url) {
+                    Some(url) => break url,
+                    None => return Err(AppError::BaiduApi("长文本合成完成，但没有返回音频地址".to_string())),
+                },
+                "Failure" => {
+                    let reason = task.task_result.and_then(|result| result.err_msg).unwrap_or_else(|| "未知错误".to_string());
+                    return Err(AppError::BaiduApi(format!("长文本合成失败: {}", reason)));
+                }
+                status => {
+                    log::debug!("Baidu long-text task {} is {}", task_id, status);
+                    if Instant::now() >= deadline {
+                        return Err(AppError::BaiduApi(format!("长文本合成超过 {} 分钟仍未完成", LONG_TEXT_TIMEOUT.as_secs() / 60)));
+                    }
+                }
+            }
+        };
+
+        let _permit = self.acquire_permit().await;
+        let mut response = self.client.get(&speech_url).send().await?.error_for_status()?;
+        self.read_body(&mut response).await
+    }
+
+    async fn post_long_text<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        access_token: &str,
+        body: &impl Serialize,
+    ) -> Result<T, AppError> {
+        let url = format!("{}/{}", self.baidu.long_text_url.trim_end_matches('/'), method);
+        let _permit = self.acquire_permit().await;
+        let mut response = self
+            .baidu_request(self.client.post(url))
+            .query(&[("access_token", access_token)])
+            .json(body)
+            .send()
+            .await?;
+        let body = self.read_body(&mut response).await?;
+        if let Ok(LongTextError { error_code: Some(code), error_msg }) = serde_json::from_slice(&body) {
+            let kind = BaiduErrorKind::classify(code, &error_msg);
+            if kind == BaiduErrorKind::InvalidToken {
+                self.clear_token_cache();
+            }
+            return Err(AppError::BaiduService { kind, code, message: error_msg });
+        }
+        serde_json::from_slice(&body).map_err(|e| AppError::BaiduApi(format!("无法解析长文本合成响应: {}", e)))
+    }
+
+    fn synthesis_error(&self, body: &[u8]) -> AppError {
+        let error = baidu_error(body);
+        if matches!(error, AppError::BaiduService { kind: BaiduErrorKind::InvalidToken, .. }) {
+            // Revoked or expired early; don't keep handing out the same token
+            self.clear_token_cache();
+        }
+        error
+    }
+
+    fn check_content_length(&self, response: &Response) -> Result<(), AppError> {
+        match response.content_length() {
+            Some(length) if length as usize > self.max_response_bytes => Err(AppError::ResponseTooLarge(self.max_response_bytes)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the rest of the body chunk by chunk, stopping as soon as it exceeds
+    /// `max_response_bytes` instead of buffering an arbitrarily large response.
+    async fn read_body(&self, response: &mut Response) -> Result<Vec<u8>, AppError> {
+        self.check_content_length(response)?;
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > self.max_response_bytes {
+                return Err(AppError::ResponseTooLarge(self.max_response_bytes));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+/// Sent when `network.user_agent` is left empty
+pub const DEFAULT_USER_AGENT: &str = concat!("TTSmate/", env!("CARGO_PKG_VERSION"));
+
+/// The User-Agent plus `network.extra_headers`, sent with every request
+fn default_headers(network: &NetworkSettings) -> Result<HeaderMap, AppError> {
+    let user_agent = network.user_agent.trim();
+    let user_agent = if user_agent.is_empty() { DEFAULT_USER_AGENT } else { user_agent };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        USER_AGENT,
+        HeaderValue::from_str(user_agent).map_err(|_| AppError::Config(format!("无效的 User-Agent: {}", user_agent)))?,
+    );
+    for (name, value) in &network.extra_headers {
+        let header_name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| AppError::Config(format!("无效的请求头名称: {}", name)))?;
+        // The value may be a credential, so it never goes into the error message
+        let mut header_value = HeaderValue::from_str(value.trim())
+            .map_err(|_| AppError::Config(format!("请求头 {} 的值包含无效字符", name)))?;
+        header_value.set_sensitive(true);
+        headers.insert(header_name, header_value);
+    }
+    Ok(headers)
+}
+
+fn build_http_client(network: &NetworkSettings) -> Result<Client, AppError> {
+    let mut builder = Client::builder().default_headers(default_headers(network)?);
+    let ca_cert_path = network.ca_cert_path.trim();
+    if !ca_cert_path.is_empty() {
+        let pem = std::fs::read(ca_cert_path)?;
+        let certificate = Certificate::from_pem(&pem)
+            .map_err(|e| AppError::Config(format!("无法解析 CA 证书 '{}': {}", ca_cert_path, e)))?;
+        builder = builder.add_root_certificate(certificate);
+        log::info!("Trusting additional CA certificate from {}", ca_cert_path);
+    }
+    if network.accept_invalid_certs {
+        log::warn!("!!! TLS certificate verification is DISABLED (network.accept_invalid_certs = true). Connections can be intercepted; only use this for self-hosted servers. !!!");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
+
+/// One line of a DeepSeek streaming response
+enum SseEvent {
+    Delta(String),
+    Done,
+    /// Blank separators, comments and keep-alives
+    Other,
+}
+
+/// Parses one line of DeepSeek's `text/event-stream` body
+fn sse_event(line: &[u8]) -> Result<SseEvent, AppError> {
+    let line = String::from_utf8_lossy(line);
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return Ok(SseEvent::Other);
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return Ok(SseEvent::Done);
+    }
+    let chunk: DeepSeekChunk =
+        serde_json::from_str(data).map_err(|e| AppError::DeepSeekApi(format!("无法解析响应: {}", e)))?;
+    Ok(SseEvent::Delta(chunk.choices.into_iter().filter_map(|choice| choice.delta.content).collect()))
+}
+
+/// HTTP/2 always delivers the body in frames; HTTP/1.1 only streams with chunked encoding
+fn supports_chunked_delivery(response: &Response) -> bool {
+    let chunked = response
+        .headers()
+        .get(TRANSFER_ENCODING)
+        .is_some_and(|te| te.to_str().unwrap_or("").contains("chunked"));
+    if !chunked && response.version() < Version::HTTP_2 {
+        log::debug!("Baidu TTS response is not chunked, falling back to buffered playback");
+        return false;
+    }
+    true
+}
+
+fn looks_like_json(data: &[u8]) -> bool {
+    data.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{')
+}
+
+/// Baidu reports failures as `{"err_no": 502, "err_msg": "...", ...}`
+#[derive(Deserialize)]
+struct BaiduErrorBody {
+    err_no: i64,
+    #[serde(default)]
+    err_msg: String,
+}
+
+/// Passes `audio` through if it decodes; Baidu occasionally returns truncated or empty MP3s
+fn checked_audio(audio: Vec<u8>) -> Result<Vec<u8>, AppError> {
+    match audio::validate_audio(&audio) {
+        Ok(()) => Ok(audio),
+        Err(reason) => {
+            log::error!("Baidu TTS returned undecodable audio ({} bytes): {}", audio.len(), reason);
+            Err(AppError::Audio(format!("合成结果无法解码，未写入缓存: {}", reason)))
+        }
+    }
+}
+
+fn baidu_error(body: &[u8]) -> AppError {
+    let error_text = String::from_utf8_lossy(body).to_string();
+    log::error!("Baidu TTS Error: {}", error_text);
+    match serde_json::from_slice::<BaiduErrorBody>(body) {
+        Ok(error) => AppError::BaiduService {
+            kind: BaiduErrorKind::classify(error.err_no, &error.err_msg),
+            code: error.err_no,
+            message: error.err_msg,
+        },
+        Err(_) => AppError::BaiduApi(error_text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AiSettings;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const TOKEN_PATH: &str = "/oauth/2.0/token";
+    const TTS_PATH: &str = "/text2audio";
+
+    /// 百度各接口都指向 `server` 的设置
+    fn baidu_settings(server: &MockServer) -> BaiduSettings {
+        BaiduSettings {
+            tts_url: format!("{}{}", server.uri(), TTS_PATH),
+            token_url: format!("{}{}", server.uri(), TOKEN_PATH),
+            long_text_url: format!("{}/rpc/2.0/tts/v1", server.uri()),
+            ..BaiduSettings::default()
+        }
+    }
+
+    /// 所有接口都指向 `server` 的客户端
+    fn client_with(server: &MockServer, network: &NetworkSettings, cache: &CacheSettings) -> ApiClient {
+        ApiClient::new(network, cache, &baidu_settings(server), &format!("{}/deepseek/", server.uri())).unwrap()
+    }
+
+    fn client(server: &MockServer) -> ApiClient {
+        client_with(server, &NetworkSettings::default(), &CacheSettings::default())
+    }
+
+    fn keys() -> ApiKeys {
+        ApiKeys {
+            deepseek_api_key: "sk-test".to_string(),
+            baidu_api_key: "ak".to_string(),
+            baidu_secret_key: "sk".to_string(),
+        }
+    }
+
+    fn token_response() -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "token-1",
+            "expires_in": 2592000,
+            "scope": "audio_voice_assistant_get audio_tts_post",
+        }))
+    }
+
+    fn audio_response(audio: Vec<u8>) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_raw(audio, "audio/mp3")
+    }
+
+    async fn mount_token(server: &MockServer, expected_calls: u64) {
+        Mock::given(method("POST"))
+            .and(path(TOKEN_PATH))
+            .and(body_string_contains("client_id=ak"))
+            .respond_with(token_response())
+            .expect(expected_calls)
+            .mount(server)
+            .await;
+    }
+
+    async fn synthesize(client: &ApiClient, text: &str, use_cache: bool) -> Result<Vec<u8>, AppError> {
+        client.call_baidu_tts_api(&keys(), text, 5, 5, 5, 0, use_cache).await
+    }
+
+    #[tokio::test]
+    async fn permits_never_exceed_the_configured_limit() {
+        let network = NetworkSettings { max_concurrent_requests: 2, ..NetworkSettings::default() };
+        let client = Arc::new(ApiClient::new(&network, &CacheSettings::default(), &BaiduSettings::default(), &AiSettings::default().deepseek_base_url).unwrap());
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..6)
+            .map(|_| {
+                let (client, running, peak) = (client.clone(), running.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let _permit = client.acquire_permit().await;
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn empty_short_and_refused_replies_are_unusable() {
+        assert_eq!(unusable_reason("  \n"), Some("返回内容为空"));
+        assert_eq!(unusable_reason("好"), Some("返回内容过短"));
+        assert_eq!(unusable_reason("抱歉，我无法回答这个问题。"), Some("拒绝回答"));
+        assert_eq!(unusable_reason("欢迎来到直播间！"), None);
+        // 只在开头出现的拒绝语才算拒绝
+        assert_eq!(unusable_reason("他说：抱歉，我无法参加。"), None);
+    }
+
+    #[tokio::test]
+    async fn baidu_synthesis_returns_the_audio() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        let mp3 = audio::silent_mp3(10);
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains("tok=token-1"))
+            .and(body_string_contains("per=0"))
+            .and(body_string_contains("aue=3"))
+            .respond_with(audio_response(mp3.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let audio = synthesize(&client(&server), "你好", true).await.unwrap();
+        assert_eq!(audio, mp3);
+    }
+
+    #[tokio::test]
+    async fn cached_synthesis_does_not_reach_the_server() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let first = synthesize(&client, "你好", true).await.unwrap();
+        assert_eq!(synthesize(&client, "你好", true).await.unwrap(), first);
+        assert_eq!(client.cache_stats().entries, 1);
+        // 不使用缓存时每次都请求服务器
+        synthesize(&client, "你好", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn baidu_json_error_becomes_a_service_error() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "err_no": 3305,
+                "err_msg": "user request limit reached",
+                "sn": "abc",
+                "idx": 1,
+            })))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        match synthesize(&client, "你好", true).await {
+            Err(AppError::BaiduService { kind, code, message }) => {
+                assert_eq!(kind, BaiduErrorKind::QuotaExceeded);
+                assert_eq!(code, 3305);
+                assert_eq!(message, "user request limit reached");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(client.cache_stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn json_error_with_an_audio_content_type_is_recognised() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(br#"{"err_no": 501, "err_msg": "text too long"}"#.to_vec()))
+            .mount(&server)
+            .await;
+
+        let result = synthesize(&client(&server), "你好", true).await;
+        assert!(
+            matches!(result, Err(AppError::BaiduService { kind: BaiduErrorKind::TextTooLong, code: 501, .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_keys_report_baidus_reason() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(TOKEN_PATH))
+            .respond_with(ResponseTemplate::new(401).set_body_json(json!({
+                "error": "invalid_client",
+                "error_description": "unknown client id",
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(path(TTS_PATH)).respond_with(ResponseTemplate::new(500)).expect(0).mount(&server).await;
+
+        match synthesize(&client(&server), "你好", true).await {
+            Err(AppError::BaiduApi(message)) => assert!(message.contains("unknown client id"), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_never_exceed_the_limit() {
+        const DELAY: Duration = Duration::from_millis(200);
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        let arrivals = Arc::new(Mutex::new(Vec::new()));
+        let recorded = arrivals.clone();
+        let mp3 = audio::silent_mp3(10);
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(move |_: &wiremock::Request| {
+                recorded.lock().unwrap().push(Instant::now());
+                audio_response(mp3.clone()).set_delay(DELAY)
+            })
+            .expect(6)
+            .mount(&server)
+            .await;
+
+        let network = NetworkSettings { max_concurrent_requests: 2, ..NetworkSettings::default() };
+        let client = Arc::new(client_with(&server, &network, &CacheSettings::default()));
+        client.baidu_access_token("ak", "sk").await.unwrap();
+        let tasks: Vec<_> = (0..6)
+            .map(|i| {
+                let client = client.clone();
+                tokio::spawn(async move { synthesize(&client, &format!("第 {} 句", i), false).await })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        // 同时最多两个请求在服务器上：任意第 i 个和第 i+2 个请求之间至少隔了一次响应的延迟
+        let mut arrivals = arrivals.lock().unwrap().clone();
+        arrivals.sort();
+        assert_eq!(arrivals.len(), 6);
+        for window in arrivals.windows(3) {
+            assert!(window[2] - window[0] >= DELAY - Duration::from_millis(20), "{:?}", window[2] - window[0]);
+        }
+    }
+
+    #[tokio::test]
+    async fn identical_concurrent_syntheses_share_one_request() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        let mp3 = audio::silent_mp3(10);
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(mp3.clone()).set_delay(Duration::from_millis(200)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        client.baidu_access_token("ak", "sk").await.unwrap();
+        let (first, second) = tokio::join!(synthesize(&client, "你好", true), synthesize(&client, "你好", true));
+        assert_eq!(first.unwrap(), mp3);
+        assert_eq!(second.unwrap(), mp3);
+        assert!(client.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn joined_syntheses_share_the_error() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "err_no": 4, "err_msg": "rate limit" }))
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        client.baidu_access_token("ak", "sk").await.unwrap();
+        let (first, second) = tokio::join!(synthesize(&client, "你好", true), synthesize(&client, "你好", true));
+        assert!(matches!(first, Err(AppError::BaiduService { code: 4, .. })), "{:?}", first);
+        assert!(second.unwrap_err().to_string().contains("err_no 4: rate limit"));
+    }
+
+    #[tokio::test]
+    async fn different_or_uncached_syntheses_are_not_merged() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)).set_delay(Duration::from_millis(100)))
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        client.baidu_access_token("ak", "sk").await.unwrap();
+        let (a, b) = tokio::join!(synthesize(&client, "你好", true), synthesize(&client, "再见", true));
+        assert!(a.is_ok() && b.is_ok());
+        // 不使用缓存的请求也不加入进行中的相同请求
+        let (a, b) = tokio::join!(synthesize(&client, "第三句", false), synthesize(&client, "第三句", false));
+        assert!(a.is_ok() && b.is_ok());
+    }
+
+    #[tokio::test]
+    async fn ignore_volume_mode_reuses_audio_across_volumes() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains(format!("vol={}", crate::cache::REFERENCE_VOLUME)))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = CacheSettings { key_mode: CacheKeyMode::IgnoreVolume, ..CacheSettings::default() };
+        let client = client_with(&server, &NetworkSettings::default(), &cache);
+        let quiet = client.call_baidu_tts_api(&keys(), "你好", 5, 5, 2, 0, true).await.unwrap();
+        let loud = client.call_baidu_tts_api(&keys(), "你好", 5, 5, 9, 0, true).await.unwrap();
+        assert_eq!(quiet, loud);
+    }
+
+    #[tokio::test]
+    async fn oversized_audio_is_rejected() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(vec![0; 2 * 1024 * 1024]))
+            .mount(&server)
+            .await;
+
+        let network = NetworkSettings { max_response_mb: 1, ..NetworkSettings::default() };
+        let client = client_with(&server, &network, &CacheSettings::default());
+        assert!(matches!(synthesize(&client, "你好", true).await, Err(AppError::ResponseTooLarge(limit)) if limit == 1024 * 1024));
+    }
+
+    #[tokio::test]
+    async fn changing_the_namespace_misses_the_cache() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        synthesize(&client, "你好", true).await.unwrap();
+        synthesize(&client, "你好", true).await.unwrap();
+        client.set_cache_namespace(&crate::cache::new_namespace());
+        synthesize(&client, "你好", true).await.unwrap();
+        synthesize(&client, "你好", true).await.unwrap();
+    }
+
+    #[test]
+    fn baidu_error_bodies_are_classified() {
+        let cases = [
+            (r#"{"err_no":3302,"err_msg":"Access token invalid or no longer valid","sn":"abc","idx":1}"#, BaiduErrorKind::InvalidToken),
+            (r#"{"err_no":110,"err_msg":"Access token invalid or no longer valid"}"#, BaiduErrorKind::InvalidToken),
+            (r#"{"err_no":3305,"err_msg":"user request limit reached","sn":"abc","idx":1}"#, BaiduErrorKind::QuotaExceeded),
+            (r#"{"err_no":17,"err_msg":"Open api daily request limit reached"}"#, BaiduErrorKind::QuotaExceeded),
+            (r#"{"err_no":18,"err_msg":"Open api qps request limit reached"}"#, BaiduErrorKind::RateLimited),
+            (r#"{"err_no":4,"err_msg":"Open api request limit reached"}"#, BaiduErrorKind::RateLimited),
+            (r#"{"err_no":501,"err_msg":"text too long"}"#, BaiduErrorKind::TextTooLong),
+            (r#"{"err_no":500,"err_msg":"not support"}"#, BaiduErrorKind::Other),
+            (r#"{"err_no":3309}"#, BaiduErrorKind::Other),
+        ];
+        for (body, expected) in cases {
+            match baidu_error(body.as_bytes()) {
+                AppError::BaiduService { kind, .. } => assert_eq!(kind, expected, "{}", body),
+                other => panic!("{} -> {:?}", body, other),
+            }
+        }
+    }
+
+    #[test]
+    fn unparseable_baidu_error_falls_back_to_baidu_api() {
+        for body in ["<html>502 Bad Gateway</html>", r#"{"err_no":3302,"err_msg":"#, r#"{"error":"invalid_client"}"#, ""] {
+            match baidu_error(body.as_bytes()) {
+                AppError::BaiduApi(text) => assert_eq!(text, body),
+                other => panic!("{} -> {:?}", body, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_voice_falls_back_in_order() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        let unavailable = ResponseTemplate::new(200).set_body_json(json!({ "err_no": 500, "err_msg": "not support" }));
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains("per=111&"))
+            .respond_with(unavailable.clone())
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains("per=0&"))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // 成功后不再尝试后面的发音人，也不重试已失败的首选发音人
+        Mock::given(method("POST")).and(path(TTS_PATH)).respond_with(unavailable).expect(0).mount(&server).await;
+
+        let client = client(&server);
+        let error = AppError::BaiduService { kind: BaiduErrorKind::Other, code: 500, message: "not support".to_string() };
+        let (result, used) = client.call_baidu_tts_fallbacks(&keys(), "你好", 5, 5, 5, 5003, &[5003, 111, 0, 1], true, error).await;
+        assert!(result.is_ok());
+        assert_eq!(used, 0);
+    }
+
+    #[tokio::test]
+    async fn exhausted_fallbacks_report_the_last_error() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "err_no": 3305, "err_msg": "user request limit reached" })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let error = AppError::BaiduApi("first".to_string());
+        let (result, used) = client.call_baidu_tts_fallbacks(&keys(), "你好", 5, 5, 5, 5003, &[1, 0], true, error).await;
+        assert!(matches!(result, Err(AppError::BaiduService { code: 3305, .. })), "{:?}", result);
+        assert_eq!(used, 0);
+
+        // 没有其他备用发音人时原样返回首选发音人的错误
+        let error = AppError::BaiduApi("first".to_string());
+        let (result, used) = client.call_baidu_tts_fallbacks(&keys(), "你好", 5, 5, 5, 5003, &[5003], true, error).await;
+        assert!(matches!(result, Err(AppError::BaiduApi(ref text)) if text == "first"));
+        assert_eq!(used, 5003);
+    }
+
+    #[tokio::test]
+    async fn access_token_is_reused_until_cleared() {
+        let server = MockServer::start().await;
+        mount_token(&server, 2).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains("tok=token-1"))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        synthesize(&client, "第一句", false).await.unwrap();
+        synthesize(&client, "第二句", false).await.unwrap();
+        client.clear_token_cache();
+        synthesize(&client, "第三句", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn token_close_to_expiry_is_refreshed() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(TOKEN_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "access_token": "token-1", "expires_in": 60 })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        synthesize(&client, "第一句", false).await.unwrap();
+        synthesize(&client, "第二句", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_token_is_fetched_again() {
+        let server = MockServer::start().await;
+        mount_token(&server, 2).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "err_no": 3302, "err_msg": "Access token invalid or no longer valid" })))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        let first = synthesize(&client, "你好", false).await;
+        assert!(matches!(first, Err(AppError::BaiduService { kind: BaiduErrorKind::InvalidToken, .. })), "{:?}", first);
+        synthesize(&client, "你好", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cache_keeps_only_max_entries() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(audio::silent_mp3(10)))
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let cache = CacheSettings { max_entries: 2, ..CacheSettings::default() };
+        let client = client_with(&server, &NetworkSettings::default(), &cache);
+        assert_eq!(client.cache.lock().unwrap().capacity(), 2);
+        synthesize(&client, "甲", true).await.unwrap();
+        synthesize(&client, "乙", true).await.unwrap();
+        // 命中的“甲”变为最近使用，放入“丙”时淘汰的是“乙”
+        synthesize(&client, "甲", true).await.unwrap();
+        synthesize(&client, "丙", true).await.unwrap();
+        synthesize(&client, "甲", true).await.unwrap();
+        synthesize(&client, "乙", true).await.unwrap();
+        assert_eq!(client.cache.lock().unwrap().stats().entries, 2);
+    }
+
+    #[tokio::test]
+    async fn cache_capacity_is_at_least_one() {
+        let server = MockServer::start().await;
+        let cache = CacheSettings { max_entries: 0, ..CacheSettings::default() };
+        let client = client_with(&server, &NetworkSettings::default(), &cache);
+        assert_eq!(client.cache.lock().unwrap().capacity(), 1);
+        client.set_cache_capacity(50);
+        assert_eq!(client.cache.lock().unwrap().capacity(), 50);
+        client.set_cache_capacity(0);
+        assert_eq!(client.cache.lock().unwrap().capacity(), 1);
+    }
+
+    #[tokio::test]
+    async fn undecodable_audio_is_not_cached() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(b"not an mp3 at all".to_vec()))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        for _ in 0..2 {
+            let result = synthesize(&client, "你好", true).await;
+            assert!(matches!(result, Err(AppError::Audio(ref message)) if message.contains("未写入缓存")), "{:?}", result);
+        }
+        assert_eq!(client.cache.lock().unwrap().stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn spilled_audio_is_read_back_without_a_request() {
+        let server = MockServer::start().await;
+        mount_token(&server, 1).await;
+        // 每段约 625 KB，两段超出 1 MB 的内存上限
+        let first = audio::silent_mp3(1500);
+        let second = [audio::silent_mp3(1499), vec![0; 417]].concat();
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .and(body_string_contains("tex=%E7%94%B2"))
+            .respond_with(audio_response(first.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(TTS_PATH))
+            .respond_with(audio_response(second.clone()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let cache = CacheSettings { max_memory_mb: 1, ..CacheSettings::default() };
+        let client = client_with(&server, &NetworkSettings::default(), &cache);
+        synthesize(&client, "甲", true).await.unwrap();
+        synthesize(&client, "乙", true).await.unwrap();
+        let stats = client.cache_stats();
+        assert_eq!((stats.entries, stats.spilled, stats.memory_bytes), (2, 1, second.len()));
+
+        assert_eq!(synthesize(&client, "甲", true).await.unwrap(), first);
+        let stats = client.cache_stats();
+        // 读回的“甲”留在内存中，“乙”转而被转存
+        assert_eq!((stats.entries, stats.spilled, stats.memory_bytes), (2, 1, first.len()));
+        assert_eq!(synthesize(&client, "乙", true).await.unwrap(), second);
+    }
+
+    /// DeepSeek 的流式响应：每段文本一个 `data:` 事件，最后是 `[DONE]`
+    fn deepseek_stream(parts: &[&str]) -> ResponseTemplate {
+        let mut body = String::new();
+        for part in parts {
+            let chunk = json!({ "choices": [{ "index": 0, "delta": { "content": part } }] });
+            body.push_str(&format!("data: {}\n\n", chunk));
+        }
+        body.push_str("data: [DONE]\n\n");
+        ResponseTemplate::new(200).set_body_raw(body, "text/event-stream")
+    }
+
+    async fn generate(client: &ApiClient, max_retries: u32) -> Result<String, AppError> {
+        client.generate_text("sk-test", "系统提示", "用户输入", max_retries, None, |_| {}).await
+    }
+
+    #[tokio::test]
+    async fn deepseek_stream_is_assembled_and_reported() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .and(header("authorization", "Bearer sk-test"))
+            .and(body_string_contains("\"stream\":true"))
+            .respond_with(deepseek_stream(&["欢迎", "来到", "直播间"]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut progress = Vec::new();
+        let text = client(&server)
+            .call_deepseek_api("sk-test", "系统提示", "用户输入", None, &mut |text: &str| progress.push(text.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(text, "欢迎来到直播间");
+        assert_eq!(progress.last().map(String::as_str), Some("欢迎来到直播间"));
+    }
+
+    #[tokio::test]
+    async fn deepseek_chunks_without_choices_add_nothing() {
+        let server = MockServer::start().await;
+        let body = "data: {\"choices\": []}\n\ndata: {\"choices\": [{\"delta\": {}}]}\n\n: keep-alive\n\ndata: [DONE]\n\n";
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body, "text/event-stream"))
+            .mount(&server)
+            .await;
+
+        let text = client(&server).call_deepseek_api("sk-test", "s", "u", None, &mut |_: &str| {}).await.unwrap();
+        assert_eq!(text, "");
+    }
+
+    #[tokio::test]
+    async fn deepseek_http_errors_map_to_deepseek_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .respond_with(ResponseTemplate::new(402).set_body_string("Insufficient Balance"))
+            .mount(&server)
+            .await;
+
+        match generate(&client(&server), 0).await {
+            Err(AppError::DeepSeekApi(message)) => {
+                assert!(message.contains("402") && message.contains("Insufficient Balance"), "{}", message)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_reply_is_retried_with_the_firmer_prompt() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .and(body_string_contains(RETRY_INSTRUCTION))
+            .respond_with(deepseek_stream(&["欢迎来到直播间，", "今天给大家带来新品。"]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .respond_with(deepseek_stream(&[]))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(generate(&client(&server), 2).await.unwrap(), "欢迎来到直播间，今天给大家带来新品。");
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .respond_with(deepseek_stream(&["抱歉，我无法回答这个问题。"]))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        match generate(&client(&server), 2).await {
+            Err(AppError::DeepSeekApi(message)) => assert!(message.contains("已重试 2 次"), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_deepseek_stream_is_rejected() {
+        let server = MockServer::start().await;
+        let part = "字".repeat(100 * 1024);
+        Mock::given(method("POST"))
+            .and(path("/deepseek/chat/completions"))
+            .respond_with(deepseek_stream(&[part.as_str(); 8]))
+            .mount(&server)
+            .await;
+
+        let network = NetworkSettings { max_response_mb: 1, ..NetworkSettings::default() };
+        let client = client_with(&server, &network, &CacheSettings::default());
+        assert!(matches!(generate(&client, 0).await, Err(AppError::ResponseTooLarge(_))));
+    }
+
+    #[tokio::test]
+    async fn deepseek_key_check_lists_models() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/deepseek/models"))
+            .and(header("authorization", "Bearer good"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "object": "list",
+                "data": [{ "id": "deepseek-chat", "object": "model" }, { "id": "deepseek-reasoner", "object": "model" }],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/deepseek/models"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let client = client(&server);
+        assert_eq!(client.list_deepseek_models("good").await.unwrap(), vec!["deepseek-chat", "deepseek-reasoner"]);
+        assert!(client.verify_deepseek_key("good").await.is_ok());
+        match client.verify_deepseek_key("bad").await {
+            Err(AppError::DeepSeekApi(message)) => assert_eq!(message, "API 密钥无效"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_is_a_network_error() {
+        let server = MockServer::start().await;
+        let client = client(&server);
+        drop(server);
+        assert!(matches!(synthesize(&client, "你好", true).await, Err(AppError::Reqwest(_))));
+    }
+
+    /// 令牌和合成接口都要求带上全部 `headers`，各被请求一次
+    async fn mount_requiring_headers(server: &MockServer, headers: &[(&'static str, &str)]) {
+        let mut token = Mock::given(method("POST")).and(path(TOKEN_PATH));
+        let mut tts = Mock::given(method("POST")).and(path(TTS_PATH));
+        for &(name, value) in headers {
+            token = token.and(header(name, value));
+            tts = tts.and(header(name, value));
+        }
+        token
+            .and(body_string_contains("client_id=ak"))
+            .and(body_string_contains("client_secret=sk"))
+            .respond_with(token_response())
+            .expect(1)
+            .mount(server)
+            .await;
+        tts.respond_with(audio_response(audio::silent_mp3(10))).expect(1).mount(server).await;
+    }
+
+    #[tokio::test]
+    async fn default_user_agent_is_sent() {
+        let server = MockServer::start().await;
+        mount_requiring_headers(&server, &[("user-agent", DEFAULT_USER_AGENT)]).await;
+        synthesize(&client(&server), "你好", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn custom_user_agent_and_extra_headers_are_sent() {
+        let server = MockServer::start().await;
+        mount_requiring_headers(&server, &[("user-agent", "Studio/2.0"), ("x-gateway-key", "gw-secret")]).await;
+        let network = NetworkSettings {
+            user_agent: " Studio/2.0 ".to_string(),
+            extra_headers: [(" X-Gateway-Key".to_string(), "gw-secret ".to_string())].into(),
+            ..NetworkSettings::default()
+        };
+        // 前后的空白会被去掉
+        synthesize(&client_with(&server, &network, &CacheSettings::default()), "你好", false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn server_auth_is_sent_to_the_baidu_endpoints() {
+        let cases = [
+            (ServerAuth::Bearer { token: "proxy-token".to_string() }, "Bearer proxy-token"),
+            // base64("user:pass")
+            (ServerAuth::Basic { username: "user".to_string(), password: "pass".to_string() }, "Basic dXNlcjpwYXNz"),
+        ];
+        for (auth, expected) in cases {
+            let server = MockServer::start().await;
+            mount_requiring_headers(&server, &[("authorization", expected)]).await;
+            let baidu = BaiduSettings { auth, ..baidu_settings(&server) };
+            let client = ApiClient::new(&NetworkSettings::default(), &CacheSettings::default(), &baidu, &format!("{}/deepseek/", server.uri())).unwrap();
+            synthesize(&client, "你好", false).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected_without_echoing_the_value() {
+        let network = NetworkSettings { extra_headers: [("bad name".to_string(), "v".to_string())].into(), ..NetworkSettings::default() };
+        assert!(matches!(default_headers(&network), Err(AppError::Config(_))));
+        let network = NetworkSettings { extra_headers: [("X-Key".to_string(), "secret\nvalue".to_string())].into(), ..NetworkSettings::default() };
+        match default_headers(&network) {
+            Err(AppError::Config(message)) => assert!(!message.contains("secret"), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let network = NetworkSettings { user_agent: "bad\nagent".to_string(), ..NetworkSettings::default() };
+        assert!(matches!(default_headers(&network), Err(AppError::Config(_))));
+    }
+}
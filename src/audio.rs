@@ -0,0 +1,892 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::cpal::traits::DeviceTrait;
+use rodio::decoder::DecoderError;
+use rodio::source::SeekError;
+use rodio::Source;
+
+/// 软限幅的起始阈值，低于此值的样本原样输出
+const LIMITER_THRESHOLD: f32 = 0.8;
+/// 软限幅输出的上限。输入很大时 f32 的 tanh 会舍入为 1，需要再收紧一点才能保持在 (-1, 1) 内
+const LIMITER_CEILING: f32 = 1.0 - f32::EPSILON;
+/// 音量变化的过渡时长，避免拖动滑块时产生“拉链”噪声
+const GAIN_RAMP: Duration = Duration::from_millis(20);
+
+/// 采样率和声道数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl StreamFormat {
+    pub fn of_source<S: Source>(source: &S) -> Self
+    where
+        S::Item: rodio::Sample,
+    {
+        Self {
+            sample_rate: source.sample_rate(),
+            channels: source.channels(),
+        }
+    }
+}
+
+impl fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.channels {
+            1 => write!(f, "{} Hz 单声道", self.sample_rate),
+            2 => write!(f, "{} Hz 立体声", self.sample_rate),
+            n => write!(f, "{} Hz {} 声道", self.sample_rate, n),
+        }
+    }
+}
+
+/// 打开的输出流及其实际使用的格式
+pub struct OpenedOutput {
+    pub stream: rodio::OutputStream,
+    pub handle: rodio::OutputStreamHandle,
+    pub format: StreamFormat,
+    /// 请求的采样率被调整时的说明
+    pub adjusted: Option<String>,
+}
+
+/// 以请求的采样率打开输出流，0 为设备默认；声道数和采样格式沿用设备默认。设备不支持该
+/// 采样率时改用最接近的支持值，无法查询时退回默认配置。rodio 不提供设置缓冲区大小的接口，
+/// 需要更小或更稳定的缓冲时使用低延迟输出（`monitor` 模块）
+pub fn open_output_stream(device: &rodio::cpal::Device, sample_rate: u32) -> Result<OpenedOutput, rodio::StreamError> {
+    let default = device.default_output_config()?;
+    let (config, adjusted) = if sample_rate == 0 || sample_rate == default.sample_rate().0 {
+        (default, None)
+    } else {
+        closest_output_config(device, default, sample_rate)
+    };
+    let format = StreamFormat {
+        sample_rate: config.sample_rate().0,
+        channels: config.channels(),
+    };
+    let (stream, handle) = rodio::OutputStream::try_from_device_config(device, config)?;
+    Ok(OpenedOutput { stream, handle, format, adjusted })
+}
+
+/// 在与默认配置声道数、采样格式相同的配置中找最接近 `requested` 的采样率
+fn closest_output_config(
+    device: &rodio::cpal::Device,
+    default: rodio::cpal::SupportedStreamConfig,
+    requested: u32,
+) -> (rodio::cpal::SupportedStreamConfig, Option<String>) {
+    let ranges: Vec<_> = match device.supported_output_configs() {
+        Ok(configs) => configs
+            .filter(|range| range.channels() == default.channels() && range.sample_format() == default.sample_format())
+            .collect(),
+        Err(e) => {
+            log::warn!("无法查询设备支持的输出格式: {}", e);
+            Vec::new()
+        }
+    };
+    let closest = ranges
+        .into_iter()
+        .map(|range| {
+            let rate = requested.clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+            (range, rate)
+        })
+        .min_by_key(|&(_, rate)| rate.abs_diff(requested));
+    match closest {
+        Some((range, rate)) => {
+            let adjusted = (rate != requested).then(|| format!("设备不支持 {} Hz 输出，已改用 {} Hz", requested, rate));
+            (range.with_sample_rate(rodio::cpal::SampleRate(rate)), adjusted)
+        }
+        None => {
+            let adjusted = format!("无法以 {} Hz 打开设备，已使用默认的 {} Hz", requested, default.sample_rate().0);
+            (default, Some(adjusted))
+        }
+    }
+}
+
+/// 输出设备实际支持的采样率范围和声道数，来自 cpal 的 `supported_output_configs()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceCapabilities {
+    /// 各配置的采样率范围 (最小, 最大)，已排序去重
+    sample_rates: Vec<(u32, u32)>,
+    /// 支持的声道数，升序且不重复
+    channels: Vec<u16>,
+}
+
+impl DeviceCapabilities {
+    /// 查询设备支持的输出配置；设备已断开或驱动不支持查询时返回 `None`
+    pub fn probe(device: &rodio::cpal::Device) -> Option<Self> {
+        match device.supported_output_configs() {
+            Ok(configs) => Self::from_ranges(configs),
+            Err(e) => {
+                log::warn!("无法查询设备支持的输出格式: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn from_ranges(ranges: impl IntoIterator<Item = rodio::cpal::SupportedStreamConfigRange>) -> Option<Self> {
+        let mut sample_rates = Vec::new();
+        let mut channels = Vec::new();
+        for range in ranges {
+            sample_rates.push((range.min_sample_rate().0, range.max_sample_rate().0));
+            channels.push(range.channels());
+        }
+        if sample_rates.is_empty() {
+            return None;
+        }
+        sample_rates.sort_unstable();
+        sample_rates.dedup();
+        channels.sort_unstable();
+        channels.dedup();
+        Some(Self { sample_rates, channels })
+    }
+
+    pub fn min_sample_rate(&self) -> u32 {
+        self.sample_rates.iter().map(|&(min, _)| min).min().unwrap_or(0)
+    }
+
+    pub fn max_sample_rate(&self) -> u32 {
+        self.sample_rates.iter().map(|&(_, max)| max).max().unwrap_or(0)
+    }
+
+    /// 采样率落在任一配置的范围内即视为支持，范围之间的空隙不算
+    pub fn supports_sample_rate(&self, sample_rate: u32) -> bool {
+        self.sample_rates.iter().any(|&(min, max)| (min..=max).contains(&sample_rate))
+    }
+
+    pub fn supports_channels(&self, channels: u16) -> bool {
+        self.channels.contains(&channels)
+    }
+
+    /// 设备能否不经转换直接输出该格式
+    pub fn supports(&self, format: StreamFormat) -> bool {
+        self.supports_sample_rate(format.sample_rate) && self.supports_channels(format.channels)
+    }
+}
+
+impl fmt::Display for DeviceCapabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels = self.channels.iter().map(u16::to_string).collect::<Vec<_>>().join("/");
+        let (min, max) = (self.min_sample_rate(), self.max_sample_rate());
+        if min == max {
+            write!(f, "支持 {} Hz，{} 声道", min, channels)
+        } else {
+            write!(f, "支持 {}–{} Hz，{} 声道", min, max, channels)
+        }
+    }
+}
+
+/// 输出设备的连接状态
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputHealth {
+    Connected,
+    /// 设备已拔出或驱动报错，附带原因
+    Lost(String),
+}
+
+impl fmt::Display for OutputHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputHealth::Connected => write!(f, "正常"),
+            OutputHealth::Lost(reason) => write!(f, "已断开 ({})", reason),
+        }
+    }
+}
+
+/// 设备被拔出后查询其输出配置会失败，借此判断流是否已经失效
+pub fn check_device(device: &rodio::cpal::Device) -> OutputHealth {
+    match device.default_output_config() {
+        Ok(_) => OutputHealth::Connected,
+        Err(e) => OutputHealth::Lost(e.to_string()),
+    }
+}
+
+/// 解码失败的具体原因，用于界面提示
+pub fn describe_decode_error(error: &DecoderError) -> String {
+    match error {
+        DecoderError::UnrecognizedFormat => "不支持的音频格式，或文件不是音频".to_string(),
+        DecoderError::NoStreams => "文件中没有音频流".to_string(),
+        DecoderError::DecodeError(reason) => format!("音频数据已损坏 ({})", reason),
+        DecoderError::IoError(reason) => format!("读取音频数据失败 ({})", reason),
+        other => other.to_string(),
+    }
+}
+
+/// 确认数据能被解码出至少一个采样，合成结果写入缓存前用它排除损坏或截断的音频
+pub fn validate_audio(data: &[u8]) -> Result<(), String> {
+    let mut decoder = rodio::Decoder::new(std::io::Cursor::new(data.to_vec())).map_err(|e| describe_decode_error(&e))?;
+    match decoder.next() {
+        Some(_) => Ok(()),
+        None => Err("音频中没有任何采样".to_string()),
+    }
+}
+
+/// MPEG-1 Layer III 的比特率表（kbps），下标为帧头中的比特率索引
+const MP3_BITRATES_V1: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+/// MPEG-2/2.5 Layer III 的比特率表（kbps）
+const MP3_BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// MP3 帧头给出的帧长（字节）和每帧的采样数、采样率；不是 Layer III 帧头时返回 `None`
+fn mp3_frame(header: [u8; 4]) -> Option<(usize, u32, u32)> {
+    if header[0] != 0xff || header[1] & 0xe0 != 0xe0 || (header[1] >> 1) & 0x03 != 0x01 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0x03;
+    let bitrate_index = usize::from(header[2] >> 4);
+    let rate_index = usize::from((header[2] >> 2) & 0x03);
+    if version == 0x01 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+    let padding = u32::from((header[2] >> 1) & 0x01);
+    let (bitrate, sample_rate, samples) = match version {
+        0x03 => (MP3_BITRATES_V1[bitrate_index], [44100, 48000, 32000][rate_index], 1152),
+        0x02 => (MP3_BITRATES_V2[bitrate_index], [22050, 24000, 16000][rate_index], 576),
+        _ => (MP3_BITRATES_V2[bitrate_index], [11025, 12000, 8000][rate_index], 576),
+    };
+    let length = samples / 8 * bitrate * 1000 / sample_rate + padding;
+    Some((length as usize, samples, sample_rate))
+}
+
+/// 在帧边界处把 MP3 截断到不超过 `max` 的时长，开头的 ID3v2 标签原样保留。
+/// 不超长，或遇到无法识别的数据无法可靠截断时返回 `None`
+pub fn trim_mp3(data: &[u8], max: Duration) -> Option<Vec<u8>> {
+    let mut offset = 0;
+    if data.len() >= 10 && data.starts_with(b"ID3") {
+        let size = data[6..10].iter().fold(0usize, |size, &b| (size << 7) | usize::from(b & 0x7f));
+        offset = 10 + size;
+    }
+    let mut elapsed = Duration::ZERO;
+    while offset + 4 <= data.len() {
+        let (length, samples, sample_rate) = mp3_frame([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])?;
+        elapsed += Duration::from_secs_f64(f64::from(samples) / f64::from(sample_rate));
+        if elapsed > max {
+            return Some(data[..offset].to_vec());
+        }
+        offset += length;
+    }
+    None
+}
+
+/// 音源与输出设备格式不一致时返回说明。rodio 会在混音前自动重采样和转换声道，
+/// 这里只是让用户知道转换正在发生（例如路由到虚拟声卡时）
+pub fn format_mismatch(source: StreamFormat, device: StreamFormat) -> Option<String> {
+    if source == device {
+        return None;
+    }
+    let mut steps = Vec::new();
+    if source.sample_rate != device.sample_rate {
+        steps.push("重采样");
+    }
+    if source.channels != device.channels {
+        steps.push("声道转换");
+    }
+    Some(format!("语音为 {}，输出设备为 {}，播放时自动{}", source, device, steps.join("和")))
+}
+
+/// 一路输出（语音或音效）的增益与限幅器开关，可在 UI 线程与音频线程间共享
+pub struct OutputControl {
+    gain: AtomicU32,
+    limiter_enabled: AtomicBool,
+}
+
+impl OutputControl {
+    pub fn new(gain: f32) -> Arc<Self> {
+        Arc::new(Self {
+            gain: AtomicU32::new(gain.to_bits()),
+            limiter_enabled: AtomicBool::new(true),
+        })
+    }
+
+    pub fn set_gain(&self, gain: f32) {
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn gain(&self) -> f32 {
+        f32::from_bits(self.gain.load(Ordering::Relaxed))
+    }
+
+    pub fn set_limiter_enabled(&self, enabled: bool) {
+        self.limiter_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn limiter_enabled(&self) -> bool {
+        self.limiter_enabled.load(Ordering::Relaxed)
+    }
+}
+
+/// 内置提示音：两声短促的上行音，用于语音播报前提醒观众
+pub fn builtin_chime() -> impl Source<Item = f32> + Send {
+    let tone = |frequency: f32| {
+        rodio::source::SineWave::new(frequency)
+            .take_duration(Duration::from_millis(120))
+            .amplify(0.25)
+    };
+    rodio::source::from_iter([tone(880.0), tone(1320.0)])
+}
+
+/// 分贝换算为线性增益
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// 语音电平表的释放时间：字与字之间的短暂停顿不会立即解除闪避
+const LEVEL_METER_RELEASE: Duration = Duration::from_millis(200);
+
+/// 语音播放时压低音效（闪避）。语音一路通过 `ControlledSource::with_level_meter` 写入
+/// 当前电平，音效一路通过 `with_ducking` 读取：电平高于阈值时在 attack 时间内衰减到
+/// 设定的深度，语音停顿或结束后在 release 时间内恢复
+pub struct Ducking {
+    enabled: AtomicBool,
+    /// 线性幅度
+    threshold: AtomicU32,
+    /// 闪避时的线性增益
+    depth: AtomicU32,
+    attack_ms: AtomicU32,
+    release_ms: AtomicU32,
+    /// 语音当前的峰值包络，语音音源结束时归零
+    level: AtomicU32,
+}
+
+impl Ducking {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            enabled: AtomicBool::new(false),
+            threshold: AtomicU32::new(db_to_gain(-40.0).to_bits()),
+            depth: AtomicU32::new(db_to_gain(-12.0).to_bits()),
+            attack_ms: AtomicU32::new(50),
+            release_ms: AtomicU32::new(500),
+            level: AtomicU32::new(0f32.to_bits()),
+        })
+    }
+
+    pub fn configure(&self, enabled: bool, threshold_db: f32, amount_db: f32, attack: Duration, release: Duration) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.threshold.store(db_to_gain(threshold_db).to_bits(), Ordering::Relaxed);
+        self.depth.store(db_to_gain(-amount_db.abs()).to_bits(), Ordering::Relaxed);
+        self.attack_ms.store(attack.as_millis() as u32, Ordering::Relaxed);
+        self.release_ms.store(release.as_millis() as u32, Ordering::Relaxed);
+    }
+
+    /// 音效当前应当趋近的增益
+    fn target(&self) -> f32 {
+        let level = f32::from_bits(self.level.load(Ordering::Relaxed));
+        let threshold = f32::from_bits(self.threshold.load(Ordering::Relaxed));
+        if self.enabled.load(Ordering::Relaxed) && level > 0.0 && level >= threshold {
+            f32::from_bits(self.depth.load(Ordering::Relaxed))
+        } else {
+            1.0
+        }
+    }
+
+    fn set_level(&self, level: f32) {
+        self.level.store(level.to_bits(), Ordering::Relaxed);
+    }
+
+    /// 语音暂停或停止时调用，音效随即开始恢复
+    pub fn reset_level(&self) {
+        self.set_level(0.0);
+    }
+}
+
+/// 闪避增益包络：衰减和恢复分别按 attack、release 时间匀速完成一次满幅变化
+pub struct DuckEnvelope {
+    current: f32,
+    samples_per_ms: f32,
+}
+
+impl DuckEnvelope {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            current: 1.0,
+            samples_per_ms: sample_rate as f32 * channels as f32 / 1000.0,
+        }
+    }
+
+    /// 推进一个样本并返回当前增益
+    pub fn advance(&mut self, target: f32, attack_ms: u32, release_ms: u32) -> f32 {
+        let ramp_ms = if target < self.current { attack_ms } else { release_ms };
+        let step = 1.0 / (ramp_ms as f32 * self.samples_per_ms).max(1.0);
+        if self.current > target {
+            self.current = (self.current - step).max(target);
+        } else if self.current < target {
+            self.current = (self.current + step).min(target);
+        }
+        self.current
+    }
+}
+
+/// 峰值包络电平表，写入 `Ducking` 供音效一路读取
+struct LevelMeter {
+    ducking: Arc<Ducking>,
+    level: f32,
+    decay: f32,
+}
+
+/// 记录当前音源的播放位置，rodio 的 `Sink` 本身不提供进度查询
+#[derive(Default)]
+pub struct PlaybackClock {
+    position_us: AtomicU64,
+}
+
+impl PlaybackClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_micros(self.position_us.load(Ordering::Relaxed))
+    }
+
+    fn set_position(&self, position: Duration) {
+        self.position_us.store(position.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// 软限幅：阈值以下线性，阈值以上用 tanh 平滑压缩，输出始终落在 (-1, 1) 内，
+/// 避免硬削波带来的刺耳失真
+pub fn soft_limit(sample: f32) -> f32 {
+    let magnitude = sample.abs();
+    if magnitude <= LIMITER_THRESHOLD {
+        return sample;
+    }
+    let headroom = 1.0 - LIMITER_THRESHOLD;
+    let compressed = LIMITER_THRESHOLD + headroom * ((magnitude - LIMITER_THRESHOLD) / headroom).tanh();
+    compressed.min(LIMITER_CEILING).copysign(sample)
+}
+
+/// 线性增益过渡：目标变化后恰好用 `ramp_samples` 个样本匀速到达，不会越过目标
+pub struct GainRamp {
+    current: f32,
+    target: f32,
+    step: f32,
+    ramp_samples: u32,
+    /// 到达目标还需的样本数
+    remaining: u32,
+}
+
+impl GainRamp {
+    pub fn new(initial: f32, sample_rate: u32, channels: u16) -> Self {
+        let ramp_samples = (GAIN_RAMP.as_secs_f32() * sample_rate as f32 * channels as f32).round().max(1.0) as u32;
+        Self {
+            current: initial,
+            target: initial,
+            step: 0.0,
+            ramp_samples,
+            remaining: 0,
+        }
+    }
+
+    /// 推进一个样本并返回当前增益
+    pub fn advance(&mut self, target: f32) -> f32 {
+        if target != self.target {
+            self.target = target;
+            self.step = (target - self.current) / self.ramp_samples as f32;
+            self.remaining = self.ramp_samples;
+        }
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            // 最后一步直接落在目标上，避免累加误差；中途的舍入误差也不能越过目标
+            self.current = if self.remaining == 0 {
+                self.target
+            } else if self.step > 0.0 {
+                (self.current + self.step).min(self.target)
+            } else {
+                (self.current + self.step).max(self.target)
+            };
+        }
+        self.current
+    }
+}
+
+/// 在音源上施加 `OutputControl` 的增益，并在增益之后做软限幅。
+/// 增益由这里而不是 `Sink::set_volume` 施加，限幅器才能看到放大后的信号；
+/// 增益变化经过 `GainRamp` 平滑过渡。
+pub struct ControlledSource<S> {
+    input: S,
+    control: Arc<OutputControl>,
+    ramp: GainRamp,
+    clock: Option<Arc<PlaybackClock>>,
+    samples_played: u64,
+    meter: Option<LevelMeter>,
+    ducking: Option<(Arc<Ducking>, DuckEnvelope)>,
+    on_end: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl<S> ControlledSource<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, control: Arc<OutputControl>) -> Self {
+        let ramp = GainRamp::new(control.gain(), input.sample_rate(), input.channels());
+        Self {
+            input,
+            control,
+            ramp,
+            clock: None,
+            samples_played: 0,
+            meter: None,
+            ducking: None,
+            on_end: None,
+        }
+    }
+
+    /// 音源自然播放完毕时调用一次 `on_end`（在音频线程上）。被 `Sink::stop`、`clear`
+    /// 或低延迟输出的 `stop` 提前丢弃时不会调用
+    pub fn with_end_notification(mut self, on_end: impl FnOnce() + Send + 'static) -> Self {
+        self.on_end = Some(Box::new(on_end));
+        self
+    }
+
+    /// 把本音源（语音）的电平写入 `ducking`，音源结束或被丢弃时电平归零
+    pub fn with_level_meter(mut self, ducking: Arc<Ducking>) -> Self {
+        let release_samples = LEVEL_METER_RELEASE.as_secs_f32() * self.samples_per_second() as f32;
+        self.meter = Some(LevelMeter {
+            ducking,
+            level: 0.0,
+            decay: (-1.0 / release_samples.max(1.0)).exp(),
+        });
+        self
+    }
+
+    /// 本音源（音效）随语音电平闪避
+    pub fn with_ducking(mut self, ducking: Arc<Ducking>) -> Self {
+        let envelope = DuckEnvelope::new(self.input.sample_rate(), self.input.channels());
+        self.ducking = Some((ducking, envelope));
+        self
+    }
+
+    /// 播放时把进度写入 `clock`，同一个时钟同时只应挂在一个音源上
+    pub fn with_clock(mut self, clock: Arc<PlaybackClock>) -> Self {
+        clock.set_position(Duration::ZERO);
+        self.clock = Some(clock);
+        self
+    }
+
+    fn samples_per_second(&self) -> u64 {
+        self.input.sample_rate() as u64 * self.input.channels().max(1) as u64
+    }
+}
+
+impl<S> Iterator for ControlledSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let Some(input) = self.input.next() else {
+            if let Some(meter) = &self.meter {
+                meter.ducking.reset_level();
+            }
+            if let Some(on_end) = self.on_end.take() {
+                on_end();
+            }
+            return None;
+        };
+        let mut sample = input * self.ramp.advance(self.control.gain());
+        if let Some((ducking, envelope)) = &mut self.ducking {
+            let attack = ducking.attack_ms.load(Ordering::Relaxed);
+            let release = ducking.release_ms.load(Ordering::Relaxed);
+            sample *= envelope.advance(ducking.target(), attack, release);
+        }
+        if let Some(meter) = &mut self.meter {
+            meter.level = sample.abs().max(meter.level * meter.decay);
+            meter.ducking.set_level(meter.level);
+        }
+        if let Some(clock) = &self.clock {
+            self.samples_played += 1;
+            clock.set_position(Duration::from_micros(self.samples_played * 1_000_000 / self.samples_per_second()));
+        }
+        if self.control.limiter_enabled() {
+            Some(soft_limit(sample))
+        } else {
+            Some(sample)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<S> Drop for ControlledSource<S> {
+    fn drop(&mut self) {
+        if let Some(meter) = &self.meter {
+            meter.ducking.reset_level();
+        }
+    }
+}
+
+impl<S> Source for ControlledSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.samples_played = (pos.as_secs_f64() * self.samples_per_second() as f64) as u64;
+        if let Some(clock) = &self.clock {
+            clock.set_position(pos);
+        }
+        Ok(())
+    }
+}
+
+/// 测试用的静音 MP3：MPEG-1 Layer III、128 kbps、44.1 kHz 立体声，每帧 1152 个采样
+#[cfg(test)]
+pub(crate) fn silent_mp3(frames: usize) -> Vec<u8> {
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+    frame.repeat(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn controlled(samples: Vec<f32>, gain: f32, limiter: bool) -> Vec<f32> {
+        let control = OutputControl::new(gain);
+        control.set_limiter_enabled(limiter);
+        ControlledSource::new(SamplesBuffer::new(1, 1000, samples), control).collect()
+    }
+
+    #[test]
+    fn limiter_keeps_over_unity_input_inside_unit_range() {
+        let input: Vec<f32> = (0..=200).map(|i| i as f32 * 0.05 - 5.0).collect();
+        let output = controlled(input.clone(), 1.5, true);
+        assert_eq!(output.len(), input.len());
+        assert!(output.iter().all(|s| s.abs() < 1.0), "{:?}", output);
+        // 平滑压缩：输出随输入单调变化，不会像硬削波那样在阈值处折断成平顶
+        assert!(output.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(soft_limit(0.9) < soft_limit(1.0) && soft_limit(1.0) < soft_limit(1.2));
+        assert!(soft_limit(1000.0) < 1.0 && soft_limit(-1000.0) > -1.0);
+    }
+
+    #[test]
+    fn limiter_passes_samples_below_threshold_unchanged() {
+        let input = vec![0.0, 0.25, -0.5, LIMITER_THRESHOLD, -LIMITER_THRESHOLD];
+        assert_eq!(controlled(input.clone(), 1.0, true), input);
+    }
+
+    /// 切换目标后逐个样本推进，直到到达目标，返回经过的增益
+    fn ramp_to(ramp: &mut GainRamp, target: f32) -> Vec<f32> {
+        let mut gains = Vec::new();
+        loop {
+            let gain = ramp.advance(target);
+            gains.push(gain);
+            if gain == target || gains.len() > 10 * ramp.ramp_samples as usize {
+                return gains;
+            }
+        }
+    }
+
+    #[test]
+    fn gain_ramp_reaches_target_in_exactly_ramp_samples_steps() {
+        // 1 kHz 单声道下 20 ms 为 20 个样本
+        let mut ramp = GainRamp::new(1.0, 1000, 1);
+        assert_eq!(ramp.ramp_samples, 20);
+        for target in [0.3, 1.5, 1.4999, 0.0, 0.7] {
+            let start = ramp.current;
+            let gains = ramp_to(&mut ramp, target);
+            assert_eq!(gains.len(), 20, "{} -> {}", start, target);
+            let (low, high) = if start < target { (start, target) } else { (target, start) };
+            assert!(gains.iter().all(|&g| (low..=high).contains(&g)), "{} -> {} 越过了目标: {:?}", start, target, gains);
+            if start < target {
+                assert!(gains.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", gains);
+            } else {
+                assert!(gains.windows(2).all(|pair| pair[0] > pair[1]), "{:?}", gains);
+            }
+            assert_eq!(ramp.advance(target), target);
+        }
+    }
+
+    #[test]
+    fn gain_ramp_restarts_from_the_current_gain_when_retargeted() {
+        let mut ramp = GainRamp::new(0.0, 1000, 1);
+        for _ in 0..10 {
+            ramp.advance(1.0);
+        }
+        let midway = ramp.current;
+        assert!((midway - 0.5).abs() < 1e-6);
+        let gains = ramp_to(&mut ramp, 0.0);
+        assert_eq!(gains.len(), 20);
+        assert!(gains.iter().all(|&g| g < midway && g >= 0.0));
+    }
+
+    #[test]
+    fn disabled_limiter_does_not_touch_the_signal() {
+        assert_eq!(controlled(vec![1.5, -3.0], 1.0, false), vec![1.5, -3.0]);
+    }
+
+    /// 带结束通知的 3 个样本的音源，以及通知被调用的次数
+    fn notifying_source() -> (ControlledSource<SamplesBuffer<f32>>, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let source = ControlledSource::new(SamplesBuffer::new(1, 1000, vec![0.1, 0.2, 0.3]), OutputControl::new(1.0))
+            .with_end_notification(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        (source, calls)
+    }
+
+    #[test]
+    fn end_notification_fires_once_when_the_source_runs_out() {
+        let (mut source, calls) = notifying_source();
+        for _ in 0..3 {
+            assert!(source.next().is_some());
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert_eq!(source.next(), None);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // 输出端在结束后可能继续轮询
+        assert_eq!(source.next(), None);
+        drop(source);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn end_notification_does_not_fire_when_dropped_early() {
+        let (mut source, calls) = notifying_source();
+        assert!(source.next().is_some());
+        drop(source);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let (source, calls) = notifying_source();
+        drop(source);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    /// 以固定目标推进包络，直到到达目标，返回经过的增益
+    fn envelope_to(envelope: &mut DuckEnvelope, target: f32, attack_ms: u32, release_ms: u32) -> Vec<f32> {
+        let mut gains = Vec::new();
+        while gains.last() != Some(&target) && gains.len() < 100_000 {
+            gains.push(envelope.advance(target, attack_ms, release_ms));
+        }
+        gains
+    }
+
+    #[test]
+    fn duck_envelope_attacks_and_releases_at_their_own_rates() {
+        // 1000 Hz 单声道：每毫秒一个样本
+        let mut envelope = DuckEnvelope::new(1000, 1);
+        let attack = envelope_to(&mut envelope, 0.2, 10, 40);
+        // 满幅变化用 10 ms，0.8 的变化约 8 个样本
+        assert!((8..=9).contains(&attack.len()), "{:?}", attack);
+        assert!(attack.windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(attack.iter().all(|&g| g >= 0.2));
+        assert_eq!(envelope.advance(0.2, 10, 40), 0.2);
+
+        let release = envelope_to(&mut envelope, 1.0, 10, 40);
+        assert!((32..=33).contains(&release.len()), "{:?}", release);
+        assert!(release.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(release.iter().all(|&g| g <= 1.0));
+    }
+
+    #[test]
+    fn duck_envelope_counts_every_channel() {
+        let mut envelope = DuckEnvelope::new(1000, 2);
+        assert!((16..=17).contains(&envelope_to(&mut envelope, 0.2, 10, 10).len()));
+        // 时间为 0 时一个样本内完成
+        let mut envelope = DuckEnvelope::new(48000, 2);
+        assert_eq!(envelope.advance(0.0, 0, 0), 0.0);
+        assert_eq!(envelope.advance(1.0, 0, 0), 1.0);
+    }
+
+    #[test]
+    fn validation_rejects_broken_audio() {
+        assert_eq!(validate_audio(&silent_mp3(10)), Ok(()));
+        assert!(validate_audio(b"").is_err());
+        assert!(validate_audio(b"{\"err_no\":502}").is_err());
+        // 只有帧头、没有完整帧的截断数据
+        assert!(validate_audio(&silent_mp3(1)[..4]).is_err());
+    }
+
+    /// `silent_mp3` 每帧的时长，约 26 ms
+    fn frame_duration() -> Duration {
+        Duration::from_secs_f64(1152.0 / 44100.0)
+    }
+
+    #[test]
+    fn trim_leaves_audio_within_the_limit_alone() {
+        let mp3 = silent_mp3(10);
+        assert_eq!(trim_mp3(&mp3, Duration::from_secs(1)), None);
+        assert_eq!(trim_mp3(&mp3, frame_duration() * 10), None);
+    }
+
+    #[test]
+    fn trimmed_audio_ends_on_the_last_whole_frame_within_the_limit() {
+        let max = Duration::from_secs(1);
+        let trimmed = trim_mp3(&silent_mp3(100), max).unwrap();
+        // 38 帧约 0.993 秒，再多一帧就超过 1 秒
+        assert_eq!(trimmed, silent_mp3(38));
+        assert!(frame_duration() * 38 <= max && frame_duration() * 39 > max);
+        assert_eq!(validate_audio(&trimmed), Ok(()));
+        // 截断后的结果不会再被截断
+        assert_eq!(trim_mp3(&trimmed, max), None);
+    }
+
+    #[test]
+    fn trim_keeps_the_id3_tag() {
+        let tag = [b"ID3\x04\x00\x00\x00\x00\x00\x05".as_slice(), b"tags!"].concat();
+        let mp3 = [tag.clone(), silent_mp3(100)].concat();
+        let trimmed = trim_mp3(&mp3, Duration::from_secs(1)).unwrap();
+        assert_eq!(trimmed, [tag, silent_mp3(38)].concat());
+    }
+
+    #[test]
+    fn trim_gives_up_on_unrecognized_frames() {
+        let mut mp3 = silent_mp3(100);
+        mp3[10 * 417] = 0;
+        assert_eq!(trim_mp3(&mp3, Duration::from_secs(1)), None);
+        // 超出上限之后的数据不影响截断
+        let mut mp3 = silent_mp3(100);
+        mp3[60 * 417] = 0;
+        assert_eq!(trim_mp3(&mp3, Duration::from_secs(1)), Some(silent_mp3(38)));
+    }
+
+    fn range(channels: u16, min: u32, max: u32) -> rodio::cpal::SupportedStreamConfigRange {
+        use rodio::cpal::{SampleFormat, SampleRate, SupportedBufferSize};
+        rodio::cpal::SupportedStreamConfigRange::new(channels, SampleRate(min), SampleRate(max), SupportedBufferSize::Unknown, SampleFormat::F32)
+    }
+
+    #[test]
+    fn capabilities_merge_the_device_ranges() {
+        let capabilities =
+            DeviceCapabilities::from_ranges([range(2, 44100, 48000), range(1, 8000, 16000), range(2, 44100, 48000), range(6, 96000, 96000)])
+                .unwrap();
+        assert_eq!(capabilities.min_sample_rate(), 8000);
+        assert_eq!(capabilities.max_sample_rate(), 96000);
+        assert!(capabilities.supports_sample_rate(16000) && capabilities.supports_sample_rate(44100));
+        // 范围之间的空隙不算支持
+        assert!(!capabilities.supports_sample_rate(22050));
+        assert!(capabilities.supports_channels(1) && capabilities.supports_channels(6));
+        assert!(!capabilities.supports_channels(4));
+        assert!(capabilities.supports(StreamFormat { sample_rate: 48000, channels: 2 }));
+        assert!(!capabilities.supports(StreamFormat { sample_rate: 24000, channels: 2 }));
+        assert_eq!(capabilities.to_string(), "支持 8000–96000 Hz，1/2/6 声道");
+    }
+
+    #[test]
+    fn capabilities_of_a_device_without_configs_are_unknown() {
+        assert_eq!(DeviceCapabilities::from_ranges([]), None);
+        let fixed = DeviceCapabilities::from_ranges([range(2, 48000, 48000)]).unwrap();
+        assert_eq!(fixed.to_string(), "支持 48000 Hz，2 声道");
+    }
+}
@@ -0,0 +1,78 @@
+//! 批量生成：把多行文本逐行合成后保存为单独的 MP3 文件。
+//!
+//! 每行一段文本，行末可以用英文逗号附加发音人（名称或代码），例如 `欢迎来到直播间,度小宇 (男声)`
+//! 或 `欢迎来到直播间,1`；最后一栏不是已知的发音人时整行都当作文本。CSV 文件按同样的规则读取，
+//! 文本两端的英文双引号会被去掉。
+
+use std::path::PathBuf;
+
+use crate::config::VOICES;
+
+/// 待合成的一行
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchLine {
+    pub text: String,
+    /// 未指定时使用界面当前选择的发音人
+    pub person: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchStatus {
+    Pending,
+    Running,
+    Done(PathBuf),
+    Failed(String),
+    Canceled,
+}
+
+impl BatchStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, BatchStatus::Done(_) | BatchStatus::Failed(_) | BatchStatus::Canceled)
+    }
+}
+
+/// 结果表中的一行
+pub struct BatchRow {
+    pub line: BatchLine,
+    pub status: BatchStatus,
+}
+
+impl BatchRow {
+    pub fn new(line: BatchLine) -> Self {
+        Self { line, status: BatchStatus::Pending }
+    }
+}
+
+/// 解析粘贴的文本或 CSV 内容，跳过空行
+pub fn parse_lines(input: &str) -> Vec<BatchLine> {
+    input.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<BatchLine> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let (text, person) = match line.rsplit_once(',') {
+        Some((text, voice)) => match parse_voice(voice) {
+            Some(person) => (text, Some(person)),
+            None => (line, None),
+        },
+        None => (line, None),
+    };
+    let text = text.trim();
+    let text = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')).unwrap_or(text).trim();
+    (!text.is_empty()).then(|| BatchLine { text: text.to_string(), person })
+}
+
+/// 接受发音人代码或 `VOICES` 中的名称（名称可省略括号中的说明）
+fn parse_voice(value: &str) -> Option<i32> {
+    let value = value.trim().trim_matches('"').trim();
+    if let Ok(code) = value.parse::<i32>() {
+        return VOICES.iter().any(|&(_, person)| person == code).then_some(code);
+    }
+    VOICES
+        .iter()
+        .find(|(name, _)| *name == value || name.split(" (").next() == Some(value))
+        .map(|&(_, person)| person)
+}
@@ -0,0 +1,468 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// 百度音量参数的基准值：忽略音量的缓存模式下统一按此音量合成，
+/// 播放时再按 `volume / REFERENCE_VOLUME` 调整增益
+pub const REFERENCE_VOLUME: i32 = 5;
+
+/// 缓存键包含哪些合成参数
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheKeyMode {
+    /// 文本、发音人、语速、音调、音量全部参与，任何改动都会重新合成
+    #[default]
+    Full,
+    /// 音量不参与缓存键，改变音量时复用已有音频并在本地调整增益
+    IgnoreVolume,
+}
+
+impl CacheKeyMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CacheKeyMode::Full => "全部参数",
+            CacheKeyMode::IgnoreVolume => "忽略音量（本地调节）",
+        }
+    }
+
+    /// 实际发送给百度的音量
+    pub fn request_volume(&self, volume: i32) -> i32 {
+        match self {
+            CacheKeyMode::Full => volume,
+            CacheKeyMode::IgnoreVolume => REFERENCE_VOLUME,
+        }
+    }
+
+    /// 播放时需要额外施加的增益，近似补偿未发送给百度的音量
+    pub fn playback_gain(&self, volume: i32) -> f32 {
+        match self {
+            CacheKeyMode::Full => 1.0,
+            CacheKeyMode::IgnoreVolume => volume.max(0) as f32 / REFERENCE_VOLUME as f32,
+        }
+    }
+}
+
+/// `namespace` 区分不同时期的合成结果：服务端音色更新后换一个命名空间，
+/// 之前缓存的条目就不会再被命中
+pub fn generate_cache_key(
+    mode: CacheKeyMode,
+    namespace: &str,
+    text: &str,
+    speed: i32,
+    pitch: i32,
+    volume: i32,
+    person: i32,
+) -> String {
+    match mode {
+        CacheKeyMode::Full => format!("{}|{}|{}|{}|{}|{}", namespace, person, speed, pitch, volume, text),
+        CacheKeyMode::IgnoreVolume => format!("{}|{}|{}|{}|*|{}", namespace, person, speed, pitch, text),
+    }
+}
+
+/// 用当前时间生成新的缓存命名空间
+pub fn new_namespace() -> String {
+    chrono::Local::now().format("%Y%m%d%H%M%S").to_string()
+}
+
+/// 转存文件的序号，在所有缓存实例间共享，重新连接前后的两个缓存不会写到同一个文件
+static NEXT_SPILL_FILE: AtomicU64 = AtomicU64::new(0);
+
+/// 缓存中的一段音频：在内存中，或因超出内存上限已转存到临时文件
+enum LazyAudio {
+    Memory(Arc<Vec<u8>>),
+    Disk { path: PathBuf, len: usize },
+}
+
+impl LazyAudio {
+    fn remove_file(&self) {
+        if let LazyAudio::Disk { path, .. } = self {
+            if let Err(e) = fs::remove_file(path) {
+                log::debug!("删除缓存临时文件 {} 失败: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// 查找缓存的结果。已转存的条目由调用方在释放缓存锁之后读回，再交给 `TtsCache::restore`
+pub enum Lookup {
+    Hit(Arc<Vec<u8>>),
+    OnDisk(PathBuf),
+    Miss,
+}
+
+/// 选中转存的一条音频。磁盘写入由调用方在释放缓存锁之后执行，完成后交给 `TtsCache::finish_spill`；
+/// 写完之前条目仍留在内存中，照常可以命中
+pub struct SpillJob {
+    key: String,
+    path: PathBuf,
+    audio: Arc<Vec<u8>>,
+}
+
+impl SpillJob {
+    pub fn write(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, self.audio.as_slice())
+    }
+}
+
+/// 缓存的占用情况，用于界面显示
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub entries: usize,
+    /// 留在内存中的音频总字节数
+    pub memory_bytes: usize,
+    /// 已转存到磁盘的条数和字节数
+    pub spilled: usize,
+    pub spilled_bytes: usize,
+}
+
+/// 合成结果的缓存，超出条数上限时淘汰最久未使用的条目；内存占用超出 `memory_budget`
+/// 时把最久未使用的音频转存到临时目录，再次命中时读回内存。
+///
+/// 缓存本身不做磁盘读写：需要转存时返回 `SpillJob`，命中已转存的条目时返回文件路径，
+/// 由调用方在锁外完成读写，避免持锁阻塞其他合成请求
+pub struct TtsCache {
+    entries: HashMap<String, LazyAudio>,
+    order: VecDeque<String>,
+    capacity: usize,
+    /// 内存上限（字节），0 表示不限
+    memory_budget: usize,
+    memory_used: usize,
+    spill_dir: PathBuf,
+    /// 已选中转存、尚未写完的条目
+    spilling: HashSet<String>,
+}
+
+impl TtsCache {
+    pub fn new(capacity: usize, memory_budget: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+            memory_budget,
+            memory_used: 0,
+            spill_dir: std::env::temp_dir().join(format!("ttsmate-cache-{}", std::process::id())),
+            spilling: HashSet::new(),
+        }
+    }
+
+    /// 取出全部条目交给新的缓存，本实例变为空缓存。尚未写完的转存仍在本实例上结束
+    /// （条目已不在，写出的文件随即删除），取出的缓存需要时会重新转存这些条目
+    pub fn take(&mut self) -> Self {
+        let empty = Self::new(self.capacity, self.memory_budget);
+        let mut taken = std::mem::replace(self, empty);
+        self.spilling = std::mem::take(&mut taken.spilling);
+        taken
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let mut stats = CacheStats { entries: self.entries.len(), memory_bytes: self.memory_used, ..CacheStats::default() };
+        for audio in self.entries.values() {
+            if let LazyAudio::Disk { len, .. } = audio {
+                stats.spilled += 1;
+                stats.spilled_bytes += len;
+            }
+        }
+        stats
+    }
+
+    /// 调整容量，缩小时立即淘汰多出的最久未使用条目
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.evict();
+    }
+
+    /// 调整内存上限，返回需要转存到磁盘的条目
+    #[must_use]
+    pub fn set_memory_budget(&mut self, memory_budget: usize) -> Vec<SpillJob> {
+        self.memory_budget = memory_budget;
+        self.spill()
+    }
+
+    pub fn get(&mut self, key: &str) -> Lookup {
+        let lookup = match self.entries.get(key) {
+            None => return Lookup::Miss,
+            Some(LazyAudio::Memory(audio)) => Lookup::Hit(audio.clone()),
+            Some(LazyAudio::Disk { path, .. }) => Lookup::OnDisk(path.clone()),
+        };
+        self.touch(key);
+        lookup
+    }
+
+    /// 读回 `get` 给出的转存文件后调用：音频重新放进内存，返回因此需要转存的其他条目；
+    /// `audio` 为 `None` 表示读取失败，丢弃该条目
+    #[must_use]
+    pub fn restore(&mut self, key: &str, path: &Path, audio: Option<Arc<Vec<u8>>>) -> Vec<SpillJob> {
+        // 读取期间条目可能已被淘汰或替换
+        if !matches!(self.entries.get(key), Some(LazyAudio::Disk { path: current, .. }) if current == path) {
+            return Vec::new();
+        }
+        let Some(audio) = audio else {
+            self.remove(key);
+            return Vec::new();
+        };
+        self.memory_used += audio.len();
+        if let Some(spilled) = self.entries.insert(key.to_string(), LazyAudio::Memory(audio)) {
+            spilled.remove_file();
+        }
+        self.spill()
+    }
+
+    /// 放入一条音频，返回超出内存上限后需要转存的条目
+    #[must_use]
+    pub fn insert(&mut self, key: String, audio: Arc<Vec<u8>>) -> Vec<SpillJob> {
+        self.memory_used += audio.len();
+        if let Some(previous) = self.entries.insert(key.clone(), LazyAudio::Memory(audio)) {
+            self.forget(&previous);
+            self.touch(&key);
+        } else {
+            self.order.push_back(key);
+            self.evict();
+        }
+        self.spill()
+    }
+
+    /// 转存文件写完（或写入失败）后调用。写入期间条目被淘汰、替换或重新成为最近使用的
+    /// 一条时保留内存中的音频，删除写出的文件
+    pub fn finish_spill(&mut self, job: SpillJob, written: io::Result<()>) {
+        self.spilling.remove(&job.key);
+        let unchanged = matches!(self.entries.get(&job.key), Some(LazyAudio::Memory(audio)) if Arc::ptr_eq(audio, &job.audio));
+        let still_old = self.order.back() != Some(&job.key);
+        let spilled = LazyAudio::Disk { path: job.path, len: job.audio.len() };
+        match written {
+            Ok(()) if unchanged && still_old => {
+                self.memory_used -= job.audio.len();
+                self.entries.insert(job.key, spilled);
+            }
+            Ok(()) => spilled.remove_file(),
+            Err(e) if unchanged => {
+                // 磁盘不可写时退回为直接淘汰，保证内存不超出上限
+                log::warn!("缓存音频转存到 {} 失败，改为淘汰: {}", self.spill_dir.display(), e);
+                self.remove(&job.key);
+            }
+            Err(e) => log::debug!("缓存音频转存失败，条目已不在缓存中: {}", e),
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(audio) = self.entries.remove(key) {
+            self.forget(&audio);
+        }
+        self.order.retain(|k| k != key);
+    }
+
+    /// 释放一个已移出 `entries` 的条目占用的内存或临时文件
+    fn forget(&mut self, audio: &LazyAudio) {
+        match audio {
+            LazyAudio::Memory(audio) => self.memory_used -= audio.len(),
+            LazyAudio::Disk { .. } => audio.remove_file(),
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                if let Some(audio) = self.entries.remove(&oldest) {
+                    self.forget(&audio);
+                }
+            }
+        }
+    }
+
+    /// 从最久未使用的条目开始选出要转存的音频，直到（计入正在写入的条目后）内存占用不超过上限；
+    /// 最近使用的一条始终留在内存中
+    fn spill(&mut self) -> Vec<SpillJob> {
+        let mut jobs = Vec::new();
+        if self.memory_budget == 0 {
+            return jobs;
+        }
+        let in_memory = |key: &String| match self.entries.get(key) {
+            Some(LazyAudio::Memory(audio)) => Some(audio),
+            _ => None,
+        };
+        let pending: usize = self.spilling.iter().filter_map(in_memory).map(|audio| audio.len()).sum();
+        let mut remaining = self.memory_used.saturating_sub(pending);
+        for key in self.order.iter().take(self.order.len().saturating_sub(1)) {
+            if remaining <= self.memory_budget {
+                break;
+            }
+            if self.spilling.contains(key) {
+                continue;
+            }
+            let Some(audio) = in_memory(key) else {
+                continue;
+            };
+            remaining -= audio.len();
+            let path = self.spill_dir.join(format!("{}.mp3", NEXT_SPILL_FILE.fetch_add(1, Ordering::Relaxed)));
+            jobs.push(SpillJob { key: key.clone(), path, audio: audio.clone() });
+        }
+        self.spilling.extend(jobs.iter().map(|job| job.key.clone()));
+        jobs
+    }
+
+    fn touch(&mut self, key: &str) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+impl Drop for TtsCache {
+    fn drop(&mut self) {
+        for audio in self.entries.values() {
+            audio.remove_file();
+        }
+        // 其他实例（重新连接前后）可能还在使用该目录，非空时删除会失败，忽略即可
+        let _ = fs::remove_dir(&self.spill_dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(mode: CacheKeyMode, volume: i32) -> String {
+        generate_cache_key(mode, "", "你好", 5, 5, volume, 0)
+    }
+
+    #[test]
+    fn ignore_volume_shares_a_key_across_volumes() {
+        assert_eq!(key(CacheKeyMode::IgnoreVolume, 3), key(CacheKeyMode::IgnoreVolume, 9));
+        assert_ne!(key(CacheKeyMode::Full, 3), key(CacheKeyMode::Full, 9));
+        assert_ne!(key(CacheKeyMode::Full, 5), key(CacheKeyMode::IgnoreVolume, 5));
+    }
+
+    #[test]
+    fn ignore_volume_still_separates_other_parameters() {
+        let mode = CacheKeyMode::IgnoreVolume;
+        let base = generate_cache_key(mode, "", "你好", 5, 5, 5, 0);
+        assert_ne!(base, generate_cache_key(mode, "", "你好", 6, 5, 5, 0));
+        assert_ne!(base, generate_cache_key(mode, "", "你好", 5, 6, 5, 0));
+        assert_ne!(base, generate_cache_key(mode, "", "你好", 5, 5, 5, 1));
+        assert_ne!(base, generate_cache_key(mode, "", "你好！", 5, 5, 5, 0));
+    }
+
+    #[test]
+    fn a_new_namespace_changes_every_key() {
+        for mode in [CacheKeyMode::Full, CacheKeyMode::IgnoreVolume] {
+            let old = generate_cache_key(mode, "", "你好", 5, 5, 5, 0);
+            assert_ne!(old, generate_cache_key(mode, "20240101000000", "你好", 5, 5, 5, 0));
+        }
+    }
+
+    #[test]
+    fn ignore_volume_requests_the_reference_volume_and_applies_gain_locally() {
+        assert_eq!(CacheKeyMode::Full.request_volume(9), 9);
+        assert_eq!(CacheKeyMode::Full.playback_gain(9), 1.0);
+        assert_eq!(CacheKeyMode::IgnoreVolume.request_volume(9), REFERENCE_VOLUME);
+        assert_eq!(CacheKeyMode::IgnoreVolume.playback_gain(REFERENCE_VOLUME), 1.0);
+        assert_eq!(CacheKeyMode::IgnoreVolume.playback_gain(10), 2.0);
+        assert_eq!(CacheKeyMode::IgnoreVolume.playback_gain(0), 0.0);
+        assert_eq!(CacheKeyMode::IgnoreVolume.playback_gain(-3), 0.0);
+    }
+
+    /// 转存到 `dir` 的缓存，便于测试结束后清理
+    fn cache_in(dir: &tempfile::TempDir, memory_budget: usize) -> TtsCache {
+        let mut cache = TtsCache::new(10, memory_budget);
+        cache.spill_dir = dir.path().to_path_buf();
+        cache
+    }
+
+    fn audio(len: usize, byte: u8) -> Arc<Vec<u8>> {
+        Arc::new(vec![byte; len])
+    }
+
+    fn write_all(cache: &mut TtsCache, jobs: Vec<SpillJob>) {
+        for job in jobs {
+            let result = job.write();
+            cache.finish_spill(job, result);
+        }
+    }
+
+    #[test]
+    fn spilled_entries_stay_in_memory_until_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = cache_in(&dir, 100);
+        assert!(cache.insert("a".to_string(), audio(60, 1)).is_empty());
+        let jobs = cache.insert("b".to_string(), audio(60, 2));
+        assert_eq!(jobs.len(), 1);
+        // 写入期间照常命中，也不会重复选中同一条
+        assert!(matches!(cache.get("a"), Lookup::Hit(_)));
+        assert!(cache.set_memory_budget(100).is_empty());
+
+        write_all(&mut cache, jobs);
+        // 命中“a”让它成为最近使用的一条，写完后仍留在内存中
+        assert_eq!(cache.stats().spilled, 0);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn spilled_entries_are_restored_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = cache_in(&dir, 100);
+        let _ = cache.insert("a".to_string(), audio(60, 1));
+        let jobs = cache.insert("b".to_string(), audio(60, 2));
+        write_all(&mut cache, jobs);
+        let stats = cache.stats();
+        assert_eq!((stats.spilled, stats.spilled_bytes, stats.memory_bytes), (1, 60, 60));
+
+        let Lookup::OnDisk(path) = cache.get("a") else { panic!("a 应已转存") };
+        let read = Arc::new(std::fs::read(&path).unwrap());
+        assert_eq!(read, audio(60, 1));
+        let jobs = cache.restore("a", &path, Some(read));
+        assert!(!path.exists());
+        // 读回“a”后内存再次超限，轮到“b”转存
+        assert_eq!(jobs.len(), 1);
+        write_all(&mut cache, jobs);
+        assert!(matches!(cache.get("a"), Lookup::Hit(_)));
+        assert!(matches!(cache.get("b"), Lookup::OnDisk(_)));
+    }
+
+    #[test]
+    fn unreadable_or_unwritable_entries_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = cache_in(&dir, 100);
+        let _ = cache.insert("a".to_string(), audio(60, 1));
+        let jobs = cache.insert("b".to_string(), audio(60, 2));
+        for job in jobs {
+            cache.finish_spill(job, Err(io::Error::other("disk full")));
+        }
+        assert!(matches!(cache.get("a"), Lookup::Miss));
+        assert_eq!(cache.stats().memory_bytes, 60);
+
+        let jobs = cache.insert("c".to_string(), audio(60, 3));
+        write_all(&mut cache, jobs);
+        let Lookup::OnDisk(path) = cache.get("b") else { panic!("b 应已转存") };
+        assert!(cache.restore("b", &path, None).is_empty());
+        assert!(matches!(cache.get("b"), Lookup::Miss));
+    }
+
+    #[test]
+    fn replaced_entries_discard_the_written_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cache = cache_in(&dir, 100);
+        let _ = cache.insert("a".to_string(), audio(60, 1));
+        let jobs = cache.insert("b".to_string(), audio(60, 2));
+        let _ = cache.insert("a".to_string(), audio(10, 9));
+        write_all(&mut cache, jobs);
+        assert!(matches!(cache.get("a"), Lookup::Hit(audio) if audio.len() == 10));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,494 @@
+//! 后台命令队列。
+//!
+//! 界面线程把需要在后台完成的工作包装成 `AppCommand` 放入有界队列，由运行时上唯一的
+//! 工作者取出执行，结果照旧通过 `UIMessage` 通道送回界面。同时执行的命令数有上限，
+//! 超出的命令在队列中等待；队列也满时立即拒绝，界面线程永远不会因此阻塞。
+//! 批量生成和多版本生成的每一行、每一版都各占一个执行名额，与其他命令共用同一上限。
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use rodio::Decoder;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinSet;
+
+use crate::api_client::ApiClient;
+use crate::batch::{BatchLine, BatchStatus};
+use crate::config::{ApiKeys, Config, ExportSettings};
+use crate::content;
+use crate::export::{self, ClipInfo};
+use crate::filter;
+use crate::naming::{self, FileNameFields};
+use crate::processing::CachedPreview;
+use crate::prompt_macros::{self, MacroContext};
+use crate::soundboard;
+use crate::stream;
+use crate::tasks::TaskGuard;
+use crate::variations::{Take, VariationStatus};
+use crate::wakeup::UiSender;
+use crate::{limit_audio_duration, limit_text_duration, prepare_tts_text, voice_name, AppState, KeyService, TextOrigin, UIMessage};
+
+/// 排队等待执行的命令数上限
+const QUEUE_CAPACITY: usize = 32;
+/// 同时执行的命令数上限
+const MAX_RUNNING: usize = 8;
+
+/// 交给后台执行的工作
+pub enum AppCommand {
+    /// 生成（可能先经过 DeepSeek）并合成、播放一段语音
+    Generate(Box<GenerationJob>),
+    /// 读取音效文件，读完后交给界面播放
+    LoadSound { index: usize, path: String, momentary: bool },
+    /// 把合成结果写入自动保存目录
+    AutoSave { dir: PathBuf, file_name: String, audio: Arc<Vec<u8>>, info: ClipInfo },
+    /// 合成一次预热短句，结果不播放也不写入缓存
+    WarmUp { api_client: Arc<ApiClient>, config: Arc<Config>, phrase: String, speed: i32, pitch: i32, volume: i32, person: i32 },
+    /// 逐行合成并保存批量输入
+    Batch(Box<BatchJob>),
+    /// 同一话题以不同温度各生成、合成一版
+    Variations(Box<VariationsJob>),
+    /// 把用户选择位置的音频写入文件；`processed` 为保存前处理后的 WAV，否则写入带标签的 MP3
+    SaveAudio { task: TaskGuard, path: PathBuf, audio: Arc<Vec<u8>>, processed: Option<Arc<Vec<u8>>>, info: Option<ClipInfo> },
+    /// 获取一次百度访问令牌和 DeepSeek 模型列表
+    TestConnection { api_client: Arc<ApiClient>, api_keys: ApiKeys },
+    /// 用尚未保存的密钥发出一次最小的鉴权请求
+    VerifyKeys { api_client: Arc<ApiClient>, service: KeyService, keys: ApiKeys },
+    /// 调整缓存的内存上限，超出的部分转存到磁盘
+    SetCacheMemoryBudget { api_client: Arc<ApiClient>, megabytes: usize },
+    /// 逐个分析音效的时长、响度和波形
+    AnalyzeSounds { paths: Vec<String> },
+    /// 按保存前处理设置处理音频，结果用于预览、试听和保存
+    ExportPreview { audio: Arc<Vec<u8>>, settings: ExportSettings },
+}
+
+/// 一次生成所需的全部参数，在界面线程按当时的设置取好
+pub struct GenerationJob {
+    pub task: TaskGuard,
+    pub api_client: Arc<ApiClient>,
+    pub config: Arc<Config>,
+    pub prompt_text: String,
+    pub system_prompt: String,
+    pub use_deepseek: bool,
+    pub person: i32,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub stream_playback: bool,
+    pub use_cache: bool,
+    pub normalize_text: bool,
+}
+
+/// 批量生成所需的参数；同时进行的行数不超过 `concurrency`
+pub struct BatchJob {
+    pub task: TaskGuard,
+    pub api_client: Arc<ApiClient>,
+    pub config: Arc<Config>,
+    pub lines: Vec<BatchLine>,
+    pub dir: PathBuf,
+    pub template: String,
+    pub cancel: Arc<AtomicBool>,
+    pub concurrency: usize,
+    pub default_person: i32,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub normalize_text: bool,
+    pub use_cache: bool,
+}
+
+/// 多版本生成所需的参数，各版本使用同一次展开的提示词；同时进行的版本数不超过 `concurrency`
+pub struct VariationsJob {
+    pub task: TaskGuard,
+    pub api_client: Arc<ApiClient>,
+    pub config: Arc<Config>,
+    pub temperatures: Vec<f32>,
+    pub system_prompt: String,
+    pub user_prompt: String,
+    pub prompt_text: String,
+    pub concurrency: usize,
+    pub person: i32,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub normalize_text: bool,
+}
+
+/// 命令队列的发送端
+#[derive(Clone)]
+pub struct CommandQueue {
+    sender: mpsc::Sender<AppCommand>,
+}
+
+impl CommandQueue {
+    /// 在运行时上启动工作者
+    pub fn start(handle: &Handle, ui_sender: UiSender<UIMessage>) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        handle.spawn(run_worker(receiver, ui_sender));
+        Self { sender }
+    }
+
+    /// 放入队列，返回是否成功；队列已满或工作者已停止时丢弃命令（其中的 `TaskGuard` 随之结束任务）
+    pub fn submit(&self, command: AppCommand) -> bool {
+        self.sender.try_send(command).is_ok()
+    }
+}
+
+async fn run_worker(mut receiver: mpsc::Receiver<AppCommand>, ui_sender: UiSender<UIMessage>) {
+    let limiter = Arc::new(Semaphore::new(MAX_RUNNING));
+    let mut running = JoinSet::new();
+    while let Some(command) = receiver.recv().await {
+        // 信号量从不关闭，acquire 不会失败；在这里等待时新命令留在队列中
+        let permit = limiter.clone().acquire_owned().await.expect("command limiter closed");
+        let ui_sender = ui_sender.clone();
+        let limiter = limiter.clone();
+        running.spawn(async move {
+            let _permit = permit;
+            execute(command, ui_sender, limiter).await;
+        });
+        // 顺手回收已结束的命令，避免 JoinSet 无限增长
+        while running.try_join_next().is_some() {}
+    }
+}
+
+/// `limiter` 是工作者的执行名额，批量和多版本生成的每一项另外各占一个
+async fn execute(command: AppCommand, sender: UiSender<UIMessage>, limiter: Arc<Semaphore>) {
+    match command {
+        AppCommand::Generate(job) => job.run(sender).await,
+        AppCommand::LoadSound { index, path, momentary } => match tokio::fs::read(&path).await {
+            Ok(data) => {
+                let _ = sender.send(UIMessage::PlaySound { index, data, momentary });
+            }
+            Err(e) => {
+                log::error!("读取音效文件 '{}' 失败: {}", path, e);
+            }
+        },
+        AppCommand::AutoSave { dir, file_name, audio, info } => {
+            let result = tokio::fs::create_dir_all(&dir).await;
+            let path = naming::unique_path(&dir, &file_name);
+            let result = match result {
+                Ok(_) => tokio::fs::write(&path, export::tag_mp3(&audio, &info)).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(_) => log::info!("已自动保存音频到 {}", path.display()),
+                Err(e) => {
+                    log::error!("自动保存音频到 {} 失败: {}", path.display(), e);
+                    let _ = sender.send(UIMessage::Error(format!("自动保存失败: {}", e)));
+                }
+            }
+        }
+        AppCommand::WarmUp { api_client, config, phrase, speed, pitch, volume, person } => {
+            let started = Instant::now();
+            match api_client.call_baidu_tts_api(&config.api_keys, &phrase, speed, pitch, volume, person, false).await {
+                Ok(audio) => log::info!("启动预热完成，用时 {} ms（{} 字节，未播放）", started.elapsed().as_millis(), audio.len()),
+                Err(e) => log::warn!("启动预热失败，已跳过（用时 {} ms）: {}", started.elapsed().as_millis(), e),
+            }
+        }
+        AppCommand::Batch(job) => job.run(sender, limiter).await,
+        AppCommand::Variations(job) => job.run(sender, limiter).await,
+        AppCommand::SaveAudio { task, path, audio, processed, info } => {
+            let _task = task;
+            let data = match (&processed, &info) {
+                (Some(wav), _) => wav.to_vec(),
+                (None, Some(info)) => export::tag_mp3(&audio, info),
+                (None, None) => audio.to_vec(),
+            };
+            let message = match tokio::fs::write(&path, data).await {
+                Ok(_) => UIMessage::Saved(path.display().to_string()),
+                Err(e) => UIMessage::Error(format!("保存失败: {}", e)),
+            };
+            let _ = sender.send(message);
+        }
+        AppCommand::TestConnection { api_client, api_keys } => {
+            let result = api_client.test_connection(&api_keys).await.map_err(|e| e.to_string());
+            let _ = sender.send(UIMessage::ConnectionTested(result));
+        }
+        AppCommand::VerifyKeys { api_client, service, keys } => {
+            let result = match service {
+                KeyService::DeepSeek => api_client.verify_deepseek_key(keys.deepseek_api_key.trim()).await,
+                KeyService::Baidu => api_client.verify_baidu_keys(keys.baidu_api_key.trim(), keys.baidu_secret_key.trim()).await,
+            };
+            let _ = sender.send(UIMessage::KeyVerified { service, result: result.map_err(|e| e.to_string()) });
+        }
+        AppCommand::SetCacheMemoryBudget { api_client, megabytes } => api_client.set_cache_memory_budget(megabytes).await,
+        AppCommand::AnalyzeSounds { paths } => {
+            let _ = tokio::task::spawn_blocking(move || {
+                for path in paths {
+                    match soundboard::analyze(&path) {
+                        Some(analysis) => {
+                            let _ = sender.send(UIMessage::SoundAnalyzed { path, analysis });
+                        }
+                        None => log::warn!("无法分析音效 '{}'", path),
+                    }
+                }
+            })
+            .await;
+        }
+        AppCommand::ExportPreview { audio, settings } => {
+            let input = (audio.clone(), settings.clone());
+            let preview = match tokio::task::spawn_blocking(move || CachedPreview::new(input.0, &input.1)).await {
+                Ok(preview) => preview,
+                Err(e) => CachedPreview { audio, settings, result: Err(e.to_string()) },
+            };
+            let _ = sender.send(UIMessage::ExportPreviewReady(Box::new(preview)));
+        }
+    }
+}
+
+impl BatchJob {
+    /// 取消后尚未开始的行标记为已取消，已经开始的行会完成
+    async fn run(self, sender: UiSender<UIMessage>, limiter: Arc<Semaphore>) {
+        let BatchJob { task, api_client, config, lines, dir, template, cancel, concurrency, default_person, speed, pitch, volume, normalize_text, use_cache } =
+            self;
+        let _task = task;
+        if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+            let _ = sender.send(UIMessage::Error(format!("无法创建目录 {}: {}", dir.display(), e)));
+            for index in 0..lines.len() {
+                let _ = sender.send(UIMessage::BatchProgress(index, BatchStatus::Failed(e.to_string())));
+            }
+            return;
+        }
+        let batch_limiter = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut jobs = JoinSet::new();
+        for (index, line) in lines.into_iter().enumerate() {
+            // 信号量从不关闭，acquire 不会失败
+            let batch_permit = batch_limiter.clone().acquire_owned().await.expect("batch limiter closed");
+            let permit = limiter.clone().acquire_owned().await.expect("command limiter closed");
+            if cancel.load(Ordering::Relaxed) {
+                let _ = sender.send(UIMessage::BatchProgress(index, BatchStatus::Canceled));
+                continue;
+            }
+            let _ = sender.send(UIMessage::BatchProgress(index, BatchStatus::Running));
+            let (sender, api_client, config, dir, template) = (sender.clone(), api_client.clone(), config.clone(), dir.clone(), template.clone());
+            jobs.spawn(async move {
+                let _permits = (batch_permit, permit);
+                let BatchLine { text, person } = line;
+                let person = person.unwrap_or(default_person);
+                let status = match prepare_tts_text(&text, &config, normalize_text) {
+                    Err(reason) => BatchStatus::Failed(reason.to_string()),
+                    Ok(prepared) if !api_client.accepts_text(&prepared) => BatchStatus::Failed("超出百度单次合成上限".to_string()),
+                    Ok(prepared) => {
+                        // 与单段生成相同的时长上限；截断的行只记入日志，结果列表里照常显示
+                        let (prepared, notice) = limit_text_duration(prepared, speed, &config.app_settings);
+                        if let Some(notice) = notice {
+                            log::info!("批量第 {} 行: {}", index + 1, notice);
+                        }
+                        match api_client.call_baidu_tts_api(&config.api_keys, &prepared, speed, pitch, volume, person, use_cache).await {
+                            Ok(audio) => {
+                                let (audio, _) = limit_audio_duration(audio, &config.app_settings);
+                                let info = ClipInfo::new(String::new(), prepared, voice_name(person).to_string());
+                                let fields = FileNameFields { index: index + 1, created_at: info.created_at, voice: &info.voice, text: &text };
+                                let path = naming::unique_path(&dir, &naming::render(&template, &fields));
+                                match tokio::fs::write(&path, export::tag_mp3(&audio, &info)).await {
+                                    Ok(()) => BatchStatus::Done(path),
+                                    Err(e) => BatchStatus::Failed(format!("保存失败: {}", e)),
+                                }
+                            }
+                            Err(e) => BatchStatus::Failed(e.to_string()),
+                        }
+                    }
+                };
+                let _ = sender.send(UIMessage::BatchProgress(index, status));
+            });
+        }
+        while jobs.join_next().await.is_some() {}
+    }
+}
+
+impl VariationsJob {
+    async fn run(self, sender: UiSender<UIMessage>, limiter: Arc<Semaphore>) {
+        let VariationsJob {
+            task,
+            api_client,
+            config,
+            temperatures,
+            system_prompt,
+            user_prompt,
+            prompt_text,
+            concurrency,
+            person,
+            speed,
+            pitch,
+            volume,
+            normalize_text,
+        } = self;
+        let _task = task;
+        let variation_limiter = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut jobs = JoinSet::new();
+        for (index, temperature) in temperatures.into_iter().enumerate() {
+            let (sender, api_client, config, limiter, variation_limiter) =
+                (sender.clone(), api_client.clone(), config.clone(), limiter.clone(), variation_limiter.clone());
+            let (system_prompt, user_prompt, prompt_text) = (system_prompt.clone(), user_prompt.clone(), prompt_text.clone());
+            jobs.spawn(async move {
+                // 信号量从不关闭，acquire 不会失败
+                let _variation_permit = variation_limiter.acquire_owned().await.expect("variation limiter closed");
+                let _permit = limiter.acquire_owned().await.expect("command limiter closed");
+                let api_keys = &config.api_keys;
+                let status = match api_client
+                    .generate_text(&api_keys.deepseek_api_key, &system_prompt, &user_prompt, config.ai_settings.max_retries, Some(temperature), |_: &str| {})
+                    .await
+                {
+                    Err(e) => VariationStatus::Failed(format!("DeepSeek: {}", e)),
+                    // 被过滤拒绝的版本只显示原因；删除屏蔽词的版本在列表中可以直接看到结果，不再单独提示
+                    Ok(text) => match filter::screen(text, &config.content_filter)
+                        .and_then(|(text, _)| prepare_tts_text(&text, &config, normalize_text).map_err(str::to_string))
+                    {
+                        Err(reason) => VariationStatus::Failed(reason),
+                        Ok(prepared) => {
+                            let prepared = if api_client.accepts_text(&prepared) { prepared } else { content::truncate_for_baidu(&prepared) };
+                            // 多个版本同时完成时不逐个提示，超长的版本照样可以在列表中比较
+                            let (prepared, _) = limit_text_duration(prepared, speed, &config.app_settings);
+                            match api_client.call_baidu_tts_api(api_keys, &prepared, speed, pitch, volume, person, true).await {
+                                Ok(audio) => VariationStatus::Done(Take {
+                                    info: ClipInfo::new(prompt_text.clone(), prepared, voice_name(person).to_string()),
+                                    audio: Arc::new(limit_audio_duration(audio, &config.app_settings).0),
+                                }),
+                                Err(e) => VariationStatus::Failed(format!("BaiduTTS: {}", e)),
+                            }
+                        }
+                    },
+                };
+                let _ = sender.send(UIMessage::VariationFinished(index, status));
+            });
+        }
+        while jobs.join_next().await.is_some() {}
+    }
+}
+
+impl GenerationJob {
+    async fn run(self, sender: UiSender<UIMessage>) {
+        let GenerationJob {
+            task,
+            api_client,
+            config,
+            prompt_text,
+            system_prompt,
+            use_deepseek,
+            person,
+            speed,
+            pitch,
+            volume,
+            stream_playback,
+            use_cache,
+            normalize_text,
+        } = self;
+
+        let text_to_speak = if use_deepseek {
+            let macros = MacroContext::new(&config.ai_settings.variables, voice_name(person));
+            let (system_prompt, user_prompt, notice) = prompt_macros::expand_prompts(&system_prompt, &prompt_text, &macros);
+            if let Some(notice) = notice {
+                let _ = sender.send(UIMessage::Notice(notice));
+            }
+            let task_id = task.id();
+            let progress_sender = sender.clone();
+            let progress_config = config.clone();
+            let on_progress = move |text: &str| {
+                if let Some(text) = filter::screen_partial(text, &progress_config.content_filter) {
+                    let _ = progress_sender.send(UIMessage::ResponseProgress(task_id, text));
+                }
+            };
+            match api_client
+                .generate_text(&config.api_keys.deepseek_api_key, &system_prompt, &user_prompt, config.ai_settings.max_retries, None, on_progress)
+                .await
+            {
+                Ok(text) => {
+                    // 拒绝模式下流式过程中不显示内容，整段通过检查后才写入文本区
+                    let text = match filter::screen(text, &config.content_filter) {
+                        Ok((text, notice)) => {
+                            if let Some(notice) = notice {
+                                let _ = sender.send(UIMessage::Notice(notice));
+                            }
+                            text
+                        }
+                        Err(reason) => {
+                            let _ = sender.send(UIMessage::Error(reason));
+                            return;
+                        }
+                    };
+                    // 界面已关闭时不必再合成
+                    if sender.send(UIMessage::SetResponseText(text.clone(), TextOrigin::Ai)).is_err() {
+                        log::debug!("界面已关闭，放弃本次生成");
+                        return;
+                    }
+                    text
+                }
+                Err(e) => {
+                    let _ = sender.send(UIMessage::Error(format!("DeepSeek: {}", e)));
+                    return;
+                }
+            }
+        } else {
+            prompt_text.clone()
+        };
+
+        let text_to_speak = match prepare_tts_text(&text_to_speak, &config, normalize_text) {
+            Ok(text) => text,
+            Err(reason) => {
+                let _ = sender.send(UIMessage::Error(format!("无有效文本: {}", reason)));
+                return;
+            }
+        };
+        // 超出百度上限的文本会被整段拒绝，未开启长文本合成时截掉超出部分总比完全无法合成好
+        let text_to_speak = if !api_client.accepts_text(&text_to_speak) {
+            let truncated = content::truncate_for_baidu(&text_to_speak);
+            let _ = sender.send(UIMessage::Notice(format!("文本超过百度单次合成上限，只朗读前 {} 字", truncated.chars().count())));
+            truncated
+        } else {
+            text_to_speak
+        };
+        let (text_to_speak, notice) = limit_text_duration(text_to_speak, speed, &config.app_settings);
+        if let Some(notice) = notice {
+            let _ = sender.send(UIMessage::Notice(notice));
+        }
+
+        task.set_stage(AppState::SynthesizingAudio);
+        let mut streamed = stream_playback;
+        let mut result = if stream_playback {
+            let (writer, reader) = stream::audio_stream();
+            let stream_sender = sender.clone();
+            // 解码器读取头部时会阻塞等待数据，放在阻塞线程池里创建
+            tokio::task::spawn_blocking(move || match Decoder::new_mp3(reader) {
+                Ok(decoder) => {
+                    let _ = stream_sender.send(UIMessage::StreamTts(Box::new(decoder)));
+                }
+                Err(e) => log::debug!("流式解码未开始: {}", e),
+            });
+            api_client
+                .call_baidu_tts_streaming(&config.api_keys, &text_to_speak, speed, pitch, volume, person, use_cache, &writer)
+                .await
+        } else {
+            api_client.call_baidu_tts_api(&config.api_keys, &text_to_speak, speed, pitch, volume, person, use_cache).await
+        };
+        // 失败后按顺序尝试备用发音人，备用合成不再流式播放；全部失败时报告最后一个错误
+        let mut used_person = person;
+        if config.fallback.enabled {
+            if let Err(e) = result {
+                (result, used_person) = api_client
+                    .call_baidu_tts_fallbacks(&config.api_keys, &text_to_speak, speed, pitch, volume, person, &config.fallback.persons, use_cache, e)
+                    .await;
+                streamed = false;
+            }
+            if result.is_ok() && used_person != person {
+                log::info!("备用发音人 {} 合成成功", voice_name(used_person));
+                let _ = sender.send(UIMessage::Notice(format!("{} 合成失败，已改用 {}", voice_name(person), voice_name(used_person))));
+            }
+        }
+        let message = match result {
+            Ok(audio) => {
+                let (audio, notice) = limit_audio_duration(audio, &config.app_settings);
+                if let Some(notice) = notice {
+                    let _ = sender.send(UIMessage::Notice(notice));
+                }
+                let info = ClipInfo::new(prompt_text, text_to_speak, voice_name(used_person).to_string());
+                UIMessage::PlayTts { audio, info, streamed }
+            }
+            Err(e) => UIMessage::Error(format!("BaiduTTS: {}", e)),
+        };
+        if sender.send(message).is_err() {
+            log::debug!("界面已关闭，丢弃合成结果");
+        }
+    }
+}
@@ -1,55 +1,903 @@
-use serde::Deserialize;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use toml;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Deserialize, Debug)]
+use crate::cache::CacheKeyMode;
+use crate::content::DurationLimit;
+use crate::error::AppError;
+use crate::filter::FilterAction;
+
+const CONFIG_PATH: &str = "config.toml";
+/// 保留的历史备份数量：config.toml.bak 为最近一份，其后为 .bak.2、.bak.3
+const BACKUP_COUNT: usize = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct ApiKeys {
     pub deepseek_api_key: String,
     pub baidu_api_key: String,
     pub baidu_secret_key: String,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+/// 密钥只能由可打印的 ASCII 字符组成；`required` 为 false 时允许留空
+pub fn validate_key(label: &str, value: &str, required: bool) -> Result<(), String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return if required { Err(format!("请填写{}", label)) } else { Ok(()) };
+    }
+    if value.chars().any(|c| c.is_whitespace() || !c.is_ascii_graphic()) {
+        return Err(format!("{}包含空格或非 ASCII 字符，请检查是否复制完整", label));
+    }
+    if value.len() < 16 {
+        return Err(format!("{}过短，请检查是否复制完整", label));
+    }
+    Ok(())
+}
+
+/// 只显示密钥首尾各 4 个字符
+pub fn mask_key(key: &str) -> String {
+    let key = key.trim();
+    if key.len() <= 8 {
+        return "*".repeat(key.len());
+    }
+    format!("{}…{}", &key[..4], &key[key.len() - 4..])
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PromptTemplate {
     pub name: String,
     pub template: String,
+    /// 该类内容建议的最大字数，未设置时使用 `content::DEFAULT_MAX_WORDS`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_words: Option<usize>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AiSettings {
     pub default_prompt: String,
     pub prompts: Vec<PromptTemplate>,
+    /// “发送到AI优化”时使用的系统提示词
+    #[serde(default = "default_refine_prompt")]
+    pub refine_prompt: String,
+    /// AI 返回空白、过短或拒绝回答时自动重试的次数
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// DeepSeek 接口的根地址，其下为 /chat/completions 和 /models；可指向兼容 OpenAI 协议的代理
+    #[serde(default = "default_deepseek_base_url")]
+    pub deepseek_base_url: String,
+    /// 提示词中 `{{名称}}` 引用的自定义变量，见 `prompt_macros`
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+fn default_max_retries() -> u32 {
+    1
+}
+
+fn default_deepseek_base_url() -> String {
+    "https://api.deepseek.com".to_string()
+}
+
+/// 首次运行时生成的默认设置，与随程序发布的 config.toml 示例一致
+impl Default for AiSettings {
+    fn default() -> Self {
+        let prompt = |name: &str, template: &str| PromptTemplate {
+            name: name.to_string(),
+            template: template.to_string(),
+            max_words: Some(100),
+        };
+        Self {
+            default_prompt: "你是一个为TTS语音合成生成文本的助手，请将回答限制在100个汉字以内。".to_string(),
+            prompts: vec![
+                prompt("标准助手", "你是一个为TTS语音合成生成文本的助手，请将回答限制在100个汉字以内。"),
+                prompt("客服模式", "你是一位专业的客服，请用友好和耐心的语气回答问题，并把回答限制在100个汉字以内。"),
+                prompt("游戏旁白", "请你扮演一个游戏旁白（GM），用神秘且引人入胜的语气描述以下场景，并把回答限制在100个汉字以内。"),
+            ],
+            refine_prompt: default_refine_prompt(),
+            max_retries: default_max_retries(),
+            deepseek_base_url: default_deepseek_base_url(),
+            variables: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_refine_prompt() -> String {
+    "请优化这段话，使其更通顺、更适合语音播报，保持原意，并把回答限制在100个汉字以内。".to_string()
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SoundboardItem {
     pub name: String,
     pub path: String,
+    /// 全局快捷键，留空表示不绑定
+    #[serde(default)]
+    pub hotkey: String,
+    /// 为 true 时只在按住快捷键期间播放，松开即停止
+    #[serde(default)]
+    pub momentary: bool,
+    #[serde(default)]
+    pub category: String,
+    /// 收藏的音效固定显示在音效板顶部
+    #[serde(default)]
+    pub is_favorite: bool,
+    /// 添加时解码得到的实际时长（毫秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// 添加时分析得到的 RMS 响度（dBFS），用于响度均衡
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loudness_db: Option<f32>,
+    /// 波形缩略图：每段的峰值（0～255），以十六进制字符串保存
+    #[serde(default, with = "hex_bytes", skip_serializing_if = "Vec::is_empty")]
+    pub waveform: Vec<u8>,
+    /// 分析时文件的修改时间（Unix 秒），文件被替换后据此重新分析
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub analyzed_mtime: Option<u64>,
+    /// 该音效自己的音量倍数，在响度均衡之后生效
+    #[serde(default = "default_item_volume", skip_serializing_if = "is_default_item_volume")]
+    pub volume: f32,
+    /// 加载时发现文件不存在，按钮置灰
+    #[serde(skip)]
+    pub missing: bool,
+}
+
+impl SoundboardItem {
+    pub fn new(name: String, path: String) -> Self {
+        Self {
+            name,
+            path,
+            hotkey: String::new(),
+            momentary: false,
+            category: String::new(),
+            is_favorite: false,
+            duration_ms: None,
+            loudness_db: None,
+            waveform: Vec::new(),
+            analyzed_mtime: None,
+            volume: default_item_volume(),
+            missing: false,
+        }
+    }
+
+    pub fn duration(&self) -> Option<Duration> {
+        self.duration_ms.map(Duration::from_millis)
+    }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppSettings {
     pub speed: i32,
     pub pitch: i32,
     pub volume: i32,
     pub person: i32,
+    /// 每次合成成功后自动把音频写入 `auto_save_dir`
+    #[serde(default)]
+    pub auto_save: bool,
+    #[serde(default)]
+    pub auto_save_dir: String,
+    /// 音效相对路径的根目录，留空时相对于配置文件所在目录
+    #[serde(default)]
+    pub soundboard_root: String,
+    /// 边下载边播放百度返回的音频
+    #[serde(default = "default_stream_playback")]
+    pub stream_playback: bool,
+    /// 辅助功能：允许通过快捷键模拟复制并朗读选中的文本
+    #[serde(default)]
+    pub read_selection_enabled: bool,
+    /// 语音改用低延迟输出流播放，绕过 rodio 的混音缓冲
+    #[serde(default)]
+    pub low_latency_monitor: bool,
+    /// 低延迟输出的目标延迟（毫秒）
+    #[serde(default = "default_monitor_latency_ms")]
+    pub monitor_latency_ms: u32,
+    /// 低延迟输出的设备缓冲帧数，0 表示取目标延迟的一半
+    #[serde(default)]
+    pub monitor_buffer_frames: u32,
+    /// 合成前把数字、日期、单位和缩写展开成读法
+    #[serde(default)]
+    pub normalize_text: bool,
+    /// 日志级别：error、warn、info、debug 或 trace
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// 启动时在后台合成一次 `warm_up_phrase`（不播放），提前建立到百度的连接
+    #[serde(default)]
+    pub warm_up_on_start: bool,
+    #[serde(default = "default_warm_up_phrase")]
+    pub warm_up_phrase: String,
+    /// 语音输出设备名称，留空使用系统默认设备
+    #[serde(default)]
+    pub tts_output_device: String,
+    /// 音效输出设备名称，留空与语音相同
+    #[serde(default)]
+    pub sound_output_device: String,
+    /// 音效同时输出到的监听设备名称，留空不输出
+    #[serde(default)]
+    pub sound_monitor_device: String,
+    /// 语音播报前先播放一段提示音
+    #[serde(default)]
+    pub chime_enabled: bool,
+    /// 提示音文件，留空使用内置提示音
+    #[serde(default)]
+    pub chime_path: String,
+    /// 循环播放时每一遍都播放提示音
+    #[serde(default)]
+    pub chime_on_repeat: bool,
+    /// 设置改动后最多等待多少秒再写入 config.toml，连续的改动合并为一次写入；0 为立即写入
+    #[serde(default = "default_settings_save_interval_secs")]
+    pub settings_save_interval_secs: u64,
+    /// 自动保存和手动保存音频的文件名模板，占位符见 `naming` 模块
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+    /// 批量生成的文件名模板
+    #[serde(default = "default_batch_filename_template")]
+    pub batch_filename_template: String,
+    /// 播放音效时按分析得到的响度自动调整增益，使各音效音量一致
+    #[serde(default)]
+    pub sound_normalize: bool,
+    /// 响度均衡的目标电平（dBFS RMS）
+    #[serde(default = "default_sound_target_db")]
+    pub sound_target_db: f32,
+    /// 合成音频的时长上限（秒），按字数和语速预估；0 为不限
+    #[serde(default = "default_max_audio_seconds")]
+    pub max_audio_seconds: u32,
+    /// 预计时长超过上限时只提示还是自动截断
+    #[serde(default)]
+    pub duration_limit: DurationLimit,
+    /// 低功耗模式：降低播放和生成期间的界面刷新率，空闲时更少地轮询
+    #[serde(default)]
+    pub power_saving: bool,
+    /// 输出设备的采样率（Hz），0 为设备默认；设备不支持时自动改用最接近的值
+    #[serde(default)]
+    pub output_sample_rate: u32,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            speed: 5,
+            pitch: 5,
+            volume: 5,
+            person: 0,
+            auto_save: false,
+            auto_save_dir: String::new(),
+            soundboard_root: String::new(),
+            stream_playback: default_stream_playback(),
+            read_selection_enabled: false,
+            low_latency_monitor: false,
+            monitor_latency_ms: default_monitor_latency_ms(),
+            monitor_buffer_frames: 0,
+            normalize_text: false,
+            log_level: default_log_level(),
+            warm_up_on_start: false,
+            warm_up_phrase: default_warm_up_phrase(),
+            tts_output_device: String::new(),
+            sound_output_device: String::new(),
+            sound_monitor_device: String::new(),
+            chime_enabled: false,
+            chime_path: String::new(),
+            chime_on_repeat: false,
+            settings_save_interval_secs: default_settings_save_interval_secs(),
+            filename_template: default_filename_template(),
+            batch_filename_template: default_batch_filename_template(),
+            sound_normalize: false,
+            sound_target_db: default_sound_target_db(),
+            max_audio_seconds: default_max_audio_seconds(),
+            duration_limit: DurationLimit::default(),
+            power_saving: false,
+            output_sample_rate: 0,
+        }
+    }
+}
+
+fn default_stream_playback() -> bool {
+    true
+}
+
+fn default_monitor_latency_ms() -> u32 {
+    30
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_warm_up_phrase() -> String {
+    "你好".to_string()
+}
+
+fn default_settings_save_interval_secs() -> u64 {
+    5
+}
+
+fn default_filename_template() -> String {
+    crate::naming::DEFAULT_TEMPLATE.to_string()
+}
+
+fn default_batch_filename_template() -> String {
+    crate::naming::DEFAULT_BATCH_TEMPLATE.to_string()
+}
+
+fn default_sound_target_db() -> f32 {
+    -20.0
+}
+
+fn default_max_audio_seconds() -> u32 {
+    120
+}
+
+/// 把字节序列保存为紧凑的十六进制字符串，无法解析时视为空
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let bytes: Option<Vec<u8>> = (0..hex.len())
+            .step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+            .collect();
+        Ok(bytes.unwrap_or_default())
+    }
+}
+
+fn default_item_volume() -> f32 {
+    1.0
+}
+
+fn is_default_item_volume(volume: &f32) -> bool {
+    *volume == default_item_volume()
+}
+
+/// 快捷键配置，取值形如 "F1"、"Ctrl+Up"，由 `hotkeys::KeyCombo` 解析
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct KeyBindingConfig {
+    pub tts_generate: String,
+    pub ai_generate: String,
+    pub stop_all: String,
+    pub volume_up: String,
+    pub volume_down: String,
+    pub undo: String,
+    /// 全局快捷键：朗读剪贴板中的文本，留空则不注册
+    #[serde(default)]
+    pub speak_clipboard: String,
+    /// 全局快捷键：复制并朗读其他程序中选中的文本，需在辅助功能中开启
+    #[serde(default)]
+    pub read_selection: String,
+}
+
+/// “停止全部”的默认快捷键。带修饰键才能注册为全局快捷键，其他程序在前台时同样生效
+pub const DEFAULT_STOP_ALL: &str = "Ctrl+Alt+X";
+
+impl KeyBindingConfig {
+    /// 旧版本默认的 Escape 不能注册为全局快捷键，读取时改为新的默认值。
+    /// Esc 仍是窗口内“停止全部”的别名，见 `hotkeys::Shortcuts::from_config`
+    pub fn migrate(&mut self) {
+        if self.stop_all.trim().eq_ignore_ascii_case("escape") {
+            log::info!("“停止全部”快捷键由 Escape 改为 {}", DEFAULT_STOP_ALL);
+            self.stop_all = DEFAULT_STOP_ALL.to_string();
+        }
+    }
+}
+
+impl Default for KeyBindingConfig {
+    fn default() -> Self {
+        Self {
+            tts_generate: "F1".to_string(),
+            ai_generate: "F2".to_string(),
+            stop_all: DEFAULT_STOP_ALL.to_string(),
+            volume_up: "Ctrl+Up".to_string(),
+            volume_down: "Ctrl+Down".to_string(),
+            undo: "Ctrl+Z".to_string(),
+            speak_clipboard: "Ctrl+Alt+V".to_string(),
+            read_selection: "Ctrl+Alt+S".to_string(),
+        }
+    }
+}
+
+/// 网络请求相关设置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// 同时向 DeepSeek/百度发出的最大请求数，超出的请求排队等待
+    pub max_concurrent_requests: usize,
+    /// 不校验服务器证书，仅用于自建的自签名证书服务器
+    pub accept_invalid_certs: bool,
+    /// 额外信任的 CA 证书（PEM 格式）路径，留空则只使用系统证书
+    pub ca_cert_path: String,
+    /// 单个响应体的最大大小（MB），超出时报错而不是继续占用内存
+    pub max_response_mb: usize,
+    /// 所有请求的 User-Agent，留空使用 `TTSmate/<版本>`
+    pub user_agent: String,
+    /// 附加到每个请求（DeepSeek 和百度）的请求头，与内置请求头同名时覆盖内置值
+    pub extra_headers: BTreeMap<String, String>,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 2,
+            accept_invalid_certs: false,
+            ca_cert_path: String::new(),
+            max_response_mb: 20,
+            user_agent: String::new(),
+            extra_headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// 合成失败时的备用发音人，如精品音色未开通或配额用尽时改用基础音色
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FallbackSettings {
+    pub enabled: bool,
+    /// 按顺序尝试，与本次所选发音人相同的会被跳过
+    pub persons: Vec<i32>,
+}
+
+impl Default for FallbackSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            persons: vec![0],
+        }
+    }
+}
+
+/// 语音播放时压低音效（闪避）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DuckingSettings {
+    pub enabled: bool,
+    /// 语音电平超过该值（dBFS）时开始闪避
+    pub threshold_db: f32,
+    /// 音效被压低的幅度（dB）
+    pub amount_db: f32,
+    pub attack_ms: u32,
+    pub release_ms: u32,
+}
+
+impl Default for DuckingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold_db: -40.0,
+            amount_db: 12.0,
+            attack_ms: 50,
+            release_ms: 500,
+        }
+    }
+}
+
+/// 输入框的历史记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct HistorySettings {
+    /// 每个输入框最多保留的条数
+    pub max_entries: usize,
+    /// “输入话题/文本”提交过的内容，最早的在前
+    pub prompts: Vec<String>,
+    /// 自定义提示词提交过的内容
+    pub custom_prompts: Vec<String>,
+    /// “生成历史”面板最多保留的记录条数
+    pub max_records: usize,
+    /// 关闭时把生成历史保存到配置目录下的 history.json，下次启动时载入
+    pub persist_records: bool,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            max_entries: 50,
+            prompts: Vec::new(),
+            custom_prompts: Vec::new(),
+            max_records: 50,
+            persist_records: false,
+        }
+    }
+}
+
+/// 主窗口的大小和位置，关闭时自动保存
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub width: f32,
+    pub height: f32,
+    /// 窗口左上角在桌面上的位置，未保存过时由系统决定
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    /// 保存位置时窗口所在显示器的大小，启动时显示器变了就不再使用保存的位置
+    pub monitor_width: Option<f32>,
+    pub monitor_height: Option<f32>,
+    pub maximized: bool,
+}
+
+impl WindowSettings {
+    pub const MIN_WIDTH: f32 = 480.0;
+    pub const MIN_HEIGHT: f32 = 400.0;
+
+    /// 手工编辑或异常退出可能留下过小或无效的尺寸
+    pub fn size(&self) -> [f32; 2] {
+        let clamp = |value: f32, min: f32, default: f32| if value.is_finite() { value.max(min) } else { default };
+        let default = Self::default();
+        [clamp(self.width, Self::MIN_WIDTH, default.width), clamp(self.height, Self::MIN_HEIGHT, default.height)]
+    }
+
+    pub fn position(&self) -> Option<[f32; 2]> {
+        match (self.x, self.y) {
+            (Some(x), Some(y)) if x.is_finite() && y.is_finite() => Some([x, y]),
+            _ => None,
+        }
+    }
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            width: 800.0,
+            height: 700.0,
+            x: None,
+            y: None,
+            monitor_width: None,
+            monitor_height: None,
+            maximized: false,
+        }
+    }
+}
+
+/// 定时任务的触发方式
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobSchedule {
+    /// 在指定时刻播报一次
+    Once { at: DateTime<Local> },
+    /// 每隔若干分钟播报一次
+    Every { minutes: u32 },
+}
+
+/// 定时播报：到时把 `text` 用 `person` 发音人合成并播放
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduledJob {
+    pub text: String,
+    pub person: i32,
+    pub schedule: JobSchedule,
+}
+
+/// 发音词典中的一条：合成前把 `word` 替换为 `replacement`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PronunciationEntry {
+    pub word: String,
+    pub replacement: String,
+    /// 默认忽略英文大小写
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+/// 手动保存音频前的处理，开启后保存为 WAV，见 `processing`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ExportSettings {
+    pub enabled: bool,
+    /// 重采样到的采样率（Hz），0 为保持原采样率
+    pub sample_rate: u32,
+    /// 去掉首尾的静音
+    pub trim_silence: bool,
+    /// 把峰值调整到 `peak_db`（dBFS）
+    pub normalize: bool,
+    pub peak_db: f32,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: 0,
+            trim_silence: true,
+            normalize: true,
+            peak_db: -1.0,
+        }
+    }
+}
+
+/// AI 回复的内容过滤，默认关闭
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ContentFilterSettings {
+    pub enabled: bool,
+    /// 屏蔽词，忽略大小写；英文词只匹配完整单词
+    pub blocked_words: Vec<String>,
+    /// AI 回复的字数上限，超出时与命中屏蔽词同样处理；0 为不限
+    pub max_words: usize,
+    pub action: FilterAction,
+}
+
+/// 预设使用的提示词：按名称引用 `ai_settings.prompts` 中的模板，或保存一段自定义提示词
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PresetPrompt {
+    Template { name: String },
+    Custom { prompt: String },
+}
+
+/// 一次生成使用的选项
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenerationOptions {
+    pub use_deepseek: bool,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub person: i32,
+}
+
+/// 用户保存的生成预设，可在“AI 设置”中一键套用
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenerationPreset {
+    pub name: String,
+    pub prompt: PresetPrompt,
+    pub options: GenerationOptions,
+}
+
+/// 百度语音合成的默认接口地址
+pub const BAIDU_TTS_URL: &str = "https://tsn.baidu.com/text2audio";
+/// 百度鉴权的默认接口地址
+pub const BAIDU_TOKEN_URL: &str = "https://aip.baidubce.com/oauth/2.0/token";
+/// 百度长文本在线合成的默认接口地址（其下有 create 和 query 两个接口）
+pub const BAIDU_LONG_TEXT_URL: &str = "https://aip.baidubce.com/rpc/2.0/tts/v1";
+
+/// 百度语音合成的请求参数
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BaiduSettings {
+    /// 用户唯一标识，百度控制台据此区分设备和统计配额
+    pub cuid: String,
+    /// 客户端类型，目前固定为 1
+    pub ctp: String,
+    /// 语言
+    pub lan: String,
+    /// 合成接口地址，可指向兼容百度协议的代理或私有部署
+    pub tts_url: String,
+    /// 获取 access token 的接口地址
+    pub token_url: String,
+    /// 附加到每次合成请求的表单参数，与内置参数同名时覆盖内置值
+    pub extra_params: BTreeMap<String, String>,
+    /// 超过单次合成上限的文本改用长文本在线合成（提交任务后轮询结果），而不是截断
+    pub long_text: bool,
+    /// 长文本在线合成的接口地址
+    pub long_text_url: String,
+    /// 合成、鉴权和长文本接口前面的认证代理所需的凭据
+    pub auth: ServerAuth,
+}
+
+/// 访问合成服务器时附加的 Authorization 请求头
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ServerAuth {
+    #[default]
+    None,
+    Bearer { token: String },
+    Basic { username: String, password: String },
 }
 
-#[derive(Deserialize, Debug)]
+impl ServerAuth {
+    /// 需要在日志中隐藏的值
+    pub fn secrets(&self) -> Vec<&str> {
+        match self {
+            ServerAuth::None => Vec::new(),
+            ServerAuth::Bearer { token } => vec![token],
+            ServerAuth::Basic { password, .. } => vec![password],
+        }
+    }
+}
+
+impl Default for BaiduSettings {
+    fn default() -> Self {
+        Self {
+            cuid: "ttsmate_rust_client".to_string(),
+            ctp: "1".to_string(),
+            lan: "zh".to_string(),
+            tts_url: BAIDU_TTS_URL.to_string(),
+            token_url: BAIDU_TOKEN_URL.to_string(),
+            extra_params: BTreeMap::new(),
+            long_text: false,
+            long_text_url: BAIDU_LONG_TEXT_URL.to_string(),
+            auth: ServerAuth::default(),
+        }
+    }
+}
+
+impl BaiduSettings {
+    /// cuid 最长 60 个字符，只允许字母、数字和 `-_:.`（如 MAC 地址）
+    pub fn validate(&self) -> Result<(), String> {
+        let cuid = self.cuid.as_str();
+        if cuid.is_empty() || cuid.chars().count() > 60 {
+            return Err(format!("cuid 长度必须在 1 到 60 个字符之间: '{}'", cuid));
+        }
+        if let Some(c) = cuid.chars().find(|c| !c.is_ascii_alphanumeric() && !"-_:.".contains(*c)) {
+            return Err(format!("cuid 包含不允许的字符 '{}'", c));
+        }
+        if self.ctp.trim().is_empty() || self.lan.trim().is_empty() {
+            return Err("ctp 和 lan 不能为空".to_string());
+        }
+        for (name, url) in [("tts_url", &self.tts_url), ("token_url", &self.token_url), ("long_text_url", &self.long_text_url)] {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                return Err(format!("{} 必须以 http:// 或 https:// 开头: '{}'", name, url));
+            }
+        }
+        if let Some(name) = self.extra_params.keys().find(|name| matches!(name.as_str(), "tex" | "tok")) {
+            return Err(format!("extra_params 不能覆盖 {}（由程序填写）", name));
+        }
+        Ok(())
+    }
+}
+
+/// 合成结果缓存设置
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CacheSettings {
+    pub key_mode: CacheKeyMode,
+    /// 参与缓存键的命名空间，修改后之前的缓存全部失效
+    pub namespace: String,
+    /// 内存中最多保留的合成结果条数，至少为 1
+    pub max_entries: usize,
+    /// 缓存音频在内存中的最大占用（MB），超出时把最久未使用的转存到临时文件；0 为不限
+    pub max_memory_mb: usize,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        Self {
+            key_mode: CacheKeyMode::default(),
+            namespace: String::new(),
+            max_entries: 100,
+            max_memory_mb: 64,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Config {
     pub api_keys: ApiKeys,
     pub app_settings: AppSettings,
     pub ai_settings: AiSettings,
     #[serde(default)]
+    pub network: NetworkSettings,
+    #[serde(default)]
+    pub baidu: BaiduSettings,
+    #[serde(default)]
+    pub fallback: FallbackSettings,
+    #[serde(default)]
+    pub ducking: DuckingSettings,
+    #[serde(default)]
+    pub cache: CacheSettings,
+    #[serde(default)]
+    pub window: WindowSettings,
+    #[serde(default)]
+    pub history: HistorySettings,
+    #[serde(default)]
+    pub key_bindings: KeyBindingConfig,
+    #[serde(default)]
     pub soundboard: Vec<SoundboardItem>,
+    #[serde(default)]
+    pub schedule: Vec<ScheduledJob>,
+    #[serde(default)]
+    pub pronunciations: Vec<PronunciationEntry>,
+    #[serde(default)]
+    pub presets: Vec<GenerationPreset>,
+    #[serde(default)]
+    pub content_filter: ContentFilterSettings,
+    #[serde(default)]
+    pub export: ExportSettings,
+}
+
+/// 配置文件所在目录的绝对路径
+pub fn config_dir() -> PathBuf {
+    let dir = Path::new(CONFIG_PATH).parent().unwrap_or(Path::new(""));
+    std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
 }
 
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let config_str = fs::read_to_string("config.toml")?;
-    let config: Config = toml::from_str(&config_str)?;
+    let config_str = fs::read_to_string(CONFIG_PATH)?;
+    let mut config: Config = toml::from_str(&config_str)?;
+    config.key_bindings.migrate();
     Ok(config)
 }
 
+/// 先写入临时文件再原子替换，写入中途失败不会破坏原有配置；
+/// 替换前把旧配置轮换到备份中
+pub fn save_config(config: &Config) -> Result<(), AppError> {
+    save_config_at(Path::new(CONFIG_PATH), config)
+}
+
+fn save_config_at(path: &Path, config: &Config) -> Result<(), AppError> {
+    let config_str = toml::to_string_pretty(config).map_err(|e| AppError::Config(e.to_string()))?;
+    let temp_path = sibling(path, "tmp");
+    if let Err(e) = write_synced(&temp_path, config_str.as_bytes()) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+    if path.exists() {
+        rotate_backups(path)?;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// `path` 加上扩展名后缀，如 config.toml → config.toml.tmp
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    if index == 1 {
+        sibling(path, "bak")
+    } else {
+        sibling(path, &format!("bak.{}", index))
+    }
+}
+
+/// 依次后移已有备份，丢弃最旧的一份，再把当前配置复制为最新备份
+fn rotate_backups(path: &Path) -> std::io::Result<()> {
+    for index in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// 配置文件是否存在，不存在时启动设置向导
+pub fn config_exists() -> bool {
+    Path::new(CONFIG_PATH).exists()
+}
+
+/// 配置文件存在但无法解析（而非缺失）
+pub fn config_is_corrupt() -> bool {
+    fs::read_to_string(CONFIG_PATH).is_ok_and(|text| toml::from_str::<Config>(&text).is_err())
+}
+
+/// 最近一份能成功解析的备份
+pub fn latest_valid_backup() -> Option<PathBuf> {
+    (1..=BACKUP_COUNT)
+        .map(|index| backup_path(Path::new(CONFIG_PATH), index))
+        .find(|path| fs::read_to_string(path).is_ok_and(|text| toml::from_str::<Config>(&text).is_ok()))
+}
+
+/// 用备份覆盖当前配置，损坏的配置另存为 config.toml.corrupt 以便排查
+pub fn restore_backup(backup: &Path) -> std::io::Result<()> {
+    restore_backup_at(Path::new(CONFIG_PATH), backup)
+}
+
+/// 与保存配置一样经临时文件原子替换，复制中途失败时原配置保持不变
+fn restore_backup_at(path: &Path, backup: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        fs::copy(path, sibling(path, "corrupt"))?;
+    }
+    let temp_path = sibling(path, "tmp");
+    let result = fs::read(backup).and_then(|data| write_synced(&temp_path, &data));
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, path)
+}
+
 pub const VOICES: [(&str, i32); 11] = [
     ("度小美 (女声)", 0),
     ("度小宇 (男声)", 1),
@@ -62,4 +910,100 @@ pub const VOICES: [(&str, i32); 11] = [
     ("度米朵 (女声)", 103),
     ("度逍遥 (精品)", 5003),
     ("度小鹿 (精品)", 5118),
-]; 
\ No newline at end of file
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_voice(person: i32) -> Config {
+        let mut config = Config::default();
+        config.app_settings.person = person;
+        config
+    }
+
+    fn saved_voice(path: &Path) -> i32 {
+        toml::from_str::<Config>(&fs::read_to_string(path).unwrap()).unwrap().app_settings.person
+    }
+
+    #[test]
+    fn failed_write_leaves_the_config_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        save_config_at(&path, &config_with_voice(1)).unwrap();
+        // 临时文件的位置被目录占用，写入必然失败
+        fs::create_dir(sibling(&path, "tmp")).unwrap();
+        assert!(save_config_at(&path, &config_with_voice(4)).is_err());
+        assert_eq!(saved_voice(&path), 1);
+        assert!(!backup_path(&path, 1).exists());
+    }
+
+    #[test]
+    fn backups_rotate_and_drop_the_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        for person in 0..5 {
+            save_config_at(&path, &config_with_voice(person)).unwrap();
+        }
+        assert_eq!(saved_voice(&path), 4);
+        assert_eq!(saved_voice(&backup_path(&path, 1)), 3);
+        assert_eq!(saved_voice(&backup_path(&path, 2)), 2);
+        assert_eq!(saved_voice(&backup_path(&path, 3)), 1);
+        assert!(!backup_path(&path, 4).exists());
+        assert!(!sibling(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn restoring_keeps_the_corrupt_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        save_config_at(&path, &config_with_voice(3)).unwrap();
+        save_config_at(&path, &config_with_voice(4)).unwrap();
+        fs::write(&path, "not = [valid").unwrap();
+
+        restore_backup_at(&path, &backup_path(&path, 1)).unwrap();
+        assert_eq!(saved_voice(&path), 3);
+        assert_eq!(fs::read_to_string(sibling(&path, "corrupt")).unwrap(), "not = [valid");
+        assert!(!sibling(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn key_format_checks() {
+        assert!(validate_key("百度 API Key", "", true).is_err());
+        assert_eq!(validate_key("DeepSeek API Key", "  ", false), Ok(()));
+        assert_eq!(validate_key("百度 API Key", " AbCdEfGh12345678 ", true), Ok(()));
+        assert!(validate_key("百度 API Key", "AbCdEfGh 12345678", true).unwrap_err().contains("空格"));
+        assert!(validate_key("百度 API Key", "AbCdEfGh1234567８", true).is_err());
+        assert!(validate_key("百度 API Key", "short", true).unwrap_err().contains("过短"));
+    }
+
+    #[test]
+    fn masked_keys_show_only_the_ends() {
+        assert_eq!(mask_key("sk-1234567890abcdef"), "sk-1…cdef");
+        assert_eq!(mask_key("12345678"), "********");
+    }
+
+    #[test]
+    fn presets_survive_a_save_and_load() {
+        let options = GenerationOptions { use_deepseek: true, speed: 7, pitch: 4, volume: 9, person: 5003 };
+        let presets = vec![
+            GenerationPreset {
+                name: "开场".to_string(),
+                prompt: PresetPrompt::Template { name: "欢迎词".to_string() },
+                options: options.clone(),
+            },
+            GenerationPreset {
+                name: "带货".to_string(),
+                prompt: PresetPrompt::Custom { prompt: "介绍这款商品的\"三大卖点\"\n每点一句".to_string() },
+                options: GenerationOptions { use_deepseek: false, ..options },
+            },
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        save_config_at(&path, &Config { presets: presets.clone(), ..Config::default() }).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("kind = \"template\"") && text.contains("kind = \"custom\""), "{}", text);
+        assert_eq!(toml::from_str::<Config>(&text).unwrap().presets, presets);
+    }
+}
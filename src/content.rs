@@ -0,0 +1,360 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// 语速为 5（百度默认中语速）时每秒朗读的字数
+const CHARS_PER_SECOND_AT_DEFAULT_SPEED: f32 = 4.5;
+/// 提示词模板未设置 `max_words` 时的建议字数上限
+pub const DEFAULT_MAX_WORDS: usize = 100;
+/// 百度短文本合成的 `tex` 上限为 1024 个 GBK 字节
+pub const BAIDU_MAX_BYTES: usize = 1024;
+/// 达到百度上限的该比例时开始提醒
+const NEAR_LIMIT_RATIO: f32 = 0.8;
+
+fn is_sentence_end(c: char) -> bool {
+    matches!(c, '。' | '！' | '？' | '；' | '!' | '?' | ';' | '\n')
+}
+
+/// 生成文本的统计信息，用于在合成前判断时长是否合适
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentMetadata {
+    pub word_count: usize,
+    pub estimated_duration: Duration,
+    /// 按 `baidu_text_len` 计算的长度
+    pub baidu_len: usize,
+}
+
+impl ContentMetadata {
+    pub fn analyze(text: &str, speed: i32) -> Self {
+        let word_count = count_words(text);
+        Self {
+            word_count,
+            estimated_duration: estimate_duration(word_count, speed),
+            baidu_len: baidu_text_len(text),
+        }
+    }
+
+    pub fn baidu_level(&self) -> LengthLevel {
+        if self.baidu_len > BAIDU_MAX_BYTES {
+            LengthLevel::OverLimit
+        } else if self.baidu_len as f32 >= BAIDU_MAX_BYTES as f32 * NEAR_LIMIT_RATIO {
+            LengthLevel::NearLimit
+        } else {
+            LengthLevel::Comfortable
+        }
+    }
+}
+
+/// 文本长度相对百度单次合成上限的程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthLevel {
+    Comfortable,
+    NearLimit,
+    OverLimit,
+}
+
+/// 中文按字计数，连续的字母数字按一个词计数，标点和空白不计
+pub fn count_words(text: &str) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            if !in_word {
+                count += 1;
+                in_word = true;
+            }
+        } else {
+            in_word = false;
+            if c.is_alphanumeric() {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// 百度语速参数（0-15）对应的每秒字数
+fn words_per_second(speed: i32) -> f32 {
+    CHARS_PER_SECOND_AT_DEFAULT_SPEED * (0.5 + speed.clamp(0, 15) as f32 / 10.0)
+}
+
+/// 按百度语速参数（0-15）估算朗读时长
+pub fn estimate_duration(word_count: usize, speed: i32) -> Duration {
+    Duration::from_secs_f32(word_count as f32 / words_per_second(speed))
+}
+
+/// 按同样的估算在 `duration` 内能读完的字数，至少为 1
+pub fn words_within(duration: Duration, speed: i32) -> usize {
+    ((duration.as_secs_f32() * words_per_second(speed)) as usize).max(1)
+}
+
+/// 预计时长超过 `max_audio_seconds` 时的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationLimit {
+    /// 只提示，照常合成
+    #[default]
+    Warn,
+    /// 合成前截断文本；合成结果仍然超长时再截断音频
+    Truncate,
+}
+
+impl DurationLimit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DurationLimit::Warn => "仅提示",
+            DurationLimit::Truncate => "自动截断",
+        }
+    }
+}
+
+/// 朗读文本中的一句及其估算的起始时间
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sentence {
+    pub text: String,
+    pub start: Duration,
+}
+
+/// 按中英文句末标点和换行切分，并按字数估算每句的起始时间。
+/// 如果已知音频实际时长，则按比例校准估算值。
+pub fn split_sentences(text: &str, speed: i32, actual_duration: Option<Duration>) -> Vec<Sentence> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if c != '\n' {
+            current.push(c);
+        }
+        if is_sentence_end(c) {
+            if !current.trim().is_empty() {
+                pieces.push(current.trim().to_string());
+            }
+            current.clear();
+        }
+    }
+    if !current.trim().is_empty() {
+        pieces.push(current.trim().to_string());
+    }
+
+    let durations: Vec<Duration> = pieces.iter().map(|p| estimate_duration(count_words(p), speed)).collect();
+    let estimated_total: Duration = durations.iter().sum();
+    let scale = match actual_duration {
+        Some(actual) if !estimated_total.is_zero() => actual.as_secs_f64() / estimated_total.as_secs_f64(),
+        _ => 1.0,
+    };
+
+    let mut start = Duration::ZERO;
+    pieces
+        .into_iter()
+        .zip(durations)
+        .map(|(text, duration)| {
+            let sentence = Sentence { text, start };
+            start += duration.mul_f64(scale);
+            sentence
+        })
+        .collect()
+}
+
+/// 把文本截断到不超过 `max_words` 字（计数方式同 `count_words`），
+/// 优先在最后一个完整句子处截断；第一句就超长时退而在词边界截断，不会切开英文单词
+pub fn truncate_at_sentence(text: &str, max_words: usize) -> String {
+    let mut words = 0;
+    let mut in_word = false;
+    let mut sentence_end = None;
+    let mut word_end = text.len();
+    for (i, c) in text.char_indices() {
+        let starts_word = if c.is_ascii_alphanumeric() {
+            !std::mem::replace(&mut in_word, true)
+        } else {
+            in_word = false;
+            c.is_alphanumeric()
+        };
+        if starts_word {
+            words += 1;
+            if words > max_words {
+                word_end = i;
+                break;
+            }
+        }
+        if is_sentence_end(c) {
+            sentence_end = Some(i + c.len_utf8());
+        }
+    }
+    if words <= max_words {
+        return text.to_string();
+    }
+    text[..sentence_end.unwrap_or(word_end)].trim_end().to_string()
+}
+
+/// 送去合成前统一清理文本：去掉控制字符和零宽字符，行内连续空白合并为一个空格，连续空行
+/// 合并为一个换行，并去掉首尾空白。清理后没有任何文字或数字（如只有空白、标点或表情）时返回原因
+pub fn sanitize_tts_input(text: &str) -> Result<String, &'static str> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut cleaned = String::with_capacity(line.len());
+        for c in line.chars() {
+            if c.is_whitespace() {
+                if !cleaned.is_empty() && !cleaned.ends_with(' ') {
+                    cleaned.push(' ');
+                }
+            } else if !c.is_control() && !matches!(c, '\u{200b}'..='\u{200f}' | '\u{2060}' | '\u{feff}') {
+                cleaned.push(c);
+            }
+        }
+        let cleaned = cleaned.trim_end();
+        if !cleaned.is_empty() {
+            lines.push(cleaned.to_string());
+        }
+    }
+    let text = lines.join("\n");
+    if text.is_empty() {
+        Err("文本为空")
+    } else if !text.chars().any(char::is_alphanumeric) {
+        Err("文本中没有可朗读的文字")
+    } else {
+        Ok(text)
+    }
+}
+
+/// 按 GBK 编码估算文本长度：ASCII 占 1 字节，其余字符占 2 字节
+pub fn baidu_text_len(text: &str) -> usize {
+    text.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+/// 截断到百度单次合成的长度上限以内，优先在句末截断，否则在英文单词之间截断
+pub fn truncate_for_baidu(text: &str) -> String {
+    if baidu_text_len(text) <= BAIDU_MAX_BYTES {
+        return text.to_string();
+    }
+    let mut len = 0;
+    let mut sentence_end = None;
+    let mut word_end = 0;
+    for (i, c) in text.char_indices() {
+        len += if c.is_ascii() { 1 } else { 2 };
+        if len > BAIDU_MAX_BYTES {
+            break;
+        }
+        let end = i + c.len_utf8();
+        if is_sentence_end(c) {
+            sentence_end = Some(end);
+        }
+        if !c.is_ascii_alphanumeric() || !text[end..].starts_with(|next: char| next.is_ascii_alphanumeric()) {
+            word_end = end;
+        }
+    }
+    text[..sentence_end.unwrap_or(word_end)].trim_end().to_string()
+}
+
+/// 剪贴板等外部来源的文本是否值得朗读：排除空白和夹杂大量控制字符的二进制内容
+#[cfg_attr(not(feature = "clipboard"), allow(dead_code))]
+pub fn is_speakable(text: &str) -> bool {
+    let total = text.chars().count();
+    if text.trim().is_empty() || text.contains('\0') {
+        return false;
+    }
+    let control = text.chars().filter(|c| c.is_control() && !c.is_whitespace()).count();
+    control * 10 < total
+}
+
+/// 返回 `position` 所处的句子序号
+pub fn sentence_at(sentences: &[Sentence], position: Duration) -> Option<usize> {
+    sentences.iter().rposition(|s| s.start <= position)
+}
+
+/// “上一句/下一句”要跳到的句子序号。`current` 为 `None`（播放已结束）时向后从第一句、
+/// 向前从最后一句开始；已在第一句时向前仍停在第一句，已在最后一句时向后不跳转
+pub fn skip_target(current: Option<usize>, count: usize, forward: bool) -> Option<usize> {
+    let target = match (current, forward) {
+        (Some(index), true) => index + 1,
+        (Some(index), false) => index.saturating_sub(1),
+        (None, true) => 0,
+        (None, false) => count.checked_sub(1)?,
+    };
+    (target < count).then_some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation_keeps_the_last_full_sentence() {
+        let text = "今天天气很好。我们去公园吧！然后回家。";
+        assert_eq!(truncate_at_sentence(text, 12), "今天天气很好。我们去公园吧！");
+        assert_eq!(truncate_at_sentence(text, 15), "今天天气很好。我们去公园吧！");
+        assert_eq!(truncate_at_sentence(text, 16), text);
+    }
+
+    #[test]
+    fn overlong_first_sentence_is_cut_at_a_word_boundary() {
+        assert_eq!(truncate_at_sentence("Hello wonderful world of streaming. Bye.", 3), "Hello wonderful world");
+        assert_eq!(truncate_at_sentence("今天天气很好，我们去公园吧。", 4), "今天天气");
+    }
+
+    #[test]
+    fn truncation_never_splits_an_ascii_word() {
+        assert_eq!(truncate_at_sentence("abc defghij", 1), "abc");
+        assert_eq!(truncate_at_sentence("我爱Rust编程", 2), "我爱");
+        assert_eq!(truncate_at_sentence("我爱Rust编程", 3), "我爱Rust");
+        assert_eq!(truncate_at_sentence("TTS2024 works", 1), "TTS2024");
+    }
+
+    #[test]
+    fn sanitizing_strips_invisible_characters() {
+        assert_eq!(sanitize_tts_input("你\u{200b}好\u{feff}，欢\u{2060}迎\u{7}"), Ok("你好，欢迎".to_string()));
+        assert_eq!(sanitize_tts_input("a\u{0}b\u{1b}c"), Ok("abc".to_string()));
+    }
+
+    #[test]
+    fn sanitizing_collapses_whitespace_and_blank_lines() {
+        assert_eq!(sanitize_tts_input("  第一行 \t  结尾  \r\n\n\n   \n第二行\n"), Ok("第一行 结尾\n第二行".to_string()));
+        assert_eq!(sanitize_tts_input("a\u{3000}\u{3000}b"), Ok("a b".to_string()));
+    }
+
+    #[test]
+    fn text_without_words_is_rejected() {
+        assert_eq!(sanitize_tts_input(""), Err("文本为空"));
+        assert_eq!(sanitize_tts_input(" \n\u{200b}\t"), Err("文本为空"));
+        assert_eq!(sanitize_tts_input("？！……。"), Err("文本中没有可朗读的文字"));
+        assert_eq!(sanitize_tts_input("😀🎉 👍"), Err("文本中没有可朗读的文字"));
+        assert!(sanitize_tts_input("😀 3").is_ok());
+    }
+
+    fn sentences(starts_ms: &[u64]) -> Vec<Sentence> {
+        starts_ms.iter().map(|&ms| Sentence { text: String::new(), start: Duration::from_millis(ms) }).collect()
+    }
+
+    #[test]
+    fn position_maps_to_the_sentence_being_read() {
+        let sentences = sentences(&[0, 1000, 2500]);
+        assert_eq!(sentence_at(&sentences, Duration::ZERO), Some(0));
+        assert_eq!(sentence_at(&sentences, Duration::from_millis(999)), Some(0));
+        assert_eq!(sentence_at(&sentences, Duration::from_millis(1000)), Some(1));
+        assert_eq!(sentence_at(&sentences, Duration::from_secs(60)), Some(2));
+        assert_eq!(sentence_at(&[], Duration::ZERO), None);
+    }
+
+    #[test]
+    fn skipping_moves_one_sentence_and_stops_at_the_ends() {
+        assert_eq!(skip_target(Some(1), 3, true), Some(2));
+        assert_eq!(skip_target(Some(1), 3, false), Some(0));
+        assert_eq!(skip_target(Some(2), 3, true), None);
+        assert_eq!(skip_target(Some(0), 3, false), Some(0));
+        // 播放结束后重新开始
+        assert_eq!(skip_target(None, 3, true), Some(0));
+        assert_eq!(skip_target(None, 3, false), Some(2));
+        assert_eq!(skip_target(None, 0, false), None);
+        assert_eq!(skip_target(None, 0, true), None);
+    }
+
+    #[test]
+    fn sentence_starts_are_scaled_to_the_real_duration() {
+        let estimated = split_sentences("你好。欢迎来到直播间！\n再见", 5, None);
+        let texts: Vec<&str> = estimated.iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(texts, ["你好。", "欢迎来到直播间！", "再见"]);
+        // 按字数分配：2 + 7 + 2 字
+        let scaled = split_sentences("你好。欢迎来到直播间！\n再见", 5, Some(Duration::from_secs(11)));
+        assert_eq!(scaled[0].start, Duration::ZERO);
+        assert!((scaled[1].start.as_secs_f64() - 2.0).abs() < 1e-3, "{:?}", scaled);
+        assert!((scaled[2].start.as_secs_f64() - 9.0).abs() < 1e-3, "{:?}", scaled);
+    }
+}
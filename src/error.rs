@@ -7,6 +7,11 @@ pub enum AppError {
     Config(String),
     Audio(String),
     BaiduApi(String),
+    /// 百度返回的 `{err_no, err_msg}` 错误，`kind` 由 `BAIDU_ERROR_CODES` 归类
+    BaiduService { kind: BaiduErrorKind, code: i64, message: String },
+    DeepSeekApi(String),
+    /// 响应体超过 `network.max_response_mb` 限制，值为限制的字节数
+    ResponseTooLarge(usize),
 }
 
 impl fmt::Display for AppError {
@@ -17,6 +22,57 @@ impl fmt::Display for AppError {
             AppError::Config(s) => write!(f, "配置错误: {}", s),
             AppError::Audio(s) => write!(f, "音频错误: {}", s),
             AppError::BaiduApi(s) => write!(f, "百度API错误: {}", s),
+            AppError::BaiduService { kind, code, message } => write!(f, "{} (err_no {}: {})", kind.advice(), code, message),
+            AppError::DeepSeekApi(s) => write!(f, "DeepSeek API错误: {}", s),
+            AppError::ResponseTooLarge(limit) => write!(f, "响应数据超过上限 {} MB，已中止读取", limit / (1024 * 1024)),
+        }
+    }
+}
+
+/// 百度错误的类别，决定界面上给出的处理建议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaiduErrorKind {
+    QuotaExceeded,
+    RateLimited,
+    InvalidToken,
+    TextTooLong,
+    Other,
+}
+
+/// 已知的百度 err_no：通用开放平台错误码和语音合成错误码，新增时在此补充
+const BAIDU_ERROR_CODES: &[(i64, BaiduErrorKind)] = &[
+    (4, BaiduErrorKind::RateLimited),
+    (17, BaiduErrorKind::QuotaExceeded),
+    (18, BaiduErrorKind::RateLimited),
+    (19, BaiduErrorKind::QuotaExceeded),
+    (110, BaiduErrorKind::InvalidToken),
+    (111, BaiduErrorKind::InvalidToken),
+    (502, BaiduErrorKind::InvalidToken),
+    (3302, BaiduErrorKind::InvalidToken),
+    (3304, BaiduErrorKind::RateLimited),
+    (3305, BaiduErrorKind::QuotaExceeded),
+];
+
+impl BaiduErrorKind {
+    /// 先查错误码表；501（参数错误）没有单独的文本过长错误码，只能从说明中判断
+    pub fn classify(code: i64, message: &str) -> Self {
+        if let Some(&(_, kind)) = BAIDU_ERROR_CODES.iter().find(|(known, _)| *known == code) {
+            return kind;
+        }
+        let message = message.to_ascii_lowercase();
+        if message.contains("too long") || message.contains("length") {
+            return BaiduErrorKind::TextTooLong;
+        }
+        BaiduErrorKind::Other
+    }
+
+    pub fn advice(&self) -> &'static str {
+        match self {
+            BaiduErrorKind::QuotaExceeded => "百度语音合成今日配额已用尽，请明天再试或在百度控制台提升配额",
+            BaiduErrorKind::RateLimited => "请求过于频繁，已超过百度的并发/QPS 限制，请稍后再试",
+            BaiduErrorKind::InvalidToken => "百度鉴权失败，请检查 API Key 和 Secret Key 是否正确",
+            BaiduErrorKind::TextTooLong => "文本过长，请缩短后再合成",
+            BaiduErrorKind::Other => "百度API错误",
         }
     }
 }
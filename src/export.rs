@@ -0,0 +1,244 @@
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::content::ContentMetadata;
+use crate::error::AppError;
+
+/// 一段合成语音的来源信息，导出时写入文件元数据
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipInfo {
+    pub prompt: String,
+    pub text: String,
+    pub voice: String,
+    pub created_at: DateTime<Local>,
+}
+
+impl ClipInfo {
+    pub fn new(prompt: String, text: String, voice: String) -> Self {
+        Self {
+            prompt,
+            text,
+            voice,
+            created_at: Local::now(),
+        }
+    }
+}
+
+/// 导出为 JSON 的一条生成记录，包含重新合成所需的参数
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContentRecord {
+    #[serde(flatten)]
+    pub info: ClipInfo,
+    pub word_count: usize,
+    pub estimated_seconds: f32,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub person: i32,
+}
+
+impl ContentRecord {
+    pub fn new(info: ClipInfo, speed: i32, pitch: i32, volume: i32, person: i32) -> Self {
+        let metadata = ContentMetadata::analyze(&info.text, speed);
+        Self {
+            info,
+            word_count: metadata.word_count,
+            estimated_seconds: metadata.estimated_duration.as_secs_f32(),
+            speed,
+            pitch,
+            volume,
+            person,
+        }
+    }
+}
+
+/// 导入时既接受单条记录，也接受整个会话的记录数组
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ContentFile {
+    One(ContentRecord),
+    Many(Vec<ContentRecord>),
+}
+
+pub fn export_content(record: &ContentRecord, path: &Path) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(record).map_err(|e| AppError::Config(e.to_string()))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+pub fn export_session(records: &[ContentRecord], path: &Path) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(records).map_err(|e| AppError::Config(e.to_string()))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+pub fn import_content(path: &Path) -> Result<Vec<ContentRecord>, AppError> {
+    let json = std::fs::read_to_string(path)?;
+    let file: ContentFile =
+        serde_json::from_str(&json).map_err(|e| AppError::Config(format!("无法解析 {}: {}", path.display(), e)))?;
+    Ok(match file {
+        ContentFile::One(record) => vec![record],
+        ContentFile::Many(records) => records,
+    })
+}
+
+/// 为 MP3 数据加上 ID3v2.3 标签，让播放器、DAW 和文件管理器显示朗读内容和原始提示词。
+///
+/// 使用 v2.3 而不是 v2.4 是因为 Windows 资源管理器只识别前者；文本帧统一用
+/// 带 BOM 的 UTF-16 编码以支持中文。
+pub fn tag_mp3(audio: &[u8], info: &ClipInfo) -> Vec<u8> {
+    let title: String = info.text.chars().take(30).collect();
+    let comment = format!("提示词: {}\n文本: {}", info.prompt, info.text);
+
+    let mut frames = Vec::new();
+    frames.extend(text_frame(b"TIT2", &title));
+    frames.extend(text_frame(b"TPE1", &format!("TTSmate - {}", info.voice)));
+    frames.extend(text_frame(b"TYER", &info.created_at.format("%Y").to_string()));
+    frames.extend(text_frame(b"TDAT", &info.created_at.format("%d%m").to_string()));
+    frames.extend(text_frame(b"TIME", &info.created_at.format("%H%M").to_string()));
+    frames.extend(comment_frame(&comment));
+
+    let mut tagged = Vec::with_capacity(10 + frames.len() + audio.len());
+    tagged.extend_from_slice(b"ID3");
+    tagged.extend_from_slice(&[3, 0, 0]);
+    tagged.extend_from_slice(&synchsafe(frames.len() as u32));
+    tagged.extend(frames);
+    tagged.extend_from_slice(audio);
+    tagged
+}
+
+/// 编码为可直接用作 `<audio src>` 的 `data:audio/mpeg;base64,...` URI，不加 ID3 标签以减小体积
+#[cfg(feature = "clipboard")]
+pub fn data_uri(audio: &[u8]) -> String {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+
+    format!("data:audio/mpeg;base64,{}", STANDARD.encode(audio))
+}
+
+/// 标签头中的长度使用每字节 7 位的 synchsafe 整数
+fn synchsafe(size: u32) -> [u8; 4] {
+    [
+        ((size >> 21) & 0x7f) as u8,
+        ((size >> 14) & 0x7f) as u8,
+        ((size >> 7) & 0x7f) as u8,
+        (size & 0x7f) as u8,
+    ]
+}
+
+fn utf16_with_bom(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xff, 0xfe];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+    bytes
+}
+
+fn frame(id: &[u8; 4], body: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(10 + body.len());
+    frame.extend_from_slice(id);
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend(body);
+    frame
+}
+
+fn text_frame(id: &[u8; 4], text: &str) -> Vec<u8> {
+    let mut body = vec![1];
+    body.extend(utf16_with_bom(text));
+    frame(id, body)
+}
+
+fn comment_frame(text: &str) -> Vec<u8> {
+    let mut body = vec![1];
+    body.extend_from_slice(b"chi");
+    // 空的短描述，以 UTF-16 的双字节 0 结尾
+    body.extend(utf16_with_bom(""));
+    body.extend_from_slice(&[0, 0]);
+    body.extend(utf16_with_bom(text));
+    frame(b"COMM", body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio;
+    use chrono::TimeZone;
+    use rodio::Source;
+
+    fn info() -> ClipInfo {
+        ClipInfo {
+            prompt: "欢迎新观众".to_string(),
+            text: "欢迎来到直播间！".to_string(),
+            voice: "度小美".to_string(),
+            created_at: Local.with_ymd_and_hms(2024, 3, 5, 20, 30, 0).unwrap(),
+        }
+    }
+
+    fn sample_count(data: &[u8]) -> usize {
+        rodio::Decoder::new(std::io::Cursor::new(data.to_vec())).unwrap().count()
+    }
+
+    /// 标签中某一帧的内容（去掉 10 字节的帧头）
+    fn frame_body<'a>(tag: &'a [u8], id: &[u8; 4]) -> &'a [u8] {
+        let mut offset = 0;
+        while offset + 10 <= tag.len() {
+            let size = u32::from_be_bytes(tag[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            if &tag[offset..offset + 4] == id {
+                return &tag[offset + 10..offset + 10 + size];
+            }
+            offset += 10 + size;
+        }
+        panic!("缺少 {} 帧", String::from_utf8_lossy(id));
+    }
+
+    fn decode_utf16(bytes: &[u8]) -> String {
+        assert_eq!(&bytes[..2], &[0xff, 0xfe]);
+        let units: Vec<u16> = bytes[2..].chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16(&units).unwrap()
+    }
+
+    #[test]
+    fn id3_tag_carries_prompt_and_timestamp() {
+        let mp3 = audio::silent_mp3(10);
+        let tagged = tag_mp3(&mp3, &info());
+        assert_eq!(&tagged[..5], b"ID3\x03\x00");
+        let size = tagged[6..10].iter().fold(0usize, |size, &b| (size << 7) | usize::from(b & 0x7f));
+        let (tag, audio) = tagged[10..].split_at(size);
+        assert_eq!(audio, mp3.as_slice());
+
+        assert_eq!(decode_utf16(&frame_body(tag, b"TIT2")[1..]), "欢迎来到直播间！");
+        assert_eq!(decode_utf16(&frame_body(tag, b"TPE1")[1..]), "TTSmate - 度小美");
+        assert_eq!(decode_utf16(&frame_body(tag, b"TYER")[1..]), "2024");
+        assert_eq!(decode_utf16(&frame_body(tag, b"TDAT")[1..]), "0503");
+        assert_eq!(decode_utf16(&frame_body(tag, b"TIME")[1..]), "2030");
+        let comment = frame_body(tag, b"COMM");
+        assert_eq!(&comment[1..4], b"chi");
+        assert_eq!(decode_utf16(&comment[8..]), "提示词: 欢迎新观众\n文本: 欢迎来到直播间！");
+    }
+
+    #[test]
+    fn tagged_mp3_still_decodes() {
+        let mp3 = audio::silent_mp3(10);
+        let tagged = tag_mp3(&mp3, &info());
+        assert_eq!(audio::validate_audio(&tagged), Ok(()));
+        assert_eq!(sample_count(&tagged), sample_count(&mp3));
+        let decoder = rodio::Decoder::new(std::io::Cursor::new(tagged)).unwrap();
+        assert_eq!(decoder.sample_rate(), 44100);
+    }
+
+    #[test]
+    fn exported_session_imports_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let second = ClipInfo { text: "感谢关注，下次见。".to_string(), voice: "度小宇".to_string(), ..info() };
+        let records = vec![ContentRecord::new(info(), 5, 5, 9, 0), ContentRecord::new(second, 7, 4, 5, 1)];
+        export_session(&records, &path).unwrap();
+        assert_eq!(import_content(&path).unwrap(), records);
+
+        export_content(&records[1], &path).unwrap();
+        assert_eq!(import_content(&path).unwrap(), &records[1..]);
+    }
+}
@@ -0,0 +1,199 @@
+//! AI 回复的内容过滤：在显示和合成之前按用户的屏蔽词表检查 DeepSeek 生成的文本，
+//! 适合用于面向公众的播报。
+//!
+//! 屏蔽词忽略大小写；以英文字母或数字开头/结尾的词只匹配完整单词（与发音词典相同），
+//! 避免 "ass" 命中 "class"。命中后按设置删除这些词后照常朗读，或整段不显示也不朗读。
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ContentFilterSettings;
+use crate::content;
+use crate::normalize;
+
+/// 命中屏蔽词或超出字数上限时的处理方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// 删除命中的词（超长时在句末截断），其余内容照常显示和朗读
+    #[default]
+    Blank,
+    /// 整段不显示、不朗读，只提示命中的原因
+    Reject,
+}
+
+impl FilterAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FilterAction::Blank => "删除屏蔽词后朗读",
+            FilterAction::Reject => "整段不朗读",
+        }
+    }
+}
+
+/// 把屏蔽词表整理成待匹配的字符序列，较长的优先
+fn prepare(words: &[String]) -> Vec<Vec<char>> {
+    let mut words: Vec<Vec<char>> = words
+        .iter()
+        .map(|word| word.trim())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.chars().collect())
+        .collect();
+    words.sort_by_key(|word| std::cmp::Reverse(word.len()));
+    words
+}
+
+/// 逐个位置匹配，每次命中时把屏蔽词交给 `on_match`；返回删除命中内容后的文本
+fn scan(text: &str, words: &[String], mut on_match: impl FnMut(&[char])) -> String {
+    let words = prepare(words);
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match words.iter().find(|word| normalize::word_matches_at(&chars, i, word, false)) {
+            Some(word) => {
+                on_match(word);
+                i += word.len();
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// 文本中命中的屏蔽词（按屏蔽词表中的写法，去重，按首次出现的顺序）
+pub fn find_blocked(text: &str, words: &[String]) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    scan(text, words, |word| {
+        let word: String = word.iter().collect();
+        if !found.contains(&word) {
+            found.push(word);
+        }
+    });
+    found
+}
+
+/// 删除文本中命中的屏蔽词
+pub fn blank_blocked(text: &str, words: &[String]) -> String {
+    scan(text, words, |_| {})
+}
+
+/// 按设置检查一段 AI 回复。返回要显示和朗读的文本及给用户的提示；
+/// 拒绝模式下命中时返回原因，调用方不应显示或合成这段文本
+pub fn screen(text: String, settings: &ContentFilterSettings) -> Result<(String, Option<String>), String> {
+    if !settings.enabled {
+        return Ok((text, None));
+    }
+    let blocked = find_blocked(&text, &settings.blocked_words);
+    let word_count = content::count_words(&text);
+    let too_long = settings.max_words > 0 && word_count > settings.max_words;
+    if blocked.is_empty() && !too_long {
+        return Ok((text, None));
+    }
+
+    let mut reasons = Vec::new();
+    if !blocked.is_empty() {
+        reasons.push(format!("包含屏蔽词: {}", blocked.join("、")));
+    }
+    if too_long {
+        reasons.push(format!("共 {} 字，超过上限 {} 字", word_count, settings.max_words));
+    }
+    let reason = reasons.join("；");
+    match settings.action {
+        FilterAction::Reject => Err(format!("AI 回复{}，已停止朗读", reason)),
+        FilterAction::Blank => {
+            let mut text = blank_blocked(&text, &settings.blocked_words);
+            if too_long {
+                text = content::truncate_at_sentence(&text, settings.max_words);
+            }
+            Ok((text, Some(format!("AI 回复{}，已过滤", reason))))
+        }
+    }
+}
+
+/// 流式显示中的部分回复。删除模式下先删除已出现的屏蔽词、超长部分在句末截断再显示；
+/// 拒绝模式下返回 `None`，整段回复通过 `screen` 检查之前不显示任何内容
+pub fn screen_partial(text: &str, settings: &ContentFilterSettings) -> Option<String> {
+    if !settings.enabled {
+        return Some(text.to_string());
+    }
+    match settings.action {
+        FilterAction::Reject => None,
+        FilterAction::Blank => {
+            let text = blank_blocked(text, &settings.blocked_words);
+            if settings.max_words > 0 && content::count_words(&text) > settings.max_words {
+                Some(content::truncate_at_sentence(&text, settings.max_words))
+            } else {
+                Some(text)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(words: &[&str], max_words: usize, action: FilterAction) -> ContentFilterSettings {
+        ContentFilterSettings {
+            enabled: true,
+            blocked_words: words.iter().map(|w| w.to_string()).collect(),
+            max_words,
+            action,
+        }
+    }
+
+    #[test]
+    fn english_words_match_only_whole_words() {
+        let words = vec!["ass".to_string()];
+        assert!(find_blocked("a class act", &words).is_empty());
+        assert_eq!(find_blocked("you ASS!", &words), vec!["ass"]);
+        assert_eq!(blank_blocked("ass, class", &words), ", class");
+    }
+
+    #[test]
+    fn chinese_words_match_anywhere() {
+        let words = vec!["坏词".to_string()];
+        assert_eq!(blank_blocked("这是坏词吗", &words), "这是吗");
+    }
+
+    #[test]
+    fn blank_mode_removes_words_and_reports() {
+        let (text, notice) = screen("hello bad world".to_string(), &settings(&["bad"], 0, FilterAction::Blank)).unwrap();
+        assert_eq!(text, "hello  world");
+        assert!(notice.unwrap().contains("bad"));
+    }
+
+    #[test]
+    fn reject_mode_refuses_the_reply() {
+        let reason = screen("hello bad world".to_string(), &settings(&["bad"], 0, FilterAction::Reject)).unwrap_err();
+        assert!(reason.contains("bad"));
+        assert!(screen("hello world".to_string(), &settings(&["bad"], 0, FilterAction::Reject)).is_ok());
+    }
+
+    #[test]
+    fn over_length_replies_are_filtered() {
+        let text = "第一句话。第二句话。".to_string();
+        assert!(screen(text.clone(), &settings(&[], 5, FilterAction::Reject)).is_err());
+        let (text, notice) = screen(text, &settings(&[], 5, FilterAction::Blank)).unwrap();
+        assert_eq!(text, "第一句话。");
+        assert!(notice.is_some());
+    }
+
+    #[test]
+    fn disabled_filter_passes_everything() {
+        let mut settings = settings(&["bad"], 1, FilterAction::Reject);
+        settings.enabled = false;
+        assert_eq!(screen("bad bad".to_string(), &settings).unwrap(), ("bad bad".to_string(), None));
+        assert_eq!(screen_partial("bad bad", &settings).as_deref(), Some("bad bad"));
+    }
+
+    #[test]
+    fn partial_replies_are_hidden_in_reject_mode() {
+        assert_eq!(screen_partial("hello", &settings(&["bad"], 0, FilterAction::Reject)), None);
+        assert_eq!(screen_partial("hello bad", &settings(&["bad"], 0, FilterAction::Blank)).as_deref(), Some("hello "));
+        assert_eq!(screen_partial("第一句话。第二句", &settings(&[], 5, FilterAction::Blank)).as_deref(), Some("第一句话。"));
+    }
+}
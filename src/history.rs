@@ -0,0 +1,76 @@
+//! 输入框的历史记录。
+//!
+//! 提交过的内容按时间顺序保存在 config.toml 中，连续提交相同内容只记一条。输入框为空
+//! （或正显示某条历史记录）时可以用上/下方向键翻阅，不会覆盖用户正在输入的内容。
+
+/// 历史记录的条目，最早的在前
+pub struct InputHistory {
+    entries: Vec<String>,
+    max_entries: usize,
+    /// 正在显示的历史记录下标，`None` 表示没有在翻阅
+    cursor: Option<usize>,
+}
+
+impl InputHistory {
+    pub fn new(mut entries: Vec<String>, max_entries: usize) -> Self {
+        let max_entries = max_entries.max(1);
+        if entries.len() > max_entries {
+            entries.drain(..entries.len() - max_entries);
+        }
+        Self { entries, max_entries, cursor: None }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 记录一次提交，返回历史记录是否有变化（需要保存）
+    pub fn push(&mut self, entry: &str) -> bool {
+        self.cursor = None;
+        let entry = entry.trim();
+        if entry.is_empty() || self.entries.last().is_some_and(|last| last == entry) {
+            return false;
+        }
+        self.entries.push(entry.to_string());
+        if self.entries.len() > self.max_entries {
+            self.entries.remove(0);
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.cursor = None;
+    }
+
+    /// 文本为空或仍是正在显示的历史记录时才允许翻阅
+    pub fn can_browse(&self, text: &str) -> bool {
+        text.trim().is_empty() || self.cursor.is_some_and(|i| self.entries[i] == text)
+    }
+
+    /// 上一条（更早的）记录；已经是最早的一条时停在原处
+    pub fn previous(&mut self) -> Option<&str> {
+        let index = match self.cursor {
+            None => self.entries.len().checked_sub(1)?,
+            Some(i) => i.saturating_sub(1),
+        };
+        self.cursor = Some(index);
+        Some(&self.entries[index])
+    }
+
+    /// 下一条（更新的）记录；越过最新的一条时回到空白输入
+    pub fn next(&mut self) -> Option<&str> {
+        let index = self.cursor?;
+        if index + 1 < self.entries.len() {
+            self.cursor = Some(index + 1);
+            Some(&self.entries[index + 1])
+        } else {
+            self.cursor = None;
+            Some("")
+        }
+    }
+}
@@ -0,0 +1,576 @@
+use std::fmt;
+use std::sync::mpsc;
+
+use eframe::egui::{self, Key, Modifiers};
+use global_hotkey::hotkey::HotKey;
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState};
+
+use crate::config::{KeyBindingConfig, SoundboardItem};
+
+/// 应用内快捷键可触发的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortcutAction {
+    TtsGenerate,
+    AiGenerate,
+    StopAll,
+    VolumeUp,
+    VolumeDown,
+    Undo,
+    /// 只注册为全局快捷键，不参与应用内快捷键匹配
+    SpeakClipboard,
+    /// 同上，且只在开启辅助功能时注册
+    ReadSelection,
+}
+
+impl ShortcutAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ShortcutAction::TtsGenerate => "直接朗读",
+            ShortcutAction::AiGenerate => "AI 生成并朗读",
+            ShortcutAction::StopAll => "停止全部",
+            ShortcutAction::VolumeUp => "主音量 +",
+            ShortcutAction::VolumeDown => "主音量 -",
+            ShortcutAction::Undo => "撤销音效板操作",
+            ShortcutAction::SpeakClipboard => "朗读剪贴板",
+            ShortcutAction::ReadSelection => "朗读选中文本",
+        }
+    }
+}
+
+/// 一个按键组合，例如 `Ctrl+Up`、`F1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: Key,
+}
+
+impl KeyCombo {
+    /// 从配置文件中的字符串解析，不区分修饰键大小写
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut ctrl = false;
+        let mut alt = false;
+        let mut shift = false;
+        let mut key = None;
+
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ctrl = true,
+                "alt" => alt = true,
+                "shift" => shift = true,
+                "" => return Err(format!("无效的快捷键: '{}'", text)),
+                _ => {
+                    if key.is_some() {
+                        return Err(format!("快捷键 '{}' 包含多个主键", text));
+                    }
+                    key = Some(Key::from_name(part).ok_or_else(|| format!("无法识别的按键: '{}'", part))?);
+                }
+            }
+        }
+
+        let key = key.ok_or_else(|| format!("快捷键 '{}' 缺少主键", text))?;
+        Self::from_key(key, ctrl, alt, shift)
+    }
+
+    /// 从捕获到的按键事件构造，拒绝不适合作为快捷键的组合
+    pub fn from_event(key: Key, modifiers: Modifiers) -> Result<Self, String> {
+        Self::from_key(key, modifiers.ctrl || modifiers.mac_cmd, modifiers.alt, modifiers.shift)
+    }
+
+    fn from_key(key: Key, ctrl: bool, alt: bool, shift: bool) -> Result<Self, String> {
+        let is_function_key = key.name().starts_with('F') && key.name().len() > 1;
+        let is_navigation_key = matches!(
+            key,
+            Key::ArrowUp | Key::ArrowDown | Key::ArrowLeft | Key::ArrowRight
+                | Key::Home | Key::End | Key::PageUp | Key::PageDown
+                | Key::Insert | Key::Delete | Key::Escape
+        );
+
+        if matches!(key, Key::Copy | Key::Cut | Key::Paste) {
+            return Err(format!("不支持绑定系统按键 '{}'", key.name()));
+        }
+        // 没有 Ctrl/Alt 的普通字符键会和文本输入冲突
+        if !ctrl && !alt && !is_function_key && !is_navigation_key {
+            return Err(format!("'{}' 需要配合 Ctrl 或 Alt 使用，否则会与文字输入冲突", key.name()));
+        }
+
+        Ok(Self { ctrl, alt, shift, key })
+    }
+
+    /// 转换为系统级全局快捷键，部分按键（如 Plus、Colon）系统层不支持
+    pub fn to_global(self) -> Result<HotKey, String> {
+        let key_name = match self.key {
+            Key::Equals => "Equal",
+            Key::OpenBracket => "BracketLeft",
+            Key::CloseBracket => "BracketRight",
+            Key::Backtick => "Backquote",
+            other => other.name(),
+        };
+        let mut text = String::new();
+        if self.ctrl {
+            text.push_str("Ctrl+");
+        }
+        if self.alt {
+            text.push_str("Alt+");
+        }
+        if self.shift {
+            text.push_str("Shift+");
+        }
+        text.push_str(key_name);
+        text.parse::<HotKey>().map_err(|_| format!("'{}' 不能注册为全局快捷键", self))
+    }
+
+    /// 带有 Ctrl、Alt 或 Shift
+    pub fn has_modifier(&self) -> bool {
+        self.ctrl || self.alt || self.shift
+    }
+
+    /// 能否注册为全局快捷键。注册后其他程序就收不到这个键（如 Windows 的 `RegisterHotKey`），
+    /// 所以 Esc、回车、方向键这类各个程序都要用的按键必须带修饰键；F 键等专用键可以单独使用
+    pub fn check_global(&self) -> Result<(), String> {
+        let shared_key = matches!(
+            self.key,
+            Key::Escape | Key::Enter | Key::Tab | Key::Space | Key::Backspace
+                | Key::ArrowUp | Key::ArrowDown | Key::ArrowLeft | Key::ArrowRight
+                | Key::Home | Key::End | Key::PageUp | Key::PageDown | Key::Insert | Key::Delete
+        );
+        if shared_key && !self.has_modifier() {
+            return Err(format!("{} 是其他程序也要用的按键，需要配合 Ctrl、Alt 或 Shift 才能注册为全局快捷键", self));
+        }
+        Ok(())
+    }
+
+    /// 文本框持有焦点时会被当作文字编辑的组合：没有 Ctrl/Alt 的按键（F1、Esc、Shift+方向键等），
+    /// 光标移动和删除类按键，以及全选、剪贴板、撤销和重做
+    pub fn edits_text(&self) -> bool {
+        let editing_key = matches!(
+            self.key,
+            Key::ArrowUp | Key::ArrowDown | Key::ArrowLeft | Key::ArrowRight
+                | Key::Home | Key::End | Key::PageUp | Key::PageDown
+                | Key::Backspace | Key::Delete | Key::Enter | Key::Tab | Key::Escape
+        );
+        let clipboard_key = self.ctrl
+            && !self.alt
+            && match self.key {
+                // Ctrl+Shift+Z 是重做
+                Key::Z => true,
+                Key::A | Key::C | Key::V | Key::X | Key::Y => !self.shift,
+                _ => false,
+            };
+        (!self.ctrl && !self.alt) || editing_key || clipboard_key
+    }
+
+    pub fn matches(&self, key: Key, modifiers: Modifiers) -> bool {
+        self.key == key
+            && self.ctrl == (modifiers.ctrl || modifiers.mac_cmd)
+            && self.alt == modifiers.alt
+            && self.shift == modifiers.shift
+    }
+}
+
+/// 从配置解析出的快捷键表。无效或重复的绑定会被跳过并记录在 `conflicts` 中。
+#[derive(Default)]
+pub struct Shortcuts {
+    bindings: Vec<(KeyCombo, ShortcutAction)>,
+    conflicts: Vec<String>,
+}
+
+impl Shortcuts {
+    pub fn from_config(config: &KeyBindingConfig) -> Self {
+        let entries = [
+            (&config.tts_generate, ShortcutAction::TtsGenerate),
+            (&config.ai_generate, ShortcutAction::AiGenerate),
+            (&config.stop_all, ShortcutAction::StopAll),
+            (&config.volume_up, ShortcutAction::VolumeUp),
+            (&config.volume_down, ShortcutAction::VolumeDown),
+            (&config.undo, ShortcutAction::Undo),
+        ];
+
+        let mut shortcuts = Self::default();
+        for (text, action) in entries {
+            if text.trim().is_empty() {
+                continue;
+            }
+            match KeyCombo::parse(text) {
+                Ok(combo) => shortcuts.insert(combo, action),
+                Err(e) => {
+                    log::warn!("快捷键 '{}' ({}) 无效: {}", text, action.label(), e);
+                    shortcuts.conflicts.push(format!("{}: {}", action.label(), e));
+                }
+            }
+        }
+        // Esc 在窗口内也能停止全部，除非已被其他动作占用或没有设置停止键
+        let escape = KeyCombo { ctrl: false, alt: false, shift: false, key: Key::Escape };
+        if !config.stop_all.trim().is_empty() && !shortcuts.contains(&escape) {
+            shortcuts.bindings.push((escape, ShortcutAction::StopAll));
+        }
+        shortcuts
+    }
+
+    fn insert(&mut self, combo: KeyCombo, action: ShortcutAction) {
+        if let Some((_, existing)) = self.bindings.iter().find(|(c, _)| *c == combo) {
+            self.conflicts.push(format!("{} 与 {} 都绑定了 {}，已忽略后者", existing.label(), action.label(), combo));
+            return;
+        }
+        self.bindings.push((combo, action));
+    }
+
+    /// 返回本帧被按下的快捷键动作。文本框持有焦点时跳过与文字编辑冲突的组合，
+    /// 例如输入时按 F1 不会开始生成，Ctrl+Up 移动光标而不是调节音量
+    pub fn pressed(&self, ctx: &egui::Context) -> Vec<ShortcutAction> {
+        let typing = ctx.wants_keyboard_input();
+        ctx.input(|i| {
+            i.events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Key { key, pressed: true, repeat, modifiers, .. } => Some((*key, *repeat, *modifiers)),
+                    _ => None,
+                })
+                .filter_map(|(key, repeat, modifiers)| {
+                    self.bindings
+                        .iter()
+                        .find(|(combo, _)| combo.matches(key, modifiers))
+                        .filter(|(combo, _)| !(typing && combo.edits_text()))
+                        .map(|(_, action)| *action)
+                        // 音量键允许长按连续调节，其余动作只在首次按下时触发
+                        .filter(|action| !repeat || matches!(action, ShortcutAction::VolumeUp | ShortcutAction::VolumeDown))
+                })
+                .collect()
+        })
+    }
+
+    pub fn conflicts(&self) -> &[String] {
+        &self.conflicts
+    }
+
+    pub fn contains(&self, combo: &KeyCombo) -> bool {
+        self.bindings.iter().any(|(c, _)| c == combo)
+    }
+}
+
+/// 全局快捷键对应的目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyTarget {
+    /// 紧急停止：立即停止语音和全部音效
+    StopAll,
+    /// 朗读剪贴板中的文本
+    SpeakClipboard,
+    /// 复制并朗读选中的文本，在松开时触发以免修饰键干扰模拟的复制
+    ReadSelection,
+    /// 音效板中的第 N 个音效
+    Sound(usize),
+}
+
+/// 全局快捷键事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyEvent {
+    Pressed(HotkeyTarget),
+    Released(HotkeyTarget),
+}
+
+/// 系统级快捷键（紧急停止与音效板），窗口不在前台时同样生效。
+///
+/// `global_hotkey` 对同一个快捷键会分别上报按下和松开事件；松开事件只对
+/// “按住播放”的音效有意义，普通音效在按下时触发一次即可。部分平台在长按
+/// 时会重复上报按下事件，因此调用方需要自行忽略已处于按下状态的音效。
+pub struct GlobalHotkeys {
+    manager: Option<GlobalHotKeyManager>,
+    registered: Vec<(HotKey, HotkeyTarget)>,
+    errors: Vec<String>,
+    read_selection_enabled: bool,
+    /// 设置了 `wake_on_events` 后事件改由这里接收
+    events: Option<mpsc::Receiver<GlobalHotKeyEvent>>,
+}
+
+impl GlobalHotkeys {
+    pub fn new() -> Self {
+        let manager = match GlobalHotKeyManager::new() {
+            Ok(manager) => Some(manager),
+            Err(e) => {
+                log::error!("初始化全局快捷键失败: {}", e);
+                None
+            }
+        };
+        Self {
+            manager,
+            registered: Vec::new(),
+            errors: Vec::new(),
+            read_selection_enabled: false,
+            events: None,
+        }
+    }
+
+    /// 收到快捷键事件时唤醒界面，界面空闲不重绘时也能立即响应。
+    /// `global_hotkey` 的事件处理函数每个进程只能设置一次，因此只应调用一次
+    pub fn wake_on_events(&mut self, ctx: &egui::Context) {
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        GlobalHotKeyEvent::set_event_handler(Some(move |event| {
+            if sender.send(event).is_ok() {
+                ctx.request_repaint();
+            }
+        }));
+        self.events = Some(receiver);
+    }
+
+    /// 下次 `register_all` 时是否注册“朗读选中文本”快捷键
+    pub fn set_read_selection_enabled(&mut self, enabled: bool) {
+        self.read_selection_enabled = enabled;
+    }
+
+    /// 重新注册紧急停止键、朗读剪贴板键和全部音效快捷键。音效与应用内快捷键冲突时跳过该音效。
+    pub fn register_all(&mut self, key_bindings: &KeyBindingConfig, items: &[SoundboardItem], shortcuts: &Shortcuts) {
+        self.unregister_all();
+        if self.manager.is_none() {
+            self.errors.push("全局快捷键不可用".to_string());
+            return;
+        }
+
+        // 没有修饰键的紧急停止键只作为应用内快捷键，界面中会提示
+        match KeyCombo::parse(&key_bindings.stop_all) {
+            Ok(combo) if combo.check_global().is_err() => log::info!("紧急停止键 {} 没有修饰键，只在窗口内生效", combo),
+            Ok(combo) => {
+                if let Err(e) = self.register(combo, HotkeyTarget::StopAll) {
+                    log::warn!("紧急停止快捷键注册失败: {}", e);
+                    self.errors.push(format!("{}: {}", ShortcutAction::StopAll.label(), e));
+                }
+            }
+            // 空绑定和无效绑定已经由应用内快捷键报告
+            Err(_) => {}
+        }
+
+        let mut global_actions = vec![(&key_bindings.speak_clipboard, ShortcutAction::SpeakClipboard, HotkeyTarget::SpeakClipboard)];
+        if self.read_selection_enabled {
+            global_actions.push((&key_bindings.read_selection, ShortcutAction::ReadSelection, HotkeyTarget::ReadSelection));
+        }
+        for (binding, action, target) in global_actions {
+            if binding.trim().is_empty() {
+                continue;
+            }
+            let result = KeyCombo::parse(binding).and_then(|combo| {
+                if shortcuts.contains(&combo) {
+                    return Err(format!("{} 已被应用快捷键占用", combo));
+                }
+                self.register(combo, target)
+            });
+            if let Err(e) = result {
+                log::warn!("{}快捷键注册失败: {}", action.label(), e);
+                self.errors.push(format!("{}: {}", action.label(), e));
+            }
+        }
+
+        for (index, item) in items.iter().enumerate() {
+            if item.hotkey.trim().is_empty() {
+                continue;
+            }
+            let result = KeyCombo::parse(&item.hotkey).and_then(|combo| {
+                if shortcuts.contains(&combo) {
+                    return Err(format!("{} 已被应用快捷键占用", combo));
+                }
+                self.register(combo, HotkeyTarget::Sound(index))
+            });
+            if let Err(e) = result {
+                log::warn!("音效 '{}' 的快捷键注册失败: {}", item.name, e);
+                self.errors.push(format!("{}: {}", item.name, e));
+            }
+        }
+    }
+
+    fn register(&mut self, combo: KeyCombo, target: HotkeyTarget) -> Result<(), String> {
+        let Some(manager) = &self.manager else {
+            return Err("全局快捷键不可用".to_string());
+        };
+        combo.check_global()?;
+        let hotkey = combo.to_global()?;
+        if self.registered.iter().any(|(h, _)| h.id() == hotkey.id()) {
+            return Err(format!("{} 已被其他全局快捷键占用", combo));
+        }
+        manager.register(hotkey).map_err(|e| e.to_string())?;
+        self.registered.push((hotkey, target));
+        Ok(())
+    }
+
+    pub fn unregister_all(&mut self) {
+        if let Some(manager) = &self.manager {
+            let hotkeys: Vec<HotKey> = self.registered.iter().map(|(h, _)| *h).collect();
+            if let Err(e) = manager.unregister_all(&hotkeys) {
+                log::warn!("注销全局快捷键失败: {}", e);
+            }
+        }
+        self.registered.clear();
+        self.errors.clear();
+    }
+
+    /// 取出自上次调用以来的所有快捷键事件
+    pub fn poll(&self) -> Vec<HotkeyEvent> {
+        let mut events = Vec::new();
+        let next_event = || match &self.events {
+            Some(events) => events.try_recv().ok(),
+            None => GlobalHotKeyEvent::receiver().try_recv().ok(),
+        };
+        while let Some(event) = next_event() {
+            let Some((_, target)) = self.registered.iter().find(|(h, _)| h.id() == event.id()) else {
+                continue;
+            };
+            events.push(match event.state() {
+                HotKeyState::Pressed => HotkeyEvent::Pressed(*target),
+                HotKeyState::Released => HotkeyEvent::Released(*target),
+            });
+        }
+        events
+    }
+
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+/// 按键捕获控件的状态：点击按钮后等待下一次按键
+#[derive(Default)]
+pub struct KeyCapture {
+    target: Option<String>,
+    message: Option<String>,
+}
+
+impl KeyCapture {
+    /// 绘制一个捕获按钮，捕获成功时把新的组合写回 `binding` 并返回 true。
+    /// 再次点击按钮取消捕获。`global` 为 true 时拒绝不能注册为全局快捷键的组合。
+    pub fn show(&mut self, ui: &mut egui::Ui, id: &str, binding: &mut String, global: bool) -> bool {
+        let capturing = self.target.as_deref() == Some(id);
+        let label = if capturing {
+            "按下要绑定的键...".to_string()
+        } else if binding.is_empty() {
+            "未绑定".to_string()
+        } else {
+            binding.clone()
+        };
+
+        let button = ui.add(egui::Button::new(label).selected(capturing));
+        if button.clicked() {
+            self.target = if capturing { None } else { Some(id.to_string()) };
+            self.message = None;
+        }
+
+        if !capturing || button.clicked() {
+            return false;
+        }
+
+        let pressed = ui.input(|i| {
+            i.events.iter().find_map(|event| match event {
+                egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                _ => None,
+            })
+        });
+
+        let Some((key, modifiers)) = pressed else {
+            return false;
+        };
+
+        let combo = KeyCombo::from_event(key, modifiers).and_then(|combo| {
+            if global {
+                combo.check_global()?;
+            }
+            Ok(combo)
+        });
+        match combo {
+            Ok(combo) => {
+                *binding = combo.to_string();
+                self.target = None;
+                self.message = None;
+                true
+            }
+            Err(e) => {
+                self.message = Some(e);
+                false
+            }
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.target.is_some()
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combo(text: &str) -> KeyCombo {
+        KeyCombo::parse(text).unwrap()
+    }
+
+    #[test]
+    fn escape_stays_an_in_app_alias_for_stop_all() {
+        let shortcuts = Shortcuts::from_config(&KeyBindingConfig::default());
+        assert!(shortcuts.bindings.contains(&(combo("Escape"), ShortcutAction::StopAll)));
+        assert!(shortcuts.bindings.contains(&(combo(crate::config::DEFAULT_STOP_ALL), ShortcutAction::StopAll)));
+        assert!(shortcuts.conflicts().is_empty());
+
+        let config = KeyBindingConfig { volume_up: "Escape".to_string(), ..KeyBindingConfig::default() };
+        let shortcuts = Shortcuts::from_config(&config);
+        assert!(shortcuts.bindings.contains(&(combo("Escape"), ShortcutAction::VolumeUp)));
+        assert!(!shortcuts.bindings.contains(&(combo("Escape"), ShortcutAction::StopAll)));
+    }
+
+    #[test]
+    fn default_stop_all_is_global_and_bare_escape_is_migrated() {
+        assert!(combo(&KeyBindingConfig::default().stop_all).check_global().is_ok());
+        let mut config = KeyBindingConfig { stop_all: "Escape".to_string(), ..KeyBindingConfig::default() };
+        config.migrate();
+        assert_eq!(config.stop_all, crate::config::DEFAULT_STOP_ALL);
+        let mut config = KeyBindingConfig { stop_all: "Ctrl+Escape".to_string(), ..KeyBindingConfig::default() };
+        config.migrate();
+        assert_eq!(config.stop_all, "Ctrl+Escape");
+    }
+
+    #[test]
+    fn dedicated_keys_can_be_global_without_modifiers() {
+        for text in ["F5", "F13", "F24", "Ctrl+Escape", "Ctrl+Enter", "Shift+Up"] {
+            assert!(combo(text).check_global().is_ok(), "{}", text);
+        }
+        for text in ["Escape", "Up", "Home", "Delete", "PageDown"] {
+            assert!(combo(text).check_global().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn keys_without_ctrl_or_alt_belong_to_text_fields() {
+        for text in ["F1", "Escape", "Shift+F2", "Shift+Left"] {
+            assert!(combo(text).edits_text(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn editing_combos_belong_to_text_fields() {
+        for text in ["Ctrl+Up", "Ctrl+Down", "Ctrl+Z", "Ctrl+Shift+Z", "Ctrl+Backspace", "Alt+Left", "Ctrl+Escape"] {
+            assert!(combo(text).edits_text(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn other_modified_combos_work_while_typing() {
+        for text in ["Ctrl+Alt+V", "Ctrl+F1", "Alt+S", "Ctrl+Shift+X"] {
+            assert!(!combo(text).edits_text(), "{}", text);
+        }
+    }
+}
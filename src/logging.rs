@@ -0,0 +1,127 @@
+//! 日志初始化与运行时级别调整。
+//!
+//! env_logger 本身放行所有级别，实际输出的级别由 `log::set_max_level` 决定，
+//! 因此可以在界面中随时切换而不必重启。每条日志在输出前都会把已登记的密钥
+//! 替换为 `***`，任何级别下都不会把 API Key 和服务器签发的访问令牌写进日志。
+
+use std::io::Write;
+use std::sync::RwLock;
+
+use log::LevelFilter;
+
+pub const LOG_LEVELS: [LevelFilter; 5] =
+    [LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug, LevelFilter::Trace];
+
+/// 短于此长度的值不当作密钥，避免把普通词语也替换掉
+const MIN_SECRET_LEN: usize = 8;
+
+/// 记住的签发令牌个数，更早的令牌已经过期或被替换
+const MAX_ISSUED_SECRETS: usize = 4;
+
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+/// 运行中由服务器签发的令牌，不随配置变化而清除
+static ISSUED_SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// 初始化全局日志；`RUST_LOG` 仍可用来按模块过滤
+pub fn init(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Trace)
+        .parse_default_env()
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                redact(&record.args().to_string())
+            )
+        })
+        .init();
+    set_level(level);
+}
+
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+/// 配置中的取值，无法识别时按 Info 处理
+pub fn parse_level(value: &str) -> LevelFilter {
+    value.parse().unwrap_or(LevelFilter::Info)
+}
+
+pub fn level_label(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "关闭",
+        LevelFilter::Error => "Error（仅错误）",
+        LevelFilter::Warn => "Warn（警告）",
+        LevelFilter::Info => "Info（默认）",
+        LevelFilter::Debug => "Debug（调试）",
+        LevelFilter::Trace => "Trace（全部）",
+    }
+}
+
+/// 替换需要在日志中隐藏的值，如各类 API Key
+pub fn set_secrets<'a>(secrets: impl IntoIterator<Item = &'a str>) {
+    let secrets = secrets
+        .into_iter()
+        .map(str::trim)
+        .filter(|secret| secret.len() >= MIN_SECRET_LEN)
+        .map(str::to_string)
+        .collect();
+    if let Ok(mut registered) = SECRETS.write() {
+        *registered = secrets;
+    }
+}
+
+/// 登记一个运行中获得的令牌（例如百度 access token），它可能出现在请求 URL 和错误信息中
+pub fn add_issued_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+    if let Ok(mut issued) = ISSUED_SECRETS.write() {
+        if !issued.iter().any(|known| known == secret) {
+            if issued.len() == MAX_ISSUED_SECRETS {
+                issued.remove(0);
+            }
+            issued.push(secret.to_string());
+        }
+    }
+}
+
+fn redact(message: &str) -> String {
+    let mut message = message.to_string();
+    for secrets in [&SECRETS, &ISSUED_SECRETS] {
+        let Ok(secrets) = secrets.read() else {
+            continue;
+        };
+        for secret in secrets.iter() {
+            if message.contains(secret.as_str()) {
+                message = message.replace(secret.as_str(), "***");
+            }
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issued_tokens_are_redacted_alongside_configured_keys() {
+        let url = "https://example.com/rpc?access_token=24.issued-token-a&task=1";
+        add_issued_secret("24.issued-token-a");
+        assert_eq!(redact(url), "https://example.com/rpc?access_token=***&task=1");
+        // 短值不当作密钥
+        add_issued_secret("task=1");
+        assert!(redact(url).ends_with("&task=1"));
+        // 只记住最近几个令牌
+        for i in 0..MAX_ISSUED_SECRETS {
+            add_issued_secret(&format!("24.issued-token-{}", i));
+        }
+        assert_eq!(redact(url), url);
+        assert_eq!(redact("tok=24.issued-token-0"), "tok=***");
+    }
+}
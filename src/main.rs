@@ -1,15 +1,70 @@
 mod config;
 mod api_client;
+mod audio;
+mod batch;
+mod cache;
+mod commands;
+mod content;
 mod error;
+mod export;
+mod filter;
+mod history;
+mod hotkeys;
+mod logging;
+mod monitor;
+mod naming;
+mod normalize;
+mod processing;
+mod prompt_macros;
+mod scheduler;
+mod setup;
+#[cfg(feature = "selection")]
+mod selection;
+mod soundboard;
+mod stream;
+mod style_tokens;
+mod tasks;
+mod toast;
+mod utils;
+mod variations;
+mod wakeup;
 
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 use eframe::egui;
-use tokio::runtime::{Runtime, Handle};
-use rodio::{OutputStream, OutputStreamHandle, Decoder, Sink};
+use tokio::runtime::Runtime;
+use rodio::{OutputStream, OutputStreamHandle, Decoder, Sink, Source};
 use rodio::cpal::traits::{HostTrait, DeviceTrait};
 
-use crate::api_client::ApiClient;
-use crate::config::{Config, load_config, VOICES, SoundboardItem};
+use crate::api_client::{ApiClient, ServerInfo};
+use crate::batch::{BatchRow, BatchStatus};
+use crate::audio::{ControlledSource, DeviceCapabilities, Ducking, OutputControl, OutputHealth, PlaybackClock, StreamFormat};
+use crate::cache::CacheKeyMode;
+use crate::commands::{AppCommand, BatchJob, CommandQueue, GenerationJob, VariationsJob};
+use crate::content::{ContentMetadata, DurationLimit, LengthLevel, Sentence};
+use crate::config::{ApiKeys, AppSettings, Config, load_config, save_config, VOICES, SoundboardItem, KeyBindingConfig, JobSchedule, ScheduledJob, PronunciationEntry, FallbackSettings, DuckingSettings, GenerationOptions, GenerationPreset, PresetPrompt, WindowSettings, NetworkSettings, ContentFilterSettings, ExportSettings};
+use crate::error::AppError;
+use crate::export::{ClipInfo, ContentRecord};
+use crate::filter::FilterAction;
+use crate::history::InputHistory;
+use crate::hotkeys::{GlobalHotkeys, HotkeyEvent, HotkeyTarget, KeyCapture, KeyCombo, ShortcutAction, Shortcuts};
+use crate::naming::FileNameFields;
+use crate::monitor::{LowLatencyOutput, MonitorConfig};
+use crate::normalize::Lang;
+use crate::processing::{CachedPreview, ExportPreview, PreviewCache};
+use crate::prompt_macros::MacroContext;
+use crate::scheduler::Scheduler;
+use crate::setup::SetupWizard;
+use crate::soundboard::{PlayingSound, SoundboardEdit, UndoStack};
+use crate::stream::StreamReader;
+use crate::tasks::{TaskGuard, TaskId, TaskKind, TaskRegistry};
+use crate::toast::{Severity, Toasts};
+use crate::variations::{Variation, VariationStatus};
+use crate::wakeup::UiSender;
 
 // --- App State & Messages ---
 
@@ -17,48 +72,264 @@ enum AppState {
     Idle,
     GeneratingText,
     SynthesizingAudio,
+    SavingAudio,
 }
 
-impl AppState {
-    fn to_string(&self) -> String {
+impl fmt::Display for AppState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AppState::Idle => "就绪".to_string(),
-            AppState::GeneratingText => "正在生成文本...".to_string(),
-            AppState::SynthesizingAudio => "正在合成语音...".to_string(),
+            AppState::Idle => write!(f, "就绪"),
+            AppState::GeneratingText => write!(f, "正在生成文本..."),
+            AppState::SynthesizingAudio => write!(f, "正在合成语音..."),
+            AppState::SavingAudio => write!(f, "正在保存音频..."),
+        }
+    }
+}
+
+/// 文本区内容的来源，用于区分 AI 结果与用户改写，避免来回覆盖
+#[derive(Clone, Copy, PartialEq)]
+enum TextOrigin {
+    UserInput,
+    Ai,
+    Edited,
+    Imported,
+}
+
+impl fmt::Display for TextOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextOrigin::UserInput => write!(f, "输入原文"),
+            TextOrigin::Ai => write!(f, "AI 生成"),
+            TextOrigin::Edited => write!(f, "已手动编辑"),
+            TextOrigin::Imported => write!(f, "已导入"),
         }
     }
 }
 
 enum UIMessage {
-    UpdateState(AppState),
-    SetResponseText(String),
-    PlayTts(Vec<u8>),
-    PlaySound(Vec<u8>),
+    TaskStage(TaskId, AppState),
+    TaskFinished(TaskId),
+    SetResponseText(String, TextOrigin),
+    /// DeepSeek 流式返回中已收到的文本，完成前只用于显示
+    ResponseProgress(TaskId, String),
+    /// `streamed` 为 true 时音频已经边下载边播放，这里只记录结果
+    PlayTts { audio: Vec<u8>, info: ClipInfo, streamed: bool },
+    /// 流式下载的第一批数据已能解码，开始播放
+    StreamTts(Box<Decoder<StreamReader>>),
+    ContentImported(Vec<ContentRecord>),
+    /// 批量生成中第几行的新状态
+    BatchProgress(usize, BatchStatus),
+    /// 多版本生成中某个版本完成或失败
+    VariationFinished(usize, VariationStatus),
+    /// 第几次语音播放自然结束（不含手动停止和被新的播放替换）
+    PlaybackFinished(u64),
+    /// 从其他程序读到的选中文本，直接朗读
+    #[cfg_attr(not(feature = "selection"), allow(dead_code))]
+    SpeakText(String),
+    Notice(String),
+    /// 文件已写入，显示成功提示
+    Saved(String),
+    /// `momentary` 为 true 时松开快捷键即停止
+    PlaySound { index: usize, data: Vec<u8>, momentary: bool },
+    SoundAnalyzed { path: String, analysis: soundboard::SoundAnalysis },
+    /// 后台算好的保存前处理预览
+    ExportPreviewReady(Box<CachedPreview>),
+    KeyVerified { service: KeyService, result: Result<(), String> },
+    /// 重新连接后测试的结果和往返耗时
+    ConnectionTested(Result<ServerInfo, String>),
     Error(String),
 }
 
+/// “生成历史”面板中对某条记录的操作
+#[derive(Clone, Copy)]
+enum HistoryAction {
+    Load(usize),
+    Speak(usize),
+    Edit(usize),
+    Export(usize),
+}
+
+/// 句子导航：上一句、下一句或点选的某一句
+#[derive(Clone, Copy)]
+enum SentenceSkip {
+    Previous,
+    Next,
+    To(usize),
+}
+
+/// 需要验证密钥的服务
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KeyService {
+    DeepSeek,
+    Baidu,
+}
+
+/// “API 密钥”面板中尚未保存的输入和验证结果
+#[derive(Default)]
+struct ApiKeyForm {
+    keys: ApiKeys,
+    show: bool,
+    deepseek_status: Option<Result<(), String>>,
+    baidu_status: Option<Result<(), String>>,
+    verifying: Option<KeyService>,
+}
+
+impl ApiKeyForm {
+    fn new(keys: &ApiKeys) -> Self {
+        Self {
+            keys: keys.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn status_mut(&mut self, service: KeyService) -> &mut Option<Result<(), String>> {
+        match service {
+            KeyService::DeepSeek => &mut self.deepseek_status,
+            KeyService::Baidu => &mut self.baidu_status,
+        }
+    }
+}
+
+/// “定时播报”面板中正在填写的新任务
+struct NewJobForm {
+    text: String,
+    person: i32,
+    repeat: bool,
+    time: String,
+    minutes: u32,
+}
+
+impl Default for NewJobForm {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            person: 0,
+            repeat: false,
+            time: "12:00".to_string(),
+            minutes: 30,
+        }
+    }
+}
+
+/// 朗读文本框的 id，插入语气标记时用来读取光标位置
+const RESPONSE_TEXT_ID: &str = "response_text";
+const PROMPT_TEXT_ID: &str = "prompt_text";
+const CUSTOM_PROMPT_ID: &str = "custom_prompt";
+
+fn voice_name(person: i32) -> &'static str {
+    VOICES.iter().find(|&&(_, p)| p == person).map_or("未知", |(name, _)| name)
+}
+
+fn monitor_config(latency_ms: u32, buffer_frames: u32) -> MonitorConfig {
+    MonitorConfig {
+        latency: Duration::from_millis(latency_ms as u64),
+        buffer_frames: (buffer_frames > 0).then_some(buffer_frames),
+    }
+}
+
 // --- Main App Struct ---
 
 struct TTSApp {
-    rt: Runtime,
-    handle: Handle,
+    /// 只在关闭时取出并限时关停，后台工作都经由 `commands` 派发到这个运行时
+    rt: Option<Runtime>,
+    /// 生成、音效读取等后台工作的入口，见 `commands` 模块
+    commands: CommandQueue,
     prompt_text: String,
     response_text: String,
+    response_origin: TextOrigin,
+    /// 最近一次的结果或错误；有任务进行时状态栏改为显示任务
     status_text: String,
+    tasks: TaskRegistry,
+    toasts: Toasts,
+    /// 正在流式生成的任务及其已收到的文本
+    streaming_response: Option<(TaskId, String)>,
+    /// 下一帧把键盘焦点交给输入框；启动时为 true
+    focus_prompt: bool,
+    /// 上一帧是否有文本框持有焦点；Esc 只用于离开文本框，不触发“全部停止”
+    editing_text: bool,
+    /// 上次紧急停止的时间，用于合并同一次按键从应用内和全局两条路径先后到达的触发
+    stop_all_at: Option<Instant>,
+    #[cfg(feature = "clipboard")]
+    clipboard: Option<arboard::Clipboard>,
     config: Arc<Config>,
     api_client: Arc<ApiClient>,
-    ui_sender: mpsc::Sender<UIMessage>,
+    ui_sender: UiSender<UIMessage>,
     ui_receiver: mpsc::Receiver<UIMessage>,
     
     // --- Audio State ---
     audio_devices: Vec<rodio::cpal::Device>,
     audio_device_names: Vec<String>,
+    /// 与 `audio_devices` 一一对应，查询失败时为 `None`
+    audio_device_caps: Vec<Option<DeviceCapabilities>>,
     selected_device_index: usize,
     _stream: OutputStream,
     stream_handle: OutputStreamHandle,
+    output_health: OutputHealth,
+    /// 音效单独使用的输出流；`None` 时与语音共用 `stream_handle`
+    sound_stream: Option<(OutputStream, OutputStreamHandle)>,
+    /// 音效输出设备的名称，`None` 表示与语音相同
+    sound_device: Option<String>,
+    /// 音效同时输出到的监听设备
+    sound_monitor: Option<(OutputStream, OutputStreamHandle)>,
+    sound_monitor_device: Option<String>,
+    /// 用户在界面中选择的设备名称，写入配置；设备暂时断开时保留，下次启动仍会使用
+    tts_device_preference: String,
+    sound_device_preference: String,
+    sound_monitor_preference: String,
+    device_format: Option<StreamFormat>,
+    /// 最近一次播放的语音格式，用于提示与设备格式不一致
+    tts_format: Option<StreamFormat>,
     tts_sink: Sink,
-    sound_sinks: Vec<Sink>,
+    /// 开启低延迟监听时语音改由它播放，`tts_sink` 保持空闲
+    tts_monitor: Option<LowLatencyOutput>,
+    low_latency_monitor: bool,
+    monitor_latency_ms: u32,
+    /// 0 表示自动
+    monitor_buffer_frames: u32,
+    sound_sinks: Vec<PlayingSound>,
     last_tts_audio: Option<Arc<Vec<u8>>>,
+    last_tts_info: Option<ClipInfo>,
+    /// 合成过的内容（开启持久化时包括以前保存的），最早的在前，可整体导出为 JSON
+    session_records: Vec<ContentRecord>,
+    max_records: usize,
+    persist_records: bool,
+    /// 批量生成的输入：每行一段文本，可带发音人
+    batch_input: String,
+    batch_dir: String,
+    batch_filename_template: String,
+    /// 自动保存和手动保存音频的文件名模板
+    filename_template: String,
+    batch_concurrency: usize,
+    batch_rows: Vec<BatchRow>,
+    /// 多版本生成的版本数和结果
+    variation_count: usize,
+    variations: Vec<Variation>,
+    /// 正在进行的批次的取消标志
+    batch_cancel: Option<Arc<AtomicBool>>,
+    tts_output: Arc<OutputControl>,
+    sound_output: Arc<OutputControl>,
+    /// 语音播放时压低音效
+    ducking: Arc<Ducking>,
+    ducking_settings: DuckingSettings,
+    /// 界面中编辑的网络设置，点“重新连接”后生效
+    network: NetworkSettings,
+    keep_cache_on_reconnect: bool,
+    /// 最近一次连接测试的结果（服务信息或错误），`None` 表示还没有测试或正在测试
+    connection_status: Option<Result<ServerInfo, String>>,
+    /// 当前窗口的大小和位置，关闭时写入配置
+    window: WindowSettings,
+    /// 是否已检查过保存的窗口位置在当前显示器上是否可用
+    window_checked: bool,
+    /// 设置改动后等待写入的时间，0 为立即写入
+    settings_save_interval: Duration,
+    /// 第一次未写入的改动发生的时间
+    settings_dirty_since: Option<Instant>,
+    /// 最近一次写入 config.toml 的内容，内容没变时跳过写入
+    saved_settings: String,
+    /// 最近一次自动保存的时间，用于状态栏提示
+    auto_saved_at: Option<Instant>,
+    tts_clock: Arc<PlaybackClock>,
+    tts_sentences: Vec<Sentence>,
 
     // --- Audio Controls ---
     master_volume: f32,
@@ -66,405 +337,3961 @@ struct TTSApp {
     sound_volume: f32,
     is_tts_paused: bool,
     repeat_tts: bool,
+    /// 每次开始播放语音时加一，用来认出过时的 `PlaybackFinished`
+    tts_playback_id: u64,
+    limiter_enabled: bool,
+    auto_save: bool,
+    auto_save_dir: String,
+    soundboard_root: String,
+    stream_playback: bool,
+    warm_up_on_start: bool,
+    warm_up_phrase: String,
+    chime_enabled: bool,
+    chime_path: String,
+    chime_on_repeat: bool,
+    /// `chime_path` 指向的文件内容，为 `None` 时使用内置提示音
+    chime_data: Option<Arc<Vec<u8>>>,
+    /// 播放音效时按分析得到的响度调整增益
+    sound_normalize: bool,
+    sound_target_db: f32,
+    /// 合成音频的时长上限（秒，0 为不限）及超出时的处理方式
+    max_audio_seconds: u32,
+    duration_limit: DurationLimit,
+    power_saving: bool,
+    /// 输出流的采样率（Hz），0 为设备默认
+    output_sample_rate: u32,
+    read_selection_enabled: bool,
+    normalize_text: bool,
+    pronunciations: Vec<PronunciationEntry>,
+    fallback: FallbackSettings,
+    content_filter: ContentFilterSettings,
+    /// 屏蔽词的编辑框内容，每行一个，失去焦点时写回 `content_filter`
+    blocked_words_text: String,
+    /// 提示词变量（名称，值），按编辑顺序排列，保存时写入 `ai_settings.variables`
+    prompt_variables: Vec<(String, String)>,
+    export_settings: ExportSettings,
+    /// 保存前处理的预览，音频或设置变化后在后台重新计算
+    export_preview: PreviewCache,
+    log_level: log::LevelFilter,
 
     // --- TTS parameters ---
     speed: i32,
     pitch: i32,
     volume: i32,
     person: i32,
+    cache_key_mode: CacheKeyMode,
+    cache_namespace: String,
+    cache_max_entries: usize,
+    cache_max_memory_mb: usize,
+    force_resynthesize: bool,
     // --- AI control ---
     use_deepseek: bool,
+    /// 未配置 DeepSeek 密钥而改为直接朗读的提示只显示一次
+    deepseek_missing_noticed: bool,
     selected_prompt_index: usize,
     custom_prompt: String,
+    presets: Vec<GenerationPreset>,
+    prompt_history: InputHistory,
+    custom_prompt_history: InputHistory,
+    /// “保存为预设”输入框中的名称
+    preset_name: String,
     // --- Soundboard ---
     soundboard_items: Vec<SoundboardItem>,
+    held_sounds: HashSet<usize>,
+    soundboard_undo: UndoStack,
+    /// 音效列表中勾选的音效下标，删除或撤销后清空
+    sound_selection: BTreeSet<usize>,
+    /// 批量操作的输入值
+    bulk_category: String,
+    bulk_volume: f32,
+    scheduler: Scheduler,
+    new_job: NewJobForm,
+    key_form: ApiKeyForm,
+    global_hotkeys: GlobalHotkeys,
+    // --- Key bindings ---
+    key_bindings: KeyBindingConfig,
+    key_capture: KeyCapture,
+    shortcuts: Shortcuts,
 }
 
 impl TTSApp {
-    fn new(config: Config) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let (ui_sender, ui_receiver) = mpsc::channel();
+    fn new(config: Config, ctx: &egui::Context) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let (ui_sender, ui_receiver) = wakeup::channel(ctx);
         let speed = config.app_settings.speed;
         let pitch = config.app_settings.pitch;
         let volume = config.app_settings.volume;
         let person = config.app_settings.person;
-        let soundboard_items = config.soundboard.clone();
+        let auto_save = config.app_settings.auto_save;
+        let auto_save_dir = config.app_settings.auto_save_dir.clone();
+        let cache_key_mode = config.cache.key_mode;
+        let cache_namespace = config.cache.namespace.clone();
+        if config.cache.max_entries == 0 {
+            log::warn!("cache.max_entries 至少为 1，已按 1 处理");
+        }
+        let cache_max_entries = config.cache.max_entries.max(1);
+        let cache_max_memory_mb = config.cache.max_memory_mb;
+        let soundboard_root = config.app_settings.soundboard_root.clone();
+        let scheduler = Scheduler::new(&config.schedule, chrono::Local::now());
+        let stream_playback = config.app_settings.stream_playback;
+        let warm_up_on_start = config.app_settings.warm_up_on_start;
+        let warm_up_phrase = config.app_settings.warm_up_phrase.clone();
+        let chime_enabled = config.app_settings.chime_enabled;
+        let chime_path = config.app_settings.chime_path.clone();
+        let chime_on_repeat = config.app_settings.chime_on_repeat;
+        let sound_normalize = config.app_settings.sound_normalize;
+        let sound_target_db = config.app_settings.sound_target_db;
+        let max_audio_seconds = config.app_settings.max_audio_seconds;
+        let duration_limit = config.app_settings.duration_limit;
+        let power_saving = config.app_settings.power_saving;
+        let output_sample_rate = config.app_settings.output_sample_rate;
+        let read_selection_enabled = config.app_settings.read_selection_enabled;
+        let normalize_text = config.app_settings.normalize_text;
+        let pronunciations = config.pronunciations.clone();
+        let fallback = config.fallback.clone();
+        let content_filter = config.content_filter.clone();
+        let blocked_words_text = content_filter.blocked_words.join("\n");
+        let prompt_variables = config.ai_settings.variables.clone().into_iter().collect();
+        let export_settings = config.export.clone();
+        let ducking_settings = config.ducking.clone();
+        let ducking = Ducking::new();
+        apply_ducking(&ducking, &ducking_settings);
+        let presets = config.presets.clone();
+        let window = config.window.clone();
+        let network = config.network.clone();
+        let session_records = load_saved_records(&config.history);
+        let max_records = config.history.max_records.max(1);
+        let persist_records = config.history.persist_records;
+        let filename_template = config.app_settings.filename_template.clone();
+        let batch_filename_template = config.app_settings.batch_filename_template.clone();
+        let settings_save_interval = Duration::from_secs(config.app_settings.settings_save_interval_secs);
+        let saved_settings = toml::to_string_pretty(&config).unwrap_or_default();
+        let key_form = ApiKeyForm::new(&config.api_keys);
+        let log_level = logging::parse_level(&config.app_settings.log_level);
+        let mut soundboard_items = config.soundboard.clone();
+        soundboard::resolve_items(&soundboard::soundboard_root(&soundboard_root), &mut soundboard_items);
+        let key_bindings = config.key_bindings.clone();
+        let tts_output_device = config.app_settings.tts_output_device.clone();
+        let sound_output_device = config.app_settings.sound_output_device.clone();
+        let sound_monitor_device = config.app_settings.sound_monitor_device.clone();
 
         // --- Audio Device Initialization ---
         let host = rodio::cpal::default_host();
         let devices = host.output_devices()?.collect::<Vec<_>>();
-        let device_names = devices.iter().map(|d| d.name().unwrap_or_else(|_| "未知设备".to_string())).collect();
+        let device_names: Vec<String> = devices.iter().map(|d| d.name().unwrap_or_else(|_| "未知设备".to_string())).collect();
+        let device_caps = devices.iter().map(DeviceCapabilities::probe).collect();
         let default_device = host.default_output_device().ok_or("未找到默认音频输出设备")?;
         
-        let selected_device_index = devices.iter().position(|d| d.name().ok() == default_device.name().ok()).unwrap_or(0);
+        let default_index = devices.iter().position(|d| d.name().ok() == default_device.name().ok()).unwrap_or(0);
+        // 上次选择的设备已拔出时退回系统默认设备，但保留设置，下次插上后仍会使用
+        let selected_device_index = match device_names.iter().position(|name| !tts_output_device.is_empty() && *name == tts_output_device) {
+            Some(index) => index,
+            None => {
+                if !tts_output_device.is_empty() {
+                    log::warn!("未找到语音输出设备 {}，改用系统默认设备", tts_output_device);
+                }
+                default_index
+            }
+        };
 
-        let (_stream, stream_handle) = OutputStream::try_from_device(&devices[selected_device_index])?;
+        let output = audio::open_output_stream(&devices[selected_device_index], config.app_settings.output_sample_rate)?;
+        if let Some(adjusted) = &output.adjusted {
+            log::warn!("{}", adjusted);
+        }
+        let (_stream, stream_handle, device_format) = (output.stream, output.handle, Some(output.format));
+        let output_adjusted = output.adjusted;
         let tts_sink = Sink::try_new(&stream_handle)?;
+        let low_latency_monitor = config.app_settings.low_latency_monitor;
+        let monitor_latency_ms = config.app_settings.monitor_latency_ms;
+        let monitor_buffer_frames = config.app_settings.monitor_buffer_frames;
+        let tts_monitor = if low_latency_monitor {
+            LowLatencyOutput::open(&devices[selected_device_index], monitor_config(monitor_latency_ms, monitor_buffer_frames))
+                .inspect_err(|e| log::error!("打开低延迟输出失败: {}", e))
+                .ok()
+        } else {
+            None
+        };
         
         let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
-        let handle = rt.handle().clone();
+        let commands = CommandQueue::start(rt.handle(), ui_sender.clone());
 
-        Ok(Self {
-            rt,
-            handle,
+        let shortcuts = Shortcuts::from_config(&key_bindings);
+        let mut global_hotkeys = GlobalHotkeys::new();
+        global_hotkeys.wake_on_events(ctx);
+        global_hotkeys.set_read_selection_enabled(read_selection_enabled);
+        global_hotkeys.register_all(&key_bindings, &soundboard_items, &shortcuts);
+
+        let mut app = Self {
+            rt: Some(rt),
+            commands,
             prompt_text: "你好".to_string(),
             response_text: "".to_string(),
+            response_origin: TextOrigin::UserInput,
             status_text: AppState::Idle.to_string(),
+            tasks: TaskRegistry::default(),
+            toasts: Toasts::default(),
+            streaming_response: None,
+            focus_prompt: true,
+            editing_text: false,
+            stop_all_at: None,
+            #[cfg(feature = "clipboard")]
+            clipboard: None,
             custom_prompt: config.ai_settings.default_prompt.clone(),
+            presets,
+            prompt_history: InputHistory::new(config.history.prompts.clone(), config.history.max_entries),
+            custom_prompt_history: InputHistory::new(config.history.custom_prompts.clone(), config.history.max_entries),
+            preset_name: String::new(),
+            api_client: Arc::new(ApiClient::new(&config.network, &config.cache, &config.baidu, &config.ai_settings.deepseek_base_url)?),
             config: Arc::new(config),
-            api_client: Arc::new(ApiClient::new()),
             ui_sender,
             ui_receiver,
             audio_devices: devices,
             audio_device_names: device_names,
+            audio_device_caps: device_caps,
             selected_device_index,
             _stream,
             stream_handle,
+            output_health: OutputHealth::Connected,
+            sound_stream: None,
+            sound_device: None,
+            sound_monitor: None,
+            sound_monitor_device: None,
+            tts_device_preference: tts_output_device,
+            sound_device_preference: sound_output_device.clone(),
+            sound_monitor_preference: sound_monitor_device.clone(),
+            device_format,
+            tts_format: None,
             tts_sink,
+            tts_monitor,
+            low_latency_monitor,
+            monitor_latency_ms,
+            monitor_buffer_frames,
             sound_sinks: Vec::new(),
             last_tts_audio: None,
+            last_tts_info: None,
+            session_records,
+            max_records,
+            persist_records,
+            batch_input: String::new(),
+            batch_dir: String::new(),
+            batch_filename_template,
+            filename_template,
+            batch_concurrency: 2,
+            batch_rows: Vec::new(),
+            variation_count: 3,
+            variations: Vec::new(),
+            batch_cancel: None,
+            tts_output: OutputControl::new(1.0),
+            sound_output: OutputControl::new(0.5),
+            ducking,
+            ducking_settings,
+            network,
+            keep_cache_on_reconnect: true,
+            connection_status: None,
+            window,
+            window_checked: false,
+            settings_save_interval,
+            settings_dirty_since: None,
+            saved_settings,
+            auto_saved_at: None,
+            tts_clock: PlaybackClock::new(),
+            tts_sentences: Vec::new(),
             master_volume: 1.0,
             tts_volume: 1.0,
             sound_volume: 0.5,
             is_tts_paused: false,
             repeat_tts: false,
+            tts_playback_id: 0,
+            limiter_enabled: true,
+            auto_save,
+            auto_save_dir,
+            soundboard_root,
+            stream_playback,
+            warm_up_on_start,
+            warm_up_phrase,
+            chime_enabled,
+            chime_path,
+            chime_on_repeat,
+            chime_data: None,
+            sound_normalize,
+            sound_target_db,
+            max_audio_seconds,
+            duration_limit,
+            power_saving,
+            output_sample_rate,
+            read_selection_enabled,
+            normalize_text,
+            pronunciations,
+            fallback,
+            content_filter,
+            blocked_words_text,
+            prompt_variables,
+            export_settings,
+            export_preview: PreviewCache::default(),
+            log_level,
             speed,
             pitch,
             volume,
             person,
+            cache_key_mode,
+            cache_namespace,
+            cache_max_entries,
+            cache_max_memory_mb,
+            force_resynthesize: false,
             use_deepseek: true,
+            deepseek_missing_noticed: false,
             selected_prompt_index: 0,
             soundboard_items,
-        })
+            held_sounds: HashSet::new(),
+            soundboard_undo: UndoStack::default(),
+            sound_selection: BTreeSet::new(),
+            bulk_category: String::new(),
+            bulk_volume: 1.0,
+            scheduler,
+            new_job: NewJobForm::default(),
+            key_form,
+            global_hotkeys,
+            key_capture: KeyCapture::default(),
+            shortcuts,
+            key_bindings,
+        };
+        if !sound_output_device.is_empty() {
+            match app.audio_device_names.iter().position(|name| *name == sound_output_device) {
+                Some(index) => {
+                    if let Err(e) = app.set_sound_output_device(Some(index)) {
+                        log::error!("打开音效输出设备失败: {}", e);
+                    }
+                }
+                None => log::warn!("未找到音效输出设备 {}，与语音共用输出", sound_output_device),
+            }
+        }
+        if !sound_monitor_device.is_empty() {
+            match app.audio_device_names.iter().position(|name| *name == sound_monitor_device) {
+                Some(index) => {
+                    if let Err(e) = app.set_sound_monitor_device(Some(index)) {
+                        log::error!("打开音效监听设备失败: {}", e);
+                    }
+                }
+                None => log::warn!("未找到音效监听设备 {}", sound_monitor_device),
+            }
+        }
+        if let Some(adjusted) = output_adjusted {
+            app.show_warning(&adjusted);
+        }
+        app.load_chime();
+        app.analyze_sounds(false);
+        if app.warm_up_on_start {
+            app.warm_up();
+        }
+        if !app.config.api_keys.baidu_api_key.trim().is_empty() {
+            app.test_connection();
+        }
+        Ok(app)
+    }
+
+    /// 在后台合成一次预热短句，提前完成 DNS 解析、TLS 握手和 access token 获取；
+    /// 结果不播放也不写入缓存，失败时只记录日志
+    fn warm_up(&self) {
+        let phrase = self.warm_up_phrase.trim().to_string();
+        if phrase.is_empty() || self.config.api_keys.baidu_api_key.trim().is_empty() {
+            log::info!("未设置预热短句或百度密钥，跳过启动预热");
+            return;
+        }
+        self.dispatch(AppCommand::WarmUp {
+            api_client: self.api_client.clone(),
+            config: self.config.clone(),
+            phrase,
+            speed: self.speed,
+            pitch: self.pitch,
+            volume: self.volume,
+            person: self.person,
+        });
+    }
+
+    /// 读取自定义提示音；相对路径相对于配置文件所在目录，读取失败时退回内置提示音
+    fn load_chime(&mut self) {
+        self.chime_data = None;
+        if self.chime_path.trim().is_empty() {
+            return;
+        }
+        let path = config::config_dir().join(self.chime_path.trim());
+        let result = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| match Decoder::new(std::io::Cursor::new(data.clone())) {
+                Ok(_) => Ok(data),
+                Err(e) => Err(audio::describe_decode_error(&e)),
+            });
+        match result {
+            Ok(data) => self.chime_data = Some(Arc::new(data)),
+            Err(e) => {
+                log::warn!("无法加载提示音 {}: {}", path.display(), e);
+                self.show_warning(&format!("无法加载提示音 {}，改用内置提示音: {}", self.chime_path, e));
+            }
+        }
+    }
+
+    /// 播报前的提示音，经过语音一路的音量控制
+    fn chime_source(&self) -> Box<dyn Source<Item = f32> + Send> {
+        if let Some(data) = &self.chime_data {
+            if let Ok(decoder) = Decoder::new(std::io::Cursor::new(data.as_ref().clone())) {
+                return Box::new(ControlledSource::new(decoder.convert_samples(), self.tts_output.clone()));
+            }
+        }
+        Box::new(ControlledSource::new(audio::builtin_chime(), self.tts_output.clone()))
     }
 
-    fn play_tts_data(&self, data: Arc<Vec<u8>>) {
+    /// 播放 TTS 音频，返回解码器报告的总时长（MP3 可能无法得知）。
+    /// `with_chime` 为 true 且开启了提示音时先播放提示音
+    fn play_tts_data(&mut self, data: Arc<Vec<u8>>, with_chime: bool) -> Option<Duration> {
         let data_slice = data.as_ref().clone();
-        if let Ok(source) = Decoder::new(std::io::Cursor::new(data_slice)) {
-            self.tts_sink.clear();
-            self.tts_sink.append(source);
-            self.tts_sink.play();
-        } else {
-            log::error!("解码TTS音频失败");
+        match Decoder::new(std::io::Cursor::new(data_slice)) {
+            Ok(source) => {
+                let total_duration = source.total_duration();
+                self.play_tts_source(source, with_chime);
+                total_duration
+            }
+            Err(e) => {
+                log::error!("解码TTS音频失败: {}", e);
+                let _ = self.ui_sender.send(UIMessage::Error(format!("无法播放合成的语音: {}", audio::describe_decode_error(&e))));
+                None
+            }
         }
     }
 
-    fn play_sound_data(&mut self, data: Vec<u8>) {
-        if let Ok(source) = Decoder::new(std::io::Cursor::new(data)) {
-            if let Ok(sink) = Sink::try_new(&self.stream_handle) {
-                sink.append(source);
-                self.sound_sinks.push(sink);
+    fn play_tts_source<R>(&mut self, source: Decoder<R>, with_chime: bool)
+    where
+        R: std::io::Read + std::io::Seek + Send + Sync + 'static,
+    {
+        if !self.ensure_output() {
+            return;
+        }
+        self.tts_format = Some(StreamFormat::of_source(&source));
+        self.tts_sink.clear();
+        self.tts_playback_id += 1;
+        let (playback_id, sender) = (self.tts_playback_id, self.ui_sender.clone());
+        let source = ControlledSource::new(source.convert_samples(), self.tts_output.clone())
+            .with_clock(self.tts_clock.clone())
+            .with_level_meter(self.ducking.clone())
+            .with_end_notification(move || {
+                let _ = sender.send(UIMessage::PlaybackFinished(playback_id));
+            });
+        let chime = (with_chime && self.chime_enabled).then(|| self.chime_source());
+        if let Some(monitor) = &self.tts_monitor {
+            match chime {
+                Some(chime) => {
+                    let speech: Box<dyn Source<Item = f32> + Send> = Box::new(source);
+                    monitor.play(rodio::source::from_iter([chime, speech]));
+                }
+                None => monitor.play(source),
             }
-        } else {
-            log::error!("解码音效失败");
+            return;
         }
+        // 提示音排在语音之前，语音的播放进度从提示音结束后才开始计算
+        if let Some(chime) = chime {
+            self.tts_sink.append(chime);
+        }
+        self.tts_sink.append(source);
+        self.tts_sink.play();
     }
 
-    fn change_output_device(&mut self, device_index: usize) -> Result<(), Box<dyn std::error::Error>> {
-        if device_index == self.selected_device_index || device_index >= self.audio_devices.len() {
-            return Ok(());
+    /// 播放、后台任务进行中时持续重绘（低功耗模式下限制帧率），好让进度、电平和流式文字
+    /// 及时更新；空闲时只按间隔醒来检查定时任务，不再每帧都请求重绘。鼠标和键盘输入、
+    /// 后台消息（见 `wakeup` 模块）和全局快捷键总会立即触发重绘
+    fn schedule_repaint(&self, ctx: &egui::Context) {
+        let playing = !self.tts_idle() && !self.is_tts_paused;
+        let busy = playing || !self.sound_sinks.is_empty() || !self.tasks.is_empty();
+        match (busy, self.power_saving) {
+            (true, false) => ctx.request_repaint(),
+            (true, true) => ctx.request_repaint_after(POWER_SAVING_FRAME_INTERVAL),
+            (false, false) => ctx.request_repaint_after(IDLE_POLL_INTERVAL),
+            (false, true) => ctx.request_repaint_after(POWER_SAVING_IDLE_POLL_INTERVAL),
         }
-        
-        self.tts_sink.stop();
-        self.sound_sinks.clear();
+    }
 
-        let device = &self.audio_devices[device_index];
-        let (_stream, stream_handle) = OutputStream::try_from_device(device)?;
-        self.tts_sink = Sink::try_new(&stream_handle)?;
-        
-        self._stream = _stream;
-        self.stream_handle = stream_handle;
-        self.selected_device_index = device_index;
+    /// 语音播放已结束（两种输出路径都没有待播放的内容）
+    fn tts_idle(&self) -> bool {
+        self.tts_sink.empty() && self.tts_monitor.as_ref().is_none_or(LowLatencyOutput::is_idle)
+    }
 
-        Ok(())
+    fn set_tts_paused(&mut self, paused: bool) {
+        if paused {
+            self.ducking.reset_level();
+        }
+        if let Some(monitor) = &self.tts_monitor {
+            monitor.set_paused(paused);
+        } else if paused {
+            self.tts_sink.pause();
+        } else {
+            self.tts_sink.play();
+        }
+        self.is_tts_paused = paused;
     }
 
-    fn handle_ui_messages(&mut self) {
-        while let Ok(msg) = self.ui_receiver.try_recv() {
-            match msg {
-                UIMessage::UpdateState(state) => self.status_text = state.to_string(),
-                UIMessage::SetResponseText(text) => self.response_text = text,
-                UIMessage::Error(e) => self.status_text = format!("错误: {}", e),
-                UIMessage::PlayTts(audio_data) => {
-                    self.status_text = AppState::Idle.to_string();
-                    self.is_tts_paused = false;
-                    let audio_arc = Arc::new(audio_data);
-                    self.last_tts_audio = Some(audio_arc.clone());
-                    self.play_tts_data(audio_arc);
-                }
-                UIMessage::PlaySound(audio_data) => {
-                    self.play_sound_data(audio_data);
+    fn stop_tts(&mut self) {
+        self.tts_sink.stop();
+        self.ducking.reset_level();
+        if let Some(monitor) = &self.tts_monitor {
+            monitor.stop();
+        }
+    }
+
+    /// 按当前设置打开或关闭低延迟输出，正在播放的语音会停止
+    fn reopen_monitor(&mut self) {
+        self.stop_tts();
+        self.tts_monitor = None;
+        if self.low_latency_monitor {
+            let config = monitor_config(self.monitor_latency_ms, self.monitor_buffer_frames);
+            match LowLatencyOutput::open(&self.audio_devices[self.selected_device_index], config) {
+                Ok(monitor) => self.tts_monitor = Some(monitor),
+                Err(e) => {
+                    log::error!("打开低延迟输出失败: {}", e);
+                    self.low_latency_monitor = false;
+                    self.show_error(format!("无法打开低延迟输出，已恢复普通播放 ({})", e));
                 }
             }
         }
     }
 
-    fn start_generation_task(&mut self) {
-        let sender = self.ui_sender.clone();
-        let api_client = self.api_client.clone();
-        let config = self.config.clone();
-        let prompt_text = self.prompt_text.clone();
-        let speed = self.speed;
-        let pitch = self.pitch;
-        let volume = self.volume;
-        let person = self.person;
-        let use_deepseek = self.use_deepseek;
-
-        let system_prompt = if self.selected_prompt_index == self.config.ai_settings.prompts.len() {
-            self.custom_prompt.clone()
-        } else {
-            self.config.ai_settings.prompts[self.selected_prompt_index].template.clone()
+    fn current_sentence(&self) -> Option<usize> {
+        if self.tts_idle() {
+            return None;
+        }
+        content::sentence_at(&self.tts_sentences, self.tts_clock.position())
+    }
+
+    fn seek_to_sentence(&mut self, index: usize) {
+        let Some(start) = self.tts_sentences.get(index).map(|sentence| sentence.start) else {
+            return;
         };
+        if self.tts_monitor.is_some() {
+            self.show_error("低延迟监听模式下不支持跳转".to_string());
+            return;
+        }
+        if self.tts_sink.empty() {
+            if let Some(audio) = self.last_tts_audio.clone() {
+                self.play_tts_data(audio, false);
+            }
+        }
+        if let Err(e) = self.tts_sink.try_seek(start) {
+            log::warn!("跳转到第 {} 句失败: {}", index + 1, e);
+            self.show_error(format!("当前音频不支持跳转 ({})", e));
+        }
+    }
 
-        self.rt.spawn(async move {
-            let text_to_speak = if use_deepseek {
-                sender.send(UIMessage::UpdateState(AppState::GeneratingText)).unwrap();
-                match api_client.call_deepseek_api(&config.api_keys.deepseek_api_key, &system_prompt, &prompt_text).await {
-                    Ok(text) => {
-                        sender.send(UIMessage::SetResponseText(text.clone())).unwrap();
-                        text
-                    }
-                    Err(e) => {
-                        sender.send(UIMessage::Error(format!("DeepSeek: {}", e))).unwrap();
-                        return;
-                    }
-                }
-            } else {
-                sender.send(UIMessage::SetResponseText(prompt_text.clone())).unwrap();
-                prompt_text
-            };
+    /// 跳到上一句、下一句或点选的句子，前后跳转的边界见 `content::skip_target`。
+    /// 低延迟监听模式下由 `seek_to_sentence` 提示不支持。暂停中跳转后仍保持暂停，继续播放时从新的位置开始
+    fn skip_sentence(&mut self, skip: SentenceSkip) {
+        let target = match skip {
+            SentenceSkip::Next => content::skip_target(self.current_sentence(), self.tts_sentences.len(), true),
+            SentenceSkip::Previous => content::skip_target(self.current_sentence(), self.tts_sentences.len(), false),
+            SentenceSkip::To(index) => (index < self.tts_sentences.len()).then_some(index),
+        };
+        let Some(target) = target else {
+            return;
+        };
+        self.seek_to_sentence(target);
+        if self.is_tts_paused {
+            self.tts_sink.pause();
+        }
+    }
 
-            if text_to_speak.trim().is_empty() {
-                sender.send(UIMessage::Error("无有效文本".to_string())).unwrap();
+    /// “上一句/下一句”按钮是否可用：低延迟监听模式不支持跳转，已在最后一句时不能再向后
+    fn can_skip_sentence(&self, forward: bool) -> bool {
+        self.tts_monitor.is_none() && content::skip_target(self.current_sentence(), self.tts_sentences.len(), forward).is_some()
+    }
+
+    fn play_sound_data(&mut self, index: usize, data: Vec<u8>, momentary: bool) {
+        // 文件读取完成前快捷键已经松开
+        if momentary && !self.held_sounds.contains(&index) {
+            return;
+        }
+        let name = self.soundboard_items.get(index).map_or("未知音效", |item| item.path.as_str()).to_string();
+        let gain = self.sound_gain(index);
+        let monitor_data = self.sound_monitor.is_some().then(|| data.clone());
+        let source = match Decoder::new(std::io::Cursor::new(data)) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("解码音效 {} 失败: {}", name, e);
+                let _ = self.ui_sender.send(UIMessage::Error(format!("无法播放音效 {}: {}", name, audio::describe_decode_error(&e))));
                 return;
             }
-
-            sender.send(UIMessage::UpdateState(AppState::SynthesizingAudio)).unwrap();
-            match api_client.call_baidu_tts_api(&config.api_keys, &text_to_speak, speed, pitch, volume, person).await {
-                Ok(audio_data) => sender.send(UIMessage::PlayTts(audio_data)).unwrap(),
-                Err(e) => sender.send(UIMessage::Error(format!("BaiduTTS: {}", e))).unwrap(),
+        };
+        if !self.ensure_sound_output() {
+            return;
+        }
+        // 流在检查之后才失效时，重连一次再试
+        let sink = Sink::try_new(self.sound_handle()).or_else(|e| {
+            log::warn!("创建音效播放器失败，尝试重新连接输出设备: {}", e);
+            if self.sound_stream.is_some() {
+                self.reopen_sound_output().map_err(|_| e)?;
+            } else {
+                self.output_health = OutputHealth::Lost(e.to_string());
+                self.reconnect_output().map_err(|_| e)?;
             }
+            Sink::try_new(self.sound_handle())
         });
+        match sink {
+            Ok(sink) => {
+                let clock = PlaybackClock::new();
+                sink.append(
+                    ControlledSource::new(source.convert_samples().amplify(gain), self.sound_output.clone())
+                        .with_clock(clock.clone())
+                        .with_ducking(self.ducking.clone()),
+                );
+                let monitor = monitor_data.and_then(|data| self.play_on_sound_monitor(data, gain));
+                self.sound_sinks.push(PlayingSound { index, momentary, sink, monitor, clock });
+            }
+            Err(e) => {
+                log::error!("创建音效播放器失败: {}", e);
+                let _ = self.ui_sender.send(UIMessage::Error(format!("无法播放音效 {}: {}", name, e)));
+            }
+        }
     }
-}
 
-// --- Eframe App Implementation ---
+    fn sound_handle(&self) -> &OutputStreamHandle {
+        self.sound_stream.as_ref().map_or(&self.stream_handle, |(_, handle)| handle)
+    }
 
-impl eframe::App for TTSApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // --- Process background messages & state updates ---
-        self.handle_ui_messages();
-        self.sound_sinks.retain(|s| !s.empty());
+    /// 把音效改到指定设备（`None` 为与语音相同）上播放。正在播放的音效会停止，
+    /// 音效音量由 `sound_output` 控制，不随输出流重建而改变
+    fn set_sound_output_device(&mut self, device_index: Option<usize>) -> Result<(), AppError> {
+        self.sound_sinks.clear();
+        self.held_sounds.clear();
+        let Some(index) = device_index.filter(|&i| i < self.audio_devices.len()) else {
+            self.sound_stream = None;
+            self.sound_device = None;
+            return Ok(());
+        };
+        let output = audio::open_output_stream(&self.audio_devices[index], self.output_sample_rate).map_err(|e| AppError::Audio(e.to_string()))?;
+        if let Some(adjusted) = &output.adjusted {
+            self.show_warning(&format!("音效输出: {}", adjusted));
+        }
+        self.sound_stream = Some((output.stream, output.handle));
+        self.sound_device = Some(self.audio_device_names[index].clone());
+        log::info!("音效输出设备: {}", self.audio_device_names[index]);
+        Ok(())
+    }
 
-        if self.repeat_tts && self.tts_sink.empty() {
-            if let Some(audio) = self.last_tts_audio.clone() {
-                self.play_tts_data(audio);
+    /// 把音效同时输出到监听设备（`None` 为不输出）
+    fn set_sound_monitor_device(&mut self, device_index: Option<usize>) -> Result<(), AppError> {
+        for sound in &mut self.sound_sinks {
+            sound.monitor = None;
+        }
+        self.sound_monitor = None;
+        self.sound_monitor_device = None;
+        let Some(index) = device_index.filter(|&i| i < self.audio_devices.len()) else {
+            return Ok(());
+        };
+        let output = audio::open_output_stream(&self.audio_devices[index], self.output_sample_rate).map_err(|e| AppError::Audio(e.to_string()))?;
+        if let Some(adjusted) = &output.adjusted {
+            self.show_warning(&format!("音效监听: {}", adjusted));
+        }
+        self.sound_monitor = Some((output.stream, output.handle));
+        self.sound_monitor_device = Some(self.audio_device_names[index].clone());
+        log::info!("音效监听设备: {}", self.audio_device_names[index]);
+        Ok(())
+    }
+
+    /// 监听副本只是方便自己听到，失败时记录日志而不影响主输出
+    fn play_on_sound_monitor(&self, data: Vec<u8>, gain: f32) -> Option<Sink> {
+        let (_, handle) = self.sound_monitor.as_ref()?;
+        let result = Decoder::new(std::io::Cursor::new(data))
+            .map_err(|e| e.to_string())
+            .and_then(|source| Sink::try_new(handle).map(|sink| (sink, source)).map_err(|e| e.to_string()));
+        match result {
+            Ok((sink, source)) => {
+                sink.append(ControlledSource::new(source.convert_samples().amplify(gain), self.sound_output.clone()).with_ducking(self.ducking.clone()));
+                Some(sink)
+            }
+            Err(e) => {
+                log::warn!("音效监听输出失败: {}", e);
+                None
             }
         }
-        
-        self.tts_sink.set_volume(self.master_volume * self.tts_volume);
-        for sink in &self.sound_sinks {
-            sink.set_volume(self.master_volume * self.sound_volume);
+    }
+
+    /// 音效设备断开后按名称重新打开；设备已不存在时改为与语音共用输出
+    fn reopen_sound_output(&mut self) -> Result<(), AppError> {
+        self.refresh_devices();
+        let name = self.sound_device.clone();
+        let index = self.audio_device_names.iter().position(|n| Some(n) == name.as_ref());
+        if index.is_none() {
+            self.show_warning(&format!("音效输出设备 {} 已断开，改为与语音相同", name.as_deref().unwrap_or("")));
         }
+        self.set_sound_output_device(index)
+    }
 
-        let mut new_device_index_to_set = None;
+    /// 播放音效前确认其输出设备可用
+    fn ensure_sound_output(&mut self) -> bool {
+        let Some(name) = self.sound_device.clone() else {
+            return self.ensure_output();
+        };
+        let index = self.audio_device_names.iter().position(|n| *n == name);
+        let available = index.is_some_and(|i| audio::check_device(&self.audio_devices[i]) == OutputHealth::Connected);
+        if available {
+            return true;
+        }
+        log::warn!("音效输出设备 {} 不可用，尝试重新连接", name);
+        match self.reopen_sound_output() {
+            Ok(()) => self.sound_stream.is_some() || self.ensure_output(),
+            Err(e) => {
+                self.show_error(format!("音效输出设备不可用: {}", e));
+                false
+            }
+        }
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.heading("TTSmate");
-            ui.separator();
+    fn change_output_device(&mut self, device_index: usize) -> Result<(), AppError> {
+        if device_index == self.selected_device_index || device_index >= self.audio_devices.len() {
+            return Ok(());
+        }
+        self.open_output(device_index)?;
+        self.tts_device_preference = self.audio_device_names[device_index].clone();
+        self.save_settings();
+        Ok(())
+    }
 
-            // --- AI Controls ---
-            ui.collapsing("AI 设置", |ui| {
-                ui.checkbox(&mut self.use_deepseek, "使用 DeepSeek 生成文案");
-                let prompts = &self.config.ai_settings.prompts;
-                let mut prompt_names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
-                prompt_names.push("自定义模板");
-                ui.horizontal(|ui| {
-                    ui.label("提示词模板:");
-                    egui::ComboBox::from_id_source("prompt_template_combobox")
-                        .selected_text(prompt_names[self.selected_prompt_index])
-                        .show_ui(ui, |ui| {
-                            for (i, name) in prompt_names.iter().enumerate() {
-                                ui.selectable_value(&mut self.selected_prompt_index, i, *name);
-                            }
-                        });
-                });
-                if self.selected_prompt_index == prompts.len() {
-                    ui.label("自定义提示词:");
-                    ui.text_edit_multiline(&mut self.custom_prompt);
-                }
-            });
-            ui.separator();
+    /// 在指定设备上重建语音输出流。正在播放的语音会停止；
+    /// 音效只有与语音共用输出时才会随之停止
+    fn open_output(&mut self, device_index: usize) -> Result<(), AppError> {
+        self.stop_tts();
+        if self.sound_stream.is_none() {
+            self.sound_sinks.clear();
+        }
 
-            // --- Main Input ---
-            ui.horizontal(|ui| {
-                ui.label("输入话题/文本:");
-                ui.text_edit_singleline(&mut self.prompt_text);
-            });
+        let output = audio::open_output_stream(&self.audio_devices[device_index], self.output_sample_rate)
+            .map_err(|e| AppError::Audio(e.to_string()))?;
+        self.tts_sink = Sink::try_new(&output.handle).map_err(|e| AppError::Audio(e.to_string()))?;
+        if let Some(adjusted) = &output.adjusted {
+            self.show_warning(adjusted);
+        }
+
+        self._stream = output.stream;
+        self.stream_handle = output.handle;
+        self.selected_device_index = device_index;
+        self.device_format = Some(output.format);
+        self.output_health = OutputHealth::Connected;
+        if self.low_latency_monitor {
+            self.reopen_monitor();
+        }
+
+        Ok(())
+    }
+
+    /// 按新的采样率重新打开语音、音效和音效监听的输出流
+    fn reopen_outputs(&mut self) {
+        if let Err(e) = self.open_output(self.selected_device_index) {
+            log::error!("重新打开输出设备失败: {}", e);
+            self.show_error(format!("重新打开输出设备失败: {}", e));
+        }
+        let index_of = |name: &Option<String>| self.audio_device_names.iter().position(|n| Some(n) == name.as_ref());
+        let (sound, monitor) = (index_of(&self.sound_device), index_of(&self.sound_monitor_device));
+        if let Err(e) = self.set_sound_output_device(sound) {
+            self.show_error(format!("重新打开音效输出设备失败: {}", e));
+        }
+        if let Err(e) = self.set_sound_monitor_device(monitor) {
+            self.show_error(format!("重新打开音效监听设备失败: {}", e));
+        }
+    }
 
-            let is_running_task = self.status_text != AppState::Idle.to_string() && !self.status_text.starts_with("错误:");
-            if ui.add_enabled(!is_running_task, egui::Button::new("生成并播放")).clicked() {
-                self.start_generation_task();
+    /// 重新枚举输出设备，保持当前选择（按名称）；原设备已不存在时返回 `None`
+    fn refresh_devices(&mut self) -> Option<usize> {
+        let host = rodio::cpal::default_host();
+        let devices = match host.output_devices() {
+            Ok(devices) => devices.collect::<Vec<_>>(),
+            Err(e) => {
+                log::error!("枚举音频设备失败: {}", e);
+                return None;
             }
+        };
+        // 一个设备都没有时保留旧列表，界面仍能显示原来的选择
+        if devices.is_empty() {
+            log::warn!("没有找到任何音频输出设备");
+            return None;
+        }
+        let current_name = self.audio_device_names.get(self.selected_device_index).cloned();
+        self.audio_device_names = devices.iter().map(|d| d.name().unwrap_or_else(|_| "未知设备".to_string())).collect();
+        self.audio_device_caps = devices.iter().map(DeviceCapabilities::probe).collect();
+        self.audio_devices = devices;
+        let current = self.audio_device_names.iter().position(|name| Some(name) == current_name.as_ref());
+        // 列表变化后原下标可能越界，先指向一个存在的设备
+        self.selected_device_index = current.unwrap_or(0).min(self.audio_devices.len().saturating_sub(1));
+        current
+    }
 
-            // --- Audio Playback Controls ---
-            ui.collapsing("音频设置", |ui| {
-                // Device Selection
-                let selected_name = self.audio_device_names[self.selected_device_index].clone();
-                egui::ComboBox::from_label("输出设备")
-                    .selected_text(selected_name)
-                    .show_ui(ui, |ui| {
-                        for (i, device_name) in self.audio_device_names.iter().enumerate() {
-                            if ui.selectable_label(self.selected_device_index == i, device_name).clicked() {
-                                new_device_index_to_set = Some(i);
-                            }
-                        }
-                    });
-                
-                ui.separator();
-                
-                // Volume Controls
-                ui.add(egui::Slider::new(&mut self.master_volume, 0.0..=1.5).text("主音量"));
-                ui.add(egui::Slider::new(&mut self.tts_volume, 0.0..=1.5).text("语音音量"));
-                ui.add(egui::Slider::new(&mut self.sound_volume, 0.0..=1.5).text("音效音量"));
-                
-                ui.separator();
+    /// 在原设备（若仍存在）或系统默认设备上重新打开输出流
+    fn reconnect_output(&mut self) -> Result<(), AppError> {
+        let index = match self.refresh_devices() {
+            Some(index) => index,
+            None => {
+                let default_name = rodio::cpal::default_host().default_output_device().and_then(|d| d.name().ok());
+                self.audio_device_names
+                    .iter()
+                    .position(|name| Some(name) == default_name.as_ref())
+                    .ok_or_else(|| AppError::Audio("未找到可用的音频输出设备".to_string()))?
+            }
+        };
+        self.open_output(index)?;
+        log::info!("已重新连接输出设备: {}", self.audio_device_names[index]);
+        self.show_warning(&format!("输出设备已重新连接: {}", self.audio_device_names[index]));
+        Ok(())
+    }
 
-                // Play/Pause/Repeat Controls
-                ui.horizontal(|ui| {
-                    let tts_button_text = if self.is_tts_paused { "▶ 播放" } else { "⏸ 暂停" };
-                    if ui.add_enabled(!self.tts_sink.empty(), egui::Button::new(tts_button_text)).clicked() {
-                        if self.tts_sink.is_paused() {
-                            self.tts_sink.play();
-                            self.is_tts_paused = false;
-                        } else {
-                            self.tts_sink.pause();
-                            self.is_tts_paused = true;
-                        }
-                    }
-                    ui.checkbox(&mut self.repeat_tts, "循环播放");
-                });
-            });
-            ui.separator();
+    /// 播放前确认输出设备仍然可用，断开时尝试重连；返回能否继续播放
+    fn ensure_output(&mut self) -> bool {
+        let health = audio::check_device(&self.audio_devices[self.selected_device_index]);
+        if health == OutputHealth::Connected && self.output_health == OutputHealth::Connected {
+            return true;
+        }
+        if let OutputHealth::Lost(reason) = &health {
+            log::warn!("输出设备不可用: {}", reason);
+        }
+        self.output_health = health;
+        self.try_reconnect_output()
+    }
 
-            // --- TTS Parameter Controls ---
-            ui.collapsing("语音参数", |ui| {
+    fn try_reconnect_output(&mut self) -> bool {
+        match self.reconnect_output() {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("重新连接输出设备失败: {}", e);
+                self.output_health = OutputHealth::Lost(e.to_string());
+                self.show_error(format!("音频输出设备不可用: {}", e));
+                false
+            }
+        }
+    }
+
+    fn handle_ui_messages(&mut self) {
+        while let Ok(msg) = self.ui_receiver.try_recv() {
+            match msg {
+                UIMessage::TaskStage(id, stage) => self.tasks.update(id, stage),
+                UIMessage::TaskFinished(id) => {
+                    self.tasks.finish(id);
+                    if self.streaming_response.as_ref().is_some_and(|(task, _)| *task == id) {
+                        self.streaming_response = None;
+                    }
+                }
+                UIMessage::SetResponseText(text, origin) => {
+                    self.streaming_response = None;
+                    self.response_text = text;
+                    self.response_origin = origin;
+                }
+                UIMessage::ResponseProgress(id, text) => self.streaming_response = Some((id, text)),
+                UIMessage::Error(e) => self.show_error(e),
+                UIMessage::PlayTts { audio, info, streamed } => {
+                    self.status_text = AppState::Idle.to_string();
+                    let audio_arc = Arc::new(audio);
+                    self.last_tts_audio = Some(audio_arc.clone());
+                    if self.auto_save {
+                        self.auto_save_audio(audio_arc.clone(), &info);
+                    }
+                    let total_duration = if streamed {
+                        Decoder::new(std::io::Cursor::new(audio_arc.as_ref().clone())).ok().and_then(|d| d.total_duration())
+                    } else {
+                        self.is_tts_paused = false;
+                        self.play_tts_data(audio_arc, true)
+                    };
+                    self.tts_sentences = content::split_sentences(&info.text, self.speed, total_duration);
+                    self.push_records([ContentRecord::new(info.clone(), self.speed, self.pitch, self.volume, self.person)]);
+                    self.last_tts_info = Some(info);
+                }
+                UIMessage::ContentImported(records) => {
+                    if let Some(record) = records.last() {
+                        self.load_record(&record.clone());
+                    }
+                    self.show_success(&format!("已导入 {} 条内容", records.len()));
+                    self.push_records(records);
+                }
+                UIMessage::BatchProgress(index, status) => {
+                    if let Some(row) = self.batch_rows.get_mut(index) {
+                        row.status = status;
+                    }
+                }
+                // 播放中途重新开始或换成其他语音时，旧的结束通知不再触发循环
+                UIMessage::PlaybackFinished(id) if id == self.tts_playback_id && self.repeat_tts => {
+                    if let Some(audio) = self.last_tts_audio.clone() {
+                        self.play_tts_data(audio, self.chime_on_repeat);
+                    }
+                }
+                UIMessage::PlaybackFinished(_) => {}
+                UIMessage::VariationFinished(index, status) => {
+                    if let Some(variation) = self.variations.get_mut(index) {
+                        variation.status = status;
+                    }
+                }
+                UIMessage::SpeakText(text) => self.speak_text(text),
+                UIMessage::Notice(text) => self.show_notice(&text),
+                UIMessage::Saved(path) => self.show_success(&format!("已保存到 {}", path)),
+                UIMessage::StreamTts(decoder) => {
+                    self.is_tts_paused = false;
+                    self.play_tts_source(*decoder, true);
+                }
+                UIMessage::PlaySound { index, data, momentary } => {
+                    self.play_sound_data(index, data, momentary);
+                }
+                UIMessage::ConnectionTested(result) => {
+                    match &result {
+                        Ok(info) => {
+                            log::info!("连接测试成功: {}（百度权限: {}）", info.summary(), info.baidu_scopes.join(" "));
+                            for warning in info.warnings() {
+                                log::warn!("{}", warning);
+                                self.show_warning(&warning);
+                            }
+                        }
+                        Err(e) => log::warn!("连接测试失败: {}", e),
+                    }
+                    self.connection_status = Some(result);
+                }
+                UIMessage::KeyVerified { service, result } => {
+                    if self.key_form.verifying == Some(service) {
+                        self.key_form.verifying = None;
+                    }
+                    *self.key_form.status_mut(service) = Some(result);
+                }
+                UIMessage::ExportPreviewReady(preview) => self.export_preview.finish(*preview),
+                UIMessage::SoundAnalyzed { path, analysis } => {
+                    let mut changed = false;
+                    for item in self.soundboard_items.iter_mut().filter(|item| item.path == path) {
+                        item.duration_ms = Some(analysis.duration.as_millis() as u64);
+                        item.loudness_db = Some(analysis.loudness_db);
+                        item.waveform = analysis.waveform.clone();
+                        item.analyzed_mtime = analysis.mtime;
+                        changed = true;
+                    }
+                    if changed {
+                        self.save_settings();
+                    }
+                }
+            }
+        }
+    }
+
+    /// 在后台解码尚未分析或文件已被替换的音效（`all` 为 true 时重新分析全部），
+    /// 结果通过 `UIMessage::SoundAnalyzed` 写回
+    fn analyze_sounds(&self, all: bool) {
+        let paths = self
+            .soundboard_items
+            .iter()
+            .filter(|item| {
+                all || item.duration_ms.is_none()
+                    || item.loudness_db.is_none()
+                    || item.waveform.is_empty()
+                    || item.analyzed_mtime != soundboard::modified_time(&item.path)
+            })
+            .map(|item| item.path.clone())
+            .collect();
+        self.analyze_paths(paths);
+    }
+
+    fn analyze_paths(&self, mut paths: Vec<String>) {
+        paths.retain(|path| self.soundboard_items.iter().any(|item| item.path == *path && !item.missing));
+        paths.sort();
+        paths.dedup();
+        if !paths.is_empty() {
+            self.dispatch(AppCommand::AnalyzeSounds { paths });
+        }
+    }
+
+    /// 播放该音效的线性增益：响度均衡（开启且已分析时）乘以音效自己的音量
+    fn sound_gain(&self, index: usize) -> f32 {
+        let Some(item) = self.soundboard_items.get(index) else {
+            return 1.0;
+        };
+        match item.loudness_db {
+            Some(loudness_db) if self.sound_normalize => {
+                audio::db_to_gain(soundboard::normalization_gain_db(loudness_db, self.sound_target_db)) * item.volume
+            }
+            _ => item.volume,
+        }
+    }
+
+    fn trigger_sound(&mut self, index: usize, momentary: bool) {
+        let Some(item) = self.soundboard_items.get(index) else {
+            return;
+        };
+        if item.missing {
+            let message = format!("音效文件不存在: {}", item.path);
+            self.show_warning(&message);
+            return;
+        }
+        let path = item.path.clone();
+        self.dispatch(AppCommand::LoadSound { index, path, momentary });
+    }
+
+    fn handle_global_hotkeys(&mut self, ctx: &egui::Context) {
+        for event in self.global_hotkeys.poll() {
+            match event {
+                // 窗口在前台时应用内快捷键通常已经处理了这次按键，由 `emergency_stop` 合并；
+                // 正在编辑文本时绑定在 Esc 上的组合只用于离开文本框，与应用内快捷键相同
+                HotkeyEvent::Pressed(HotkeyTarget::StopAll) => {
+                    let escape = KeyCombo::parse(&self.key_bindings.stop_all).is_ok_and(|combo| combo.key == egui::Key::Escape);
+                    if !(escape && self.editing_text && ctx.input(|i| i.focused)) {
+                        self.emergency_stop();
+                    }
+                }
+                HotkeyEvent::Pressed(HotkeyTarget::SpeakClipboard) => self.speak_clipboard(),
+                // 等快捷键松开再模拟复制，否则按住的 Alt 等修饰键会和 Ctrl+C 组合在一起
+                HotkeyEvent::Released(HotkeyTarget::ReadSelection) => self.read_selection(),
+                HotkeyEvent::Pressed(HotkeyTarget::ReadSelection)
+                | HotkeyEvent::Released(HotkeyTarget::StopAll | HotkeyTarget::SpeakClipboard) => {}
+                HotkeyEvent::Pressed(HotkeyTarget::Sound(index)) => {
+                    let momentary = self.soundboard_items.get(index).is_some_and(|item| item.momentary);
+                    if !momentary {
+                        self.trigger_sound(index, false);
+                    } else if self.held_sounds.insert(index) {
+                        // 长按时的重复按下事件不会重新触发
+                        self.trigger_sound(index, true);
+                    }
+                }
+                HotkeyEvent::Released(HotkeyTarget::Sound(index)) => {
+                    if self.held_sounds.remove(&index) {
+                        self.sound_sinks.retain(|sound| !(sound.momentary && sound.index == index));
+                    }
+                }
+            }
+        }
+    }
+
+    /// 本次运行中的第几段合成结果；`session_records` 会在保存之后才加入这一段
+    fn file_name_fields<'a>(&self, info: &'a ClipInfo) -> FileNameFields<'a> {
+        FileNameFields {
+            index: self.session_records.len() + 1,
+            created_at: info.created_at,
+            voice: &info.voice,
+            text: &info.text,
+        }
+    }
+
+    /// 选择位置保存最近一次合成的音频；开启了保存前处理时保存处理后的 WAV，与试听的完全相同
+    fn save_audio(&mut self) {
+        let Some(audio_data) = self.last_tts_audio.clone() else {
+            return;
+        };
+        let processed = if self.export_settings.enabled {
+            match self.export_preview() {
+                Some(Ok(preview)) => Some(preview.wav.clone()),
+                Some(Err(e)) => {
+                    let message = format!("保存前处理失败: {}", e);
+                    self.show_error(message);
+                    return;
+                }
+                None => {
+                    self.show_warning("保存前处理尚未完成，请稍后再保存");
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        let info = self.last_tts_info.clone();
+        let file_name = match &info {
+            // 最近一段已经加入了生成历史
+            Some(info) => naming::render(&self.filename_template, &FileNameFields {
+                index: self.session_records.len(),
+                ..self.file_name_fields(info)
+            }),
+            None => "tts_audio.mp3".to_string(),
+        };
+        let (filter, extension, file_name) = match processed {
+            Some(_) => ("WAV Audio", "wav", format!("{}.wav", file_name.trim_end_matches(".mp3"))),
+            None => ("MPEG Audio", "mp3", file_name),
+        };
+        let commands = self.commands.clone();
+        let sender = self.ui_sender.clone();
+        let task = TaskGuard::new(self.tasks.start(TaskKind::SaveAudio, AppState::SavingAudio), sender.clone());
+        std::thread::spawn(move || {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter(filter, &[extension])
+                .set_file_name(file_name)
+                .save_file()
+            {
+                if !commands.submit(AppCommand::SaveAudio { task, path, audio: audio_data, processed, info }) {
+                    let _ = sender.send(UIMessage::Error("保存失败: 后台任务过多，请稍后重试".to_string()));
+                }
+            }
+            // 取消对话框时 task 在这里丢弃，任务随之结束
+        });
+    }
+
+    /// 最近一次合成的音频按保存前处理设置处理后的预览。音频或设置变化后交给后台重新计算，
+    /// 算好之前返回 `None`
+    fn export_preview(&mut self) -> Option<&Result<ExportPreview, String>> {
+        let audio = self.last_tts_audio.clone()?;
+        if self.export_preview.start(&audio, &self.export_settings)
+            && !self.commands.submit(AppCommand::ExportPreview { audio: audio.clone(), settings: self.export_settings.clone() })
+        {
+            self.export_preview.abort();
+        }
+        self.export_preview.get(&audio, &self.export_settings)
+    }
+
+    /// 按文件名模板（`filename_template`）命名，把音频异步写入自动保存目录
+    fn auto_save_audio(&self, audio: Arc<Vec<u8>>, info: &ClipInfo) {
+        if self.auto_save_dir.is_empty() {
+            let _ = self.ui_sender.send(UIMessage::Error("自动保存失败: 未选择保存目录".to_string()));
+            return;
+        }
+        let file_name = naming::render(&self.filename_template, &self.file_name_fields(info));
+        let dir = PathBuf::from(&self.auto_save_dir);
+        self.dispatch(AppCommand::AutoSave { dir, file_name, audio, info: info.clone() });
+    }
+
+    /// 首次使用时才打开系统剪贴板，失败时在状态栏报告
+    #[cfg(feature = "clipboard")]
+    fn clipboard(&mut self) -> Option<&mut arboard::Clipboard> {
+        if self.clipboard.is_none() {
+            match arboard::Clipboard::new() {
+                Ok(clipboard) => self.clipboard = Some(clipboard),
+                Err(e) => self.show_error(format!("无法访问剪贴板: {}", e)),
+            }
+        }
+        self.clipboard.as_mut()
+    }
+
+    /// 用当前语音参数直接朗读剪贴板中的文本，超过百度长度上限的部分会被截掉
+    #[cfg(feature = "clipboard")]
+    fn speak_clipboard(&mut self) {
+        let Some(clipboard) = self.clipboard() else {
+            return;
+        };
+        let text = match clipboard.get_text() {
+            Ok(text) if content::is_speakable(&text) => text,
+            Ok(_) | Err(arboard::Error::ContentNotAvailable) => {
+                self.show_warning("剪贴板中没有可朗读的文本");
+                return;
+            }
+            Err(e) => {
+                self.show_error(format!("读取剪贴板失败: {}", e));
+                return;
+            }
+        };
+        self.speak_text(text);
+    }
+
+    #[cfg(not(feature = "clipboard"))]
+    fn speak_clipboard(&mut self) {
+        self.show_warning("此版本未启用剪贴板功能");
+    }
+
+    /// 不经过 AI，用当前语音参数直接朗读外部来源的文本
+    fn speak_text(&mut self, text: String) {
+        // 这里不限制长度，超出百度上限的部分由 `GenerationJob::run` 在合成前截掉
+        let text = text.trim().to_string();
+        if self.tasks.generations_full() {
+            self.show_warning(&format!("已有 {} 个任务进行中，请稍后再试", tasks::MAX_CONCURRENT_GENERATIONS));
+            return;
+        }
+        self.response_text = text.clone();
+        self.response_origin = TextOrigin::UserInput;
+        self.spawn_generation(TaskKind::Speak, text, String::new(), false, self.person);
+    }
+
+    /// 在后台模拟复制，读取当前焦点程序中选中的文本后朗读
+    #[cfg(feature = "selection")]
+    fn read_selection(&mut self) {
+        if !self.read_selection_enabled {
+            return;
+        }
+        let sender = self.ui_sender.clone();
+        std::thread::spawn(move || {
+            let message = match selection::read_selected_text() {
+                Ok(text) if content::is_speakable(&text) => UIMessage::SpeakText(text),
+                Ok(_) => UIMessage::Notice("选中的内容没有可朗读的文本".to_string()),
+                Err(e) => UIMessage::Notice(e),
+            };
+            let _ = sender.send(message);
+        });
+    }
+
+    #[cfg(not(feature = "selection"))]
+    fn read_selection(&mut self) {
+        self.show_warning("此版本未启用朗读选中文本功能");
+    }
+
+    /// 把最近一次合成的音频写入临时文件并放入剪贴板
+    #[cfg(feature = "clipboard")]
+    fn copy_audio_to_clipboard(&mut self) {
+        let Some(audio) = self.last_tts_audio.clone() else {
+            return;
+        };
+        let data = match &self.last_tts_info {
+            Some(info) => export::tag_mp3(&audio, info),
+            None => audio.to_vec(),
+        };
+        let dir = std::env::temp_dir().join("ttsmate");
+        let path = dir.join(format!("tts_{}.mp3", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+        if let Err(e) = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(&path, data)) {
+            self.show_error(format!("复制音频失败: {}", e));
+            return;
+        }
+
+        let Some(clipboard) = self.clipboard() else {
+            return;
+        };
+        match utils::copy_file_to_clipboard(clipboard, &path) {
+            Ok(utils::ClipboardCopy::File) => self.show_success("已复制音频"),
+            Ok(utils::ClipboardCopy::Path) => self.show_success("已复制音频文件路径"),
+            Err(e) => self.show_error(format!("复制音频失败: {}", e)),
+        }
+    }
+
+    /// 把最近一次合成的音频编码为 data URI 放入剪贴板，便于直接写进网页的 `<audio>` 标签
+    #[cfg(feature = "clipboard")]
+    fn copy_audio_data_uri(&mut self) {
+        let Some(audio) = self.last_tts_audio.clone() else {
+            return;
+        };
+        let uri = export::data_uri(&audio);
+        let Some(clipboard) = self.clipboard() else {
+            return;
+        };
+        if let Err(e) = clipboard.set_text(uri.as_str()) {
+            self.show_error(format!("复制失败: {}", e));
+            return;
+        }
+        let megabytes = uri.len() as f32 / (1024.0 * 1024.0);
+        if uri.len() > LARGE_DATA_URI_BYTES {
+            self.show_warning(&format!("已复制数据 URI（{:.1} MB），体积较大，嵌入网页会明显拖慢加载，建议改用音频文件", megabytes));
+        } else {
+            self.show_success(&format!("已复制数据 URI（{:.0} KB）", uri.len() as f32 / 1024.0));
+        }
+    }
+
+    /// 加入生成历史，超出上限时丢弃最早的
+    fn push_records(&mut self, records: impl IntoIterator<Item = ContentRecord>) {
+        self.session_records.extend(records);
+        if self.session_records.len() > self.max_records {
+            self.session_records.drain(..self.session_records.len() - self.max_records);
+        }
+    }
+
+    /// 把一条记录的提示词、文本和语音参数放回界面，便于修改后重新生成或朗读
+    fn load_record(&mut self, record: &ContentRecord) {
+        self.prompt_text = record.info.prompt.clone();
+        self.response_text = record.info.text.clone();
+        self.response_origin = TextOrigin::Imported;
+        self.speed = record.speed;
+        self.pitch = record.pitch;
+        self.volume = record.volume;
+        self.person = record.person;
+    }
+
+    /// 逐行合成批量输入并保存到 `batch_dir`，同时进行的行数不超过 `batch_concurrency`。
+    /// 取消后尚未开始的行标记为已取消，已经开始的行会完成
+    fn start_batch(&mut self) {
+        let lines = batch::parse_lines(&self.batch_input);
+        if lines.is_empty() {
+            self.show_warning("请先输入要批量生成的文本，每行一段");
+            return;
+        }
+        if self.batch_dir.is_empty() {
+            self.show_warning("请先选择保存目录");
+            return;
+        }
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.batch_cancel = Some(cancel.clone());
+        self.batch_rows = lines.iter().cloned().map(BatchRow::new).collect();
+        let task = TaskGuard::new(self.tasks.start(TaskKind::Batch, AppState::SynthesizingAudio), self.ui_sender.clone());
+        self.dispatch(AppCommand::Batch(Box::new(BatchJob {
+            task,
+            api_client: self.api_client.clone(),
+            config: self.config.clone(),
+            lines,
+            dir: PathBuf::from(&self.batch_dir),
+            template: self.batch_filename_template.clone(),
+            cancel,
+            concurrency: self.batch_concurrency,
+            default_person: self.person,
+            speed: self.speed,
+            pitch: self.pitch,
+            volume: self.volume,
+            normalize_text: self.normalize_text,
+            use_cache: !self.force_resynthesize,
+        })));
+    }
+
+    fn apply_history_action(&mut self, action: HistoryAction) {
+        match action {
+            HistoryAction::Load(i) => self.load_record(&self.session_records[i].clone()),
+            HistoryAction::Speak(i) => {
+                self.load_record(&self.session_records[i].clone());
+                self.start_speak_response_task();
+            }
+            HistoryAction::Edit(i) => {
+                self.load_record(&self.session_records[i].clone());
+                self.focus_prompt = true;
+            }
+            HistoryAction::Export(i) => self.export_record_list(vec![self.session_records[i].clone()], false),
+        }
+    }
+
+    /// 导出最近一条或全部生成记录
+    fn export_records(&self, whole_session: bool) {
+        let records = if whole_session {
+            self.session_records.clone()
+        } else {
+            self.session_records.last().cloned().into_iter().collect()
+        };
+        self.export_record_list(records, whole_session);
+    }
+
+    /// 文件对话框在独立线程中打开；`whole_session` 为 false 时导出为单条记录
+    fn export_record_list(&self, records: Vec<ContentRecord>, whole_session: bool) {
+        let sender = self.ui_sender.clone();
+        std::thread::spawn(move || {
+            let file_name = if whole_session { "tts_session.json" } else { "tts_content.json" };
+            let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).set_file_name(file_name).save_file() else {
+                return;
+            };
+            let result = match records.as_slice() {
+                [record] if !whole_session => export::export_content(record, &path),
+                records => export::export_session(records, &path),
+            };
+            let _ = match result {
+                Ok(()) => sender.send(UIMessage::Notice(format!("已导出到 {}", path.display()))),
+                Err(e) => sender.send(UIMessage::Error(format!("导出失败: {}", e))),
+            };
+        });
+    }
+
+    fn import_records(&self) {
+        let sender = self.ui_sender.clone();
+        std::thread::spawn(move || {
+            let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).pick_file() else {
+                return;
+            };
+            let _ = match export::import_content(&path) {
+                Ok(records) if !records.is_empty() => sender.send(UIMessage::ContentImported(records)),
+                Ok(_) => sender.send(UIMessage::Error("导入失败: 文件中没有内容".to_string())),
+                Err(e) => sender.send(UIMessage::Error(format!("导入失败: {}", e))),
+            };
+        });
+    }
+
+    /// 记录窗口当前的大小和位置；启动后第一次得知显示器大小时，如果与保存位置时的显示器不同
+    /// （例如副屏已拔掉），保存的位置可能在屏幕外，改为居中显示
+    fn track_window(&mut self, ctx: &egui::Context) {
+        let (inner, outer, monitor, maximized) = ctx.input(|i| {
+            let viewport = i.viewport();
+            (viewport.inner_rect, viewport.outer_rect, viewport.monitor_size, viewport.maximized)
+        });
+        if let (false, Some(monitor)) = (self.window_checked, monitor) {
+            self.window_checked = true;
+            let saved_monitor = self.window.monitor_width.zip(self.window.monitor_height);
+            if self.window.position().is_some() && saved_monitor != Some((monitor.x, monitor.y)) {
+                log::info!("显示器已变化，窗口改为居中显示");
+                if let Some(command) = egui::ViewportCommand::center_on_screen(ctx) {
+                    ctx.send_viewport_cmd(command);
+                }
+            }
+        }
+        self.window.maximized = maximized.unwrap_or(false);
+        // 最大化时保留还原后的大小和位置
+        if self.window.maximized {
+            return;
+        }
+        if let Some(inner) = inner {
+            self.window.width = inner.width();
+            self.window.height = inner.height();
+        }
+        if let Some(outer) = outer {
+            self.window.x = Some(outer.min.x);
+            self.window.y = Some(outer.min.y);
+        }
+        if let Some(monitor) = monitor {
+            self.window.monitor_width = Some(monitor.x);
+            self.window.monitor_height = Some(monitor.y);
+        }
+    }
+
+    fn save_settings(&mut self) {
+        let config = Arc::make_mut(&mut self.config);
+        config.key_bindings = self.key_bindings.clone();
+        config.schedule = self.scheduler.to_config();
+        config.pronunciations = self.pronunciations.clone();
+        config.fallback = self.fallback.clone();
+        config.content_filter = self.content_filter.clone();
+        config.export = self.export_settings.clone();
+        config.ai_settings.variables = self
+            .prompt_variables
+            .iter()
+            .filter(|(name, _)| !name.trim().is_empty())
+            .map(|(name, value)| (name.trim().to_string(), value.clone()))
+            .collect();
+        config.ducking = self.ducking_settings.clone();
+        config.window = self.window.clone();
+        config.presets = self.presets.clone();
+        config.history.prompts = self.prompt_history.entries().to_vec();
+        config.history.custom_prompts = self.custom_prompt_history.entries().to_vec();
+        config.history.persist_records = self.persist_records;
+        config.soundboard = soundboard::relativize_items(&soundboard::soundboard_root(&self.soundboard_root), &self.soundboard_items);
+        config.app_settings.soundboard_root = self.soundboard_root.clone();
+        config.app_settings.stream_playback = self.stream_playback;
+        config.app_settings.tts_output_device = self.tts_device_preference.clone();
+        config.app_settings.sound_output_device = self.sound_device_preference.clone();
+        config.app_settings.sound_monitor_device = self.sound_monitor_preference.clone();
+        config.app_settings.warm_up_on_start = self.warm_up_on_start;
+        config.app_settings.warm_up_phrase = self.warm_up_phrase.clone();
+        config.app_settings.chime_enabled = self.chime_enabled;
+        config.app_settings.chime_path = self.chime_path.clone();
+        config.app_settings.chime_on_repeat = self.chime_on_repeat;
+        config.app_settings.sound_normalize = self.sound_normalize;
+        config.app_settings.sound_target_db = self.sound_target_db;
+        config.app_settings.max_audio_seconds = self.max_audio_seconds;
+        config.app_settings.duration_limit = self.duration_limit;
+        config.app_settings.power_saving = self.power_saving;
+        config.app_settings.output_sample_rate = self.output_sample_rate;
+        config.app_settings.read_selection_enabled = self.read_selection_enabled;
+        config.app_settings.normalize_text = self.normalize_text;
+        config.app_settings.log_level = self.log_level.as_str().to_lowercase();
+        config.app_settings.low_latency_monitor = self.low_latency_monitor;
+        config.app_settings.monitor_latency_ms = self.monitor_latency_ms;
+        config.app_settings.monitor_buffer_frames = self.monitor_buffer_frames;
+        config.app_settings.auto_save = self.auto_save;
+        config.app_settings.auto_save_dir = self.auto_save_dir.clone();
+        config.app_settings.filename_template = self.filename_template.clone();
+        config.app_settings.batch_filename_template = self.batch_filename_template.clone();
+        config.cache.key_mode = self.cache_key_mode;
+        config.cache.namespace = self.cache_namespace.clone();
+        config.cache.max_entries = self.cache_max_entries;
+        config.cache.max_memory_mb = self.cache_max_memory_mb;
+        if self.settings_save_interval.is_zero() {
+            self.write_settings();
+        } else {
+            self.settings_dirty_since.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// 把 `self.config` 写入 config.toml，内容与上次写入的相同时跳过；返回是否实际写入
+    fn write_settings(&mut self) -> bool {
+        self.settings_dirty_since = None;
+        let snapshot = toml::to_string_pretty(self.config.as_ref()).unwrap_or_default();
+        if snapshot == self.saved_settings {
+            return false;
+        }
+        match save_config(&self.config) {
+            Ok(()) => {
+                self.saved_settings = snapshot;
+                true
+            }
+            Err(e) => {
+                log::error!("保存配置失败: {}", e);
+                self.show_error(e.to_string());
+                false
+            }
+        }
+    }
+
+    /// 改动积累到设定的间隔后写入一次
+    fn auto_save_settings(&mut self) {
+        let Some(since) = self.settings_dirty_since else { return };
+        if since.elapsed() >= self.settings_save_interval && self.write_settings() {
+            log::debug!("设置已自动保存");
+            self.auto_saved_at = Some(Instant::now());
+        }
+    }
+
+    /// 把当前的提示词和语音参数保存为预设，同名预设会被覆盖
+    fn save_preset(&mut self) {
+        let name = self.preset_name.trim().to_string();
+        let prompt = match self.config.ai_settings.prompts.get(self.selected_prompt_index) {
+            Some(template) => PresetPrompt::Template { name: template.name.clone() },
+            None => PresetPrompt::Custom { prompt: self.custom_prompt.clone() },
+        };
+        let preset = GenerationPreset {
+            name: name.clone(),
+            prompt,
+            options: GenerationOptions {
+                use_deepseek: self.use_deepseek,
+                speed: self.speed,
+                pitch: self.pitch,
+                volume: self.volume,
+                person: self.person,
+            },
+        };
+        match self.presets.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+        self.preset_name.clear();
+        self.save_settings();
+        self.show_success(&format!("已保存预设“{}”", name));
+    }
+
+    fn apply_preset(&mut self, index: usize) {
+        let Some(preset) = self.presets.get(index).cloned() else { return };
+        match &preset.prompt {
+            PresetPrompt::Template { name } => {
+                match self.config.ai_settings.prompts.iter().position(|p| &p.name == name) {
+                    Some(i) => self.selected_prompt_index = i,
+                    None => self.show_warning(&format!("预设中的模板“{}”已不存在，保留当前模板", name)),
+                }
+            }
+            PresetPrompt::Custom { prompt } => {
+                self.selected_prompt_index = self.config.ai_settings.prompts.len();
+                self.custom_prompt = prompt.clone();
+            }
+        }
+        let options = preset.options;
+        self.use_deepseek = options.use_deepseek;
+        self.speed = options.speed;
+        self.pitch = options.pitch;
+        self.volume = options.volume;
+        self.person = options.person;
+    }
+
+    /// 短暂显示一条提示，不影响状态栏
+    fn show_notice(&mut self, text: &str) {
+        self.toasts.push(Severity::Info, text);
+    }
+
+    fn show_success(&mut self, text: &str) {
+        self.toasts.push(Severity::Success, text);
+    }
+
+    fn show_warning(&mut self, text: &str) {
+        self.toasts.push(Severity::Warning, text);
+    }
+
+    /// 错误既弹出提示，也留在状态栏中直到下一次成功
+    fn show_error(&mut self, text: String) {
+        self.status_text = format!("错误: {}", text);
+        self.toasts.push(Severity::Error, text);
+    }
+
+    /// “停止全部”快捷键。带修饰键的绑定同时是全局快捷键，窗口在前台时同一次按键在有的平台上
+    /// 会从应用内和全局两条路径先后到达，间隔很短的第二次触发直接忽略
+    fn emergency_stop(&mut self) {
+        if self.stop_all_at.is_some_and(|at| at.elapsed() < STOP_ALL_DEBOUNCE) {
+            return;
+        }
+        self.stop_all_at = Some(Instant::now());
+        self.stop_all_audio();
+        self.show_notice("已全部停止");
+    }
+
+    fn stop_all_audio(&mut self) {
+        self.stop_tts();
+        self.sound_sinks.clear();
+        self.held_sounds.clear();
+        self.repeat_tts = false;
+        self.is_tts_paused = false;
+    }
+
+    fn handle_shortcut(&mut self, action: ShortcutAction) {
+        match action {
+            ShortcutAction::TtsGenerate | ShortcutAction::AiGenerate => {
+                if !self.tasks.is_running(TaskKind::Generate) && !self.tasks.generations_full() {
+                    self.start_generation_task(action == ShortcutAction::AiGenerate);
+                }
+            }
+            ShortcutAction::StopAll => self.emergency_stop(),
+            ShortcutAction::VolumeUp => self.master_volume = (self.master_volume + 0.1).min(1.5),
+            ShortcutAction::VolumeDown => self.master_volume = (self.master_volume - 0.1).max(0.0),
+            ShortcutAction::Undo => self.undo_soundboard_edit(),
+            ShortcutAction::SpeakClipboard => self.speak_clipboard(),
+            ShortcutAction::ReadSelection => self.read_selection(),
+        }
+    }
+
+    /// 移除音效后，播放中和按住中的音效序号需要随之前移
+    fn remove_sound(&mut self, index: usize) {
+        if index >= self.soundboard_items.len() {
+            return;
+        }
+        let item = self.take_sound(index);
+        self.soundboard_undo.push(SoundboardEdit::Removed { index, item });
+    }
+
+    /// 询问后删除勾选的音效，可以一次撤销
+    fn remove_selected_sounds(&mut self) {
+        let answer = rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Warning)
+            .set_title("删除音效")
+            .set_description(format!("确定从音效板删除选中的 {} 个音效吗？音效文件不会被删除，可以用“撤销”恢复。", self.sound_selection.len()))
+            .set_buttons(rfd::MessageButtons::YesNo)
+            .show();
+        if answer != rfd::MessageDialogResult::Yes {
+            return;
+        }
+        let selection = std::mem::take(&mut self.sound_selection);
+        // 从后往前删，前面的下标不受影响
+        let mut items = Vec::new();
+        let count = self.soundboard_items.len();
+        for index in selection.into_iter().rev().filter(|&index| index < count) {
+            items.push((index, self.take_sound(index)));
+        }
+        items.reverse();
+        self.soundboard_undo.push(SoundboardEdit::BulkRemoved { items });
+    }
+
+    /// 从音效板移除一个音效并停止其播放，修正其余音效的下标
+    fn take_sound(&mut self, index: usize) -> SoundboardItem {
+        let item = self.soundboard_items.remove(index);
+        self.sound_sinks.retain(|sound| sound.index != index);
+        for sound in &mut self.sound_sinks {
+            if sound.index > index {
+                sound.index -= 1;
+            }
+        }
+        self.held_sounds = self.held_sounds.iter().filter(|&&i| i != index).map(|&i| if i > index { i - 1 } else { i }).collect();
+        self.sound_selection.clear();
+        item
+    }
+
+    fn insert_sound(&mut self, index: usize, item: SoundboardItem) {
+        let index = index.min(self.soundboard_items.len());
+        self.soundboard_items.insert(index, item);
+        for sound in &mut self.sound_sinks {
+            if sound.index >= index {
+                sound.index += 1;
+            }
+        }
+        self.held_sounds = self.held_sounds.iter().map(|&i| if i >= index { i + 1 } else { i }).collect();
+        self.sound_selection.clear();
+    }
+
+    fn undo_soundboard_edit(&mut self) {
+        let Some(edit) = self.soundboard_undo.pop() else {
+            return;
+        };
+        let label = edit.label();
+        match edit {
+            SoundboardEdit::Removed { index, item } => self.insert_sound(index, item),
+            SoundboardEdit::BulkRemoved { items } => {
+                for (index, item) in items {
+                    self.insert_sound(index, item);
+                }
+            }
+            SoundboardEdit::Recategorized { previous } => {
+                for (index, category) in previous {
+                    if let Some(item) = self.soundboard_items.get_mut(index) {
+                        item.category = category;
+                    }
+                }
+            }
+            SoundboardEdit::Unbound { index, hotkey } => {
+                if let Some(item) = self.soundboard_items.get_mut(index) {
+                    item.hotkey = hotkey;
+                }
+            }
+            SoundboardEdit::CategoryDeleted { category, indices } => {
+                for index in indices {
+                    if let Some(item) = self.soundboard_items.get_mut(index) {
+                        item.category = category.clone();
+                    }
+                }
+            }
+        }
+        self.global_hotkeys.register_all(&self.key_bindings, &self.soundboard_items, &self.shortcuts);
+        self.save_settings();
+        self.show_notice(&format!("已撤销: {}", label));
+    }
+
+    /// 按界面中的网络设置重建 API 客户端并测试连接，结果通过 `ConnectionTested` 回到界面。
+    /// 新设置在重建成功后才写入配置
+    fn reconnect(&mut self) {
+        let client = match self.api_client.reconnect(&self.network, self.keep_cache_on_reconnect) {
+            Ok(client) => Arc::new(client),
+            Err(e) => {
+                self.connection_status = Some(Err(e.to_string()));
+                self.show_error(format!("重新连接失败: {}", e));
+                return;
+            }
+        };
+        log::info!("已按新的网络设置重建客户端（{}缓存）", if self.keep_cache_on_reconnect { "保留" } else { "清空" });
+        self.api_client = client.clone();
+        Arc::make_mut(&mut self.config).network = self.network.clone();
+        self.save_settings();
+        self.test_connection();
+    }
+
+    /// 后台获取一次百度访问令牌和 DeepSeek 模型列表，结果通过 `ConnectionTested` 回到界面
+    fn test_connection(&mut self) {
+        self.connection_status = None;
+        self.dispatch(AppCommand::TestConnection { api_client: self.api_client.clone(), api_keys: self.config.api_keys.clone() });
+    }
+
+    /// 后台发出一次最小的鉴权请求，结果通过 `KeyVerified` 回到界面
+    fn verify_keys(&mut self, service: KeyService) {
+        self.key_form.verifying = Some(service);
+        *self.key_form.status_mut(service) = None;
+        self.dispatch(AppCommand::VerifyKeys { api_client: self.api_client.clone(), service, keys: self.key_form.keys.clone() });
+    }
+
+    fn save_api_keys(&mut self) {
+        let keys = ApiKeys {
+            deepseek_api_key: self.key_form.keys.deepseek_api_key.trim().to_string(),
+            baidu_api_key: self.key_form.keys.baidu_api_key.trim().to_string(),
+            baidu_secret_key: self.key_form.keys.baidu_secret_key.trim().to_string(),
+        };
+        Arc::make_mut(&mut self.config).api_keys = keys;
+        logging::set_secrets(config_secrets(&self.config));
+        self.api_client.clear_token_cache();
+        self.deepseek_missing_noticed = false;
+        self.save_settings();
+        self.show_success("API 密钥已保存");
+    }
+
+    fn deepseek_configured(&self) -> bool {
+        !self.config.api_keys.deepseek_api_key.trim().is_empty()
+    }
+
+    /// 未配置 DeepSeek 时退回直接朗读，并在第一次退回时提示
+    fn resolve_use_deepseek(&mut self, use_deepseek: bool) -> bool {
+        if !use_deepseek || self.deepseek_configured() {
+            return use_deepseek;
+        }
+        if !self.deepseek_missing_noticed {
+            self.deepseek_missing_noticed = true;
+            self.show_warning("未配置 DeepSeek API 密钥，已直接朗读输入的文本");
+        }
+        false
+    }
+
+    /// 当前选择的模板（或自定义提示词）
+    fn system_prompt(&self) -> String {
+        if self.selected_prompt_index == self.config.ai_settings.prompts.len() {
+            self.custom_prompt.clone()
+        } else {
+            self.config.ai_settings.prompts[self.selected_prompt_index].template.clone()
+        }
+    }
+
+    fn start_generation_task(&mut self, use_deepseek: bool) {
+        let use_deepseek = self.resolve_use_deepseek(use_deepseek);
+        let custom = self.selected_prompt_index == self.config.ai_settings.prompts.len();
+        let system_prompt = self.system_prompt();
+        let mut history_changed = self.prompt_history.push(&self.prompt_text);
+        if custom && use_deepseek {
+            history_changed |= self.custom_prompt_history.push(&self.custom_prompt);
+        }
+        if history_changed {
+            self.save_settings();
+        }
+        if !use_deepseek {
+            self.response_text = self.prompt_text.clone();
+            self.response_origin = TextOrigin::UserInput;
+        }
+        self.spawn_generation(TaskKind::Generate, self.prompt_text.clone(), system_prompt, use_deepseek, self.person);
+    }
+
+    /// 对输入框中的话题以不同温度各生成一版并合成，结果留在“多版本生成”面板中试听和采用。
+    /// 同时进行的版本数沿用批量生成的设置
+    fn start_variations(&mut self) {
+        if !self.deepseek_configured() {
+            self.show_warning("多版本生成需要 DeepSeek API Key");
+            return;
+        }
+        if self.prompt_text.trim().is_empty() {
+            self.show_warning("请先输入话题");
+            return;
+        }
+        let temperatures = variations::temperatures(self.variation_count);
+        self.variations = temperatures.iter().map(|&t| Variation::new(t)).collect();
+        let task = TaskGuard::new(self.tasks.start(TaskKind::Variations, AppState::GeneratingText), self.ui_sender.clone());
+        // 各版本使用同一次展开的提示词，时间类的宏不会因先后完成而不同
+        let macros = MacroContext::new(&self.config.ai_settings.variables, voice_name(self.person));
+        let (system_prompt, user_prompt, notice) = prompt_macros::expand_prompts(&self.system_prompt(), &self.prompt_text, &macros);
+        if let Some(notice) = notice {
+            self.show_warning(&notice);
+        }
+        self.dispatch(AppCommand::Variations(Box::new(VariationsJob {
+            task,
+            api_client: self.api_client.clone(),
+            config: self.config.clone(),
+            temperatures,
+            system_prompt,
+            user_prompt,
+            prompt_text: self.prompt_text.clone(),
+            concurrency: self.batch_concurrency,
+            person: self.person,
+            speed: self.speed,
+            pitch: self.pitch,
+            volume: self.volume,
+            normalize_text: self.normalize_text,
+        })));
+    }
+
+    /// 采用一个版本：文本和音频成为当前结果，可以直接保存或重播
+    fn adopt_variation(&mut self, index: usize) {
+        let Some(Variation { status: VariationStatus::Done(take), .. }) = self.variations.get(index) else {
+            return;
+        };
+        let (info, audio) = (take.info.clone(), take.audio.clone());
+        self.response_text = info.text.clone();
+        self.response_origin = TextOrigin::Ai;
+        self.last_tts_audio = Some(audio.clone());
+        if self.auto_save {
+            self.auto_save_audio(audio, &info);
+        }
+        self.push_records([ContentRecord::new(info.clone(), self.speed, self.pitch, self.volume, self.person)]);
+        self.last_tts_info = Some(info);
+        self.show_success(&format!("已采用版本 {}", index + 1));
+    }
+
+    /// 把文本区的内容交给 DeepSeek 按优化模板润色，结果回到文本区并朗读
+    fn start_refine_task(&mut self) {
+        let system_prompt = self.config.ai_settings.refine_prompt.clone();
+        let use_deepseek = self.resolve_use_deepseek(true);
+        self.spawn_generation(TaskKind::Refine, self.response_text.clone(), system_prompt, use_deepseek, self.person);
+    }
+
+    /// 直接朗读文本区中（可能已编辑过）的内容，不经过 DeepSeek
+    fn start_speak_response_task(&mut self) {
+        self.spawn_generation(TaskKind::Speak, self.response_text.clone(), String::new(), false, self.person);
+    }
+
+    /// 在文本区的光标处（没有光标时在末尾）插入文本，并把光标移到插入内容之后
+    fn insert_into_response(&mut self, ctx: &egui::Context, insert: &str) {
+        let id = egui::Id::new(RESPONSE_TEXT_ID);
+        let mut state = egui::text_edit::TextEditState::load(ctx, id).unwrap_or_default();
+        let char_count = self.response_text.chars().count();
+        let position = state
+            .cursor
+            .char_range()
+            .map_or(char_count, |range| range.primary.index.min(char_count));
+        let byte_index = self.response_text.char_indices().nth(position).map_or(self.response_text.len(), |(i, _)| i);
+        self.response_text.insert_str(byte_index, insert);
+        self.response_origin = TextOrigin::Edited;
+
+        let cursor = egui::text::CCursor::new(position + insert.chars().count());
+        state.cursor.set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+        state.store(ctx, id);
+        ctx.memory_mut(|memory| memory.request_focus(id));
+    }
+
+    /// 当前所选提示词模板建议的最大字数
+    fn max_words(&self) -> usize {
+        self.config
+            .ai_settings
+            .prompts
+            .get(self.selected_prompt_index)
+            .and_then(|prompt| prompt.max_words)
+            .unwrap_or(content::DEFAULT_MAX_WORDS)
+    }
+
+    /// 触发到期的定时播报，一次性任务触发后从配置中移除
+    fn run_scheduler(&mut self) {
+        let due = self.scheduler.take_due(chrono::Local::now());
+        if due.is_empty() {
+            return;
+        }
+        for job in &due {
+            log::info!("定时播报: {}", job.text);
+            self.spawn_generation(TaskKind::Speak, job.text.clone(), String::new(), false, job.person);
+        }
+        if due.iter().any(|job| matches!(job.schedule, JobSchedule::Once { .. })) {
+            self.save_settings();
+        }
+    }
+
+    fn spawn_generation(&mut self, kind: TaskKind, prompt_text: String, system_prompt: String, use_deepseek: bool, person: i32) {
+        let first_stage = if use_deepseek { AppState::GeneratingText } else { AppState::SynthesizingAudio };
+        let task = TaskGuard::new(self.tasks.start(kind, first_stage), self.ui_sender.clone());
+        self.dispatch(AppCommand::Generate(Box::new(GenerationJob {
+            task,
+            api_client: self.api_client.clone(),
+            config: self.config.clone(),
+            prompt_text,
+            system_prompt,
+            use_deepseek,
+            person,
+            speed: self.speed,
+            pitch: self.pitch,
+            volume: self.volume,
+            stream_playback: self.stream_playback,
+            use_cache: !self.force_resynthesize,
+            normalize_text: self.normalize_text,
+        })));
+    }
+
+    /// 交给后台命令队列；队列已满时放弃这次操作并提示
+    fn dispatch(&self, command: AppCommand) {
+        if !self.commands.submit(command) {
+            log::warn!("后台命令队列已满，丢弃一条命令");
+            let _ = self.ui_sender.send(UIMessage::Notice("后台任务过多，已忽略本次操作".to_string()));
+        }
+    }
+}
+
+// --- Eframe App Implementation ---
+
+/// 应用内和全局快捷键重复触发“停止全部”时视为同一次按键的间隔
+const STOP_ALL_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 状态栏“已自动保存”提示的显示时间
+const AUTO_SAVED_HINT_DURATION: Duration = Duration::from_secs(3);
+
+/// 空闲时检查定时任务和延迟写入的设置的间隔；后台消息和全局快捷键会直接唤醒界面
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// 低功耗模式下的空闲轮询间隔
+const POWER_SAVING_IDLE_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// 低功耗模式下播放和生成期间的帧间隔（约 20 帧/秒）
+const POWER_SAVING_FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 超过此长度的数据 URI 复制后提醒体积过大
+#[cfg(feature = "clipboard")]
+const LARGE_DATA_URI_BYTES: usize = 1024 * 1024;
+
+/// 界面中可选的输出采样率，0 为设备默认
+const OUTPUT_SAMPLE_RATES: [u32; 5] = [0, 22050, 44100, 48000, 96000];
+
+/// 关闭时等待后台任务结束的最长时间
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+impl TTSApp {
+    /// 停止运行时：最多等待 `SHUTDOWN_TIMEOUT`，仍未结束的后台任务被直接取消。任务向界面
+    /// 发送消息失败时只记录日志后退出，不会因为通道已关闭而 panic。可以重复调用
+    fn shutdown_runtime(&mut self) {
+        if let Some(rt) = self.rt.take() {
+            log::info!("正在关闭: 等待后台任务（最多 {} 秒）", SHUTDOWN_TIMEOUT.as_secs());
+            rt.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+/// 没有经过 `on_exit`（例如初始化后窗口创建失败）就被丢弃时，同样限时停止运行时，
+/// 而不是在 `Runtime` 的析构中无限等待阻塞任务
+impl Drop for TTSApp {
+    fn drop(&mut self) {
+        self.shutdown_runtime();
+    }
+}
+
+impl eframe::App for TTSApp {
+    /// 关闭顺序：先注销全局快捷键、停止音频，不再产生新的工作；再保存配置和生成历史；
+    /// 最后限时停止运行时。界面的消息通道在此之后随 `TTSApp` 一起丢弃
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        log::info!("正在关闭: 注销全局快捷键");
+        self.global_hotkeys.unregister_all();
+        log::info!("正在关闭: 停止所有音频");
+        self.stop_all_audio();
+        // 合成缓存不需要写回，转存到磁盘的临时文件在缓存丢弃时删除
+        log::info!("正在关闭: 保存配置");
+        self.save_settings();
+        self.write_settings();
+        if self.persist_records {
+            log::info!("正在关闭: 保存生成历史");
+            if let Err(e) = export::export_session(&self.session_records, &records_path()) {
+                log::error!("保存生成历史失败: {}", e);
+            }
+        }
+        self.shutdown_runtime();
+        log::info!("已关闭");
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // --- Process background messages & state updates ---
+        self.track_window(ctx);
+        self.handle_ui_messages();
+        self.handle_global_hotkeys(ctx);
+        self.run_scheduler();
+        self.auto_save_settings();
+        self.sound_sinks.retain(|sound| !sound.sink.empty());
+
+        if !self.key_capture.is_capturing() {
+            for action in self.shortcuts.pressed(ctx) {
+                self.handle_shortcut(action);
+            }
+        }
+
+        let tts_gain = self.master_volume * self.tts_volume * self.cache_key_mode.playback_gain(self.volume);
+        self.tts_output.set_gain(tts_gain);
+        self.sound_output.set_gain(self.master_volume * self.sound_volume);
+        self.tts_output.set_limiter_enabled(self.limiter_enabled);
+        self.sound_output.set_limiter_enabled(self.limiter_enabled);
+
+        let mut new_device_index_to_set = None;
+        let mut new_sound_device = None;
+        let mut new_sound_monitor = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("TTSmate");
+            ui.separator();
+
+            // --- AI Controls ---
+            ui.collapsing("AI 设置", |ui| {
+                if self.deepseek_configured() {
+                    ui.checkbox(&mut self.use_deepseek, "使用 DeepSeek 生成文案");
+                } else {
+                    ui.add_enabled(false, egui::Checkbox::new(&mut false, "使用 DeepSeek 生成文案"))
+                        .on_disabled_hover_text("未配置 DeepSeek API 密钥，请在 config.toml 的 deepseek_api_key 中填写；未配置时直接朗读输入的文本");
+                }
+                let prompts = &self.config.ai_settings.prompts;
+                let mut prompt_names: Vec<&str> = prompts.iter().map(|p| p.name.as_str()).collect();
+                prompt_names.push("自定义模板");
+                ui.horizontal(|ui| {
+                    ui.label("提示词模板:");
+                    egui::ComboBox::from_id_source("prompt_template_combobox")
+                        .selected_text(prompt_names[self.selected_prompt_index])
+                        .show_ui(ui, |ui| {
+                            for (i, name) in prompt_names.iter().enumerate() {
+                                ui.selectable_value(&mut self.selected_prompt_index, i, *name);
+                            }
+                        });
+                });
+                if self.selected_prompt_index == prompts.len() {
+                    let mut history_cleared = false;
+                    ui.horizontal(|ui| {
+                        ui.label("自定义提示词:");
+                        let (recalled, cleared) = history_menu(ui, &mut self.custom_prompt_history);
+                        if let Some(recalled) = recalled {
+                            self.custom_prompt = recalled;
+                        }
+                        history_cleared = cleared;
+                    });
+                    let id = egui::Id::new(CUSTOM_PROMPT_ID);
+                    browse_history(ui, id, &mut self.custom_prompt, &mut self.custom_prompt_history);
+                    ui.add(egui::TextEdit::multiline(&mut self.custom_prompt).id(id));
+                    length_counter(ui, &ContentMetadata::analyze(&self.custom_prompt, self.speed), false);
+                    if history_cleared {
+                        self.save_settings();
+                    }
+                }
+                let mut apply_preset = None;
+                let mut remove_preset = None;
+                ui.horizontal_wrapped(|ui| {
+                    ui.label("预设:");
+                    for (i, preset) in self.presets.iter().enumerate() {
+                        let options = &preset.options;
+                        let hover = format!(
+                            "{} · 语速 {} · 音调 {} · 音量 {}\n右键删除",
+                            voice_name(options.person), options.speed, options.pitch, options.volume
+                        );
+                        let response = ui.small_button(&preset.name).on_hover_text(hover);
+                        if response.clicked() {
+                            apply_preset = Some(i);
+                        }
+                        if response.secondary_clicked() {
+                            remove_preset = Some(i);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.preset_name).hint_text("预设名称").desired_width(120.0));
+                    if ui.add_enabled(!self.preset_name.trim().is_empty(), egui::Button::new("保存当前设置为预设")).clicked() {
+                        self.save_preset();
+                    }
+                });
+                if let Some(i) = apply_preset {
+                    self.apply_preset(i);
+                }
+                if let Some(i) = remove_preset {
+                    self.presets.remove(i);
+                    self.save_settings();
+                }
+                ui.collapsing("提示词变量", |ui| {
+                    let mut changed = false;
+                    let mut removed = None;
+                    egui::Grid::new("prompt_variable_grid").num_columns(3).striped(true).show(ui, |ui| {
+                        ui.strong("名称");
+                        ui.strong("值");
+                        ui.end_row();
+                        for (i, (name, value)) in self.prompt_variables.iter_mut().enumerate() {
+                            changed |= ui.add(egui::TextEdit::singleline(name).desired_width(100.0)).lost_focus();
+                            changed |= ui.add(egui::TextEdit::singleline(value).desired_width(200.0)).lost_focus();
+                            if ui.small_button("✖").on_hover_text("删除变量").clicked() {
+                                removed = Some(i);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(i) = removed {
+                        self.prompt_variables.remove(i);
+                        changed = true;
+                    }
+                    if ui.button("➕ 添加变量").clicked() {
+                        self.prompt_variables.push(Default::default());
+                    }
+                    ui.weak("在提示词或输入中写 {{名称}} 引用变量；内置 {{date}} {{time}} {{datetime}} {{weekday}} {{voice}} {{version}}，写 \\{{ 可保留花括号。");
+                    if changed {
+                        self.save_settings();
+                    }
+                });
+                ui.collapsing("内容过滤", |ui| {
+                    let filter = &mut self.content_filter;
+                    let mut changed = ui
+                        .checkbox(&mut filter.enabled, "过滤 AI 回复")
+                        .on_hover_text("在显示和朗读之前检查 DeepSeek 的回复，适合面向公众的播报；直接朗读的文本不受影响")
+                        .changed();
+                    ui.add_enabled_ui(filter.enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("命中时:");
+                            egui::ComboBox::from_id_source("filter_action")
+                                .selected_text(filter.action.label())
+                                .show_ui(ui, |ui| {
+                                    for action in [FilterAction::Blank, FilterAction::Reject] {
+                                        changed |= ui.selectable_value(&mut filter.action, action, action.label()).changed();
+                                    }
+                                });
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("字数上限:");
+                            let value = ui.add(egui::DragValue::new(&mut filter.max_words).range(0..=2000));
+                            changed |= value.drag_stopped() || value.lost_focus() || (value.changed() && !value.dragged() && !value.has_focus());
+                            ui.weak("0 为不限");
+                        });
+                        ui.label("屏蔽词（每行一个）:");
+                        if ui.add(egui::TextEdit::multiline(&mut self.blocked_words_text).desired_rows(3)).lost_focus() {
+                            filter.blocked_words = self
+                                .blocked_words_text
+                                .lines()
+                                .map(str::trim)
+                                .filter(|word| !word.is_empty())
+                                .map(str::to_string)
+                                .collect();
+                            changed = true;
+                        }
+                        ui.weak("忽略大小写，英文词只匹配完整单词。");
+                    });
+                    if changed {
+                        self.save_settings();
+                    }
+                });
+            });
+            ui.separator();
+
+            // --- Main Input ---
+            // Tab 顺序即控件的创建顺序：输入框 → 历史记录 → 生成并播放 → 音频设置 → 语音参数（含发音人）→ …… → 文本区
+            // 不经过 DeepSeek 时输入框的内容会直接送去合成，需要受百度上限约束
+            let prompt_is_tts = !(self.use_deepseek && self.deepseek_configured());
+            let prompt_metadata = ContentMetadata::analyze(&self.prompt_text, self.speed);
+            let prompt_over_limit = prompt_is_tts && prompt_metadata.baidu_level() == LengthLevel::OverLimit && !self.config.baidu.long_text;
+            let can_generate = !self.tasks.is_running(TaskKind::Generate) && !self.tasks.generations_full() && !prompt_over_limit;
+            let mut submitted = false;
+            let mut history_cleared = false;
+            ui.horizontal(|ui| {
+                ui.label("输入话题/文本:");
+                let id = egui::Id::new(PROMPT_TEXT_ID);
+                browse_history(ui, id, &mut self.prompt_text, &mut self.prompt_history);
+                let input = ui.add(egui::TextEdit::singleline(&mut self.prompt_text).id(id).hint_text("↑↓ 翻阅历史"));
+                if std::mem::take(&mut self.focus_prompt) {
+                    input.request_focus();
+                }
+                // 回车提交；Esc 由 egui 处理为离开输入框
+                submitted = input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let (recalled, cleared) = history_menu(ui, &mut self.prompt_history);
+                if let Some(recalled) = recalled {
+                    self.prompt_text = recalled;
+                    self.focus_prompt = true;
+                }
+                history_cleared = cleared;
+            });
+            if history_cleared {
+                self.save_settings();
+            }
+            if !self.prompt_text.trim().is_empty() {
+                length_counter(ui, &prompt_metadata, prompt_is_tts);
+            }
+
+            if ui
+                .add_enabled(can_generate, egui::Button::new("生成并播放"))
+                .on_hover_text("在输入框中按回车也可生成")
+                .on_disabled_hover_text(if prompt_over_limit { "文本超出百度单次合成上限，请缩短后再合成" } else { "正在生成" })
+                .clicked()
+            {
+                self.start_generation_task(self.use_deepseek);
+            }
+            if submitted && can_generate {
+                self.start_generation_task(self.use_deepseek);
+                // 留在输入框，方便连续输入下一条
+                self.focus_prompt = true;
+            }
+
+            // --- Audio Playback Controls ---
+            ui.collapsing("音频设置", |ui| {
+                // Device Selection
+                let selected_name = self.audio_device_names[self.selected_device_index].clone();
+                egui::ComboBox::from_label("语音输出设备")
+                    .selected_text(selected_name)
+                    .show_ui(ui, |ui| {
+                        for (i, device_name) in self.audio_device_names.iter().enumerate() {
+                            let tooltip = match &self.audio_device_caps[i] {
+                                Some(caps) => match self.tts_format {
+                                    Some(format) if caps.supports(format) => format!("{}\n可直接输出当前语音格式（{}）", caps, format),
+                                    Some(format) => format!("{}\n当前语音格式（{}）需转换后输出", caps, format),
+                                    None => caps.to_string(),
+                                },
+                                None => "无法查询设备支持的格式".to_string(),
+                            };
+                            if ui.selectable_label(self.selected_device_index == i, device_name).on_hover_text(tooltip).clicked() {
+                                new_device_index_to_set = Some(i);
+                            }
+                        }
+                    });
+                let sound_device_label = self.sound_device.clone().unwrap_or_else(|| "与语音相同".to_string());
+                egui::ComboBox::from_label("音效输出设备")
+                    .selected_text(sound_device_label)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(self.sound_device.is_none(), "与语音相同").clicked() {
+                            new_sound_device = Some(None);
+                        }
+                        for (i, device_name) in self.audio_device_names.iter().enumerate() {
+                            if ui.selectable_label(self.sound_device.as_ref() == Some(device_name), device_name).clicked() {
+                                new_sound_device = Some(Some(i));
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("音效可以输出到与语音不同的设备，例如把音效送到虚拟声卡");
+                let monitor_label = self.sound_monitor_device.clone().unwrap_or_else(|| "不输出".to_string());
+                egui::ComboBox::from_label("音效也输出到监听设备")
+                    .selected_text(monitor_label)
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(self.sound_monitor_device.is_none(), "不输出").clicked() {
+                            new_sound_monitor = Some(None);
+                        }
+                        for (i, device_name) in self.audio_device_names.iter().enumerate() {
+                            if ui.selectable_label(self.sound_monitor_device.as_ref() == Some(device_name), device_name).clicked() {
+                                new_sound_monitor = Some(Some(i));
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("音效送到虚拟声卡的同时，在自己的耳机里也能听到");
+                ui.horizontal(|ui| {
+                    match &self.output_health {
+                        OutputHealth::Connected => ui.weak(format!("输出状态: {}", self.output_health)),
+                        OutputHealth::Lost(_) => ui.colored_label(egui::Color32::YELLOW, format!("⚠ 输出状态: {}", self.output_health)),
+                    };
+                    if ui.small_button("🔄 刷新设备").on_hover_text("重新枚举输出设备；当前设备已断开时改用系统默认设备").clicked() {
+                        let still_present = self.refresh_devices().is_some();
+                        if !still_present || self.output_health != OutputHealth::Connected {
+                            self.try_reconnect_output();
+                        }
+                    }
+                });
+                let previous_rate = self.output_sample_rate;
+                let rate_label = |rate: u32| if rate == 0 { "设备默认".to_string() } else { format!("{} Hz", rate) };
+                let caps = self.audio_device_caps[self.selected_device_index].clone();
+                egui::ComboBox::from_label("输出采样率")
+                    .selected_text(rate_label(self.output_sample_rate))
+                    .show_ui(ui, |ui| {
+                        for rate in OUTPUT_SAMPLE_RATES {
+                            let supported = rate == 0 || caps.as_ref().is_none_or(|caps| caps.supports_sample_rate(rate));
+                            let label = if supported { rate_label(rate) } else { format!("{}（不支持，将自动调整）", rate_label(rate)) };
+                            ui.selectable_value(&mut self.output_sample_rate, rate, label);
+                        }
+                    })
+                    .response
+                    .on_hover_text("重新打开语音和音效的输出流，正在播放的声音会停止；设备不支持时改用最接近的采样率");
+                if self.output_sample_rate != previous_rate {
+                    self.reopen_outputs();
+                    self.save_settings();
+                }
+                if let (Some(source), Some(device)) = (self.tts_format, self.device_format) {
+                    if let Some(mismatch) = audio::format_mismatch(source, device) {
+                        ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", mismatch))
+                            .on_hover_text("格式转换会略微增加延迟；如需原样输出，可在系统中把设备格式设为与语音一致");
+                    }
+                }
+
+                // Low-latency monitor
+                let mut monitor_changed = false;
+                ui.horizontal(|ui| {
+                    monitor_changed |= ui
+                        .checkbox(&mut self.low_latency_monitor, "低延迟监听")
+                        .on_hover_text("语音直接写入小缓冲的输出流，适合卡拉OK和配音；缓冲过小时可能出现爆音，且不支持按句跳转")
+                        .changed();
+                    let slider = ui.add_enabled(
+                        self.low_latency_monitor,
+                        egui::Slider::new(&mut self.monitor_latency_ms, 5..=200).suffix(" ms").text("目标延迟"),
+                    );
+                    monitor_changed |= slider.drag_stopped() || (slider.changed() && !slider.dragged());
+                    let frames = ui
+                        .add_enabled(
+                            self.low_latency_monitor,
+                            egui::DragValue::new(&mut self.monitor_buffer_frames)
+                                .range(0..=8192)
+                                .custom_formatter(|n, _| if n == 0.0 { "自动".to_string() } else { format!("{} 帧", n) }),
+                        )
+                        .on_hover_text("设备缓冲帧数，0 为自动（目标延迟的一半）；超出设备支持范围时自动调整");
+                    monitor_changed |= frames.drag_stopped() || frames.lost_focus() || (frames.changed() && !frames.dragged() && !frames.has_focus());
+                });
+                if let Some(monitor) = &self.tts_monitor {
+                    let buffer = match monitor.buffer_latency() {
+                        Some(buffer) => format!("设备缓冲 {:.1} ms", buffer.as_secs_f64() * 1000.0),
+                        None => "设备缓冲由系统决定".to_string(),
+                    };
+                    let measured = monitor.measured_latency();
+                    if measured.is_zero() {
+                        ui.weak(format!("目标延迟 {} ms，{}，等待输出流启动", monitor.target_latency().as_millis(), buffer));
+                    } else {
+                        ui.weak(format!("实际延迟约 {} ms，{}", measured.as_millis(), buffer));
+                    }
+                }
+                if monitor_changed {
+                    self.reopen_monitor();
+                    self.save_settings();
+                }
+                
+                ui.separator();
+                
+                // Volume Controls
+                ui.add(egui::Slider::new(&mut self.master_volume, 0.0..=1.5).text("主音量"));
+                ui.add(egui::Slider::new(&mut self.tts_volume, 0.0..=1.5).text("语音音量"));
+                ui.add(egui::Slider::new(&mut self.sound_volume, 0.0..=1.5).text("音效音量"));
+                ui.checkbox(&mut self.limiter_enabled, "防削波限幅")
+                    .on_hover_text("音量叠加超过满幅时平滑压缩峰值，而不是硬削波");
+                if ui
+                    .checkbox(&mut self.power_saving, "低功耗模式")
+                    .on_hover_text("播放和生成期间降低界面刷新率，空闲时更少地检查定时播报（最多晚 3 秒）")
+                    .changed()
+                {
+                    self.save_settings();
+                }
+                let previous_ducking = self.ducking_settings.clone();
+                let mut ducking_committed = false;
+                ui.horizontal(|ui| {
+                    let settings = &mut self.ducking_settings;
+                    ducking_committed |= ui
+                        .checkbox(&mut settings.enabled, "语音时压低音效")
+                        .on_hover_text("语音播放时自动压低音效（如背景音乐），语音结束后平滑恢复")
+                        .changed();
+                    ui.add_enabled_ui(settings.enabled, |ui| {
+                        let values = [
+                            ui.add(egui::DragValue::new(&mut settings.amount_db).range(1.0..=40.0).speed(0.5).prefix("压低 ").suffix(" dB")),
+                            ui.add(egui::DragValue::new(&mut settings.threshold_db).range(-60.0..=0.0).speed(0.5).prefix("阈值 ").suffix(" dBFS"))
+                                .on_hover_text("语音电平超过该值时才压低音效"),
+                            ui.add(egui::DragValue::new(&mut settings.attack_ms).range(1..=2000).prefix("压低 ").suffix(" ms")),
+                            ui.add(egui::DragValue::new(&mut settings.release_ms).range(1..=5000).prefix("恢复 ").suffix(" ms")),
+                        ];
+                        for value in values {
+                            ducking_committed |= value.drag_stopped() || value.lost_focus() || (value.changed() && !value.dragged() && !value.has_focus());
+                        }
+                    });
+                });
+                if self.ducking_settings != previous_ducking {
+                    apply_ducking(&self.ducking, &self.ducking_settings);
+                }
+                if ducking_committed {
+                    self.save_settings();
+                }
+                if ui
+                    .checkbox(&mut self.stream_playback, "边下载边播放")
+                    .on_hover_text("收到第一段音频就开始播放，长文本启动更快")
+                    .changed()
+                {
+                    self.save_settings();
+                }
+                ui.horizontal(|ui| {
+                    let toggled = ui
+                        .checkbox(&mut self.warm_up_on_start, "启动时预热")
+                        .on_hover_text("启动后在后台合成一次短句（不播放），减少第一次合成的等待；下次启动时生效")
+                        .changed();
+                    let phrase = ui.add_enabled(
+                        self.warm_up_on_start,
+                        egui::TextEdit::singleline(&mut self.warm_up_phrase).hint_text("预热短句").desired_width(120.0),
+                    );
+                    if toggled || phrase.lost_focus() {
+                        self.save_settings();
+                    }
+                });
+                let mut chime_changed = false;
+                let mut chime_reload = false;
+                ui.horizontal(|ui| {
+                    chime_changed |= ui
+                        .checkbox(&mut self.chime_enabled, "播报前提示音")
+                        .on_hover_text("每次朗读前先播放一声提示音，提醒观众注意")
+                        .changed();
+                    ui.add_enabled_ui(self.chime_enabled, |ui| {
+                        if ui.button("📁 选择").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("音频文件", &["mp3", "wav", "ogg", "flac"])
+                                .pick_file()
+                            {
+                                self.chime_path = path.to_string_lossy().to_string();
+                                chime_reload = true;
+                            }
+                        }
+                        if self.chime_path.is_empty() {
+                            ui.label("内置提示音");
+                        } else {
+                            ui.label(&self.chime_path);
+                            if ui.small_button("✖").on_hover_text("改用内置提示音").clicked() {
+                                self.chime_path.clear();
+                                chime_reload = true;
+                            }
+                        }
+                        chime_changed |= ui.checkbox(&mut self.chime_on_repeat, "循环时也播放").changed();
+                    });
+                });
+                if chime_reload {
+                    self.load_chime();
+                }
+                if chime_changed || chime_reload {
+                    self.save_settings();
+                }
+
+                ui.separator();
+
+                // Auto Save
+                let mut auto_save_changed = false;
+                ui.horizontal(|ui| {
+                    auto_save_changed |= ui.checkbox(&mut self.auto_save, "自动保存").changed();
+                    if ui.button("📁 选择文件夹").clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.auto_save_dir = dir.to_string_lossy().to_string();
+                            auto_save_changed = true;
+                        }
+                    }
+                    if self.auto_save_dir.is_empty() {
+                        ui.label("未选择保存目录");
+                    } else {
+                        ui.label(&self.auto_save_dir);
+                    }
+                });
+                if template_editor(ui, "文件名模板:", &mut self.filename_template) {
+                    auto_save_changed = true;
+                }
+                if auto_save_changed {
+                    self.save_settings();
+                }
+                
+                ui.separator();
+
+                // Play/Pause/Repeat Controls
+                ui.horizontal(|ui| {
+                    let tts_button_text = if self.is_tts_paused { "▶ 播放" } else { "⏸ 暂停" };
+                    if ui.add_enabled(!self.tts_idle(), egui::Button::new(tts_button_text)).clicked() {
+                        self.set_tts_paused(!self.is_tts_paused);
+                    }
+                    // 没有在播放时勾选，立即从头播放上一段语音
+                    if ui.checkbox(&mut self.repeat_tts, "循环播放").changed() && self.repeat_tts && self.tts_idle() {
+                        if let Some(audio) = self.last_tts_audio.clone() {
+                            self.play_tts_data(audio, self.chime_on_repeat);
+                        }
+                    }
+                });
+            });
+            ui.separator();
+
+            // --- TTS Parameter Controls ---
+            ui.collapsing("语音参数", |ui| {
                 ui.add(egui::Slider::new(&mut self.speed, 0..=15).text("语速"));
                 ui.add(egui::Slider::new(&mut self.pitch, 0..=15).text("音调"));
                 ui.add(egui::Slider::new(&mut self.volume, 0..=15).text("音量"));
+                let previous_mode = self.cache_key_mode;
+                egui::ComboBox::from_label("缓存键")
+                    .selected_text(self.cache_key_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in [CacheKeyMode::Full, CacheKeyMode::IgnoreVolume] {
+                            ui.selectable_value(&mut self.cache_key_mode, mode, mode.label());
+                        }
+                    })
+                    .response
+                    .on_hover_text("忽略音量时，只改音量不会重新合成，而是复用缓存并在本地调整增益");
+                if self.cache_key_mode != previous_mode {
+                    self.api_client.set_cache_key_mode(self.cache_key_mode);
+                    self.save_settings();
+                }
+                let namespace_hint = if self.cache_namespace.is_empty() {
+                    "当前命名空间: (默认)".to_string()
+                } else {
+                    format!("当前命名空间: {}", self.cache_namespace)
+                };
+                if ui
+                    .button("重置缓存命名空间")
+                    .on_hover_text(format!("百度更新音色后使用，之前缓存的音频全部不再命中\n{}", namespace_hint))
+                    .clicked()
+                {
+                    self.cache_namespace = cache::new_namespace();
+                    self.api_client.set_cache_namespace(&self.cache_namespace);
+                    self.save_settings();
+                    self.show_success("已重置缓存命名空间");
+                }
+                ui.horizontal(|ui| {
+                    let entries = ui
+                        .add(egui::DragValue::new(&mut self.cache_max_entries).range(1..=10000).prefix("缓存上限 ").suffix(" 条"))
+                        .on_hover_text("内存中最多保留的合成结果条数，超出时淘汰最久未使用的");
+                    if entries.changed() {
+                        self.api_client.set_cache_capacity(self.cache_max_entries);
+                    }
+                    if entries.drag_stopped() || entries.lost_focus() || (entries.changed() && !entries.dragged() && !entries.has_focus()) {
+                        self.save_settings();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let memory = ui
+                        .add(egui::DragValue::new(&mut self.cache_max_memory_mb).range(0..=4096).prefix("内存上限 ").suffix(" MB"))
+                        .on_hover_text("缓存音频在内存中的最大占用，超出时把最久未使用的转存到临时文件，再次使用时读回；0 为不限");
+                    // 拖动过程中不逐个调整，松开或输入完成后再转存超出的部分
+                    if memory.drag_stopped() || memory.lost_focus() || (memory.changed() && !memory.dragged() && !memory.has_focus()) {
+                        self.dispatch(AppCommand::SetCacheMemoryBudget { api_client: self.api_client.clone(), megabytes: self.cache_max_memory_mb });
+                        self.save_settings();
+                    }
+                });
+                let stats = self.api_client.cache_stats();
+                let megabytes = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+                if stats.spilled > 0 {
+                    ui.weak(format!(
+                        "已缓存 {} 条，内存占用 {:.1} MB，其中 {} 条（{:.1} MB）已转存到磁盘",
+                        stats.entries,
+                        megabytes(stats.memory_bytes),
+                        stats.spilled,
+                        megabytes(stats.spilled_bytes)
+                    ));
+                } else {
+                    ui.weak(format!("已缓存 {} 条，内存占用 {:.1} MB", stats.entries, megabytes(stats.memory_bytes)));
+                }
+                ui.checkbox(&mut self.force_resynthesize, "强制重新合成")
+                    .on_hover_text("跳过缓存，每次都重新请求百度并且不写入缓存，便于对比参数调整的效果");
+                ui.horizontal(|ui| {
+                    let limit = ui
+                        .add(egui::DragValue::new(&mut self.max_audio_seconds).range(0..=3600).prefix("时长上限 ").suffix(" 秒"))
+                        .on_hover_text("按字数和语速预估合成音频的时长，防止 AI 回复过长浪费配额；0 为不限");
+                    let mut changed = limit.drag_stopped() || limit.lost_focus() || (limit.changed() && !limit.dragged() && !limit.has_focus());
+                    let previous_limit = self.duration_limit;
+                    ui.add_enabled_ui(self.max_audio_seconds > 0, |ui| {
+                        egui::ComboBox::from_id_source("duration_limit")
+                            .selected_text(self.duration_limit.label())
+                            .show_ui(ui, |ui| {
+                                for mode in [DurationLimit::Warn, DurationLimit::Truncate] {
+                                    ui.selectable_value(&mut self.duration_limit, mode, mode.label());
+                                }
+                            });
+                    });
+                    changed |= self.duration_limit != previous_limit;
+                    if changed {
+                        self.save_settings();
+                    }
+                });
+                if ui
+                    .checkbox(&mut self.normalize_text, "展开数字和缩写")
+                    .on_hover_text("合成前把数字、日期、时间、单位和常见缩写改写成读法，如 3.5kg → 三点五千克、50% → 百分之五十")
+                    .changed()
+                {
+                    self.save_settings();
+                }
                 egui::ComboBox::from_label("发音人")
-                    .selected_text(VOICES.iter().find(|&&(_, p)| p == self.person).unwrap_or(&("未知",-1)).0)
+                    .selected_text(voice_name(self.person))
                     .show_ui(ui, |ui| {
                         for (name, person_code) in VOICES.iter() {
                             ui.selectable_value(&mut self.person, *person_code, *name);
                         }
                     });
+
+                let mut fallback_changed = ui
+                    .checkbox(&mut self.fallback.enabled, "合成失败时改用备用发音人")
+                    .on_hover_text("精品音色未开通或配额用尽时，按下列顺序改用其他发音人重试")
+                    .changed();
+                if self.fallback.enabled {
+                    let persons = &mut self.fallback.persons;
+                    let mut moved = None;
+                    let mut removed = None;
+                    for (i, &person) in persons.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}. {}", i + 1, voice_name(person)));
+                            if ui.add_enabled(i > 0, egui::Button::new("⬆").small()).clicked() {
+                                moved = Some(i);
+                            }
+                            if ui.small_button("✖").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = moved {
+                        persons.swap(i - 1, i);
+                        fallback_changed = true;
+                    }
+                    if let Some(i) = removed {
+                        persons.remove(i);
+                        fallback_changed = true;
+                    }
+                    let mut added = None;
+                    egui::ComboBox::from_id_source("fallback_add")
+                        .selected_text("➕ 添加备用发音人")
+                        .show_ui(ui, |ui| {
+                            for &(name, person_code) in VOICES.iter().filter(|(_, p)| !persons.contains(p)) {
+                                if ui.selectable_label(false, name).clicked() {
+                                    added = Some(person_code);
+                                }
+                            }
+                        });
+                    if let Some(person_code) = added {
+                        persons.push(person_code);
+                        fallback_changed = true;
+                    }
+                }
+                if fallback_changed {
+                    self.save_settings();
+                }
+            });
+            ui.separator();
+
+            // --- Pronunciation Dictionary ---
+            ui.collapsing("发音词典", |ui| {
+                let mut changed = false;
+                let mut removed = None;
+                egui::Grid::new("pronunciation_grid").num_columns(4).striped(true).show(ui, |ui| {
+                    ui.strong("词语");
+                    ui.strong("读作");
+                    ui.strong("区分大小写");
+                    ui.end_row();
+                    for (i, entry) in self.pronunciations.iter_mut().enumerate() {
+                        changed |= ui.add(egui::TextEdit::singleline(&mut entry.word).desired_width(120.0)).lost_focus();
+                        changed |= ui.add(egui::TextEdit::singleline(&mut entry.replacement).desired_width(160.0)).lost_focus();
+                        changed |= ui.checkbox(&mut entry.case_sensitive, "").changed();
+                        if ui.small_button("✖").on_hover_text("删除词条").clicked() {
+                            removed = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = removed {
+                    self.pronunciations.remove(i);
+                    changed = true;
+                }
+                if ui.button("➕ 添加词条").clicked() {
+                    self.pronunciations.push(PronunciationEntry::default());
+                }
+                ui.weak("合成前按词典替换，英文词条只匹配完整单词；词条重叠时较长的优先。");
+                if changed {
+                    self.save_settings();
+                }
+            });
+            ui.separator();
+
+            // --- Scheduled Announcements ---
+            ui.collapsing("定时播报", |ui| {
+                let mut removed = None;
+                egui::Grid::new("schedule_grid").num_columns(4).striped(true).show(ui, |ui| {
+                    for (i, (job, next)) in self.scheduler.jobs().enumerate() {
+                        ui.label(job.text.chars().take(20).collect::<String>());
+                        ui.label(format!("{} · {}", voice_name(job.person), job.schedule.describe()));
+                        match next {
+                            Some(at) => ui.label(format!("下次: {}", at.format("%m-%d %H:%M:%S"))),
+                            None => ui.weak("已过期"),
+                        };
+                        if ui.small_button("🗑").clicked() {
+                            removed = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(index) = removed {
+                    self.scheduler.remove(index);
+                    self.save_settings();
+                }
+
+                ui.horizontal(|ui| {
+                    ui.add(egui::TextEdit::singleline(&mut self.new_job.text).hint_text("播报内容").desired_width(200.0));
+                    egui::ComboBox::from_id_source("new_job_voice")
+                        .selected_text(voice_name(self.new_job.person))
+                        .show_ui(ui, |ui| {
+                            for (name, person_code) in VOICES.iter() {
+                                ui.selectable_value(&mut self.new_job.person, *person_code, *name);
+                            }
+                        });
+                });
+                let mut add_clicked = false;
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.new_job.repeat, false, "定时");
+                    ui.radio_value(&mut self.new_job.repeat, true, "每隔");
+                    if self.new_job.repeat {
+                        ui.add(egui::DragValue::new(&mut self.new_job.minutes).range(1..=1440).suffix(" 分钟"));
+                    } else {
+                        ui.add(egui::TextEdit::singleline(&mut self.new_job.time).hint_text("HH:MM").desired_width(50.0));
+                    }
+                    add_clicked = ui.add_enabled(!self.new_job.text.trim().is_empty(), egui::Button::new("➕ 添加")).clicked();
+                });
+                if add_clicked {
+                    let now = chrono::Local::now();
+                    let schedule = if self.new_job.repeat {
+                        Some(JobSchedule::Every { minutes: self.new_job.minutes })
+                    } else {
+                        scheduler::next_occurrence(&self.new_job.time, now).map(|at| JobSchedule::Once { at })
+                    };
+                    match schedule {
+                        Some(schedule) => {
+                            let job = ScheduledJob { text: self.new_job.text.trim().to_string(), person: self.new_job.person, schedule };
+                            self.scheduler.add(job, now);
+                            self.new_job.text.clear();
+                            self.save_settings();
+                        }
+                        None => self.show_error(format!("无效的时间 '{}'，请使用 HH:MM 格式", self.new_job.time)),
+                    }
+                }
+            });
+            ui.separator();
+
+            // --- Batch ---
+            ui.collapsing("批量生成", |ui| {
+                let running = self.tasks.is_running(TaskKind::Batch);
+                ui.weak("每行一段文本，行末可用英文逗号加发音人名称或代码，如“欢迎来到直播间,度小宇”。使用当前的语速、音调和音量。");
+                ui.add_enabled(
+                    !running,
+                    egui::TextEdit::multiline(&mut self.batch_input).desired_rows(4).desired_width(f32::INFINITY).hint_text("粘贴文本，或从 CSV/TXT 文件载入"),
+                );
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(!running, egui::Button::new("📄 载入文件")).clicked() {
+                        if let Some(path) = rfd::FileDialog::new().add_filter("文本", &["csv", "txt"]).pick_file() {
+                            match std::fs::read_to_string(&path) {
+                                // Excel 导出的 UTF-8 CSV 带有 BOM
+                                Ok(text) => self.batch_input = text.trim_start_matches('\u{feff}').to_string(),
+                                Err(e) => self.show_error(format!("无法读取 {}: {}", path.display(), e)),
+                            }
+                        }
+                    }
+                    if ui.add_enabled(!running, egui::Button::new("📁 保存目录")).clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.batch_dir = dir.to_string_lossy().to_string();
+                        }
+                    }
+                    if self.batch_dir.is_empty() {
+                        ui.label("未选择保存目录");
+                    } else {
+                        ui.label(&self.batch_dir);
+                    }
+                });
+                if template_editor(ui, "文件名模板:", &mut self.batch_filename_template) {
+                    self.save_settings();
+                }
+                ui.horizontal(|ui| {
+                    ui.label("同时合成:");
+                    ui.add_enabled(!running, egui::DragValue::new(&mut self.batch_concurrency).range(1..=8).suffix(" 行"));
+                    if running {
+                        if ui.button("⏹ 取消").clicked() {
+                            if let Some(cancel) = &self.batch_cancel {
+                                cancel.store(true, Ordering::Relaxed);
+                            }
+                        }
+                    } else if ui.button("▶ 开始批量生成").clicked() {
+                        self.start_batch();
+                    }
+                });
+                if !self.batch_rows.is_empty() {
+                    let finished = self.batch_rows.iter().filter(|row| row.status.is_finished()).count();
+                    let failed = self.batch_rows.iter().filter(|row| matches!(row.status, BatchStatus::Failed(_))).count();
+                    ui.add(
+                        egui::ProgressBar::new(finished as f32 / self.batch_rows.len() as f32)
+                            .text(format!("{}/{} 完成，{} 失败", finished, self.batch_rows.len(), failed)),
+                    );
+                    egui::ScrollArea::vertical().id_source("batch_rows").max_height(200.0).show(ui, |ui| {
+                        egui::Grid::new("batch_grid").num_columns(4).striped(true).show(ui, |ui| {
+                            for (i, row) in self.batch_rows.iter().enumerate() {
+                                ui.label(format!("{}", i + 1));
+                                let text: String = row.line.text.chars().take(30).collect();
+                                ui.label(text).on_hover_text(&row.line.text);
+                                ui.label(voice_name(row.line.person.unwrap_or(self.person)));
+                                match &row.status {
+                                    BatchStatus::Pending => ui.weak("等待"),
+                                    BatchStatus::Running => ui.spinner(),
+                                    BatchStatus::Done(path) => ui.colored_label(egui::Color32::LIGHT_GREEN, "✔ 已保存").on_hover_text(path.display().to_string()),
+                                    BatchStatus::Failed(e) => ui.colored_label(egui::Color32::LIGHT_RED, "✖ 失败").on_hover_text(e),
+                                    BatchStatus::Canceled => ui.weak("已取消"),
+                                };
+                                ui.end_row();
+                            }
+                        });
+                    });
+                }
+            });
+            ui.separator();
+
+            // --- Variations ---
+            ui.collapsing("多版本生成", |ui| {
+                let running = self.tasks.is_running(TaskKind::Variations);
+                ui.weak("用输入框中的话题和当前模板，让 DeepSeek 以不同的温度各写一版并合成；试听后采用最满意的一版。");
+                ui.horizontal(|ui| {
+                    ui.label("版本数:");
+                    ui.add_enabled(!running, egui::DragValue::new(&mut self.variation_count).range(variations::MIN_COUNT..=variations::MAX_COUNT));
+                    if ui.add_enabled(!running, egui::Button::new("▶ 生成多个版本")).clicked() {
+                        self.start_variations();
+                    }
+                    if running {
+                        ui.spinner();
+                    }
+                });
+                let mut play = None;
+                let mut adopt = None;
+                egui::Grid::new("variation_grid").num_columns(3).striped(true).show(ui, |ui| {
+                    for (i, variation) in self.variations.iter().enumerate() {
+                        ui.label(format!("版本 {}（温度 {:.1}）", i + 1, variation.temperature));
+                        match &variation.status {
+                            VariationStatus::Running => {
+                                ui.spinner();
+                            }
+                            VariationStatus::Done(take) => {
+                                let text: String = take.info.text.chars().take(30).collect();
+                                ui.label(text).on_hover_text(&take.info.text);
+                                ui.horizontal(|ui| {
+                                    if ui.button("▶ 试听").clicked() {
+                                        play = Some(take.audio.clone());
+                                    }
+                                    if ui.button("✔ 采用").clicked() {
+                                        adopt = Some(i);
+                                    }
+                                });
+                            }
+                            VariationStatus::Failed(e) => {
+                                ui.colored_label(egui::Color32::LIGHT_RED, "✖ 失败").on_hover_text(e);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(audio) = play {
+                    self.play_tts_data(audio, false);
+                }
+                if let Some(index) = adopt {
+                    self.adopt_variation(index);
+                }
+            });
+            ui.separator();
+
+            // --- Soundboard ---
+            ui.collapsing("音效板", |ui| {
+                let mut soundboard_changed = false;
+                ui.horizontal(|ui| {
+                    ui.label("根目录:");
+                    if self.soundboard_root.is_empty() {
+                        ui.label("配置文件所在目录");
+                    } else {
+                        ui.label(&self.soundboard_root);
+                    }
+                    if ui.button("📁").on_hover_text("位于根目录下的音效以相对路径保存").clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.soundboard_root = dir.to_string_lossy().to_string();
+                            soundboard_changed = true;
+                        }
+                    }
+                    if !self.soundboard_root.is_empty() && ui.button("重置").clicked() {
+                        self.soundboard_root.clear();
+                        soundboard_changed = true;
+                    }
+                });
+                let mut undo_clicked = false;
+                ui.horizontal(|ui| {
+                    if ui.button("➕ 添加音效").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("音频文件", &["mp3", "wav", "ogg", "flac"])
+                            .pick_file()
+                        {
+                            let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("未知音效").to_string();
+                            self.soundboard_items.push(SoundboardItem::new(name, path.to_string_lossy().to_string()));
+                            self.analyze_sounds(false);
+                            soundboard_changed = true;
+                        }
+                    }
+                    let last_edit = self.soundboard_undo.last().map(SoundboardEdit::label);
+                    let undo_button = ui.add_enabled(last_edit.is_some(), egui::Button::new("↶ 撤销"));
+                    if let Some(label) = last_edit {
+                        undo_clicked = undo_button.on_hover_text(format!("撤销{} ({})", label, self.key_bindings.undo)).clicked();
+                    }
+                });
+                if undo_clicked {
+                    self.undo_soundboard_edit();
+                }
+                ui.horizontal(|ui| {
+                    soundboard_changed |= ui
+                        .checkbox(&mut self.sound_normalize, "响度均衡")
+                        .on_hover_text("按添加时分析的响度自动调整各音效的音量，使它们听起来一样响")
+                        .changed();
+                    ui.add_enabled_ui(self.sound_normalize, |ui| {
+                        let target = ui.add(egui::DragValue::new(&mut self.sound_target_db).range(-40.0..=-6.0).speed(0.5).prefix("目标 ").suffix(" dBFS"));
+                        soundboard_changed |= target.drag_stopped() || target.lost_focus() || (target.changed() && !target.dragged() && !target.has_focus());
+                    });
+                    if ui.button("重新分析全部").on_hover_text("替换过音效文件后使用").clicked() {
+                        self.analyze_sounds(true);
+                    }
+                });
+                ui.separator();
+                let mut button_action = None;
+                if self.soundboard_items.iter().any(|item| item.is_favorite) {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label("⭐");
+                        for (i, sound_item) in self.soundboard_items.iter().enumerate().filter(|(_, item)| item.is_favorite) {
+                            button_action = soundboard::show_button(ui, i, sound_item, &self.sound_sinks).or(button_action.take());
+                        }
+                    });
+                    ui.separator();
+                }
+                let categories: std::collections::BTreeSet<&str> = self.soundboard_items.iter().map(soundboard::category_of).collect();
+                for category in &categories {
+                    if categories.len() > 1 {
+                        ui.weak(*category);
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        for (i, sound_item) in self.soundboard_items.iter().enumerate() {
+                            if soundboard::category_of(sound_item) == *category {
+                                button_action = soundboard::show_button(ui, i, sound_item, &self.sound_sinks).or(button_action.take());
+                            }
+                        }
+                    });
+                }
+                match button_action {
+                    Some(soundboard::ButtonAction::Play(index)) => self.trigger_sound(index, false),
+                    Some(soundboard::ButtonAction::Reveal(index)) => {
+                        if let Some(item) = self.soundboard_items.get(index) {
+                            if let Err(e) = utils::reveal_in_file_manager(std::path::Path::new(&item.path)) {
+                                self.show_error(format!("无法打开文件夹: {}", e));
+                            }
+                        }
+                    }
+                    Some(soundboard::ButtonAction::ToggleFavorite(index)) => {
+                        if let Some(item) = self.soundboard_items.get_mut(index) {
+                            item.is_favorite = !item.is_favorite;
+                            soundboard_changed = true;
+                        }
+                    }
+                    None => {}
+                }
+
+                ui.collapsing("音效列表", |ui| {
+                    let mut unbound = None;
+                    let mut removed = None;
+                    let mut bulk_remove = false;
+                    ui.horizontal(|ui| {
+                        if ui.small_button("全选").clicked() {
+                            self.sound_selection = (0..self.soundboard_items.len()).collect();
+                        }
+                        if ui.small_button("全不选").clicked() {
+                            self.sound_selection.clear();
+                        }
+                        if !self.sound_selection.is_empty() {
+                            ui.label(format!("已选 {} 个", self.sound_selection.len()));
+                        }
+                    });
+                    if !self.sound_selection.is_empty() {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(&mut self.bulk_category).hint_text(soundboard::DEFAULT_CATEGORY).desired_width(80.0));
+                            if ui.button("移到分类").clicked() {
+                                let category = self.bulk_category.trim().to_string();
+                                let mut previous = Vec::new();
+                                for &index in &self.sound_selection {
+                                    if let Some(item) = self.soundboard_items.get_mut(index) {
+                                        previous.push((index, std::mem::replace(&mut item.category, category.clone())));
+                                    }
+                                }
+                                self.soundboard_undo.push(SoundboardEdit::Recategorized { previous });
+                                soundboard_changed = true;
+                            }
+                            ui.separator();
+                            ui.add(egui::DragValue::new(&mut self.bulk_volume).range(0.0..=2.0).speed(0.01).fixed_decimals(2).prefix("音量 ×"));
+                            if ui.button("设置音量").clicked() {
+                                for &index in &self.sound_selection {
+                                    if let Some(item) = self.soundboard_items.get_mut(index) {
+                                        item.volume = self.bulk_volume;
+                                    }
+                                }
+                                soundboard_changed = true;
+                            }
+                            ui.separator();
+                            if ui.button("重新分析").on_hover_text("重新计算选中音效的时长和响度").clicked() {
+                                let paths = self.sound_selection.iter().filter_map(|&i| self.soundboard_items.get(i)).map(|item| item.path.clone()).collect();
+                                self.analyze_paths(paths);
+                            }
+                            bulk_remove = ui.button("🗑 删除所选").clicked();
+                        });
+                    }
+                    egui::Grid::new("sound_hotkeys_grid").num_columns(7).show(ui, |ui| {
+                        for (i, sound_item) in self.soundboard_items.iter_mut().enumerate() {
+                            let mut selected = self.sound_selection.contains(&i);
+                            if ui.checkbox(&mut selected, "").changed() {
+                                if selected {
+                                    self.sound_selection.insert(i);
+                                } else {
+                                    self.sound_selection.remove(&i);
+                                }
+                            }
+                            ui.label(&sound_item.name);
+                            soundboard_changed |= ui
+                                .add(egui::TextEdit::singleline(&mut sound_item.category).hint_text(soundboard::DEFAULT_CATEGORY).desired_width(80.0))
+                                .lost_focus();
+                            soundboard_changed |= self.key_capture.show(ui, &format!("sound_{}", i), &mut sound_item.hotkey, true);
+                            soundboard_changed |= ui
+                                .checkbox(&mut sound_item.momentary, "按住播放")
+                                .on_hover_text("仅在按住快捷键时播放，松开立即停止")
+                                .changed();
+                            let volume = ui
+                                .add(egui::DragValue::new(&mut sound_item.volume).range(0.0..=2.0).speed(0.01).fixed_decimals(2).prefix("×"))
+                                .on_hover_text("该音效自己的音量倍数");
+                            soundboard_changed |= volume.drag_stopped() || volume.lost_focus() || (volume.changed() && !volume.dragged() && !volume.has_focus());
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(!sound_item.hotkey.is_empty(), egui::Button::new("✖")).on_hover_text("解绑快捷键").clicked() {
+                                    unbound = Some(i);
+                                }
+                                if ui.button("🗑").on_hover_text("删除音效").clicked() {
+                                    removed = Some(i);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(index) = unbound {
+                        let hotkey = std::mem::take(&mut self.soundboard_items[index].hotkey);
+                        self.soundboard_undo.push(SoundboardEdit::Unbound { index, hotkey });
+                        soundboard_changed = true;
+                    }
+                    if let Some(index) = removed {
+                        self.remove_sound(index);
+                        soundboard_changed = true;
+                    }
+                    if bulk_remove {
+                        self.remove_selected_sounds();
+                        soundboard_changed = true;
+                    }
+                    for error in self.global_hotkeys.errors() {
+                        ui.colored_label(egui::Color32::YELLOW, error);
+                    }
+                });
+
+                ui.collapsing("统计", |ui| {
+                    let stats = soundboard::get_stats(&self.soundboard_items);
+                    ui.label(format!(
+                        "共 {} 个音效，总时长 {:.1} 秒",
+                        stats.total_sounds,
+                        stats.total_duration.as_secs_f32()
+                    ));
+                    let mut deleted_category = None;
+                    egui::Grid::new("sound_stats_grid").num_columns(4).striped(true).show(ui, |ui| {
+                        ui.strong("分类");
+                        ui.strong("数量");
+                        ui.strong("时长");
+                        ui.end_row();
+                        for (category, (count, duration)) in &stats.per_category {
+                            ui.label(category);
+                            ui.label(count.to_string());
+                            ui.label(format!("{:.1} 秒", duration.as_secs_f32()));
+                            if category != soundboard::DEFAULT_CATEGORY
+                                && ui.small_button("🗑").on_hover_text("删除分类，其中的音效归入默认分类").clicked()
+                            {
+                                deleted_category = Some(category.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if let Some(category) = deleted_category {
+                        let mut indices = Vec::new();
+                        for (i, item) in self.soundboard_items.iter_mut().enumerate() {
+                            if soundboard::category_of(item) == category {
+                                item.category.clear();
+                                indices.push(i);
+                            }
+                        }
+                        self.soundboard_undo.push(SoundboardEdit::CategoryDeleted { category, indices });
+                        soundboard_changed = true;
+                    }
+                });
+
+                if soundboard_changed {
+                    self.global_hotkeys.register_all(&self.key_bindings, &self.soundboard_items, &self.shortcuts);
+                    self.save_settings();
+                }
+            });
+            ui.separator();
+
+            // --- Key Bindings ---
+            ui.collapsing("快捷键", |ui| {
+                let mut changed = false;
+                egui::Grid::new("key_bindings_grid").num_columns(2).show(ui, |ui| {
+                    let bindings = &mut self.key_bindings;
+                    // 最后一列表示只注册为全局快捷键；“停止全部”不能注册时退回为窗口内快捷键
+                    let rows: [(&str, ShortcutAction, &mut String, bool); 8] = [
+                        ("tts_generate", ShortcutAction::TtsGenerate, &mut bindings.tts_generate, false),
+                        ("ai_generate", ShortcutAction::AiGenerate, &mut bindings.ai_generate, false),
+                        ("stop_all", ShortcutAction::StopAll, &mut bindings.stop_all, false),
+                        ("volume_up", ShortcutAction::VolumeUp, &mut bindings.volume_up, false),
+                        ("volume_down", ShortcutAction::VolumeDown, &mut bindings.volume_down, false),
+                        ("undo", ShortcutAction::Undo, &mut bindings.undo, false),
+                        ("speak_clipboard", ShortcutAction::SpeakClipboard, &mut bindings.speak_clipboard, true),
+                        ("read_selection", ShortcutAction::ReadSelection, &mut bindings.read_selection, true),
+                    ];
+                    for (id, action, binding, global) in rows {
+                        ui.label(action.label());
+                        changed |= self.key_capture.show(ui, id, binding, global);
+                        ui.end_row();
+                    }
+                });
+                if let Some(message) = self.key_capture.message() {
+                    ui.colored_label(egui::Color32::RED, message);
+                }
+                for conflict in self.shortcuts.conflicts() {
+                    ui.colored_label(egui::Color32::YELLOW, conflict);
+                }
+                if KeyCombo::parse(&self.key_bindings.stop_all).is_ok_and(|combo| combo.check_global().is_err()) {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("“停止全部”的 {} 没有修饰键，只在本窗口内生效；需要在其他程序中也能紧急停止时，请绑定带 Ctrl 或 Alt 的组合", self.key_bindings.stop_all),
+                    );
+                }
+                ui.weak("带修饰键的“停止全部”和“朗读剪贴板”注册为全局快捷键，窗口在后台时同样生效；在本窗口内按 Esc 也能停止全部。");
+                if cfg!(feature = "selection") {
+                    changed |= ui
+                        .checkbox(&mut self.read_selection_enabled, "辅助功能：启用“朗读选中文本”")
+                        .on_hover_text(
+                            "松开快捷键后向当前窗口模拟 Ctrl+C，读取选中的文本后恢复原剪贴板。\n\
+                             剪贴板会被短暂占用，非文本内容无法恢复；macOS 需在“隐私与安全性 → 辅助功能”中授权，Wayland 下可能无效。",
+                        )
+                        .changed();
+                }
+                if changed {
+                    self.global_hotkeys.set_read_selection_enabled(self.read_selection_enabled);
+                    self.shortcuts = Shortcuts::from_config(&self.key_bindings);
+                    self.global_hotkeys.register_all(&self.key_bindings, &self.soundboard_items, &self.shortcuts);
+                    self.save_settings();
+                }
+            });
+            ui.separator();
+
+            // --- Network ---
+            ui.collapsing("网络", |ui| {
+                egui::Grid::new("network_grid").num_columns(2).show(ui, |ui| {
+                    ui.label("同时请求数:");
+                    ui.add(egui::DragValue::new(&mut self.network.max_concurrent_requests).range(1..=16));
+                    ui.end_row();
+                    ui.label("响应大小上限:");
+                    ui.add(egui::DragValue::new(&mut self.network.max_response_mb).range(1..=200).suffix(" MB"));
+                    ui.end_row();
+                    ui.label("额外信任的 CA 证书:");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut self.network.ca_cert_path).hint_text("PEM 文件，留空只用系统证书").desired_width(220.0));
+                        if ui.button("📁").clicked() {
+                            if let Some(path) = rfd::FileDialog::new().add_filter("PEM 证书", &["pem", "crt", "cer"]).pick_file() {
+                                self.network.ca_cert_path = path.to_string_lossy().to_string();
+                            }
+                        }
+                    });
+                    ui.end_row();
+                    ui.label("User-Agent:");
+                    ui.add(egui::TextEdit::singleline(&mut self.network.user_agent).hint_text(api_client::DEFAULT_USER_AGENT).desired_width(220.0));
+                    ui.end_row();
+                });
+                if !self.network.extra_headers.is_empty() {
+                    let names: Vec<&str> = self.network.extra_headers.keys().map(String::as_str).collect();
+                    ui.weak(format!("附加请求头（在 config.toml 的 [network.extra_headers] 中修改）: {}", names.join(", ")));
+                }
+                ui.checkbox(&mut self.network.accept_invalid_certs, "跳过证书校验")
+                    .on_hover_text("仅在连接使用自签名证书的自建服务器时开启");
+                if self.network.accept_invalid_certs {
+                    ui.colored_label(egui::Color32::YELLOW, "⚠ 已关闭证书校验，连接可能被中间人窃听");
+                }
+                ui.checkbox(&mut self.keep_cache_on_reconnect, "重新连接时保留合成缓存")
+                    .on_hover_text("不保留时，重新连接后所有文本都会重新向百度请求");
+                ui.horizontal(|ui| {
+                    if ui.button("🔌 重新连接").on_hover_text("按上面的设置重建网络连接，并获取一次百度访问令牌测试连通性").clicked() {
+                        self.reconnect();
+                    }
+                    if self.network != self.config.network {
+                        ui.colored_label(egui::Color32::YELLOW, "设置已修改，点“重新连接”后生效");
+                    } else {
+                        match &self.connection_status {
+                            Some(Ok(info)) => {
+                                ui.colored_label(egui::Color32::LIGHT_GREEN, format!("✔ 已连接 ({} ms)", info.latency.as_millis()));
+                            }
+                            Some(Err(e)) => {
+                                ui.colored_label(egui::Color32::LIGHT_RED, format!("✖ {}", e));
+                            }
+                            None => {}
+                        }
+                    }
+                });
+                if let Some(Ok(info)) = &self.connection_status {
+                    server_info_grid(ui, info);
+                }
+                if let Some(timing) = self.api_client.last_timing() {
+                    ui.separator();
+                    ui.label(format!("最近一次请求（{}）耗时 {} ms:", timing.operation, timing.total().as_millis()));
+                    egui::Grid::new("timing_grid").num_columns(2).show(ui, |ui| {
+                        for (phase, duration) in &timing.phases {
+                            ui.label(*phase);
+                            ui.label(format!("{} ms", duration.as_millis()));
+                            ui.end_row();
+                        }
+                    });
+                }
             });
             ui.separator();
 
-            // --- Soundboard ---
-            ui.collapsing("音效板", |ui| {
-                if ui.button("➕ 添加音效").clicked() {
-                    if let Some(path) = rfd::FileDialog::new()
-                        .add_filter("音频文件", &["mp3", "wav", "ogg", "flac"])
-                        .pick_file()
-                    {
-                        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("未知音效").to_string();
-                        self.soundboard_items.push(SoundboardItem {
-                            name,
-                            path: path.to_string_lossy().to_string(),
-                        });
+            // --- API Keys ---
+            ui.collapsing("API 密钥", |ui| {
+                let mut verify = None;
+                let form = &mut self.key_form;
+                let password = !form.show;
+                // 与设置向导使用同一套格式检查：百度密钥必填，DeepSeek 密钥可留空
+                let deepseek_check = config::validate_key("DeepSeek API Key", &form.keys.deepseek_api_key, false);
+                let baidu_check = config::validate_key("百度 API Key", &form.keys.baidu_api_key, true)
+                    .and(config::validate_key("百度 Secret Key", &form.keys.baidu_secret_key, true));
+                let keys_valid = deepseek_check.is_ok() && baidu_check.is_ok();
+                egui::Grid::new("api_keys_grid").num_columns(3).show(ui, |ui| {
+                    let rows = [
+                        ("DeepSeek API Key", &mut form.keys.deepseek_api_key, Some((KeyService::DeepSeek, &deepseek_check))),
+                        ("百度 API Key", &mut form.keys.baidu_api_key, None),
+                        ("百度 Secret Key", &mut form.keys.baidu_secret_key, Some((KeyService::Baidu, &baidu_check))),
+                    ];
+                    for (label, value, service) in rows {
+                        ui.label(label);
+                        ui.add(egui::TextEdit::singleline(value).password(password).desired_width(280.0));
+                        if let Some((service, check)) = service {
+                            let enabled = form.verifying.is_none() && check.is_ok() && !value.trim().is_empty();
+                            if ui.add_enabled(enabled, egui::Button::new("验证")).clicked() {
+                                verify = Some(service);
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+                ui.checkbox(&mut form.show, "显示密钥");
+                for check in [&deepseek_check, &baidu_check] {
+                    if let Err(message) = check {
+                        ui.colored_label(egui::Color32::RED, message);
                     }
                 }
-                ui.separator();
-                ui.horizontal_wrapped(|ui| {
-                    for sound_item in self.soundboard_items.iter() {
-                        if ui.button(&sound_item.name).clicked() {
-                            let path = sound_item.path.clone();
-                            let sender = self.ui_sender.clone();
-                            self.rt.spawn(async move {
-                                match tokio::fs::read(&path).await {
-                                    Ok(data) => {
-                                        let _ = sender.send(UIMessage::PlaySound(data));
-                                    }
-                                    Err(e) => {
-                                        log::error!("读取音效文件 '{}' 失败: {}", path, e);
-                                    }
-                                }
-                            });
+                for (name, status, service) in [
+                    ("DeepSeek", &form.deepseek_status, KeyService::DeepSeek),
+                    ("百度", &form.baidu_status, KeyService::Baidu),
+                ] {
+                    match status {
+                        _ if form.verifying == Some(service) => {
+                            ui.weak(format!("正在验证 {} 密钥...", name));
+                        }
+                        Some(Ok(())) => {
+                            ui.colored_label(egui::Color32::LIGHT_GREEN, format!("✔ {} 密钥有效", name));
                         }
+                        Some(Err(e)) => {
+                            ui.colored_label(egui::Color32::RED, format!("✖ {}: {}", name, e));
+                        }
+                        None => {}
+                    }
+                }
+                let dirty = form.keys != self.config.api_keys;
+                let save = ui.horizontal(|ui| {
+                    let save = ui.add_enabled(dirty && keys_valid, egui::Button::new("💾 保存密钥")).clicked();
+                    if ui.add_enabled(dirty, egui::Button::new("还原")).clicked() {
+                        *form = ApiKeyForm::new(&self.config.api_keys);
                     }
+                    save
                 });
+                if let Some(service) = verify {
+                    self.verify_keys(service);
+                }
+                if save.inner {
+                    self.save_api_keys();
+                }
+            });
+            ui.separator();
+
+            // --- Logging ---
+            ui.collapsing("日志", |ui| {
+                let previous_level = self.log_level;
+                egui::ComboBox::from_label("日志级别")
+                    .selected_text(logging::level_label(self.log_level))
+                    .show_ui(ui, |ui| {
+                        for level in logging::LOG_LEVELS {
+                            ui.selectable_value(&mut self.log_level, level, logging::level_label(level));
+                        }
+                    });
+                ui.weak("立即生效。反馈问题时可调到 Debug，API Key 在任何级别下都会被隐藏。");
+                if self.log_level != previous_level {
+                    logging::set_level(self.log_level);
+                    log::info!("日志级别已切换为 {}", self.log_level);
+                    self.save_settings();
+                }
             });
             ui.separator();
 
             // --- AI Response Display ---
             ui.horizontal(|ui| {
-                ui.label("AI 生成文本:");
-                let save_button_enabled = self.last_tts_audio.is_some();
+                ui.label(format!("AI 生成文本 ({}):", self.response_origin));
+                // 选择保存位置的对话框打开期间也算进行中，避免重复弹出
+                let save_button_enabled = self.last_tts_audio.is_some() && !self.tasks.is_running(TaskKind::SaveAudio);
                 if ui.add_enabled(save_button_enabled, egui::Button::new("💾 保存音频")).clicked() {
-                    if let Some(audio_data) = self.last_tts_audio.clone() {
-                        let handle = self.handle.clone();
-                        let sender = self.ui_sender.clone();
-                        self.status_text = "准备保存...".to_string();
-                        std::thread::spawn(move || {
-                            if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("MPEG Audio", &["mp3"])
-                                .set_file_name("tts_audio.mp3")
-                                .save_file()
-                            {
-                                handle.spawn(async move {
-                                    match tokio::fs::write(&path, &*audio_data).await {
-                                        Ok(_) => { let _ = sender.send(UIMessage::UpdateState(AppState::Idle)); },
-                                        Err(e) => { let _ = sender.send(UIMessage::Error(format!("保存失败: {}", e))); }
-                                    }
-                                });
-                            } else {
-                                // User cancelled dialog
-                                let _ = sender.send(UIMessage::UpdateState(AppState::Idle));
+                    self.save_audio();
+                }
+                #[cfg(feature = "clipboard")]
+                if ui.add_enabled(save_button_enabled, egui::Button::new("📋 复制音频")).clicked() {
+                    self.copy_audio_to_clipboard();
+                }
+                #[cfg(feature = "clipboard")]
+                if ui
+                    .add_enabled(save_button_enabled, egui::Button::new("🌐 复制为数据 URI"))
+                    .on_hover_text("复制 data:audio/mpeg;base64,... 形式的文本，可直接用作网页 <audio> 标签的 src")
+                    .clicked()
+                {
+                    self.copy_audio_data_uri();
+                }
+                ui.menu_button("📤 JSON", |ui| {
+                    if ui.add_enabled(!self.session_records.is_empty(), egui::Button::new("导出当前内容")).clicked() {
+                        self.export_records(false);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(!self.session_records.is_empty(), egui::Button::new(format!("导出本次会话 ({} 条)", self.session_records.len()))).clicked() {
+                        self.export_records(true);
+                        ui.close_menu();
+                    }
+                    if ui.button("导入...").clicked() {
+                        self.import_records();
+                        ui.close_menu();
+                    }
+                });
+            });
+            ui.collapsing("保存前处理", |ui| {
+                let settings = &mut self.export_settings;
+                let mut changed = ui
+                    .checkbox(&mut settings.enabled, "保存时使用处理后的音频（WAV）")
+                    .on_hover_text("处理后无法再编码为 MP3，保存为 WAV；自动保存和复制音频不受影响")
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("采样率:");
+                    let rate_label = |rate: u32| if rate == 0 { "保持原采样率".to_string() } else { format!("{} Hz", rate) };
+                    egui::ComboBox::from_id_source("export_sample_rate")
+                        .selected_text(rate_label(settings.sample_rate))
+                        .show_ui(ui, |ui| {
+                            for rate in OUTPUT_SAMPLE_RATES {
+                                changed |= ui.selectable_value(&mut settings.sample_rate, rate, rate_label(rate)).changed();
+                            }
+                        });
+                });
+                changed |= ui.checkbox(&mut settings.trim_silence, "去掉首尾静音").changed();
+                ui.horizontal(|ui| {
+                    changed |= ui.checkbox(&mut settings.normalize, "峰值归一化到").changed();
+                    let value = ui.add_enabled(
+                        settings.normalize,
+                        egui::DragValue::new(&mut settings.peak_db).range(-20.0..=0.0).speed(0.1).suffix(" dBFS"),
+                    );
+                    changed |= value.drag_stopped() || value.lost_focus() || (value.changed() && !value.dragged() && !value.has_focus());
+                });
+                if changed {
+                    self.save_settings();
+                }
+                let describe = |levels: &processing::Levels| {
+                    let peak = if levels.peak_db <= processing::SILENCE_DB { "静音".to_string() } else { format!("{:.1} dBFS", levels.peak_db) };
+                    (peak, format!("{:.2} 秒", levels.duration.as_secs_f32()))
+                };
+                let mut play = None;
+                let has_audio = self.last_tts_audio.is_some();
+                match self.export_preview() {
+                    None if !has_audio => {
+                        ui.weak("合成后可在这里比较处理前后的峰值和时长");
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.weak("正在处理…");
+                        });
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, format!("无法处理当前音频: {}", e));
+                    }
+                    Some(Ok(preview)) => {
+                        egui::Grid::new("export_preview_grid").num_columns(3).striped(true).show(ui, |ui| {
+                            ui.label("");
+                            ui.strong("峰值");
+                            ui.strong("时长");
+                            ui.end_row();
+                            for (label, levels) in [("处理前", &preview.before), ("处理后", &preview.after)] {
+                                let (peak, duration) = describe(levels);
+                                ui.label(label);
+                                ui.label(peak);
+                                ui.label(duration);
+                                ui.end_row();
                             }
                         });
+                        if ui.button("▶ 试听处理后").on_hover_text("播放的就是保存时写入文件的数据").clicked() {
+                            play = Some(preview.wav.clone());
+                        }
+                    }
+                }
+                if let Some(wav) = play {
+                    self.play_tts_data(wav, false);
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                ui.label("语气:");
+                for token in style_tokens::STYLE_TOKENS {
+                    let template = token.template();
+                    if ui.small_button(&template).on_hover_text(token.label).clicked() {
+                        self.insert_into_response(ui.ctx(), &template);
+                    }
+                }
+                ui.separator();
+                for preset in style_tokens::STYLE_PRESETS {
+                    let hover = format!("语速 {} · 音调 {} · 音量 {} {}", preset.speed, preset.pitch, preset.volume, preset.tokens);
+                    if ui.small_button(preset.name).on_hover_text(hover).clicked() {
+                        self.speed = preset.speed;
+                        self.pitch = preset.pitch;
+                        self.volume = preset.volume;
+                        if !preset.tokens.is_empty() {
+                            self.insert_into_response(ui.ctx(), preset.tokens);
+                        }
                     }
                 }
             });
             egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.label(&self.response_text);
+                // 流式生成中只读显示已收到的部分，完成后才替换文本区，避免半截内容被编辑或朗读
+                if let Some((_, partial)) = &self.streaming_response {
+                    let mut partial = partial.as_str();
+                    ui.add(egui::TextEdit::multiline(&mut partial).desired_width(f32::INFINITY));
+                    return;
+                }
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut self.response_text)
+                        .id(egui::Id::new(RESPONSE_TEXT_ID))
+                        .desired_width(f32::INFINITY),
+                );
+                if response.changed() {
+                    self.response_origin = TextOrigin::Edited;
+                }
+            });
+            let response_metadata = ContentMetadata::analyze(&self.response_text, self.speed);
+            if !self.response_text.trim().is_empty() {
+                let metadata = response_metadata;
+                length_counter(ui, &metadata, true);
+                if metadata.baidu_level() == LengthLevel::OverLimit && self.config.baidu.long_text {
+                    ui.weak("超出百度单次合成上限，将使用长文本在线合成，需等整段合成完成后才开始播放");
+                } else if metadata.baidu_level() == LengthLevel::OverLimit {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::LIGHT_RED, "⚠ 超出百度单次合成上限，需要截断后才能朗读");
+                        if ui.button("✂ 截断到百度上限").clicked() {
+                            self.response_text = content::truncate_for_baidu(&self.response_text);
+                            self.response_origin = TextOrigin::Edited;
+                        }
+                    });
+                }
+                let max_words = self.max_words();
+                if metadata.word_count > max_words {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(egui::Color32::YELLOW, format!("⚠ 超过当前模板建议的 {} 字", max_words));
+                        if ui.button("✂ 截断到完整句子").clicked() {
+                            self.response_text = content::truncate_at_sentence(&self.response_text, max_words);
+                            self.response_origin = TextOrigin::Edited;
+                        }
+                    });
+                }
+            }
+
+            // --- Sentence Navigation ---
+            if self.tts_sentences.len() > 1 {
+                let current = self.current_sentence();
+                let mut skip = None;
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.can_skip_sentence(false), egui::Button::new("◀◀ 上一句")).clicked() {
+                        skip = Some(SentenceSkip::Previous);
+                    }
+                    if ui.add_enabled(self.can_skip_sentence(true), egui::Button::new("▶▶ 下一句")).clicked() {
+                        skip = Some(SentenceSkip::Next);
+                    }
+                    match current {
+                        Some(i) => ui.label(format!("第 {}/{} 句", i + 1, self.tts_sentences.len())),
+                        None => ui.label(format!("共 {} 句", self.tts_sentences.len())),
+                    };
+                });
+                ui.horizontal_wrapped(|ui| {
+                    for (i, sentence) in self.tts_sentences.iter().enumerate() {
+                        let text = egui::RichText::new(&sentence.text);
+                        let text = if Some(i) == current { text.strong().color(egui::Color32::LIGHT_BLUE) } else { text };
+                        if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                            skip = Some(SentenceSkip::To(i));
+                        }
+                    }
+                });
+                if let Some(skip) = skip {
+                    self.skip_sentence(skip);
+                }
+            }
+            ui.horizontal(|ui| {
+                let has_text = !self.response_text.trim().is_empty();
+                let within_limit = response_metadata.baidu_level() != LengthLevel::OverLimit || self.config.baidu.long_text;
+                if ui
+                    .add_enabled(has_text && within_limit && !self.tasks.is_running(TaskKind::Speak), egui::Button::new("🔊 朗读此文本"))
+                    .on_disabled_hover_text(if within_limit { "需要文本" } else { "文本超出百度单次合成上限，请先截断" })
+                    .clicked()
+                {
+                    self.start_speak_response_task();
+                }
+                if ui
+                    .add_enabled(has_text && !self.tasks.is_running(TaskKind::Refine) && self.deepseek_configured(), egui::Button::new("✨ 发送到AI优化"))
+                    .on_hover_text("用优化模板让 DeepSeek 润色当前文本，结果会替换文本区内容")
+                    .on_disabled_hover_text("需要文本，且已配置 DeepSeek API 密钥")
+                    .clicked()
+                {
+                    self.start_refine_task();
+                }
+            });
+
+            // --- Generation History ---
+            // 标题带条数，固定 id 才不会在条数变化时折叠
+            egui::CollapsingHeader::new(format!("生成历史 ({})", self.session_records.len())).id_source("generation_history_header").show(ui, |ui| {
+                if ui
+                    .checkbox(&mut self.persist_records, "关闭时保存，下次启动时恢复")
+                    .on_hover_text("保存到配置目录下的 history.json")
+                    .changed()
+                {
+                    self.save_settings();
+                }
+                let mut action = None;
+                egui::ScrollArea::vertical().id_source("generation_history").max_height(240.0).show(ui, |ui| {
+                    for (i, record) in self.session_records.iter().enumerate().rev() {
+                        ui.group(|ui| {
+                            ui.horizontal(|ui| {
+                                ui.weak(record.info.created_at.format("%m-%d %H:%M").to_string());
+                                ui.weak(format!(
+                                    "{} · 语速 {} · {} 字 · 约 {:.1} 秒",
+                                    record.info.voice, record.speed, record.word_count, record.estimated_seconds
+                                ));
+                            });
+                            if !record.info.prompt.is_empty() && record.info.prompt != record.info.text {
+                                ui.label(format!("提示词: {}", record.info.prompt));
+                            }
+                            ui.label(egui::RichText::new(&record.info.text).strong());
+                            ui.horizontal(|ui| {
+                                if ui.small_button("↩ 载入").on_hover_text("放回输入框和文本区，并恢复当时的语音参数").clicked() {
+                                    action = Some(HistoryAction::Load(i));
+                                }
+                                if ui.small_button("🔊 重新合成").on_hover_text("用当时的语音参数朗读这段文本").clicked() {
+                                    action = Some(HistoryAction::Speak(i));
+                                }
+                                if ui.small_button("✏ 修改后重新生成").on_hover_text("载入后把焦点放到输入框，修改提示词再生成").clicked() {
+                                    action = Some(HistoryAction::Edit(i));
+                                }
+                                if ui.small_button("📤 导出").clicked() {
+                                    action = Some(HistoryAction::Export(i));
+                                }
+                            });
+                        });
+                    }
+                });
+                if ui.add_enabled(!self.session_records.is_empty(), egui::Button::new("🗑 清空历史")).clicked() {
+                    self.session_records.clear();
+                }
+                if let Some(action) = action {
+                    self.apply_history_action(action);
+                }
             });
             ui.separator();
 
             // --- Footer / Status ---
-            ui.label(&self.status_text);
+            ui.horizontal(|ui| {
+                match self.tasks.summary() {
+                    Some(summary) => {
+                        ui.spinner();
+                        ui.label(summary).on_hover_text(self.tasks.details());
+                    }
+                    None => {
+                        ui.label(&self.status_text);
+                    }
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    match &self.connection_status {
+                        Some(Ok(info)) => {
+                            let warnings = info.warnings();
+                            if warnings.is_empty() {
+                                ui.weak(info.summary()).on_hover_text("最近一次连接测试的结果，详见“网络”面板");
+                            } else {
+                                ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", info.summary())).on_hover_text(warnings.join("\n"));
+                            }
+                        }
+                        Some(Err(_)) => {
+                            ui.colored_label(egui::Color32::LIGHT_RED, "✖ 未连接").on_hover_text("连接测试失败，详见“网络”面板");
+                        }
+                        None => {}
+                    }
+                    if self.auto_saved_at.is_some_and(|at| at.elapsed() < AUTO_SAVED_HINT_DURATION) {
+                        ui.weak("已自动保存");
+                    }
+                });
+            });
         });
+        self.toasts.show(ctx);
+        self.editing_text = ctx.wants_keyboard_input();
+
+        if let Some(device) = new_sound_device {
+            match self.set_sound_output_device(device) {
+                Ok(()) => {
+                    self.sound_device_preference = self.sound_device.clone().unwrap_or_default();
+                    self.save_settings();
+                }
+                Err(e) => {
+                    log::error!("切换音效输出设备失败: {}", e);
+                    self.show_error(format!("切换音效输出设备失败: {}", e));
+                }
+            }
+        }
+        if let Some(device) = new_sound_monitor {
+            match self.set_sound_monitor_device(device) {
+                Ok(()) => {
+                    self.sound_monitor_preference = self.sound_monitor_device.clone().unwrap_or_default();
+                    self.save_settings();
+                }
+                Err(e) => {
+                    log::error!("切换音效监听设备失败: {}", e);
+                    self.show_error(format!("切换音效监听设备失败: {}", e));
+                }
+            }
+        }
 
         if let Some(index) = new_device_index_to_set {
             if let Err(e) = self.change_output_device(index) {
                 log::error!("切换音频设备失败: {}", e);
-                self.status_text = "切换音频设备失败".to_string();
+                self.show_error(format!("切换音频设备失败: {}", e));
+            }
+        }
+
+        self.schedule_repaint(ctx);
+    }
+}
+
+/// 配置可用时直接进入主界面，否则先显示设置向导，写出配置后再在同一窗口中切换过去
+enum Launcher {
+    Setup(Box<SetupWizard>),
+    Running(Box<TTSApp>),
+}
+
+impl eframe::App for Launcher {
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        if let Launcher::Running(app) = self {
+            app.on_exit(gl);
+        }
+    }
+
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        match self {
+            Launcher::Setup(wizard) => {
+                let Some(config) = wizard.show(ctx) else { return };
+                apply_logging_config(&config);
+                match TTSApp::new(config, ctx) {
+                    Ok(app) => *self = Launcher::Running(Box::new(app)),
+                    Err(e) => {
+                        log::error!("应用初始化失败: {}", e);
+                        wizard.set_error(format!("配置已保存，但应用初始化失败: {}", e));
+                    }
+                }
+            }
+            Launcher::Running(app) => app.update(ctx, frame),
+        }
+    }
+}
+
+/// 合成前的文本处理：展开语气标记、替换发音词典，按需展开数字和缩写
+fn prepare_tts_text(text: &str, config: &Config, normalize_text: bool) -> Result<String, &'static str> {
+    let text = style_tokens::render_tokens(text);
+    let text = normalize::apply_pronunciations(&text, &config.pronunciations);
+    let text = if normalize_text {
+        normalize::normalize_for_tts(&text, Lang::from_code(&config.baidu.lan))
+    } else {
+        text
+    };
+    content::sanitize_tts_input(&text)
+}
+
+/// 按设置的时长上限检查即将合成的文本：预计超出时提示，或在句末截断到上限以内。
+/// 返回要合成的文本和给用户的提示
+fn limit_text_duration(text: String, speed: i32, settings: &AppSettings) -> (String, Option<String>) {
+    if settings.max_audio_seconds == 0 {
+        return (text, None);
+    }
+    let max = Duration::from_secs(settings.max_audio_seconds.into());
+    let estimated = content::estimate_duration(content::count_words(&text), speed);
+    if estimated <= max {
+        return (text, None);
+    }
+    let exceeded = format!("预计时长 {:.0} 秒，超过上限 {} 秒", estimated.as_secs_f32(), settings.max_audio_seconds);
+    match settings.duration_limit {
+        DurationLimit::Warn => (text, Some(exceeded)),
+        DurationLimit::Truncate => {
+            let truncated = content::truncate_at_sentence(&text, content::words_within(max, speed));
+            let notice = format!("{}，只朗读前 {} 字", exceeded, truncated.chars().count());
+            (truncated, Some(notice))
+        }
+    }
+}
+
+/// 截断模式下的兜底：预估偏短、合成结果仍超过上限时，在帧边界截掉多出的部分。
+/// 边下载边播放时已经播出的部分不受影响，保存和重播的是截断后的音频
+fn limit_audio_duration(data: Vec<u8>, settings: &AppSettings) -> (Vec<u8>, Option<String>) {
+    if settings.max_audio_seconds == 0 || settings.duration_limit != DurationLimit::Truncate {
+        return (data, None);
+    }
+    match audio::trim_mp3(&data, Duration::from_secs(settings.max_audio_seconds.into())) {
+        Some(trimmed) => (trimmed, Some(format!("合成的音频超过 {} 秒，已截断", settings.max_audio_seconds))),
+        None => (data, None),
+    }
+}
+
+/// 持久化的生成历史文件
+fn records_path() -> PathBuf {
+    config::config_dir().join("history.json")
+}
+
+/// 开启持久化时载入上次保存的生成历史，文件不存在或损坏时从空白开始
+fn load_saved_records(settings: &config::HistorySettings) -> Vec<ContentRecord> {
+    if !settings.persist_records || !records_path().exists() {
+        return Vec::new();
+    }
+    match export::import_content(&records_path()) {
+        Ok(mut records) => {
+            let max_records = settings.max_records.max(1);
+            if records.len() > max_records {
+                records.drain(..records.len() - max_records);
+            }
+            records
+        }
+        Err(e) => {
+            log::warn!("无法载入生成历史: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// 文件名模板的编辑框，下方显示示例文件名或错误；编辑完成且模板有效时返回 true
+fn template_editor(ui: &mut egui::Ui, label: &str, template: &mut String) -> bool {
+    let mut committed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let response = ui
+            .add(egui::TextEdit::singleline(template).desired_width(200.0))
+            .on_hover_text("占位符: {index} 序号、{timestamp} 时间、{date}、{time}、{voice} 发音人、{text} 文本开头（{text:10} 取前10字）");
+        match naming::validate(template) {
+            Ok(()) => {
+                let fields = FileNameFields {
+                    index: 1,
+                    created_at: chrono::Local::now(),
+                    voice: voice_name(0),
+                    text: "欢迎来到直播间，今天我们一起玩新游戏",
+                };
+                ui.weak(format!("示例: {}", naming::render(template, &fields)));
+                committed = response.lost_focus();
+            }
+            Err(e) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+        }
+    });
+    committed
+}
+
+/// 输入框下方的字数统计。`tts` 为 true 时文本会直接送去合成，同时显示预计时长和
+/// 相对百度上限的长度（绿/黄/红）
+fn length_counter(ui: &mut egui::Ui, metadata: &ContentMetadata, tts: bool) {
+    ui.horizontal(|ui| {
+        if !tts {
+            ui.weak(format!("字数: {}", metadata.word_count));
+            return;
+        }
+        ui.weak(format!("字数: {} · 预计时长: {:.1}秒", metadata.word_count, metadata.estimated_duration.as_secs_f32()));
+        let color = match metadata.baidu_level() {
+            LengthLevel::Comfortable => egui::Color32::LIGHT_GREEN,
+            LengthLevel::NearLimit => egui::Color32::from_rgb(255, 191, 0),
+            LengthLevel::OverLimit => egui::Color32::LIGHT_RED,
+        };
+        ui.colored_label(color, format!("● 百度 {}/{}", metadata.baidu_len, content::BAIDU_MAX_BYTES))
+            .on_hover_text("按 GBK 字节估算，汉字占 2 字节；超出上限的文本会被百度整段拒绝");
+    });
+}
+
+/// “网络”面板中最近一次连接测试报告的服务信息，以及与本程序用法不符之处
+fn server_info_grid(ui: &mut egui::Ui, info: &ServerInfo) {
+    egui::Grid::new("server_info_grid").num_columns(2).show(ui, |ui| {
+        ui.label("百度权限:");
+        if info.baidu_scopes.is_empty() {
+            ui.weak("未返回");
+        } else {
+            ui.label(info.baidu_scopes.join(" ")).on_hover_text("百度访问令牌附带的权限列表");
+        }
+        ui.end_row();
+        ui.label("DeepSeek 模型:");
+        match &info.deepseek_models {
+            Some(Ok(models)) => {
+                ui.label(models.join(", "));
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::LIGHT_RED, e);
+            }
+            None => {
+                ui.weak("未设置密钥");
             }
         }
+        ui.end_row();
+    });
+    for warning in info.warnings() {
+        ui.colored_label(egui::Color32::YELLOW, format!("⚠ {}", warning));
+    }
+}
+
+/// 输入框有焦点且内容为空（或正显示历史记录）时，用上/下方向键翻阅历史，换入后光标移到末尾
+fn browse_history(ui: &egui::Ui, id: egui::Id, text: &mut String, history: &mut InputHistory) {
+    if !ui.memory(|memory| memory.has_focus(id)) || !history.can_browse(text) {
+        return;
+    }
+    // 先取走按键，文本框就不会再把它当作移动光标
+    let recalled = if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp)) {
+        history.previous()
+    } else if ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown)) {
+        history.next()
+    } else {
+        None
+    };
+    let Some(recalled) = recalled else { return };
+    *text = recalled.to_string();
+    let mut state = egui::text_edit::TextEditState::load(ui.ctx(), id).unwrap_or_default();
+    let end = egui::text::CCursor::new(text.chars().count());
+    state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end)));
+    state.store(ui.ctx(), id);
+}
+
+/// 最近的历史记录下拉菜单，最新的在最上面；返回选中的条目和是否清空了历史
+fn history_menu(ui: &mut egui::Ui, history: &mut InputHistory) -> (Option<String>, bool) {
+    let mut selected = None;
+    let mut cleared = false;
+    ui.add_enabled_ui(!history.is_empty(), |ui| {
+        ui.menu_button("🕘", |ui| {
+            for entry in history.entries().iter().rev() {
+                let label = if entry.chars().count() > 40 {
+                    format!("{}…", entry.chars().take(40).collect::<String>())
+                } else {
+                    entry.clone()
+                };
+                if ui.button(label).on_hover_text(entry).clicked() {
+                    selected = Some(entry.clone());
+                    ui.close_menu();
+                }
+            }
+            ui.separator();
+            if ui.button("🗑 清空历史").clicked() {
+                cleared = true;
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text("最近提交的内容");
+    });
+    if cleared {
+        history.clear();
+    }
+    (selected, cleared)
+}
 
-        ctx.request_repaint();
+/// 启动窗口的大小、位置和最大化状态取自配置
+fn initial_viewport(window: &WindowSettings) -> egui::ViewportBuilder {
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_inner_size(window.size())
+        .with_min_inner_size([WindowSettings::MIN_WIDTH, WindowSettings::MIN_HEIGHT])
+        .with_maximized(window.maximized);
+    if let Some(position) = window.position() {
+        viewport = viewport.with_position(position);
     }
+    viewport
+}
+
+fn apply_ducking(ducking: &Ducking, settings: &DuckingSettings) {
+    ducking.configure(
+        settings.enabled,
+        settings.threshold_db,
+        settings.amount_db,
+        Duration::from_millis(settings.attack_ms as u64),
+        Duration::from_millis(settings.release_ms as u64),
+    );
+}
+
+/// 配置中需要在日志中隐藏的值：API Key、认证代理的凭据和附加请求头的值
+fn config_secrets(config: &Config) -> Vec<&str> {
+    let keys = &config.api_keys;
+    let mut secrets = vec![keys.deepseek_api_key.as_str(), keys.baidu_api_key.as_str(), keys.baidu_secret_key.as_str()];
+    secrets.extend(config.baidu.auth.secrets());
+    secrets.extend(config.network.extra_headers.values().map(String::as_str));
+    secrets
+}
+
+/// 让日志脱敏和级别跟随配置
+fn apply_logging_config(config: &Config) {
+    logging::set_secrets(config_secrets(config));
+    logging::set_level(logging::parse_level(&config.app_settings.log_level));
 }
 
 // --- Main Function ---
 
+/// 配置文件损坏时询问是否从最近的有效备份恢复
+fn offer_backup_restore() {
+    let Some(backup) = config::latest_valid_backup() else {
+        log::error!("config.toml 无法解析，且没有可用的备份");
+        return;
+    };
+    let restore = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("配置文件损坏")
+        .set_description(format!("config.toml 无法解析。是否从备份 {} 恢复？\n损坏的文件会另存为 config.toml.corrupt。", backup.display()))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show();
+    if restore == rfd::MessageDialogResult::Yes {
+        match config::restore_backup(&backup) {
+            Ok(()) => log::info!("已从 {} 恢复配置", backup.display()),
+            Err(e) => log::error!("恢复配置失败: {}", e),
+        }
+    }
+}
+
+/// 配置无法加载时说明原因，询问是否用设置向导重新生成；返回 false 表示退出
+fn ask_regenerate_config(error: &str) -> bool {
+    let answer = rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Warning)
+        .set_title("无法加载配置文件")
+        .set_description(format!(
+            "config.toml 无法加载：\n{}\n\n可以退出后按提示的行号修改该文件，或打开设置向导重新生成（原文件会备份为 config.toml.bak）。\n\n是否打开设置向导？",
+            error
+        ))
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show();
+    answer == rfd::MessageDialogResult::Yes
+}
+
+/// 窗口还没有创建或已经关闭时，用系统对话框报告错误
+fn show_error_dialog(title: &str, description: &str) {
+    rfd::MessageDialog::new()
+        .set_level(rfd::MessageLevel::Error)
+        .set_title(title)
+        .set_description(description)
+        .set_buttons(rfd::MessageButtons::Ok)
+        .show();
+}
+
 fn main() {
-    env_logger::init();
+    logging::init(log::LevelFilter::Info);
+    // config.toml 只由设置向导在完成时写出；中途关闭向导时下次启动仍会进入向导
+    let first_run = !config::config_exists();
+    if first_run {
+        log::info!("未找到 config.toml，进入设置向导");
+    } else if config::config_is_corrupt() {
+        offer_backup_restore();
+    }
+    let config = if first_run {
+        None
+    } else {
+        match load_config() {
+            Ok(config) => {
+                apply_logging_config(&config);
+                Some(config)
+            }
+            Err(e) => {
+                log::error!("加载 config.toml 失败: {}", e);
+                if !ask_regenerate_config(&e.to_string()) {
+                    return;
+                }
+                None
+            }
+        }
+    };
+    let window = config.as_ref().map(|config| config.window.clone()).unwrap_or_default();
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 700.0]),
+        viewport: initial_viewport(&window),
         ..Default::default()
     };
-    let config = load_config().expect("加载 config.toml 失败");
-    
-    eframe::run_native(
+    let setup_reason = if first_run {
+        "首次运行，请完成以下设置。"
+    } else {
+        "config.toml 无法加载，请重新完成以下设置。"
+    };
+
+    let result = eframe::run_native(
         "TTSmate v1.2.1",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             let mut fonts = egui::FontDefinitions::default();
             let font_paths = ["C:/Windows/Fonts/msyh.ttf", "C:/Windows/Fonts/deng.ttf", "C:/Windows/Fonts/simhei.ttf", "C:/Windows/Fonts/simsun.ttc"];
             for path in font_paths {
                 if let Ok(font_data) = std::fs::read(path) {
-                    let font_name = path.split('/').last().unwrap_or("unknown_font").to_string();
+                    let font_name = path.split('/').next_back().unwrap_or("unknown_font").to_string();
                     fonts.font_data.insert(font_name.clone(), egui::FontData::from_owned(font_data));
                     fonts.families.entry(egui::FontFamily::Proportional).or_default().insert(0, font_name.clone());
                     fonts.families.entry(egui::FontFamily::Monospace).or_default().insert(0, font_name);
@@ -473,14 +4300,77 @@ fn main() {
             }
             cc.egui_ctx.set_fonts(fonts);
 
-            match TTSApp::new(config) {
-                Ok(app) => Ok(Box::new(app)),
+            let Some(config) = config else {
+                return Ok(Box::new(Launcher::Setup(Box::new(SetupWizard::new(setup_reason)))));
+            };
+            match TTSApp::new(config, &cc.egui_ctx) {
+                Ok(app) => Ok(Box::new(Launcher::Running(Box::new(app)))),
                 Err(e) => {
                     log::error!("应用初始化失败: {}", e);
                     Err(e)
                 }
             }
         }),
-    )
-    .unwrap();
-} 
\ No newline at end of file
+    );
+    if let Err(e) = result {
+        log::error!("启动失败: {}", e);
+        let hint = match e {
+            eframe::Error::AppCreation(_) => "请确认音频输出设备已连接并在系统中启用，然后重新启动。",
+            _ => "无法创建窗口，请更新显卡驱动，或确认系统支持 OpenGL 2.0 及以上版本。",
+        };
+        show_error_dialog("TTSmate 无法启动", &format!("{}\n\n{}", e, hint));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limited(max_audio_seconds: u32, duration_limit: DurationLimit) -> AppSettings {
+        AppSettings { max_audio_seconds, duration_limit, ..AppSettings::default() }
+    }
+
+    /// 20 句、共 160 字，默认语速下预计约 36 秒
+    fn long_text() -> String {
+        "这是一个测试句子。".repeat(20)
+    }
+
+    #[test]
+    fn text_within_the_limit_is_left_alone() {
+        for mode in [DurationLimit::Warn, DurationLimit::Truncate] {
+            assert_eq!(limit_text_duration("短句。".to_string(), 5, &limited(10, mode)), ("短句。".to_string(), None));
+            assert_eq!(limit_text_duration(long_text(), 5, &limited(0, mode)), (long_text(), None));
+        }
+    }
+
+    #[test]
+    fn warn_mode_only_reports_an_over_long_estimate() {
+        let (text, notice) = limit_text_duration(long_text(), 5, &limited(10, DurationLimit::Warn));
+        assert_eq!(text, long_text());
+        assert_eq!(notice.as_deref(), Some("预计时长 36 秒，超过上限 10 秒"));
+    }
+
+    #[test]
+    fn truncate_mode_cuts_the_text_to_fit_at_a_sentence_end() {
+        let max = Duration::from_secs(10);
+        let (text, notice) = limit_text_duration(long_text(), 5, &limited(10, DurationLimit::Truncate));
+        // 默认语速每秒 4.5 字，10 秒内最多 45 字，即完整的 5 句
+        assert_eq!(text, "这是一个测试句子。".repeat(5));
+        assert!(content::estimate_duration(content::count_words(&text), 5) <= max);
+        assert_eq!(notice.as_deref(), Some("预计时长 36 秒，超过上限 10 秒，只朗读前 45 字"));
+        // 语速越快，能保留的句子越多
+        let (faster, _) = limit_text_duration(long_text(), 15, &limited(10, DurationLimit::Truncate));
+        assert!(faster.len() > text.len() && long_text().starts_with(&faster));
+    }
+
+    #[test]
+    fn audio_is_only_trimmed_in_truncate_mode() {
+        let mp3 = audio::silent_mp3(100);
+        assert_eq!(limit_audio_duration(mp3.clone(), &limited(1, DurationLimit::Warn)), (mp3.clone(), None));
+        assert_eq!(limit_audio_duration(mp3.clone(), &limited(0, DurationLimit::Truncate)), (mp3.clone(), None));
+        assert_eq!(limit_audio_duration(mp3.clone(), &limited(5, DurationLimit::Truncate)), (mp3.clone(), None));
+        let (trimmed, notice) = limit_audio_duration(mp3, &limited(1, DurationLimit::Truncate));
+        assert_eq!(trimmed, audio::silent_mp3(38));
+        assert_eq!(notice.as_deref(), Some("合成的音频超过 1 秒，已截断"));
+    }
+}
@@ -0,0 +1,281 @@
+//! 低延迟监听输出：绕过 rodio 的混音器和缓冲，直接用一个小缓冲区的 cpal 输出流
+//! 播放解码后的样本，样本经由无锁环形缓冲区从解码线程送到音频回调。
+//!
+//! 适合卡拉 OK、配音等需要即时听到声音的场景。缓冲越小越容易因为解码线程
+//! 来不及填充而产生爆音，因此默认关闭，需要在界面中开启。
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use rodio::cpal::traits::{DeviceTrait, StreamTrait};
+use rodio::cpal::{self, FromSample, SizedSample};
+use rodio::source::UniformSourceIterator;
+use rodio::Source;
+
+/// 每次向环形缓冲区写入的样本数
+const FEED_CHUNK: usize = 256;
+/// 缓冲区满时解码线程的等待间隔
+const FEED_WAIT: Duration = Duration::from_millis(1);
+
+/// 目标延迟的允许范围
+const MIN_LATENCY: Duration = Duration::from_millis(2);
+const MAX_LATENCY: Duration = Duration::from_secs(1);
+/// 设备缓冲帧数下限，更小的缓冲几乎没有设备支持
+const MIN_BUFFER_FRAMES: u32 = 16;
+
+/// 低延迟输出流的参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorConfig {
+    /// 目标总延迟：设备缓冲加环形缓冲区
+    pub latency: Duration,
+    /// 设备缓冲帧数，`None` 时取目标延迟的一半
+    pub buffer_frames: Option<u32>,
+}
+
+impl MonitorConfig {
+    /// 检查与设备无关的取值范围；缓冲与延迟的关系取决于采样率，在打开时检查
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_LATENCY..=MAX_LATENCY).contains(&self.latency) {
+            return Err(format!(
+                "目标延迟必须在 {}–{} ms 之间",
+                MIN_LATENCY.as_millis(),
+                MAX_LATENCY.as_millis()
+            ));
+        }
+        if let Some(frames) = self.buffer_frames {
+            if frames < MIN_BUFFER_FRAMES {
+                return Err(format!("设备缓冲不能小于 {} 帧", MIN_BUFFER_FRAMES));
+            }
+        }
+        Ok(())
+    }
+
+    /// 在给定采样率下确定设备缓冲帧数，并按设备支持的范围截断
+    fn buffer_size(&self, sample_rate: u32, supported: &cpal::SupportedBufferSize) -> Result<cpal::BufferSize, String> {
+        let latency_frames = frames_for(self.latency, sample_rate);
+        let requested = self.buffer_frames.unwrap_or(latency_frames / 2);
+        // 设备缓冲不小于总延迟时环形缓冲区没有余量，任何抖动都会欠载
+        if requested >= latency_frames {
+            return Err(format!(
+                "设备缓冲 {} 帧（{:.1} ms）不小于目标延迟 {} ms",
+                requested,
+                requested as f64 * 1000.0 / sample_rate as f64,
+                self.latency.as_millis()
+            ));
+        }
+        Ok(match supported {
+            cpal::SupportedBufferSize::Range { min, max } => {
+                let frames = requested.clamp(*min, *max);
+                if frames != requested {
+                    log::warn!("设备缓冲 {} 帧超出设备支持范围 {}–{}，已调整为 {} 帧", requested, min, max, frames);
+                }
+                cpal::BufferSize::Fixed(frames)
+            }
+            cpal::SupportedBufferSize::Unknown => cpal::BufferSize::Default,
+        })
+    }
+}
+
+fn frames_for(duration: Duration, sample_rate: u32) -> u32 {
+    (duration.as_secs_f64() * sample_rate as f64).ceil().max(1.0) as u32
+}
+
+/// 回调线程与解码线程共享的状态
+#[derive(Default)]
+struct Shared {
+    /// 每次 `play`/`stop` 加一，旧的解码线程看到变化后退出
+    generation: AtomicU64,
+    /// 要求回调丢弃缓冲区中尚未播放的样本
+    flush: AtomicBool,
+    paused: AtomicBool,
+    /// 解码线程仍在写入
+    feeding: AtomicBool,
+    /// 最近一次回调测得的输出延迟（微秒）
+    latency_us: AtomicU64,
+}
+
+pub struct LowLatencyOutput {
+    _stream: cpal::Stream,
+    shared: Arc<Shared>,
+    producer: Arc<Mutex<HeapProd<f32>>>,
+    sample_rate: u32,
+    channels: u16,
+    config: MonitorConfig,
+    /// 实际使用的设备缓冲帧数，设备不支持指定时为 `None`
+    buffer_frames: Option<u32>,
+}
+
+impl LowLatencyOutput {
+    /// 在 `device` 上打开输出流，目标延迟决定环形缓冲区的大小，
+    /// 环形缓冲区吸收解码抖动，设备缓冲决定回调间隔
+    pub fn open(device: &cpal::Device, monitor_config: MonitorConfig) -> Result<Self, String> {
+        monitor_config.validate()?;
+        let default_config = device.default_output_config().map_err(|e| format!("无法获取设备输出格式: {}", e))?;
+        let sample_rate = default_config.sample_rate().0;
+        let channels = default_config.channels();
+        let buffer_size = monitor_config.buffer_size(sample_rate, default_config.buffer_size())?;
+        let config = cpal::StreamConfig {
+            channels,
+            sample_rate: default_config.sample_rate(),
+            buffer_size,
+        };
+
+        let latency_frames = frames_for(monitor_config.latency, sample_rate);
+        let ring = HeapRb::<f32>::new(latency_frames as usize * channels as usize);
+        let (producer, consumer) = ring.split();
+        let shared = Arc::new(Shared::default());
+
+        let stream = match default_config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(device, &config, consumer, shared.clone()),
+            cpal::SampleFormat::I16 => build_stream::<i16>(device, &config, consumer, shared.clone()),
+            cpal::SampleFormat::U16 => build_stream::<u16>(device, &config, consumer, shared.clone()),
+            cpal::SampleFormat::I32 => build_stream::<i32>(device, &config, consumer, shared.clone()),
+            format => return Err(format!("不支持的设备样本格式: {}", format)),
+        }?;
+        stream.play().map_err(|e| format!("无法启动输出流: {}", e))?;
+
+        Ok(Self {
+            _stream: stream,
+            shared,
+            producer: Arc::new(Mutex::new(producer)),
+            sample_rate,
+            channels,
+            config: monitor_config,
+            buffer_frames: match buffer_size {
+                cpal::BufferSize::Fixed(frames) => Some(frames),
+                cpal::BufferSize::Default => None,
+            },
+        })
+    }
+
+    /// 停止当前音源并开始播放 `source`，重采样和声道转换在解码线程中完成
+    pub fn play<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let generation = self.shared.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.shared.flush.store(true, Ordering::SeqCst);
+        self.shared.paused.store(false, Ordering::SeqCst);
+        self.shared.feeding.store(true, Ordering::SeqCst);
+
+        let samples = UniformSourceIterator::<S, f32>::new(source, self.channels, self.sample_rate);
+        let shared = self.shared.clone();
+        let producer = self.producer.clone();
+        thread::spawn(move || feed(samples, generation, &shared, &producer));
+    }
+
+    pub fn stop(&self) {
+        self.shared.generation.fetch_add(1, Ordering::SeqCst);
+        self.shared.flush.store(true, Ordering::SeqCst);
+        self.shared.feeding.store(false, Ordering::SeqCst);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.shared.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// 解码已结束且缓冲区已播完
+    pub fn is_idle(&self) -> bool {
+        !self.shared.feeding.load(Ordering::SeqCst) && self.producer.lock().is_ok_and(|producer| producer.is_empty())
+    }
+
+    pub fn target_latency(&self) -> Duration {
+        self.config.latency
+    }
+
+    /// 实际使用的设备缓冲时长
+    pub fn buffer_latency(&self) -> Option<Duration> {
+        self.buffer_frames.map(|frames| Duration::from_secs_f64(frames as f64 / self.sample_rate as f64))
+    }
+
+    /// 实际达到的延迟：环形缓冲区中待播放的样本加上设备报告的输出延迟
+    pub fn measured_latency(&self) -> Duration {
+        Duration::from_micros(self.shared.latency_us.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for LowLatencyOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn feed<I>(mut samples: I, generation: u64, shared: &Shared, producer: &Mutex<HeapProd<f32>>)
+where
+    I: Iterator<Item = f32>,
+{
+    let mut chunk = Vec::with_capacity(FEED_CHUNK);
+    loop {
+        if chunk.is_empty() {
+            chunk.extend(samples.by_ref().take(FEED_CHUNK));
+            if chunk.is_empty() {
+                break;
+            }
+        }
+        if shared.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        // 回调还没处理上一次的清空请求时先不写，避免新样本被一起丢弃
+        let written = if shared.flush.load(Ordering::SeqCst) {
+            0
+        } else {
+            let Ok(mut producer) = producer.lock() else {
+                return;
+            };
+            producer.push_slice(&chunk)
+        };
+        chunk.drain(..written);
+        if !chunk.is_empty() {
+            thread::sleep(FEED_WAIT);
+        }
+    }
+    if shared.generation.load(Ordering::SeqCst) == generation {
+        shared.feeding.store(false, Ordering::SeqCst);
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut consumer: HeapCons<f32>,
+    shared: Arc<Shared>,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let samples_per_second = config.sample_rate.0 as u64 * config.channels.max(1) as u64;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+                if shared.flush.swap(false, Ordering::SeqCst) {
+                    consumer.clear();
+                }
+                let silence = T::from_sample(0.0f32);
+                if shared.paused.load(Ordering::Relaxed) {
+                    data.fill(silence);
+                    return;
+                }
+                let mut played = 0;
+                for (out, sample) in data.iter_mut().zip(consumer.pop_iter()) {
+                    *out = T::from_sample(sample);
+                    played += 1;
+                }
+                // 缓冲区欠载时补静音，而不是重复上一段数据
+                data[played..].fill(silence);
+
+                let timestamp = info.timestamp();
+                let device_delay = timestamp.playback.duration_since(&timestamp.callback).unwrap_or_default();
+                let queued = (consumer.occupied_len() + data.len()) as u64;
+                let latency_us = device_delay.as_micros() as u64 + queued * 1_000_000 / samples_per_second;
+                shared.latency_us.store(latency_us, Ordering::Relaxed);
+            },
+            |e| log::error!("低延迟输出流错误: {}", e),
+            None,
+        )
+        .map_err(|e| format!("无法创建低延迟输出流: {}", e))
+}
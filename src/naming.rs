@@ -0,0 +1,204 @@
+//! 保存音频时的文件名模板。
+//!
+//! 模板由普通文字和占位符组成，例如 `{index}_{voice}_{text:10}`。支持的占位符：
+//!
+//! - `{index}`：序号，三位补零；批量生成时为行号，其他情况为本次运行中合成的第几段
+//! - `{timestamp}`：合成时间 `20240101_120000`，`{date}`、`{time}` 分别只取日期和时间
+//! - `{voice}`：发音人名称
+//! - `{text}`：文本开头 20 个字符，`{text:N}` 取前 N 个字符
+//!
+//! 渲染结果统一经过 `sanitize_filename`，重名时在扩展名前追加 `_2`、`_3`……
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::utils;
+
+/// 自动保存和手动保存的默认模板，与引入模板之前的文件名一致
+pub const DEFAULT_TEMPLATE: &str = "{timestamp}_{text}";
+/// 批量生成的默认模板
+pub const DEFAULT_BATCH_TEMPLATE: &str = "{index}_{text}";
+/// `{text}` 未指定长度时取的字符数
+const DEFAULT_TEXT_CHARS: usize = 20;
+/// 文件名（不含扩展名）的最大字符数
+const MAX_STEM_CHARS: usize = 120;
+
+/// 填入模板的信息
+pub struct FileNameFields<'a> {
+    pub index: usize,
+    pub created_at: DateTime<Local>,
+    pub voice: &'a str,
+    pub text: &'a str,
+}
+
+enum Part<'a> {
+    Literal(&'a str),
+    Index,
+    Timestamp,
+    Date,
+    Time,
+    Voice,
+    Text(usize),
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err("多余的 }".to_string());
+        }
+        if start > 0 {
+            parts.push(Part::Literal(&rest[..start]));
+        }
+        let Some(len) = rest[start..].find('}') else {
+            return Err("缺少 }".to_string());
+        };
+        let placeholder = &rest[start + 1..start + len];
+        parts.push(parse_placeholder(placeholder)?);
+        rest = &rest[start + len + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    Ok(parts)
+}
+
+fn parse_placeholder(placeholder: &str) -> Result<Part<'static>, String> {
+    let (name, arg) = match placeholder.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (placeholder.trim(), None),
+    };
+    let part = match name {
+        "index" => Part::Index,
+        "timestamp" => Part::Timestamp,
+        "date" => Part::Date,
+        "time" => Part::Time,
+        "voice" => Part::Voice,
+        "text" => match arg {
+            None => return Ok(Part::Text(DEFAULT_TEXT_CHARS)),
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(chars) if chars > 0 => return Ok(Part::Text(chars)),
+                _ => return Err(format!("{{text:{}}} 的长度必须是正整数", arg)),
+            },
+        },
+        _ => return Err(format!("未知的占位符 {{{}}}", placeholder)),
+    };
+    match arg {
+        Some(_) => Err(format!("{{{}}} 不接受参数", name)),
+        None => Ok(part),
+    }
+}
+
+/// 检查模板能否使用，出错时返回原因
+pub fn validate(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("模板不能为空".to_string());
+    }
+    parse(template).map(|_| ())
+}
+
+/// 渲染为带 `.mp3` 扩展名的文件名。模板无效或渲染结果为空时退回默认模板
+pub fn render(template: &str, fields: &FileNameFields) -> String {
+    let default = || render_stem(&parse(DEFAULT_TEMPLATE).unwrap_or_default(), fields);
+    let stem = match parse(template) {
+        Ok(parts) => Some(render_stem(&parts, fields)).filter(|stem| !stem.is_empty()).unwrap_or_else(default),
+        Err(_) => default(),
+    };
+    format!("{}.mp3", stem)
+}
+
+fn render_stem(parts: &[Part], fields: &FileNameFields) -> String {
+    let mut stem = String::new();
+    for part in parts {
+        match *part {
+            Part::Literal(text) => stem.push_str(text),
+            Part::Index => stem.push_str(&format!("{:03}", fields.index)),
+            Part::Timestamp => stem.push_str(&fields.created_at.format("%Y%m%d_%H%M%S").to_string()),
+            Part::Date => stem.push_str(&fields.created_at.format("%Y%m%d").to_string()),
+            Part::Time => stem.push_str(&fields.created_at.format("%H%M%S").to_string()),
+            Part::Voice => stem.push_str(fields.voice),
+            Part::Text(chars) => stem.push_str(&utils::sanitize_filename(fields.text, chars)),
+        }
+    }
+    utils::sanitize_filename(&stem, MAX_STEM_CHARS)
+}
+
+/// `dir` 中不与现有文件重名的路径
+pub fn unique_path(dir: &Path, file_name: &str) -> PathBuf {
+    let path = dir.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    (2..)
+        .map(|n| {
+            if extension.is_empty() {
+                dir.join(format!("{}_{}", stem, n))
+            } else {
+                dir.join(format!("{}_{}.{}", stem, n, extension))
+            }
+        })
+        .find(|path| !path.exists())
+        .expect("unbounded counter")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn fields<'a>(voice: &'a str, text: &'a str) -> FileNameFields<'a> {
+        FileNameFields { index: 7, created_at: Local.with_ymd_and_hms(2024, 3, 5, 20, 30, 0).unwrap(), voice, text }
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let fields = fields("度小美", "欢迎来到 直播间！今天有新品");
+        assert_eq!(render("{index}_{voice}_{text:6}", &fields), "007_度小美_欢迎来到_直.mp3");
+        assert_eq!(render("{date}-{time}", &fields), "20240305-203000.mp3");
+        assert_eq!(render(DEFAULT_TEMPLATE, &fields), "20240305_203000_欢迎来到_直播间！今天有新品.mp3");
+        assert_eq!(render("{ text : 2 }", &fields), "欢迎.mp3");
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for (template, reason) in [
+            ("{index}}", "多余的 }"),
+            ("a}b", "多余的 }"),
+            ("{index", "缺少 }"),
+            ("{text:0}", "正整数"),
+            ("{text:abc}", "正整数"),
+            ("{voice:3}", "不接受参数"),
+            ("{speaker}", "未知的占位符 {speaker}"),
+            ("  ", "不能为空"),
+        ] {
+            let error = validate(template).unwrap_err();
+            assert!(error.contains(reason), "{}: {}", template, error);
+        }
+        assert_eq!(validate("直播_{index}"), Ok(()));
+    }
+
+    #[test]
+    fn invalid_or_empty_results_fall_back_to_the_default_template() {
+        let fields = fields("", "你好");
+        let default = render(DEFAULT_TEMPLATE, &fields);
+        assert_eq!(default, "20240305_203000_你好.mp3");
+        assert_eq!(render("{text:0}", &fields), default);
+        assert_eq!(render("{voice}", &fields), default);
+        assert_eq!(render("??", &fields), default);
+    }
+
+    #[test]
+    fn existing_files_get_a_numbered_suffix() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(unique_path(dir.path(), "a.mp3"), dir.path().join("a.mp3"));
+        std::fs::write(dir.path().join("a.mp3"), b"").unwrap();
+        assert_eq!(unique_path(dir.path(), "a.mp3"), dir.path().join("a_2.mp3"));
+        std::fs::write(dir.path().join("a_2.mp3"), b"").unwrap();
+        assert_eq!(unique_path(dir.path(), "a.mp3"), dir.path().join("a_3.mp3"));
+        std::fs::write(dir.path().join("b"), b"").unwrap();
+        assert_eq!(unique_path(dir.path(), "b"), dir.path().join("b_2"));
+    }
+}
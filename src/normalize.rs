@@ -0,0 +1,702 @@
+//! 合成前的文本规范化：把数字、日期、时间、单位和常见缩写展开成读法，
+//! 避免百度把 "2024"、"3.5kg"、"Dr." 读错。
+//!
+//! 读法表都放在文件开头，新增单位、货币或缩写只需要加一行。
+//! 用户自定义的发音词典在规范化之前替换。
+
+use crate::config::PronunciationEntry;
+
+/// 展开后的读法所用的语言，取自百度的 `lan` 参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Zh,
+    En,
+}
+
+impl Lang {
+    /// 百度目前只支持 `zh`（中英混读），其余取值按英文处理
+    pub fn from_code(code: &str) -> Self {
+        if code.trim().eq_ignore_ascii_case("en") {
+            Lang::En
+        } else {
+            Lang::Zh
+        }
+    }
+}
+
+/// 跟在数字后的单位：(写法, 中文读法, 英文单数, 英文复数)。
+/// 不收录 "s"、"h" 这类容易和普通后缀（如 "1990s"）混淆的单字母单位
+const UNITS: &[(&str, &str, &str, &str)] = &[
+    ("km/h", "公里每小时", "kilometer per hour", "kilometers per hour"),
+    ("mph", "英里每小时", "mile per hour", "miles per hour"),
+    ("km", "公里", "kilometer", "kilometers"),
+    ("cm", "厘米", "centimeter", "centimeters"),
+    ("mm", "毫米", "millimeter", "millimeters"),
+    ("m", "米", "meter", "meters"),
+    ("kg", "千克", "kilogram", "kilograms"),
+    ("mg", "毫克", "milligram", "milligrams"),
+    ("g", "克", "gram", "grams"),
+    ("ml", "毫升", "milliliter", "milliliters"),
+    ("mL", "毫升", "milliliter", "milliliters"),
+    ("L", "升", "liter", "liters"),
+    ("ms", "毫秒", "millisecond", "milliseconds"),
+    ("min", "分钟", "minute", "minutes"),
+    ("KB", "千字节", "kilobyte", "kilobytes"),
+    ("MB", "兆字节", "megabyte", "megabytes"),
+    ("GB", "吉字节", "gigabyte", "gigabytes"),
+    ("TB", "太字节", "terabyte", "terabytes"),
+    ("Hz", "赫兹", "hertz", "hertz"),
+    ("kHz", "千赫兹", "kilohertz", "kilohertz"),
+    ("°C", "摄氏度", "degree Celsius", "degrees Celsius"),
+    ("℃", "摄氏度", "degree Celsius", "degrees Celsius"),
+    ("°F", "华氏度", "degree Fahrenheit", "degrees Fahrenheit"),
+    ("℉", "华氏度", "degree Fahrenheit", "degrees Fahrenheit"),
+];
+
+/// 写在数字前的货币符号：(符号, 中文读法, 英文单数, 英文复数)
+const CURRENCIES: &[(char, &str, &str, &str)] = &[
+    ('$', "美元", "dollar", "dollars"),
+    ('¥', "元", "yuan", "yuan"),
+    ('￥', "元", "yuan", "yuan"),
+    ('€', "欧元", "euro", "euros"),
+    ('£', "英镑", "pound", "pounds"),
+];
+
+/// 常见缩写：(写法, 中文读法, 英文读法)，中文读法为空时在中文模式下保留原文
+const ABBREVIATIONS: &[(&str, &str, &str)] = &[
+    ("Dr.", "", "Doctor"),
+    ("Mr.", "", "Mister"),
+    ("Mrs.", "", "Missus"),
+    ("Prof.", "", "Professor"),
+    ("etc.", "等等", "et cetera"),
+    ("vs.", "对", "versus"),
+    ("e.g.", "例如", "for example"),
+    ("i.e.", "即", "that is"),
+    ("approx.", "约", "approximately"),
+];
+
+const ZH_DIGITS: [char; 10] = ['零', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+const EN_ONES: [&str; 20] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten", "eleven", "twelve",
+    "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const EN_TENS: [&str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const EN_SCALES: [&str; 7] = ["", "thousand", "million", "billion", "trillion", "quadrillion", "quintillion"];
+const EN_MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December",
+];
+/// 超过这个位数的整数（如电话号码、编号）逐位朗读
+const MAX_CARDINAL_DIGITS: usize = 16;
+
+/// 把文本中的数字、日期、时间、单位、货币和常见缩写展开为 `lang` 的读法，
+/// 其余内容原样保留，因此中英混合的文本也可以直接处理
+pub fn normalize_for_tts(text: &str, lang: Lang) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match expand_at(&chars, i, lang) {
+            Some((spoken, len)) => {
+                push_spoken(&mut out, &spoken, chars.get(i + len).copied(), lang);
+                i += len;
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// 按发音词典替换词语。较长的词条优先，已替换的内容不会再被其他词条匹配；
+/// 以英文字母或数字开头/结尾的词条只匹配完整单词，避免 "AI" 命中 "MAIL"
+pub fn apply_pronunciations(text: &str, entries: &[PronunciationEntry]) -> String {
+    let mut entries: Vec<(Vec<char>, &PronunciationEntry)> = entries
+        .iter()
+        .filter(|entry| !entry.word.trim().is_empty())
+        .map(|entry| (entry.word.chars().collect(), entry))
+        .collect();
+    if entries.is_empty() {
+        return text.to_string();
+    }
+    entries.sort_by_key(|(word, _)| std::cmp::Reverse(word.len()));
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let matched = entries.iter().find(|(word, entry)| word_matches_at(&chars, i, word, entry.case_sensitive));
+        match matched {
+            Some((word, entry)) => {
+                out.push_str(&entry.replacement);
+                i += word.len();
+            }
+            None => {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// `word` 是否出现在 `chars` 的 `start` 处；以英文字母或数字开头/结尾的词要求前后不紧接字母数字
+pub fn word_matches_at(chars: &[char], start: usize, word: &[char], case_sensitive: bool) -> bool {
+    let Some(candidate) = chars.get(start..start + word.len()) else {
+        return false;
+    };
+    let same = if case_sensitive {
+        candidate == word
+    } else {
+        candidate.iter().zip(word).all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+    };
+    if !same {
+        return false;
+    }
+    let is_word_char = |c: &char| c.is_ascii_alphanumeric();
+    let starts_word = word.first().is_some_and(is_word_char);
+    let ends_word = word.last().is_some_and(is_word_char);
+    let before = start.checked_sub(1).and_then(|p| chars.get(p));
+    let after = chars.get(start + word.len());
+    let joined_before = starts_word && before.is_some_and(is_word_char);
+    let joined_after = ends_word && after.is_some_and(is_word_char);
+    !joined_before && !joined_after
+}
+
+/// 英文读法需要和相邻的字母数字隔开
+fn push_spoken(out: &mut String, spoken: &str, next: Option<char>, lang: Lang) {
+    let separate = lang == Lang::En || spoken.starts_with(|c: char| c.is_ascii_alphabetic());
+    if separate && out.ends_with(|c: char| c.is_ascii_alphanumeric()) {
+        out.push(' ');
+    }
+    out.push_str(spoken);
+    if separate && next.is_some_and(|c| c.is_ascii_alphanumeric()) {
+        out.push(' ');
+    }
+}
+
+/// 尝试在 `start` 处展开一个记号，返回读法和消耗的字符数
+fn expand_at(chars: &[char], start: usize, lang: Lang) -> Option<(String, usize)> {
+    let c = chars[start];
+    let prev = start.checked_sub(1).map(|p| chars[p]);
+    // "mp3"、"A4" 这类字母后紧跟的数字是名称的一部分，不展开
+    let after_word = prev.is_some_and(|p| p.is_ascii_alphanumeric() || p == '.' || p == '_');
+
+    if let Some(&(_, zh, one, many)) = CURRENCIES.iter().find(|(symbol, ..)| *symbol == c) {
+        let (number, len) = parse_number(chars, start + 1)?;
+        let amount = speak_number(&number, lang);
+        return Some(match lang {
+            Lang::Zh => (format!("{}{}", amount, zh), len + 1),
+            Lang::En => (format!("{} {}", amount, if number.is_one() { one } else { many }), len + 1),
+        });
+    }
+    if c == '-' && !after_word && chars.get(start + 1).is_some_and(char::is_ascii_digit) {
+        let (spoken, len) = expand_number(chars, start + 1, lang)?;
+        let sign = match lang {
+            Lang::Zh => "负",
+            Lang::En => "minus ",
+        };
+        return Some((format!("{}{}", sign, spoken), len + 1));
+    }
+    if c.is_ascii_digit() && !after_word {
+        return expand_number(chars, start, lang);
+    }
+    if c.is_ascii_alphabetic() && !prev.is_some_and(|p| p.is_ascii_alphabetic()) {
+        return expand_abbreviation(chars, start, lang);
+    }
+    None
+}
+
+fn expand_abbreviation(chars: &[char], start: usize, lang: Lang) -> Option<(String, usize)> {
+    let (abbr, zh, en) = ABBREVIATIONS
+        .iter()
+        .filter(|(abbr, ..)| matches_at(chars, start, abbr))
+        .max_by_key(|(abbr, ..)| abbr.chars().count())?;
+    let spoken = match lang {
+        Lang::Zh if zh.is_empty() => return None,
+        Lang::Zh => zh,
+        Lang::En => en,
+    };
+    Some((spoken.to_string(), abbr.chars().count()))
+}
+
+/// `pattern` 出现在 `start` 处，且后面不紧跟字母
+fn matches_at(chars: &[char], start: usize, pattern: &str) -> bool {
+    let mut i = start;
+    for p in pattern.chars() {
+        if chars.get(i) != Some(&p) {
+            return false;
+        }
+        i += 1;
+    }
+    !chars.get(i).is_some_and(|c| c.is_ascii_alphabetic())
+}
+
+/// 从 `start` 处读取连续的数字
+fn digits_at(chars: &[char], start: usize) -> String {
+    chars[start.min(chars.len())..].iter().take_while(|c| c.is_ascii_digit()).collect()
+}
+
+/// 一个数：整数部分（已去掉千位分隔符）和若干小数段。
+/// 多于一个小数段时是版本号之类的点分编号，逐段朗读
+struct Number {
+    integer: String,
+    fractions: Vec<String>,
+}
+
+impl Number {
+    fn is_one(&self) -> bool {
+        self.integer == "1" && self.fractions.is_empty()
+    }
+}
+
+fn parse_number(chars: &[char], start: usize) -> Option<(Number, usize)> {
+    let mut integer = digits_at(chars, start);
+    if integer.is_empty() {
+        return None;
+    }
+    let mut i = start + integer.len();
+    // 千位分隔符：首段不超过三位，之后每段恰好三位
+    if integer.len() <= 3 {
+        while chars.get(i) == Some(&',') {
+            let group = digits_at(chars, i + 1);
+            if group.len() != 3 {
+                break;
+            }
+            integer.push_str(&group);
+            i += 4;
+        }
+    }
+    let mut fractions = Vec::new();
+    while chars.get(i) == Some(&'.') {
+        let fraction = digits_at(chars, i + 1);
+        if fraction.is_empty() {
+            break;
+        }
+        i += 1 + fraction.len();
+        fractions.push(fraction);
+    }
+    Some((Number { integer, fractions }, i - start))
+}
+
+/// 展开从 `start` 开始的数字记号：日期、时间、年份、百分数或带单位的数
+fn expand_number(chars: &[char], start: usize, lang: Lang) -> Option<(String, usize)> {
+    if let Some(date) = expand_date(chars, start, lang) {
+        return Some(date);
+    }
+    if let Some(time) = expand_time(chars, start, lang) {
+        return Some(time);
+    }
+    let (number, mut len) = parse_number(chars, start)?;
+    let next = chars.get(start + len).copied();
+
+    if number.fractions.is_empty() && number.integer.len() == 4 {
+        let year: u32 = number.integer.parse().ok()?;
+        // 中文只有后面跟“年”才按年份读；英文的四位数在常见年份范围内按年份读
+        match lang {
+            Lang::Zh if next == Some('年') => return Some((zh_digits(&number.integer), len)),
+            Lang::En if (1100..2100).contains(&year) => return Some((en_year(year), len)),
+            _ => {}
+        }
+    }
+
+    let spoken = speak_number(&number, lang);
+    if matches!(next, Some('%' | '％')) {
+        len += 1;
+        return Some(match lang {
+            Lang::Zh => (format!("百分之{}", spoken), len),
+            Lang::En => (format!("{} percent", spoken), len),
+        });
+    }
+
+    // 英文常在数字和单位之间留一个空格
+    let unit_start = if next == Some(' ') { start + len + 1 } else { start + len };
+    let unit = UNITS
+        .iter()
+        .filter(|(symbol, ..)| matches_at(chars, unit_start, symbol))
+        .max_by_key(|(symbol, ..)| symbol.chars().count());
+    if let Some(&(symbol, zh, one, many)) = unit {
+        len = unit_start - start + symbol.chars().count();
+        return Some(match lang {
+            Lang::Zh => (format!("{}{}", spoken, zh), len),
+            Lang::En => (format!("{} {}", spoken, if number.is_one() { one } else { many }), len),
+        });
+    }
+    Some((spoken, len))
+}
+
+/// `2024-05-01` 或 `2024/5/1`
+fn expand_date(chars: &[char], start: usize, lang: Lang) -> Option<(String, usize)> {
+    let year = digits_at(chars, start);
+    if year.len() != 4 {
+        return None;
+    }
+    let separator = *chars.get(start + 4).filter(|c| matches!(c, '-' | '/'))?;
+    let month = digits_at(chars, start + 5);
+    let day_start = start + 5 + month.len() + 1;
+    if month.is_empty() || month.len() > 2 || chars.get(day_start - 1) != Some(&separator) {
+        return None;
+    }
+    let day = digits_at(chars, day_start);
+    if day.is_empty() || day.len() > 2 {
+        return None;
+    }
+    let (month_n, day_n): (u64, u64) = (month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month_n) || !(1..=31).contains(&day_n) {
+        return None;
+    }
+    let len = day_start + day.len() - start;
+    Some(match lang {
+        Lang::Zh => (format!("{}年{}月{}日", zh_digits(&year), zh_integer(month_n), zh_integer(day_n)), len),
+        Lang::En => (
+            format!("{} {}, {}", EN_MONTHS[month_n as usize - 1], en_ordinal(day_n), en_year(year.parse().ok()?)),
+            len,
+        ),
+    })
+}
+
+/// `10:30`，分钟必须是两位数，避免把 "3:2" 之类的比分当成时间
+fn expand_time(chars: &[char], start: usize, lang: Lang) -> Option<(String, usize)> {
+    let hour = digits_at(chars, start);
+    if hour.is_empty() || hour.len() > 2 || chars.get(start + hour.len()) != Some(&':') {
+        return None;
+    }
+    let minute = digits_at(chars, start + hour.len() + 1);
+    if minute.len() != 2 {
+        return None;
+    }
+    let (hour_n, minute_n): (u64, u64) = (hour.parse().ok()?, minute.parse().ok()?);
+    if hour_n > 24 || minute_n > 59 {
+        return None;
+    }
+    let len = hour.len() + 1 + minute.len();
+    Some(match (lang, minute_n) {
+        (Lang::Zh, 0) => (format!("{}点整", zh_integer(hour_n)), len),
+        (Lang::Zh, 1..=9) => (format!("{}点零{}分", zh_integer(hour_n), zh_integer(minute_n)), len),
+        (Lang::Zh, _) => (format!("{}点{}分", zh_integer(hour_n), zh_integer(minute_n)), len),
+        (Lang::En, 0) => (format!("{} o'clock", en_integer(hour_n)), len),
+        (Lang::En, 1..=9) => (format!("{} oh {}", en_integer(hour_n), en_integer(minute_n)), len),
+        (Lang::En, _) => (format!("{} {}", en_integer(hour_n), en_integer(minute_n)), len),
+    })
+}
+
+fn speak_number(number: &Number, lang: Lang) -> String {
+    let integer = speak_integer(&number.integer, lang);
+    let mut spoken = integer;
+    for fraction in &number.fractions {
+        // 只有一个小数段时逐位读，点分编号的每段按整数读
+        let part = if number.fractions.len() == 1 {
+            match lang {
+                Lang::Zh => zh_digits(fraction),
+                Lang::En => en_digits(fraction),
+            }
+        } else {
+            speak_integer(fraction, lang)
+        };
+        match lang {
+            Lang::Zh => spoken.push('点'),
+            Lang::En => spoken.push_str(" point "),
+        }
+        spoken.push_str(&part);
+    }
+    spoken
+}
+
+/// 有前导零或位数过多的整数逐位读，其余按数值读
+fn speak_integer(digits: &str, lang: Lang) -> String {
+    let cardinal = (digits.len() == 1 || !digits.starts_with('0')) && digits.len() <= MAX_CARDINAL_DIGITS;
+    match (lang, cardinal.then(|| digits.parse::<u64>().ok()).flatten()) {
+        (Lang::Zh, Some(n)) => zh_integer(n),
+        (Lang::En, Some(n)) => en_integer(n),
+        (Lang::Zh, None) => zh_digits(digits),
+        (Lang::En, None) => en_digits(digits),
+    }
+}
+
+fn zh_digits(digits: &str) -> String {
+    digits.chars().filter_map(|c| c.to_digit(10)).map(|d| ZH_DIGITS[d as usize]).collect()
+}
+
+fn en_digits(digits: &str) -> String {
+    digits.chars().filter_map(|c| c.to_digit(10)).map(|d| EN_ONES[d as usize]).collect::<Vec<_>>().join(" ")
+}
+
+/// 中文读法，每四位一组配“万”“亿”，组内和组间的连续零只读一个“零”
+fn zh_integer(n: u64) -> String {
+    if n == 0 {
+        return "零".to_string();
+    }
+    const GROUP_UNITS: [&str; 5] = ["", "万", "亿", "万亿", "亿亿"];
+    let mut groups = Vec::new();
+    let mut rest = n;
+    while rest > 0 {
+        groups.push(rest % 10_000);
+        rest /= 10_000;
+    }
+    let mut out = String::new();
+    let mut pending_zero = false;
+    for (i, &group) in groups.iter().enumerate().rev() {
+        if group == 0 {
+            pending_zero = !out.is_empty();
+            continue;
+        }
+        if !out.is_empty() && (pending_zero || group < 1000) {
+            out.push('零');
+        }
+        out.push_str(&zh_group(group));
+        out.push_str(GROUP_UNITS[i]);
+        pending_zero = false;
+    }
+    // 10-19 习惯读作“十几”而不是“一十几”
+    match out.strip_prefix("一十") {
+        Some(rest) => format!("十{}", rest),
+        None => out,
+    }
+}
+
+/// 1-9999 的中文读法
+fn zh_group(group: u64) -> String {
+    const PLACES: [(u64, &str); 4] = [(1000, "千"), (100, "百"), (10, "十"), (1, "")];
+    let mut out = String::new();
+    let mut pending_zero = false;
+    for (place, unit) in PLACES {
+        let digit = (group / place % 10) as usize;
+        if digit == 0 {
+            pending_zero = !out.is_empty();
+            continue;
+        }
+        if pending_zero {
+            out.push('零');
+            pending_zero = false;
+        }
+        out.push(ZH_DIGITS[digit]);
+        out.push_str(unit);
+    }
+    out
+}
+
+fn en_integer(n: u64) -> String {
+    if n == 0 {
+        return EN_ONES[0].to_string();
+    }
+    let mut parts = Vec::new();
+    let mut rest = n;
+    let mut scale = 0;
+    while rest > 0 {
+        let group = rest % 1000;
+        if group > 0 {
+            let words = en_below_thousand(group);
+            parts.push(if scale == 0 { words } else { format!("{} {}", words, EN_SCALES[scale]) });
+        }
+        rest /= 1000;
+        scale += 1;
+    }
+    parts.reverse();
+    parts.join(" ")
+}
+
+fn en_below_thousand(n: u64) -> String {
+    let (hundreds, rest) = (n / 100, n % 100);
+    let tail = match rest {
+        0 => String::new(),
+        1..=19 => EN_ONES[rest as usize].to_string(),
+        _ if rest % 10 == 0 => EN_TENS[(rest / 10) as usize].to_string(),
+        _ => format!("{}-{}", EN_TENS[(rest / 10) as usize], EN_ONES[(rest % 10) as usize]),
+    };
+    match (hundreds, tail.is_empty()) {
+        (0, _) => tail,
+        (h, true) => format!("{} hundred", EN_ONES[h as usize]),
+        (h, false) => format!("{} hundred {}", EN_ONES[h as usize], tail),
+    }
+}
+
+/// 年份按两位一组读：1999 → nineteen ninety-nine，2005 → two thousand five
+fn en_year(year: u32) -> String {
+    let (high, low) = (year / 100, year % 100);
+    if year % 1000 < 10 {
+        return en_integer(year as u64);
+    }
+    let low = match low {
+        0 => "hundred".to_string(),
+        1..=9 => format!("oh {}", EN_ONES[low as usize]),
+        _ => en_integer(low as u64),
+    };
+    format!("{} {}", en_integer(high as u64), low)
+}
+
+/// 序数词，用于日期中的“日”
+fn en_ordinal(n: u64) -> String {
+    let cardinal = en_integer(n);
+    let split = cardinal.rfind(['-', ' ']).map_or(0, |i| i + 1);
+    let (head, last) = cardinal.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        tens if tens.ends_with('y') => format!("{}ieth", &tens[..tens.len() - 1]),
+        other => format!("{}th", other),
+    };
+    format!("{}{}", head, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(lang: Lang, cases: &[(&str, &str)]) {
+        for &(input, expected) in cases {
+            assert_eq!(normalize_for_tts(input, lang), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        check(
+            Lang::Zh,
+            &[
+                ("共10个", "共十个"),
+                ("1234", "一千二百三十四"),
+                ("10086", "一万零八十六"),
+                ("100000", "十万"),
+                ("1,000元", "一千元"),
+                ("3.14", "三点一四"),
+                ("50%", "百分之五十"),
+                ("-5", "负五"),
+                ("0086", "零零八六"),
+                ("v1.2.3", "v1.2.3"),
+                ("版本1.2.3", "版本一点二点三"),
+            ],
+        );
+        check(
+            Lang::En,
+            &[
+                ("10 apples", "ten apples"),
+                ("12345", "twelve thousand three hundred forty-five"),
+                ("1,000,000", "one million"),
+                ("3.14", "three point one four"),
+                ("50%", "fifty percent"),
+                ("-5", "minus five"),
+                ("version 1.2.3", "version one point two point three"),
+            ],
+        );
+    }
+
+    #[test]
+    fn dates_and_times() {
+        check(
+            Lang::Zh,
+            &[
+                ("2024年", "二零二四年"),
+                ("2024-05-01", "二零二四年五月一日"),
+                ("2024/12/25", "二零二四年十二月二十五日"),
+                ("10:30", "十点三十分"),
+                ("8:05", "八点零五分"),
+                ("9:00", "九点整"),
+                // 比分不是时间
+                ("3:2", "三:二"),
+            ],
+        );
+        check(
+            Lang::En,
+            &[
+                ("1999", "nineteen ninety-nine"),
+                ("2005", "two thousand five"),
+                ("2024-05-01", "May first, twenty twenty-four"),
+                ("2024/12/22", "December twenty-second, twenty twenty-four"),
+                ("10:30", "ten thirty"),
+                ("8:05", "eight oh five"),
+                ("9:00", "nine o'clock"),
+            ],
+        );
+    }
+
+    #[test]
+    fn units_and_currencies() {
+        check(
+            Lang::Zh,
+            &[
+                ("3.5kg", "三点五千克"),
+                ("100 km", "一百公里"),
+                ("60km/h", "六十公里每小时"),
+                ("25℃", "二十五摄氏度"),
+                ("$5", "五美元"),
+                ("¥99", "九十九元"),
+                // 名称中的数字和没有收录的单位不展开
+                ("mp3", "mp3"),
+                ("A4纸", "A4纸"),
+            ],
+        );
+        check(
+            Lang::En,
+            &[
+                ("1 kg", "one kilogram"),
+                ("3.5kg", "three point five kilograms"),
+                ("$1", "one dollar"),
+                ("€20", "twenty euros"),
+                ("it weighs 2g", "it weighs two grams"),
+            ],
+        );
+    }
+
+    #[test]
+    fn abbreviations() {
+        check(
+            Lang::Zh,
+            &[("苹果vs.香蕉", "苹果对香蕉"), ("水果e.g.苹果", "水果例如苹果"), ("Dr. Wang", "Dr. Wang")],
+        );
+        check(
+            Lang::En,
+            &[
+                ("Dr. Smith", "Doctor Smith"),
+                ("fruit, e.g. apples", "fruit, for example apples"),
+                ("cats vs. dogs", "cats versus dogs"),
+                // 单词中间的字母不当作缩写开头，缩写后紧跟字母也不展开
+                ("CDr. Who", "CDr. Who"),
+                ("Mr.Smith", "Mr.Smith"),
+            ],
+        );
+    }
+
+    fn entry(word: &str, replacement: &str, case_sensitive: bool) -> PronunciationEntry {
+        PronunciationEntry { word: word.to_string(), replacement: replacement.to_string(), case_sensitive }
+    }
+
+    #[test]
+    fn pronunciations_respect_word_boundaries() {
+        let entries = [entry("AI", "人工智能", false)];
+        assert_eq!(apply_pronunciations("AI 很强，ai也行", &entries), "人工智能 很强，人工智能也行");
+        assert_eq!(apply_pronunciations("check your MAIL", &entries), "check your MAIL");
+        assert_eq!(apply_pronunciations("AI2 和 AIs", &entries), "AI2 和 AIs");
+        // 中文词条没有单词边界
+        let entries = [entry("重庆", "重(chong2)庆", false)];
+        assert_eq!(apply_pronunciations("去重庆玩", &entries), "去重(chong2)庆玩");
+    }
+
+    #[test]
+    fn longer_pronunciations_win() {
+        let entries = [entry("GPT", "G P T", false), entry("ChatGPT", "Chat G P T", false)];
+        assert_eq!(apply_pronunciations("ChatGPT 基于 GPT", &entries), "Chat G P T 基于 G P T");
+        // 替换结果不会再被其他词条匹配
+        let entries = [entry("A", "B", true), entry("B", "C", true)];
+        assert_eq!(apply_pronunciations("A B", &entries), "B C");
+    }
+
+    #[test]
+    fn case_sensitive_pronunciations() {
+        let entries = [entry("US", "美国", true), entry("", "忽略", false)];
+        assert_eq!(apply_pronunciations("US and us", &entries), "美国 and us");
+    }
+
+    #[test]
+    fn language_codes() {
+        assert_eq!(Lang::from_code("zh"), Lang::Zh);
+        assert_eq!(Lang::from_code(" EN "), Lang::En);
+        assert_eq!(Lang::from_code(""), Lang::Zh);
+    }
+}
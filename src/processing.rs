@@ -0,0 +1,316 @@
+//! 保存前的音频处理：把合成的 MP3 解码后按设置重采样、去掉首尾静音并做峰值归一化，
+//! 再写成 16 位 PCM 的 WAV（程序不带 MP3 编码器，处理后无法再存回 MP3）。
+//!
+//! 界面中的“试听处理后”播放的就是 `ExportPreview::wav`，保存时写入的也是同一份数据。
+//! 预览由后台命令计算（见 `PreviewCache`），界面线程不做解码和编码。
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::source::UniformSourceIterator;
+use rodio::{Decoder, Source};
+
+use crate::audio;
+use crate::config::ExportSettings;
+
+/// 低于该电平（dBFS）的首尾部分视为静音
+const SILENCE_THRESHOLD_DB: f32 = -50.0;
+/// 去静音后在首尾保留的长度，避免切掉字头和尾音
+const SILENCE_PADDING: Duration = Duration::from_millis(50);
+/// 完全静音时显示的峰值
+pub const SILENCE_DB: f32 = -90.0;
+
+/// 解码后的交错采样
+pub struct Pcm {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+/// 峰值和时长，用于比较处理前后的差异
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub peak_db: f32,
+    pub duration: Duration,
+}
+
+impl Pcm {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let decoder = Decoder::new(Cursor::new(data.to_vec())).map_err(|e| audio::describe_decode_error(&e))?;
+        let (channels, sample_rate) = (decoder.channels().max(1), decoder.sample_rate());
+        let samples = decoder.convert_samples::<f32>().collect();
+        Ok(Self { samples, channels, sample_rate })
+    }
+
+    fn peak(&self) -> f32 {
+        self.samples.iter().fold(0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    pub fn levels(&self) -> Levels {
+        let peak = self.peak();
+        let peak_db = if peak > 0.0 { (20.0 * peak.log10()).max(SILENCE_DB) } else { SILENCE_DB };
+        let frames = self.samples.len() / self.channels as usize;
+        Levels { peak_db, duration: Duration::from_secs_f64(frames as f64 / self.sample_rate.max(1) as f64) }
+    }
+
+    fn resample(self, sample_rate: u32) -> Self {
+        if sample_rate == 0 || sample_rate == self.sample_rate {
+            return self;
+        }
+        let channels = self.channels;
+        let source = rodio::buffer::SamplesBuffer::new(channels, self.sample_rate, self.samples);
+        let samples = UniformSourceIterator::<_, f32>::new(source, channels, sample_rate).collect();
+        Self { samples, channels, sample_rate }
+    }
+
+    fn trim_silence(mut self) -> Self {
+        let threshold = audio::db_to_gain(SILENCE_THRESHOLD_DB);
+        let channels = self.channels as usize;
+        let loud_frame = |frame: &[f32]| frame.iter().any(|s| s.abs() > threshold);
+        let first = self.samples.chunks(channels).position(loud_frame);
+        let last = self.samples.chunks(channels).rposition(loud_frame);
+        let (Some(first), Some(last)) = (first, last) else {
+            return self;
+        };
+        let padding = (SILENCE_PADDING.as_secs_f64() * self.sample_rate as f64) as usize;
+        let start = first.saturating_sub(padding) * channels;
+        let end = ((last + 1 + padding) * channels).min(self.samples.len());
+        self.samples.truncate(end);
+        self.samples.drain(..start);
+        self
+    }
+
+    fn normalize(mut self, peak_db: f32) -> Self {
+        let peak = self.peak();
+        if peak > 0.0 {
+            let gain = audio::db_to_gain(peak_db.min(0.0)) / peak;
+            self.samples.iter_mut().for_each(|s| *s *= gain);
+        }
+        self
+    }
+
+    /// 16 位 PCM 的 WAV 文件
+    pub fn to_wav(&self) -> Vec<u8> {
+        let data_len = (self.samples.len() * 2) as u32;
+        let block_align = self.channels * 2;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&self.channels.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            wav.extend_from_slice(&value.to_le_bytes());
+        }
+        wav
+    }
+}
+
+/// 按设置依次重采样、去首尾静音、峰值归一化
+pub fn process(pcm: Pcm, settings: &ExportSettings) -> Pcm {
+    let mut pcm = pcm.resample(settings.sample_rate);
+    if settings.trim_silence {
+        pcm = pcm.trim_silence();
+    }
+    if settings.normalize {
+        pcm = pcm.normalize(settings.peak_db);
+    }
+    pcm
+}
+
+/// 保存前预览：处理前后的电平和处理后要写入文件的 WAV
+pub struct ExportPreview {
+    pub before: Levels,
+    pub after: Levels,
+    pub wav: Arc<Vec<u8>>,
+}
+
+impl ExportPreview {
+    pub fn new(data: &[u8], settings: &ExportSettings) -> Result<Self, String> {
+        let pcm = Pcm::decode(data)?;
+        let before = pcm.levels();
+        let processed = process(pcm, settings);
+        Ok(Self { before, after: processed.levels(), wav: Arc::new(processed.to_wav()) })
+    }
+}
+
+/// 一次预览的输入和结果；音频和设置都没变时不必重新解码
+pub struct CachedPreview {
+    pub audio: Arc<Vec<u8>>,
+    pub settings: ExportSettings,
+    pub result: Result<ExportPreview, String>,
+}
+
+impl CachedPreview {
+    pub fn new(audio: Arc<Vec<u8>>, settings: &ExportSettings) -> Self {
+        let result = ExportPreview::new(&audio, settings);
+        Self { audio, settings: settings.clone(), result }
+    }
+
+    pub fn is_for(&self, audio: &Arc<Vec<u8>>, settings: &ExportSettings) -> bool {
+        Arc::ptr_eq(&self.audio, audio) && self.settings == *settings
+    }
+}
+
+/// 界面持有的预览状态：最近一次算好的结果，以及是否有一份正在后台计算。
+/// 同时只计算一份，拖动滑块时中间的设置被跳过，算完后再按最新的设置计算
+#[derive(Default)]
+pub struct PreviewCache {
+    latest: Option<CachedPreview>,
+    computing: bool,
+}
+
+impl PreviewCache {
+    /// 已经算好的 `audio` 在 `settings` 下的预览
+    pub fn get(&self, audio: &Arc<Vec<u8>>, settings: &ExportSettings) -> Option<&Result<ExportPreview, String>> {
+        self.latest.as_ref().filter(|cached| cached.is_for(audio, settings)).map(|cached| &cached.result)
+    }
+
+    /// 需要开始计算时返回 true 并记为计算中；已有结果或另一份还在计算时返回 false
+    pub fn start(&mut self, audio: &Arc<Vec<u8>>, settings: &ExportSettings) -> bool {
+        if self.computing || self.get(audio, settings).is_some() {
+            return false;
+        }
+        self.computing = true;
+        true
+    }
+
+    /// 记录后台算好的预览，其设置可能已经过时，下次 `start` 时会按当前设置重新计算
+    pub fn finish(&mut self, preview: CachedPreview) {
+        self.computing = false;
+        self.latest = Some(preview);
+    }
+
+    /// 计算没能开始（例如命令队列已满）
+    pub fn abort(&mut self) {
+        self.computing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1 kHz 的测试音频，每毫秒一帧
+    fn pcm(samples: Vec<f32>, channels: u16) -> Pcm {
+        Pcm { samples, channels, sample_rate: 1000 }
+    }
+
+    #[test]
+    fn trim_silence_keeps_padding_around_the_sound() {
+        let samples = [vec![0.0; 200], vec![0.5; 100], vec![0.0; 300]].concat();
+        let trimmed = pcm(samples, 1).trim_silence();
+        // 前后各保留 50 ms
+        assert_eq!(trimmed.samples, [vec![0.0; 50], vec![0.5; 100], vec![0.0; 50]].concat());
+        // 静音本身低于阈值也算静音
+        let quiet = audio::db_to_gain(SILENCE_THRESHOLD_DB) / 2.0;
+        let trimmed = pcm([vec![quiet; 100], vec![0.5; 10], vec![quiet; 20]].concat(), 1).trim_silence();
+        assert_eq!(trimmed.samples.len(), 50 + 10 + 20);
+    }
+
+    #[test]
+    fn trim_silence_cuts_whole_frames() {
+        // 只有右声道有声音的帧也算有声音，切口落在帧边界上
+        let mut samples = vec![0.0; 2 * 300];
+        samples[2 * 100 + 1] = 0.5;
+        let trimmed = pcm(samples, 2).trim_silence();
+        assert_eq!(trimmed.samples.len(), 2 * 101);
+        assert_eq!(trimmed.samples[2 * 50 + 1], 0.5);
+        assert_eq!(trimmed.samples.iter().filter(|&&s| s != 0.0).count(), 1);
+    }
+
+    #[test]
+    fn trim_silence_leaves_all_silent_audio_alone() {
+        assert_eq!(pcm(vec![0.0; 100], 1).trim_silence().samples.len(), 100);
+        assert!(pcm(Vec::new(), 1).trim_silence().samples.is_empty());
+    }
+
+    #[test]
+    fn normalize_scales_the_peak_to_the_target() {
+        let normalized = pcm(vec![0.25, -0.5, 0.1], 1).normalize(0.0);
+        assert_eq!(normalized.samples, vec![0.5, -1.0, 0.2]);
+        let normalized = pcm(vec![0.25, -0.5], 1).normalize(-6.0);
+        assert!((normalized.levels().peak_db + 6.0).abs() < 1e-4, "{:?}", normalized.levels());
+        // 目标高于 0 dBFS 时按 0 dBFS 处理，静音保持不变
+        assert_eq!(pcm(vec![0.25, -0.5], 1).normalize(3.0).samples, vec![0.5, -1.0]);
+        assert_eq!(pcm(vec![0.0; 3], 1).normalize(0.0).samples, vec![0.0; 3]);
+    }
+
+    #[test]
+    fn resample_changes_the_rate_but_not_the_duration() {
+        let samples: Vec<f32> = (0..2000).map(|i| (i as f32 * 0.05).sin() * 0.5).collect();
+        let resampled = pcm(samples.clone(), 2).resample(2000);
+        assert_eq!((resampled.sample_rate, resampled.channels), (2000, 2));
+        let duration = resampled.levels().duration.as_secs_f32();
+        assert!((duration - 1.0).abs() < 0.01, "{}", duration);
+        // 0 和原采样率都表示不重采样
+        assert_eq!(pcm(samples.clone(), 2).resample(0).samples, samples);
+        assert_eq!(pcm(samples.clone(), 2).resample(1000).samples, samples);
+    }
+
+    #[test]
+    fn wav_has_a_pcm_header_and_clamped_samples() {
+        let wav = Pcm { samples: vec![0.0, 1.0, -1.0, 2.0], channels: 2, sample_rate: 8000 }.to_wav();
+        assert_eq!(wav.len(), 44 + 8);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(wav[4..8].try_into().unwrap()), 36 + 8);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 2);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 8000);
+        assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 8000 * 4);
+        assert_eq!(u16::from_le_bytes([wav[32], wav[33]]), 4);
+        assert_eq!(&wav[36..40], b"data");
+        let samples: Vec<i16> = wav[44..].chunks(2).map(|pair| i16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(samples, vec![0, i16::MAX, -i16::MAX, i16::MAX]);
+        // 写出的文件可以再解码回来
+        let decoded = Pcm::decode(&wav).unwrap();
+        assert_eq!((decoded.channels, decoded.sample_rate, decoded.samples.len()), (2, 8000, 4));
+    }
+
+    #[test]
+    fn preview_of_silent_audio_keeps_its_length() {
+        let preview = ExportPreview::new(&audio::silent_mp3(40), &ExportSettings::default()).unwrap();
+        assert_eq!(preview.before.peak_db, SILENCE_DB);
+        assert_eq!(preview.after, preview.before);
+        assert_eq!(&preview.wav[..4], b"RIFF");
+        assert!(ExportPreview::new(b"not audio", &ExportSettings::default()).is_err());
+    }
+
+    fn finished(audio: &Arc<Vec<u8>>, settings: &ExportSettings) -> CachedPreview {
+        CachedPreview { audio: audio.clone(), settings: settings.clone(), result: Err("测试".to_string()) }
+    }
+
+    #[test]
+    fn preview_cache_computes_one_preview_at_a_time() {
+        let audio = Arc::new(vec![1u8]);
+        let first = ExportSettings::default();
+        let second = ExportSettings { peak_db: -3.0, ..ExportSettings::default() };
+        let mut cache = PreviewCache::default();
+        assert!(cache.get(&audio, &first).is_none());
+        assert!(cache.start(&audio, &first));
+        // 计算中设置变了：不再另起一份
+        assert!(!cache.start(&audio, &second));
+        cache.finish(finished(&audio, &first));
+        assert!(cache.get(&audio, &first).is_some());
+        // 算好的是过时的设置，按最新的设置重新计算
+        assert!(cache.get(&audio, &second).is_none());
+        assert!(cache.start(&audio, &second));
+        cache.finish(finished(&audio, &second));
+        assert!(!cache.start(&audio, &second));
+        // 内容相同但不是同一段音频时也重新计算
+        let other = Arc::new(vec![1u8]);
+        assert!(cache.get(&other, &second).is_none());
+        assert!(cache.start(&other, &second));
+        cache.abort();
+        assert!(cache.start(&other, &second));
+    }
+}
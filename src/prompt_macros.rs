@@ -0,0 +1,177 @@
+//! 提示词中的宏：发送给 DeepSeek 之前，把系统提示词和用户输入中的 `{{名称}}` 替换为对应的值。
+//!
+//! 内置宏：
+//!
+//! - `{{date}}`：当前日期 `2024-01-01`，`{{time}}` 为 `12:00`，`{{datetime}}` 为两者合在一起
+//! - `{{weekday}}`：星期几，如“星期一”
+//! - `{{voice}}`：本次使用的发音人名称
+//! - `{{version}}`：程序版本
+//!
+//! 其余名称取自 `ai_settings.variables` 中用户定义的变量（与内置宏同名的变量不生效）。
+//! 变量的值中还可以引用其他宏，最多展开 8 层；未定义的变量和循环引用保持原样并提示。
+//! 在 `{{` 前加反斜杠（`\{{`）可以原样保留花括号。
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Local};
+
+/// 变量互相引用时的最大展开层数
+const MAX_DEPTH: usize = 8;
+
+/// 展开宏时用到的信息
+pub struct MacroContext<'a> {
+    pub now: DateTime<Local>,
+    pub voice: &'a str,
+    pub variables: &'a BTreeMap<String, String>,
+}
+
+impl<'a> MacroContext<'a> {
+    pub fn new(variables: &'a BTreeMap<String, String>, voice: &'a str) -> Self {
+        Self { now: Local::now(), voice, variables }
+    }
+
+    fn builtin(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "date" => self.now.format("%Y-%m-%d").to_string(),
+            "time" => self.now.format("%H:%M").to_string(),
+            "datetime" => self.now.format("%Y-%m-%d %H:%M").to_string(),
+            "weekday" => {
+                let names = ["一", "二", "三", "四", "五", "六", "日"];
+                format!("星期{}", names[self.now.weekday().num_days_from_monday() as usize])
+            }
+            "voice" => self.voice.to_string(),
+            "version" => env!("CARGO_PKG_VERSION").to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// 展开文本中的宏，返回结果和无法展开的变量名（未定义或循环引用，去重）
+pub fn expand(text: &str, context: &MacroContext) -> (String, Vec<String>) {
+    let mut unresolved = Vec::new();
+    let text = expand_at(text, context, &mut Vec::new(), &mut unresolved);
+    (text, unresolved)
+}
+
+/// `stack` 为正在展开的变量名，再次遇到其中的名称即为循环引用
+fn expand_at<'a>(text: &'a str, context: &'a MacroContext, stack: &mut Vec<&'a str>, unresolved: &mut Vec<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(len) = rest.find("}}") else {
+            break;
+        };
+        let placeholder = &rest[..len + 2];
+        let name = placeholder[2..len].trim();
+        if let Some(value) = context.builtin(name) {
+            out.push_str(&value);
+        } else {
+            match context.variables.get_key_value(name) {
+                Some((key, value)) if stack.len() < MAX_DEPTH && !stack.contains(&name) => {
+                    stack.push(key);
+                    out.push_str(&expand_at(value, context, stack, unresolved));
+                    stack.pop();
+                }
+                _ => {
+                    if !name.is_empty() && !unresolved.iter().any(|n| n == name) {
+                        unresolved.push(name.to_string());
+                    }
+                    out.push_str(placeholder);
+                }
+            }
+        }
+        rest = &rest[len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 展开系统提示词和用户输入，返回两者的展开结果，有变量无法展开时附带给用户的提示
+pub fn expand_prompts(system: &str, user: &str, context: &MacroContext) -> (String, String, Option<String>) {
+    let (system, mut unresolved) = expand(system, context);
+    let (user, more) = expand(user, context);
+    for name in more {
+        if !unresolved.contains(&name) {
+            unresolved.push(name);
+        }
+    }
+    let notice = (!unresolved.is_empty()).then(|| format!("提示词中的变量未定义或循环引用，已保持原样: {}", unresolved.join("、")));
+    (system, user, notice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn expand_with(text: &str, variables: &[(&str, &str)]) -> (String, Vec<String>) {
+        let variables: BTreeMap<String, String> = variables.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let context = MacroContext {
+            now: Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+            voice: "度小美",
+            variables: &variables,
+        };
+        expand(text, &context)
+    }
+
+    #[test]
+    fn builtins_expand() {
+        let (text, unresolved) = expand_with("{{date}} {{ time }} {{weekday}} {{voice}}", &[]);
+        assert_eq!(text, "2024-01-01 12:00 星期一 度小美");
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn builtins_take_precedence_over_variables() {
+        assert_eq!(expand_with("{{voice}}", &[("voice", "别人")]).0, "度小美");
+    }
+
+    #[test]
+    fn user_variables_expand_recursively() {
+        let (text, unresolved) = expand_with("你好，{{greeting}}", &[("greeting", "{{name}}，今天是{{date}}"), ("name", "小明")]);
+        assert_eq!(text, "你好，小明，今天是2024-01-01");
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn unknown_macros_are_kept_and_reported() {
+        let (text, unresolved) = expand_with("{{missing}} {{missing}} {{}}", &[]);
+        assert_eq!(text, "{{missing}} {{missing}} {{}}");
+        assert_eq!(unresolved, vec!["missing"]);
+    }
+
+    #[test]
+    fn escaped_braces_are_kept() {
+        assert_eq!(expand_with(r"\{{date}} {{date", &[]).0, "{{date}} {{date");
+    }
+
+    #[test]
+    fn self_reference_stops_on_reentry() {
+        let (text, unresolved) = expand_with("{{a}}", &[("a", "x{{a}}")]);
+        assert_eq!(text, "x{{a}}");
+        assert_eq!(unresolved, vec!["a"]);
+    }
+
+    #[test]
+    fn mutual_reference_stops_on_reentry() {
+        let (text, unresolved) = expand_with("{{a}}", &[("a", "1{{b}}"), ("b", "2{{a}}")]);
+        assert_eq!(text, "12{{a}}");
+        assert_eq!(unresolved, vec!["a"]);
+    }
+
+    #[test]
+    fn repeated_use_is_not_a_cycle() {
+        let (text, unresolved) = expand_with("{{a}}", &[("a", "{{b}}{{b}}"), ("b", "x")]);
+        assert_eq!(text, "xx");
+        assert!(unresolved.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime};
+
+use crate::config::{JobSchedule, ScheduledJob};
+
+impl JobSchedule {
+    pub fn describe(&self) -> String {
+        match self {
+            JobSchedule::Once { at } => format!("{} 播报一次", at.format("%m-%d %H:%M")),
+            JobSchedule::Every { minutes } => format!("每 {} 分钟", minutes),
+        }
+    }
+}
+
+/// 解析 "HH:MM"，返回今天该时刻；若已过去则返回明天该时刻
+pub fn next_occurrence(time: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let time = NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()?;
+    let today = now.date_naive().and_time(time).and_local_timezone(Local).earliest()?;
+    if today > now {
+        Some(today)
+    } else {
+        Some(today + ChronoDuration::days(1))
+    }
+}
+
+/// 定时任务及其下次触发时间。重复任务从加载（或添加）时开始计时，
+/// 已经过期的一次性任务不会补播
+pub struct Scheduler {
+    jobs: Vec<(ScheduledJob, Option<DateTime<Local>>)>,
+}
+
+impl Scheduler {
+    pub fn new(jobs: &[ScheduledJob], now: DateTime<Local>) -> Self {
+        let mut scheduler = Self { jobs: Vec::new() };
+        for job in jobs {
+            scheduler.add(job.clone(), now);
+        }
+        scheduler
+    }
+
+    pub fn add(&mut self, job: ScheduledJob, now: DateTime<Local>) {
+        let next = match job.schedule {
+            JobSchedule::Once { at } => (at > now).then_some(at),
+            JobSchedule::Every { minutes } => Some(now + ChronoDuration::minutes(minutes.max(1) as i64)),
+        };
+        self.jobs.push((job, next));
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.jobs.len() {
+            self.jobs.remove(index);
+        }
+    }
+
+    /// 返回到期的任务并推进其下次触发时间；一次性任务触发后即移出列表
+    pub fn take_due(&mut self, now: DateTime<Local>) -> Vec<ScheduledJob> {
+        let mut due = Vec::new();
+        for (job, next) in &mut self.jobs {
+            if next.is_some_and(|at| at <= now) {
+                due.push(job.clone());
+                *next = match job.schedule {
+                    JobSchedule::Once { .. } => None,
+                    JobSchedule::Every { minutes } => Some(now + ChronoDuration::minutes(minutes.max(1) as i64)),
+                };
+            }
+        }
+        self.jobs.retain(|(job, next)| next.is_some() || !matches!(job.schedule, JobSchedule::Once { .. }));
+        due
+    }
+
+    pub fn jobs(&self) -> impl Iterator<Item = (&ScheduledJob, Option<DateTime<Local>>)> {
+        self.jobs.iter().map(|(job, next)| (job, *next))
+    }
+
+    /// 需要写回配置的任务（已过期的一次性任务除外）
+    pub fn to_config(&self) -> Vec<ScheduledJob> {
+        self.jobs
+            .iter()
+            .filter(|(job, next)| next.is_some() || !matches!(job.schedule, JobSchedule::Once { .. }))
+            .map(|(job, _)| job.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,73 @@
+//! 辅助功能“朗读选中文本”：模拟复制快捷键，把其他程序中选中的文本读出剪贴板，
+//! 再恢复用户原来的剪贴板内容。
+//!
+//! 安全与权限说明：
+//! - 模拟的按键会发送给当前拥有焦点的程序，焦点不在预期窗口时可能触发该程序中
+//!   Ctrl+C 对应的其他操作（如终端中的中断）。
+//! - 在读取期间剪贴板会被短暂清空，剪贴板管理器可能记录到选中的内容；
+//!   选中的密码等敏感文本同样会被复制并发送给百度合成。
+//! - 只能恢复原有的纯文本剪贴板内容，图片、文件等其他格式会丢失。
+//! - macOS 需要在“系统设置 → 隐私与安全性 → 辅助功能”中授权；Wayland 下
+//!   通常不允许模拟输入。
+//!
+//! 因此该功能默认关闭，需要在界面中显式开启。
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+
+/// 模拟复制后等待剪贴板更新的最长时间
+const COPY_TIMEOUT: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[cfg(target_os = "macos")]
+const COPY_MODIFIER: Key = Key::Meta;
+#[cfg(not(target_os = "macos"))]
+const COPY_MODIFIER: Key = Key::Control;
+
+/// 复制当前选中的文本并返回，随后恢复原来的剪贴板文本。
+/// 会阻塞至多约半秒，应在后台线程中调用。
+pub fn read_selected_text() -> Result<String, String> {
+    let mut clipboard = arboard::Clipboard::new().map_err(|e| format!("无法访问剪贴板: {}", e))?;
+    let previous = clipboard.get_text().ok();
+    clipboard.clear().map_err(|e| format!("无法清空剪贴板: {}", e))?;
+
+    let copied = simulate_copy().and_then(|_| wait_for_text(&mut clipboard));
+
+    if let Some(previous) = previous {
+        if let Err(e) = clipboard.set_text(previous) {
+            log::warn!("恢复剪贴板内容失败: {}", e);
+        }
+    }
+    copied
+}
+
+fn simulate_copy() -> Result<(), String> {
+    let mut enigo = Enigo::new(&Settings::default()).map_err(|e| format!("无法模拟按键: {}", e))?;
+    // 触发快捷键的修饰键可能还按着，先松开以免变成 Ctrl+Alt+C 之类的组合
+    for modifier in [Key::Alt, Key::Shift] {
+        let _ = enigo.key(modifier, Direction::Release);
+    }
+    let result = enigo
+        .key(COPY_MODIFIER, Direction::Press)
+        .and_then(|_| enigo.key(Key::Unicode('c'), Direction::Click));
+    let _ = enigo.key(COPY_MODIFIER, Direction::Release);
+    result.map_err(|e| format!("模拟复制失败: {}", e))
+}
+
+/// 目标程序处理复制需要时间，轮询直到剪贴板中出现文本或超时
+fn wait_for_text(clipboard: &mut arboard::Clipboard) -> Result<String, String> {
+    let deadline = Instant::now() + COPY_TIMEOUT;
+    loop {
+        if let Ok(text) = clipboard.get_text() {
+            if !text.is_empty() {
+                return Ok(text);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err("没有获取到选中的文本".to_string());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
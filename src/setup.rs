@@ -0,0 +1,231 @@
+//! 首次运行的设置向导。
+//!
+//! config.toml 缺失或无法解析时代替主界面显示，依次收集 API 密钥、默认发音人和设备
+//! 标识（cuid），写出一份完整的配置后交给 `TTSApp` 继续启动。百度接口地址是固定的，
+//! 不需要填写服务器地址；其余设置使用与示例 config.toml 相同的默认值。
+
+use eframe::egui;
+
+use crate::config::{self, mask_key, validate_key, ApiKeys, BaiduSettings, Config, VOICES};
+use crate::voice_name;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Keys,
+    Voice,
+    Confirm,
+}
+
+pub struct SetupWizard {
+    step: Step,
+    /// 为什么显示向导：配置缺失或解析失败的原因
+    reason: String,
+    keys: ApiKeys,
+    baidu: BaiduSettings,
+    person: i32,
+    show_keys: bool,
+    /// 进入新的一步时把焦点交给该步的第一个输入框
+    focus_first: bool,
+    error: Option<String>,
+}
+
+impl SetupWizard {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            step: Step::Keys,
+            reason: reason.into(),
+            keys: ApiKeys::default(),
+            baidu: BaiduSettings::default(),
+            person: 0,
+            show_keys: false,
+            focus_first: true,
+            error: None,
+        }
+    }
+
+    /// 主界面初始化失败时留在向导中显示原因，用户可以修改后重试
+    pub fn set_error(&mut self, message: String) {
+        self.error = Some(message);
+    }
+
+    /// 绘制向导；用户确认并成功写出配置后返回该配置。
+    /// 回车相当于“下一步”/“保存”，Esc 相当于“上一步”
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<Config> {
+        let mut finished = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("欢迎使用 TTSmate");
+            ui.weak(&self.reason);
+            ui.separator();
+            match self.step {
+                Step::Keys => self.keys_step(ui),
+                Step::Voice => self.voice_step(ui),
+                Step::Confirm => finished = self.confirm_step(ui),
+            }
+            if let Some(error) = &self.error {
+                ui.separator();
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+        finished
+    }
+
+    fn keys_step(&mut self, ui: &mut egui::Ui) {
+        ui.label("第 1 步，共 3 步：填写 API 密钥");
+        ui.label("百度语音合成的 API Key 和 Secret Key 可在百度智能云控制台的应用列表中找到。");
+        egui::Grid::new("setup_keys").num_columns(2).show(ui, |ui| {
+            ui.label("百度 API Key:");
+            let first = ui.add(egui::TextEdit::singleline(&mut self.keys.baidu_api_key).password(!self.show_keys).desired_width(320.0));
+            if std::mem::take(&mut self.focus_first) {
+                first.request_focus();
+            }
+            ui.end_row();
+            ui.label("百度 Secret Key:");
+            ui.add(egui::TextEdit::singleline(&mut self.keys.baidu_secret_key).password(!self.show_keys).desired_width(320.0));
+            ui.end_row();
+            ui.label("DeepSeek API Key:");
+            ui.add(egui::TextEdit::singleline(&mut self.keys.deepseek_api_key).password(!self.show_keys).hint_text("可选").desired_width(320.0));
+            ui.end_row();
+        });
+        ui.checkbox(&mut self.show_keys, "显示密钥");
+
+        let baidu_api = validate_key("百度 API Key", &self.keys.baidu_api_key, true);
+        let baidu_secret = validate_key("百度 Secret Key", &self.keys.baidu_secret_key, true);
+        let deepseek = validate_key("DeepSeek API Key", &self.keys.deepseek_api_key, false);
+        // 空的必填项在用户开始输入前不提示，只禁用“下一步”
+        for (value, result) in [
+            (&self.keys.baidu_api_key, &baidu_api),
+            (&self.keys.baidu_secret_key, &baidu_secret),
+            (&self.keys.deepseek_api_key, &deepseek),
+        ] {
+            if let Err(message) = result {
+                if !value.is_empty() {
+                    ui.colored_label(egui::Color32::RED, message);
+                }
+            }
+        }
+        if deepseek.is_ok() {
+            let deepseek_key = self.keys.deepseek_api_key.trim();
+            if deepseek_key.is_empty() {
+                ui.weak("未填写 DeepSeek 密钥时，AI 生成不可用，输入的文本会被直接朗读。");
+            } else if !deepseek_key.starts_with("sk-") {
+                ui.colored_label(egui::Color32::YELLOW, "⚠ DeepSeek 密钥通常以 sk- 开头，请确认没有复制错");
+            }
+        }
+
+        ui.separator();
+        let valid = baidu_api.is_ok() && baidu_secret.is_ok() && deepseek.is_ok();
+        if ui.add_enabled(valid, egui::Button::new("下一步")).clicked() || (valid && enter_pressed(ui)) {
+            self.go_to(Step::Voice);
+        }
+    }
+
+    fn voice_step(&mut self, ui: &mut egui::Ui) {
+        ui.label("第 2 步，共 3 步：选择默认发音人");
+        egui::ComboBox::from_label("发音人")
+            .selected_text(voice_name(self.person))
+            .show_ui(ui, |ui| {
+                for &(name, person) in VOICES.iter() {
+                    ui.selectable_value(&mut self.person, person, name);
+                }
+            });
+        ui.weak("精品音色（如度逍遥精品、度小鹿）需要在百度控制台单独开通。");
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            ui.label("设备标识 (cuid):");
+            let cuid = ui.text_edit_singleline(&mut self.baidu.cuid);
+            if std::mem::take(&mut self.focus_first) {
+                cuid.request_focus();
+            }
+        })
+        .response
+        .on_hover_text("百度控制台据此区分设备和统计配额，建议每台设备设置不同的值");
+        let cuid = self.baidu.validate();
+        if let Err(message) = &cuid {
+            ui.colored_label(egui::Color32::RED, message);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("上一步").clicked() || escape_pressed(ui) {
+                self.go_to(Step::Keys);
+            }
+            if ui.add_enabled(cuid.is_ok(), egui::Button::new("下一步")).clicked() || (cuid.is_ok() && enter_pressed(ui)) {
+                self.go_to(Step::Confirm);
+            }
+        });
+    }
+
+    fn confirm_step(&mut self, ui: &mut egui::Ui) -> Option<Config> {
+        ui.label("第 3 步，共 3 步：确认");
+        egui::Grid::new("setup_summary").num_columns(2).show(ui, |ui| {
+            ui.label("百度 API Key:");
+            ui.monospace(mask_key(&self.keys.baidu_api_key));
+            ui.end_row();
+            ui.label("百度 Secret Key:");
+            ui.monospace(mask_key(&self.keys.baidu_secret_key));
+            ui.end_row();
+            ui.label("DeepSeek API Key:");
+            if self.keys.deepseek_api_key.trim().is_empty() {
+                ui.label("未填写");
+            } else {
+                ui.monospace(mask_key(&self.keys.deepseek_api_key));
+            }
+            ui.end_row();
+            ui.label("发音人:");
+            ui.label(voice_name(self.person));
+            ui.end_row();
+            ui.label("设备标识:");
+            ui.label(&self.baidu.cuid);
+            ui.end_row();
+        });
+        ui.weak("配置将写入 config.toml，原有的文件会备份为 config.toml.bak。进入主界面后可在“API 密钥”面板中验证密钥。");
+
+        ui.separator();
+        let mut finished = None;
+        ui.horizontal(|ui| {
+            if ui.button("上一步").clicked() || escape_pressed(ui) {
+                self.go_to(Step::Voice);
+            }
+            if ui.button("✔ 保存并开始使用").clicked() || enter_pressed(ui) {
+                let config = self.build_config();
+                match config::save_config(&config) {
+                    Ok(()) => {
+                        log::info!("设置向导已写出 config.toml");
+                        self.error = None;
+                        finished = Some(config);
+                    }
+                    Err(e) => self.error = Some(format!("保存配置失败: {}", e)),
+                }
+            }
+        });
+        finished
+    }
+
+    fn go_to(&mut self, step: Step) {
+        self.step = step;
+        self.focus_first = true;
+    }
+
+    fn build_config(&self) -> Config {
+        let mut config = Config {
+            api_keys: ApiKeys {
+                deepseek_api_key: self.keys.deepseek_api_key.trim().to_string(),
+                baidu_api_key: self.keys.baidu_api_key.trim().to_string(),
+                baidu_secret_key: self.keys.baidu_secret_key.trim().to_string(),
+            },
+            baidu: self.baidu.clone(),
+            ..Config::default()
+        };
+        config.app_settings.person = self.person;
+        config
+    }
+}
+
+fn enter_pressed(ui: &egui::Ui) -> bool {
+    ui.input(|i| i.key_pressed(egui::Key::Enter))
+}
+
+fn escape_pressed(ui: &egui::Ui) -> bool {
+    ui.input(|i| i.key_pressed(egui::Key::Escape))
+}
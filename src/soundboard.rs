@@ -0,0 +1,459 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use eframe::egui;
+use rodio::{Decoder, Sink, Source};
+
+use crate::audio::PlaybackClock;
+use crate::config::{self, SoundboardItem};
+
+/// 未设置分类的音效归入此分类
+pub const DEFAULT_CATEGORY: &str = "默认";
+/// 达到此时长的音效在按钮上显示播放进度
+const LONG_CLIP: Duration = Duration::from_secs(3);
+/// 撤销栈最多保留的操作数
+const UNDO_LIMIT: usize = 10;
+/// 响度均衡最多提升和压低的幅度（dB），避免把几乎无声的文件放大成噪声
+const MAX_BOOST_DB: f32 = 12.0;
+const MAX_CUT_DB: f32 = 24.0;
+/// 响度低于此值（dBFS）的文件视为静音，不做均衡
+const SILENCE_DB: f32 = -90.0;
+/// 波形缩略图的段数
+const WAVEFORM_BUCKETS: usize = 64;
+/// 分析时先按这么多个采样取一次峰值，最后再合并成 `WAVEFORM_BUCKETS` 段，不必把整个文件留在内存里
+const PEAK_CHUNK_SAMPLES: usize = 1024;
+
+/// 正在播放的一个音效
+pub struct PlayingSound {
+    pub index: usize,
+    /// 按住播放的音效，松开快捷键时停止
+    pub momentary: bool,
+    pub sink: Sink,
+    /// 同时输出到监听设备的副本，随 `sink` 一起停止
+    pub monitor: Option<Sink>,
+    pub clock: Arc<PlaybackClock>,
+}
+
+/// 解码一遍音效文件得到的信息
+pub struct SoundAnalysis {
+    pub duration: Duration,
+    /// 全部采样的 RMS 电平（dBFS）
+    pub loudness_db: f32,
+    /// 各段峰值，0～255
+    pub waveform: Vec<u8>,
+    pub mtime: Option<u64>,
+}
+
+/// 文件的修改时间（Unix 秒），无法读取时为 `None`
+pub fn modified_time(path: &str) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    modified.duration_since(std::time::UNIX_EPOCH).ok().map(|elapsed| elapsed.as_secs())
+}
+
+/// 解码整个文件，得到实际时长、响度和波形缩略图。容器未记录时长时按采样数计算。
+pub fn analyze(path: &str) -> Option<SoundAnalysis> {
+    let mtime = modified_time(path);
+    let file = File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let reported = decoder.total_duration();
+    let samples_per_second = decoder.sample_rate() as u64 * decoder.channels().max(1) as u64;
+    let mut samples = 0u64;
+    let mut sum_of_squares = 0f64;
+    let mut chunk_peaks = Vec::new();
+    for sample in decoder.convert_samples::<f32>() {
+        if samples.is_multiple_of(PEAK_CHUNK_SAMPLES as u64) {
+            chunk_peaks.push(0f32);
+        }
+        if let Some(peak) = chunk_peaks.last_mut() {
+            *peak = peak.max(sample.abs());
+        }
+        samples += 1;
+        sum_of_squares += (sample as f64).powi(2);
+    }
+    let duration = reported.unwrap_or_else(|| Duration::from_secs_f64(samples as f64 / samples_per_second.max(1) as f64));
+    let loudness_db = if samples == 0 || sum_of_squares == 0.0 {
+        SILENCE_DB
+    } else {
+        (10.0 * (sum_of_squares / samples as f64).log10()).max(SILENCE_DB as f64) as f32
+    };
+    Some(SoundAnalysis { duration, loudness_db, waveform: downsample_peaks(&chunk_peaks), mtime })
+}
+
+/// 把逐块峰值合并为 `WAVEFORM_BUCKETS` 段；块数不足时每块一段
+fn downsample_peaks(chunk_peaks: &[f32]) -> Vec<u8> {
+    let buckets = WAVEFORM_BUCKETS.min(chunk_peaks.len());
+    (0..buckets)
+        .map(|bucket| {
+            let start = bucket * chunk_peaks.len() / buckets;
+            let end = ((bucket + 1) * chunk_peaks.len() / buckets).max(start + 1);
+            let peak = chunk_peaks[start..end].iter().fold(0f32, |max, &p| max.max(p));
+            (peak.min(1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// 在悬停提示中画出波形缩略图
+pub fn show_waveform(ui: &mut egui::Ui, waveform: &[u8]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(160.0, 32.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    let color = ui.visuals().text_color();
+    let step = rect.width() / waveform.len().max(1) as f32;
+    for (i, &peak) in waveform.iter().enumerate() {
+        let x = rect.left() + (i as f32 + 0.5) * step;
+        // 至少画一个像素，静音段也看得出位置
+        let half = (peak as f32 / 255.0 * rect.height() / 2.0).max(0.5);
+        painter.line_segment(
+            [egui::pos2(x, rect.center().y - half), egui::pos2(x, rect.center().y + half)],
+            egui::Stroke::new((step - 1.0).max(1.0), color),
+        );
+    }
+}
+
+/// 把响度为 `loudness_db` 的音效调到 `target_db` 所需的增益（dB），静音文件不调整
+pub fn normalization_gain_db(loudness_db: f32, target_db: f32) -> f32 {
+    if loudness_db <= SILENCE_DB {
+        return 0.0;
+    }
+    (target_db - loudness_db).clamp(-MAX_CUT_DB, MAX_BOOST_DB)
+}
+
+/// 音效根目录：配置了则用配置值（相对值基于配置文件目录），否则就是配置文件目录
+pub fn soundboard_root(configured: &str) -> PathBuf {
+    let configured = configured.trim();
+    if configured.is_empty() {
+        config::config_dir()
+    } else {
+        config::config_dir().join(configured)
+    }
+}
+
+/// 把配置中保存的路径解析为绝对路径，已是绝对路径的原样返回
+pub fn resolve_path(root: &Path, stored: &str) -> PathBuf {
+    let path = Path::new(stored);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        root.join(path)
+    }
+}
+
+/// 位于根目录之下的文件保存为相对路径（统一用 `/` 分隔，便于跨平台），否则保留绝对路径
+pub fn relativize_path(root: &Path, path: &str) -> String {
+    let Ok(relative) = Path::new(path).strip_prefix(root) else {
+        return path.to_string();
+    };
+    let parts: Option<Vec<&str>> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    match parts {
+        Some(parts) if !parts.is_empty() => parts.join("/"),
+        _ => path.to_string(),
+    }
+}
+
+/// 加载配置后调用：路径解析为绝对路径并标记缺失的文件
+pub fn resolve_items(root: &Path, items: &mut [SoundboardItem]) {
+    for item in items {
+        let path = resolve_path(root, &item.path);
+        item.missing = !path.is_file();
+        if item.missing {
+            log::warn!("音效文件不存在: {}", path.display());
+        }
+        item.path = path.to_string_lossy().to_string();
+    }
+}
+
+/// 保存配置前调用：根目录下的文件改写为相对路径
+pub fn relativize_items(root: &Path, items: &[SoundboardItem]) -> Vec<SoundboardItem> {
+    items
+        .iter()
+        .map(|item| SoundboardItem {
+            path: relativize_path(root, &item.path),
+            ..item.clone()
+        })
+        .collect()
+}
+
+pub fn category_of(item: &SoundboardItem) -> &str {
+    let category = item.category.trim();
+    if category.is_empty() {
+        DEFAULT_CATEGORY
+    } else {
+        category
+    }
+}
+
+/// 音效板统计信息
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SoundBoardStats {
+    pub total_sounds: usize,
+    pub total_duration: Duration,
+    /// 分类名 -> (音效数量, 总时长)，按分类名排序
+    pub per_category: BTreeMap<String, (usize, Duration)>,
+}
+
+pub fn get_stats(items: &[SoundboardItem]) -> SoundBoardStats {
+    let mut stats = SoundBoardStats::default();
+    for item in items {
+        let duration = item.duration().unwrap_or_default();
+        stats.total_sounds += 1;
+        stats.total_duration += duration;
+        let entry = stats.per_category.entry(category_of(item).to_string()).or_default();
+        entry.0 += 1;
+        entry.1 += duration;
+    }
+    stats
+}
+
+/// 按钮上的时长文字，如 `0.8s`、`1:05`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f32();
+    if secs < 60.0 {
+        format!("{:.1}s", secs)
+    } else {
+        format!("{}:{:02}", duration.as_secs() / 60, duration.as_secs() % 60)
+    }
+}
+
+/// 分类的边框颜色，由分类名哈希得到固定色相；默认分类不着色
+pub fn category_color(item: &SoundboardItem) -> Option<egui::Color32> {
+    let category = item.category.trim();
+    if category.is_empty() {
+        return None;
+    }
+    let hash = category.chars().fold(0u32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as u32));
+    let hue = (hash % 360) as f32 / 360.0;
+    Some(egui::ecolor::Hsva::new(hue, 0.6, 0.85, 1.0).into())
+}
+
+/// 名称加一行小号时长的按钮文字
+pub fn button_label(item: &SoundboardItem, style: &egui::Style) -> egui::text::LayoutJob {
+    let mut job = egui::text::LayoutJob::default();
+    egui::RichText::new(&item.name).append_to(&mut job, style, egui::FontSelection::Default, egui::Align::Center);
+    if let Some(duration) = item.duration() {
+        egui::RichText::new(format!("\n{}", format_duration(duration)))
+            .small()
+            .weak()
+            .append_to(&mut job, style, egui::FontSelection::Default, egui::Align::Center);
+    }
+    job
+}
+
+/// 音效按钮上的操作
+pub enum ButtonAction {
+    Play(usize),
+    Reveal(usize),
+    ToggleFavorite(usize),
+}
+
+/// 绘制一个音效按钮（含右键菜单），收藏区和分类区共用
+pub fn show_button(ui: &mut egui::Ui, index: usize, item: &SoundboardItem, playing: &[PlayingSound]) -> Option<ButtonAction> {
+    let mut button = egui::Button::new(button_label(item, ui.style()));
+    if let Some(color) = category_color(item) {
+        button = button.stroke(egui::Stroke::new(1.5, color));
+    }
+    let mut response = ui
+        .add_enabled(!item.missing, button)
+        .on_disabled_hover_text(format!("文件不存在: {}", item.path));
+    if !item.waveform.is_empty() {
+        response = response.on_hover_ui(|ui| show_waveform(ui, &item.waveform));
+    }
+    if let Some(progress) = playback_progress(item, index, playing) {
+        let mut rect = response.rect.shrink(2.0);
+        rect.set_width(rect.width() * progress);
+        ui.painter().rect_filled(rect, 2.0, ui.visuals().selection.bg_fill.gamma_multiply(0.4));
+    }
+    let mut action = response.clicked().then_some(ButtonAction::Play(index));
+    response.context_menu(|ui| {
+        let favorite_label = if item.is_favorite { "★ 取消收藏" } else { "☆ 收藏" };
+        if ui.button(favorite_label).clicked() {
+            action = Some(ButtonAction::ToggleFavorite(index));
+            ui.close_menu();
+        }
+        if ui.add_enabled(!item.missing, egui::Button::new("在文件夹中显示")).clicked() {
+            action = Some(ButtonAction::Reveal(index));
+            ui.close_menu();
+        }
+    });
+    action
+}
+
+/// 长音效的播放进度（0..=1），短音效或未在播放时返回 `None`
+pub fn playback_progress(item: &SoundboardItem, index: usize, playing: &[PlayingSound]) -> Option<f32> {
+    let duration = item.duration().filter(|d| *d >= LONG_CLIP)?;
+    let sound = playing.iter().rev().find(|sound| sound.index == index)?;
+    Some((sound.clock.position().as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0))
+}
+
+/// 一次可撤销的音效板操作，保存恢复所需的全部数据
+pub enum SoundboardEdit {
+    Removed { index: usize, item: SoundboardItem },
+    /// 批量删除，按原来的下标从小到大排列
+    BulkRemoved { items: Vec<(usize, SoundboardItem)> },
+    /// 批量移动分类，保存每个音效原来的分类
+    Recategorized { previous: Vec<(usize, String)> },
+    Unbound { index: usize, hotkey: String },
+    CategoryDeleted { category: String, indices: Vec<usize> },
+}
+
+impl SoundboardEdit {
+    pub fn label(&self) -> String {
+        match self {
+            SoundboardEdit::Removed { item, .. } => format!("删除音效“{}”", item.name),
+            SoundboardEdit::BulkRemoved { items } => format!("删除 {} 个音效", items.len()),
+            SoundboardEdit::Recategorized { previous } => format!("移动 {} 个音效的分类", previous.len()),
+            SoundboardEdit::Unbound { hotkey, .. } => format!("解绑快捷键 {}", hotkey),
+            SoundboardEdit::CategoryDeleted { category, .. } => format!("删除分类“{}”", category),
+        }
+    }
+}
+
+/// 仅保存在内存中的有界撤销栈，超出上限时丢弃最早的操作
+#[derive(Default)]
+pub struct UndoStack {
+    edits: VecDeque<SoundboardEdit>,
+}
+
+impl UndoStack {
+    pub fn push(&mut self, edit: SoundboardEdit) {
+        if self.edits.len() == UNDO_LIMIT {
+            self.edits.pop_front();
+        }
+        self.edits.push_back(edit);
+    }
+
+    pub fn pop(&mut self) -> Option<SoundboardEdit> {
+        self.edits.pop_back()
+    }
+
+    pub fn last(&self) -> Option<&SoundboardEdit> {
+        self.edits.back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, category: &str, duration_ms: Option<u64>) -> SoundboardItem {
+        SoundboardItem {
+            category: category.to_string(),
+            duration_ms,
+            ..SoundboardItem::new(name.to_string(), format!("{}.mp3", name))
+        }
+    }
+
+    #[test]
+    fn stats_group_sounds_by_category() {
+        let items = [
+            item("掌声", "效果", Some(1500)),
+            item("笑声", "效果", Some(2500)),
+            item("开场", " 音乐 ", Some(30_000)),
+            item("未分析", "音乐", None),
+            item("叮", "", Some(200)),
+        ];
+        let stats = get_stats(&items);
+        assert_eq!(stats.total_sounds, 5);
+        assert_eq!(stats.total_duration, Duration::from_millis(34_200));
+        let expected: BTreeMap<String, (usize, Duration)> = [
+            ("效果".to_string(), (2, Duration::from_millis(4000))),
+            ("音乐".to_string(), (2, Duration::from_millis(30_000))),
+            (DEFAULT_CATEGORY.to_string(), (1, Duration::from_millis(200))),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(stats.per_category, expected);
+    }
+
+    #[test]
+    fn relative_paths_resolve_against_root() {
+        let root = Path::new("/data/soundboard");
+        assert_eq!(resolve_path(root, "效果/掌声.mp3"), root.join("效果/掌声.mp3"));
+        assert_eq!(resolve_path(root, "/other/叮.wav"), PathBuf::from("/other/叮.wav"));
+    }
+
+    #[test]
+    fn files_under_root_are_stored_relative() {
+        let root = Path::new("/data/soundboard");
+        assert_eq!(relativize_path(root, "/data/soundboard/效果/掌声.mp3"), "效果/掌声.mp3");
+        assert_eq!(relativize_path(root, "/other/叮.wav"), "/other/叮.wav");
+        // 根目录本身不能存成空字符串
+        assert_eq!(relativize_path(root, "/data/soundboard"), "/data/soundboard");
+    }
+
+    #[test]
+    fn resolving_items_marks_missing_files() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("有.mp3"), b"").unwrap();
+        let mut items = vec![
+            SoundboardItem::new("有".to_string(), "有.mp3".to_string()),
+            SoundboardItem::new("无".to_string(), "无.mp3".to_string()),
+        ];
+        resolve_items(root.path(), &mut items);
+        assert!(!items[0].missing);
+        assert!(items[1].missing);
+        assert_eq!(Path::new(&items[0].path), root.path().join("有.mp3"));
+
+        let stored = relativize_items(root.path(), &items);
+        assert_eq!(stored[0].path, "有.mp3");
+        assert_eq!(stored[1].path, "无.mp3");
+    }
+
+    #[test]
+    fn empty_soundboard_has_empty_stats() {
+        assert_eq!(get_stats(&[]), SoundBoardStats::default());
+    }
+
+    /// 16 位单声道 WAV：`frames` 个采样在 ±`amplitude` 之间交替（方波，RMS 等于幅度）
+    fn square_wav(path: &Path, amplitude: i16, frames: usize) {
+        let data_len = (frames * 2) as u32;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        for field in [16u32.to_le_bytes(), [1, 0, 1, 0], 8000u32.to_le_bytes(), 16000u32.to_le_bytes(), [2, 0, 16, 0]] {
+            wav.extend_from_slice(&field);
+        }
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for i in 0..frames {
+            let sample = if i % 2 == 0 { amplitude } else { -amplitude };
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(path, wav).unwrap();
+    }
+
+    #[test]
+    fn analysis_measures_known_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        let half = dir.path().join("half.wav");
+        square_wav(&half, 16384, 8000);
+        let analysis = analyze(half.to_str().unwrap()).unwrap();
+        // 幅度 0.5 的方波：RMS 为 20·log10(0.5) ≈ -6.02 dBFS
+        assert!((analysis.loudness_db + 6.02).abs() < 0.01, "{}", analysis.loudness_db);
+        assert_eq!(analysis.duration, Duration::from_secs(1));
+        assert!(analysis.waveform.iter().all(|peak| (127..=128).contains(peak)), "{:?}", analysis.waveform);
+
+        let silent = dir.path().join("silent.wav");
+        square_wav(&silent, 0, 800);
+        assert_eq!(analyze(silent.to_str().unwrap()).unwrap().loudness_db, SILENCE_DB);
+    }
+
+    #[test]
+    fn normalization_gain_moves_toward_the_target_within_limits() {
+        assert!((normalization_gain_db(-6.02, -16.0) + 9.98).abs() < 1e-4);
+        assert_eq!(normalization_gain_db(-20.0, -16.0), 4.0);
+        assert_eq!(normalization_gain_db(-50.0, -16.0), MAX_BOOST_DB);
+        assert_eq!(normalization_gain_db(0.0, -40.0), -MAX_CUT_DB);
+        // 静音文件不调整
+        assert_eq!(normalization_gain_db(SILENCE_DB, -16.0), 0.0);
+    }
+}
@@ -0,0 +1,114 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// 读取端等待新数据的最长时间，超时后按读取错误结束，避免音频线程被永久挂起
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+struct StreamState {
+    data: Vec<u8>,
+    finished: bool,
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<StreamState>,
+    ready: Condvar,
+}
+
+/// 下载端：网络任务把收到的数据块追加进来
+#[derive(Clone)]
+pub struct StreamWriter {
+    shared: Arc<Shared>,
+}
+
+/// 播放端：实现 `Read + Seek`，数据未到达时阻塞等待，可直接交给 rodio 解码器。
+/// 已收到的数据全部保留，因此可以向回定位。
+pub struct StreamReader {
+    shared: Arc<Shared>,
+    position: usize,
+}
+
+/// 创建一对共享同一缓冲区的写入端和读取端
+pub fn audio_stream() -> (StreamWriter, StreamReader) {
+    let shared = Arc::new(Shared::default());
+    (
+        StreamWriter { shared: shared.clone() },
+        StreamReader { shared, position: 0 },
+    )
+}
+
+impl StreamWriter {
+    pub fn push(&self, chunk: &[u8]) {
+        self.shared.state.lock().unwrap().data.extend_from_slice(chunk);
+        self.shared.ready.notify_all();
+    }
+
+    pub fn finish(&self) {
+        self.shared.state.lock().unwrap().finished = true;
+        self.shared.ready.notify_all();
+    }
+
+    /// 下载失败，读取端会在读完已有数据后收到错误
+    pub fn fail(&self, error: String) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.error = Some(error);
+        state.finished = true;
+        self.shared.ready.notify_all();
+    }
+}
+
+impl StreamReader {
+    /// 等到缓冲区至少有 `len` 字节或下载结束，返回当时可用的字节数
+    fn wait_for(&self, len: usize) -> io::Result<usize> {
+        let state = self.shared.state.lock().unwrap();
+        let (state, timeout) = self
+            .shared
+            .ready
+            .wait_timeout_while(state, READ_TIMEOUT, |state| state.data.len() < len && !state.finished)
+            .unwrap();
+        if timeout.timed_out() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "等待音频数据超时"));
+        }
+        if state.data.len() < len {
+            if let Some(error) = &state.error {
+                return Err(io::Error::other(error.clone()));
+            }
+        }
+        Ok(state.data.len())
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.wait_for(self.position + 1)?;
+        let state = self.shared.state.lock().unwrap();
+        let available = &state.data[self.position.min(state.data.len())..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => self.position as i64 + offset,
+            // 总长度只有下载结束后才知道
+            SeekFrom::End(offset) => self.wait_for(usize::MAX)? as i64 + offset,
+        };
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "定位到数据开头之前"));
+        }
+        let available = self.wait_for(target as usize)?;
+        self.position = (target as usize).min(available);
+        Ok(self.position as u64)
+    }
+}
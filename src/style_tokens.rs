@@ -0,0 +1,114 @@
+//! 朗读文本中的语气与停顿标记。
+//!
+//! 标记沿用 ChatTTS 的写法（`[laugh]`、`[break_3]`），方便在两类引擎之间复用文本。
+//! 百度接口不认识这些标记，因此合成前由 `render_tokens` 转换为百度能体现的标点或
+//! 语气词；百度无法表达的标记（`[oral_N]`、`[speed_N]`）会被去掉，语速请用滑块调整。
+//!
+//! 支持的标记：
+//!
+//! | 标记          | 参数 | 百度中的效果              |
+//! |---------------|------|---------------------------|
+//! | `[laugh]`     | 无   | 插入“哈哈，”              |
+//! | `[uv_break]`  | 无   | 短停顿（逗号）            |
+//! | `[lbreak]`    | 无   | 长停顿（句号）            |
+//! | `[break_N]`   | 0-7  | 0-2 逗号，3-5 句号，6-7 省略号 |
+//! | `[oral_N]`    | 0-9  | 无，去掉                  |
+//! | `[speed_N]`   | 0-9  | 无，去掉                  |
+//!
+//! 参数超出范围时取最近的合法值；形如标记但无法识别的内容（如 `[brk]`、`[break_]`）
+//! 会被去掉，避免原样读出。含中文或空格的方括号内容不是标记，保持不变。
+
+/// 标记名、参数上限（无参数为 `None`）和面板中的说明
+pub struct StyleToken {
+    pub name: &'static str,
+    pub max_level: Option<u8>,
+    pub label: &'static str,
+}
+
+pub const STYLE_TOKENS: &[StyleToken] = &[
+    StyleToken { name: "laugh", max_level: None, label: "笑声" },
+    StyleToken { name: "uv_break", max_level: None, label: "短停顿" },
+    StyleToken { name: "lbreak", max_level: None, label: "长停顿" },
+    StyleToken { name: "break", max_level: Some(7), label: "停顿" },
+    StyleToken { name: "oral", max_level: Some(9), label: "口语化" },
+    StyleToken { name: "speed", max_level: Some(9), label: "语速" },
+];
+
+/// 语气预设：一组语音参数，外加插入到光标处的标记
+pub struct StylePreset {
+    pub name: &'static str,
+    pub speed: i32,
+    pub pitch: i32,
+    pub volume: i32,
+    pub tokens: &'static str,
+}
+
+pub const STYLE_PRESETS: &[StylePreset] = &[
+    StylePreset { name: "激动", speed: 7, pitch: 8, volume: 8, tokens: "" },
+    StylePreset { name: "平静", speed: 4, pitch: 5, volume: 5, tokens: "[break_3]" },
+    StylePreset { name: "搞笑", speed: 6, pitch: 9, volume: 6, tokens: "[laugh]" },
+];
+
+/// 标记最长的合理长度，超过的方括号内容不当作标记
+const MAX_TOKEN_LEN: usize = 16;
+
+impl StyleToken {
+    /// 插入到文本中的写法，带参数的标记取中间值
+    pub fn template(&self) -> String {
+        match self.max_level {
+            Some(max) => format!("[{}_{}]", self.name, max.div_ceil(2)),
+            None => format!("[{}]", self.name),
+        }
+    }
+
+    fn render(&self, level: Option<u8>) -> &'static str {
+        match (self.name, level) {
+            ("laugh", _) => "哈哈，",
+            ("uv_break", _) => "，",
+            ("lbreak", _) => "。",
+            ("break", Some(0..=2)) => "，",
+            ("break", Some(3..=5)) => "。",
+            ("break", _) => "……",
+            _ => "",
+        }
+    }
+}
+
+/// 把文本中的标记转换为百度能体现的形式，并去掉无法识别的标记
+pub fn render_tokens(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let inner_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let is_token = inner_len > 0 && inner_len <= MAX_TOKEN_LEN && after[inner_len..].starts_with(']');
+        if !is_token {
+            out.push('[');
+            rest = after;
+            continue;
+        }
+        let inner = &after[..inner_len];
+        match parse_token(inner) {
+            Some((token, level)) => out.push_str(token.render(level)),
+            None => log::warn!("忽略无法识别的语气标记 [{}]", inner),
+        }
+        rest = &after[inner_len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn parse_token(inner: &str) -> Option<(&'static StyleToken, Option<u8>)> {
+    let lower = inner.to_ascii_lowercase();
+    if let Some(token) = STYLE_TOKENS.iter().find(|token| token.max_level.is_none() && token.name == lower) {
+        return Some((token, None));
+    }
+    let (name, level) = lower.rsplit_once('_')?;
+    let token = STYLE_TOKENS.iter().find(|token| token.name == name)?;
+    let max = token.max_level?;
+    let level: u32 = level.parse().ok()?;
+    Some((token, Some(level.min(max as u32) as u8)))
+}
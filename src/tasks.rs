@@ -0,0 +1,143 @@
+//! 正在进行的后台任务。
+//!
+//! 每个任务有自己的编号、类型和当前阶段，互不覆盖；界面按类型决定哪些按钮可用，
+//! 状态栏在有多个任务时显示任务数。后台线程持有 `TaskGuard`，无论正常结束、
+//! 出错提前返回还是被取消，都会在丢弃时通知界面移除该任务。
+
+use std::fmt;
+
+use crate::wakeup::UiSender;
+use crate::{AppState, UIMessage};
+
+pub type TaskId = u64;
+
+/// 同时进行的生成/朗读任务上限，防止连续按全局快捷键时堆积大量请求
+pub const MAX_CONCURRENT_GENERATIONS: usize = 3;
+
+/// 任务类型，同一类型同时只允许由按钮启动一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    /// 由输入框“生成并播放”，可能经过 DeepSeek
+    Generate,
+    /// 把文本区内容交给 DeepSeek 优化后朗读
+    Refine,
+    /// 直接朗读文本区、剪贴板或定时任务的文本
+    Speak,
+    SaveAudio,
+    /// 批量生成并保存多段音频
+    Batch,
+    /// 同一话题以不同温度生成多个版本
+    Variations,
+}
+
+impl TaskKind {
+    /// 由按钮或快捷键触发、会调用 DeepSeek 或百度接口的任务；批量生成和多版本生成有自己的并发限制，不计入
+    fn is_generation(self) -> bool {
+        !matches!(self, TaskKind::SaveAudio | TaskKind::Batch | TaskKind::Variations)
+    }
+}
+
+impl fmt::Display for TaskKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskKind::Generate => write!(f, "生成"),
+            TaskKind::Refine => write!(f, "AI 优化"),
+            TaskKind::Speak => write!(f, "朗读"),
+            TaskKind::SaveAudio => write!(f, "保存音频"),
+            TaskKind::Batch => write!(f, "批量生成"),
+            TaskKind::Variations => write!(f, "多版本生成"),
+        }
+    }
+}
+
+struct Task {
+    id: TaskId,
+    kind: TaskKind,
+    stage: AppState,
+}
+
+/// 进行中的任务列表
+#[derive(Default)]
+pub struct TaskRegistry {
+    next_id: TaskId,
+    tasks: Vec<Task>,
+}
+
+impl TaskRegistry {
+    pub fn start(&mut self, kind: TaskKind, stage: AppState) -> TaskId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.push(Task { id, kind, stage });
+        id
+    }
+
+    /// 已结束的任务晚到的阶段更新会被忽略
+    pub fn update(&mut self, id: TaskId, stage: AppState) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.id == id) {
+            task.stage = stage;
+        }
+    }
+
+    pub fn finish(&mut self, id: TaskId) {
+        self.tasks.retain(|task| task.id != id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn is_running(&self, kind: TaskKind) -> bool {
+        self.tasks.iter().any(|task| task.kind == kind)
+    }
+
+    /// 生成类任务是否已达到同时进行的上限
+    pub fn generations_full(&self) -> bool {
+        self.tasks.iter().filter(|task| task.kind.is_generation()).count() >= MAX_CONCURRENT_GENERATIONS
+    }
+
+    /// 状态栏文字：只有一个任务时显示其阶段，多个时显示数量；没有任务时为 `None`
+    pub fn summary(&self) -> Option<String> {
+        match self.tasks.as_slice() {
+            [] => None,
+            [task] => Some(task.stage.to_string()),
+            tasks => Some(format!("{} 个任务进行中", tasks.len())),
+        }
+    }
+
+    /// 悬停提示中逐个列出任务
+    pub fn details(&self) -> String {
+        self.tasks
+            .iter()
+            .map(|task| format!("#{} {}: {}", task.id, task.kind, task.stage))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 后台线程持有的任务凭证，丢弃时通知界面任务已结束
+pub struct TaskGuard {
+    id: TaskId,
+    sender: UiSender<UIMessage>,
+}
+
+impl TaskGuard {
+    pub fn new(id: TaskId, sender: UiSender<UIMessage>) -> Self {
+        Self { id, sender }
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// 报告任务进入新阶段
+    pub fn set_stage(&self, stage: AppState) {
+        let _ = self.sender.send(UIMessage::TaskStage(self.id, stage));
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        // 界面已关闭时无需通知
+        let _ = self.sender.send(UIMessage::TaskFinished(self.id));
+    }
+}
@@ -0,0 +1,122 @@
+//! 窗口右下角堆叠显示的短暂提示。
+//!
+//! 状态栏只显示当前状态（进行中的任务或最近的错误），“已保存”“已复制”这类一闪而过的
+//! 结果改用提示气泡，彼此不会覆盖。提示按严重程度着色，到时自动消失，点击可提前关闭。
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+/// 同时显示的最多条数，超出时丢弃最早的
+const MAX_TOASTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Success,
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn color(self) -> egui::Color32 {
+        match self {
+            Severity::Success => egui::Color32::LIGHT_GREEN,
+            Severity::Info => egui::Color32::LIGHT_BLUE,
+            Severity::Warning => egui::Color32::YELLOW,
+            Severity::Error => egui::Color32::LIGHT_RED,
+        }
+    }
+
+    fn icon(self) -> &'static str {
+        match self {
+            Severity::Success => "✔",
+            Severity::Info => "ℹ",
+            Severity::Warning => "⚠",
+            Severity::Error => "✖",
+        }
+    }
+
+    /// 错误需要更长时间阅读
+    fn lifetime(self) -> Duration {
+        match self {
+            Severity::Success | Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+struct Toast {
+    severity: Severity,
+    text: String,
+    shown_at: Instant,
+}
+
+impl Toast {
+    fn expires_at(&self) -> Instant {
+        self.shown_at + self.severity.lifetime()
+    }
+}
+
+#[derive(Default)]
+pub struct Toasts {
+    items: VecDeque<Toast>,
+}
+
+impl Toasts {
+    /// 与最新一条内容相同时只刷新显示时间，避免连续操作刷屏
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>) {
+        let text = text.into();
+        if let Some(last) = self.items.back_mut() {
+            if last.severity == severity && last.text == text {
+                last.shown_at = Instant::now();
+                return;
+            }
+        }
+        self.items.push_back(Toast { severity, text, shown_at: Instant::now() });
+        while self.items.len() > MAX_TOASTS {
+            self.items.pop_front();
+        }
+    }
+
+    /// 清除过期提示并绘制其余的，最新的在最下方
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        self.items.retain(|toast| toast.expires_at() > now);
+        let Some(next_expiry) = self.items.iter().map(Toast::expires_at).min() else {
+            return;
+        };
+        ctx.request_repaint_after(next_expiry - now);
+
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-12.0, -36.0))
+            .order(egui::Order::Foreground)
+            .interactable(true)
+            .show(ctx, |ui| {
+                ui.set_max_width(320.0);
+                for (i, toast) in self.items.iter().enumerate() {
+                    let color = toast.severity.color();
+                    let response = egui::Frame::popup(ui.style())
+                        .stroke(egui::Stroke::new(1.0, color))
+                        .show(ui, |ui| {
+                            ui.horizontal_wrapped(|ui| {
+                                ui.colored_label(color, toast.severity.icon());
+                                ui.label(&toast.text);
+                            });
+                        })
+                        .response
+                        .interact(egui::Sense::click())
+                        .on_hover_text("点击关闭");
+                    if response.clicked() {
+                        dismissed = Some(i);
+                    }
+                }
+            });
+        if let Some(i) = dismissed {
+            self.items.remove(i);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+/// Windows 文件名中不允许出现的字符
+const ILLEGAL_FILENAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// 把任意文本转换为安全的文件名片段：去掉非法字符和控制字符，
+/// 空白折叠为下划线，并截断到 `max_chars` 个字符
+pub fn sanitize_filename(text: &str, max_chars: usize) -> String {
+    let mut result = String::new();
+    let mut last_was_separator = false;
+    for c in text.chars() {
+        if c.is_control() || ILLEGAL_FILENAME_CHARS.contains(&c) || c.is_whitespace() {
+            if !last_was_separator && !result.is_empty() {
+                result.push('_');
+                last_was_separator = true;
+            }
+            continue;
+        }
+        result.push(c);
+        last_was_separator = false;
+        if result.chars().count() >= max_chars {
+            break;
+        }
+    }
+    // 结尾的点号和下划线在 Windows 上会引发问题
+    result.trim_end_matches(['_', '.']).to_string()
+}
+
+/// 音频放入剪贴板的方式
+#[cfg(feature = "clipboard")]
+pub enum ClipboardCopy {
+    /// 以文件形式放入剪贴板，可直接粘贴到 Discord、OBS 或资源管理器
+    File,
+    /// 平台不支持文件剪贴板，退而复制文件路径文本
+    Path,
+}
+
+/// 把磁盘上的文件放入系统剪贴板。
+///
+/// 剪贴板没有通用的“音频”格式，因此这里放的是文件引用：Windows 上为 `CF_HDROP`，
+/// macOS 上为文件 URL，Linux 上为 `text/uri-list`。目标程序不接受文件时，
+/// 会退回到复制文件路径。Linux 下剪贴板内容由 `clipboard` 的持有者提供，
+/// 调用方需要让它存活到粘贴完成。
+#[cfg(feature = "clipboard")]
+pub fn copy_file_to_clipboard(clipboard: &mut arboard::Clipboard, path: &std::path::Path) -> Result<ClipboardCopy, String> {
+    match clipboard.set().file_list(&[path]) {
+        Ok(()) => Ok(ClipboardCopy::File),
+        Err(e) => {
+            log::warn!("无法以文件形式复制到剪贴板，改为复制路径: {}", e);
+            clipboard
+                .set_text(path.to_string_lossy().to_string())
+                .map(|_| ClipboardCopy::Path)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// 打开系统文件管理器并选中 `path`。
+///
+/// Windows 用 `explorer /select,`，macOS 用 `open -R`；Linux 先通过 D-Bus 的
+/// `org.freedesktop.FileManager1` 接口选中文件，文件管理器不支持时退回为用
+/// `xdg-open` 打开所在目录。
+pub fn reveal_in_file_manager(path: &std::path::Path) -> std::io::Result<()> {
+    use std::process::Command;
+
+    if !path.exists() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("文件不存在: {}", path.display())));
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        let mut arg = std::ffi::OsString::from("/select,");
+        arg.push(path.as_os_str());
+        // explorer 即使成功也常返回非零退出码，因此只检查能否启动
+        Command::new("explorer").arg(arg).spawn().map(|_| ())
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        Command::new("open").arg("-R").arg(path).spawn().map(|_| ())
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        let path = std::path::absolute(path)?;
+        let uri = format!("file://{}", path.display());
+        let selected = Command::new("dbus-send")
+            .args([
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.FileManager1",
+                "--type=method_call",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowItems",
+            ])
+            .arg(format!("array:string:{}", uri))
+            .arg("string:")
+            .output()
+            .is_ok_and(|output| output.status.success());
+        if selected {
+            return Ok(());
+        }
+        let dir = path.parent().unwrap_or(&path);
+        Command::new("xdg-open").arg(dir).spawn().map(|_| ())
+    }
+}
@@ -0,0 +1,44 @@
+//! 多版本生成：同一个话题让 DeepSeek 以不同的温度各写一版并分别合成，
+//! 试听后采用最满意的一版（进入文本区，可以保存）。
+
+use std::sync::Arc;
+
+use crate::export::ClipInfo;
+
+/// 一次生成的版本数范围
+pub const MIN_COUNT: usize = 2;
+pub const MAX_COUNT: usize = 5;
+/// 版本之间的温度在此范围内均匀分布：较低的更稳妥，较高的更有变化
+const MIN_TEMPERATURE: f32 = 0.6;
+const MAX_TEMPERATURE: f32 = 1.4;
+
+/// `count` 个版本各自使用的温度
+pub fn temperatures(count: usize) -> Vec<f32> {
+    let count = count.clamp(MIN_COUNT, MAX_COUNT);
+    let step = (MAX_TEMPERATURE - MIN_TEMPERATURE) / (count - 1) as f32;
+    (0..count).map(|i| MIN_TEMPERATURE + step * i as f32).collect()
+}
+
+/// 生成完成的一版
+pub struct Take {
+    pub info: ClipInfo,
+    pub audio: Arc<Vec<u8>>,
+}
+
+pub enum VariationStatus {
+    Running,
+    Done(Take),
+    Failed(String),
+}
+
+/// 结果列表中的一版
+pub struct Variation {
+    pub temperature: f32,
+    pub status: VariationStatus,
+}
+
+impl Variation {
+    pub fn new(temperature: f32) -> Self {
+        Self { temperature, status: VariationStatus::Running }
+    }
+}
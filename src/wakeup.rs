@@ -0,0 +1,35 @@
+//! 发往界面线程的消息通道。
+//!
+//! 界面空闲时 egui 不再重绘，`update` 里的 `try_recv` 也就不会运行。`UiSender` 在每次
+//! 发送后调用 `request_repaint`，界面恰好在有消息时醒来处理，不必靠定时轮询。
+
+use std::sync::mpsc;
+
+use eframe::egui;
+
+/// 发送后唤醒界面的发送端，用法与 `mpsc::Sender` 相同
+pub struct UiSender<T> {
+    sender: mpsc::Sender<T>,
+    ctx: egui::Context,
+}
+
+impl<T> Clone for UiSender<T> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), ctx: self.ctx.clone() }
+    }
+}
+
+impl<T> UiSender<T> {
+    /// 界面已关闭（接收端已丢弃）时返回错误，此时不再唤醒
+    pub fn send(&self, message: T) -> Result<(), mpsc::SendError<T>> {
+        self.sender.send(message)?;
+        self.ctx.request_repaint();
+        Ok(())
+    }
+}
+
+/// 创建一对通道，发送端持有 `ctx` 用于唤醒
+pub fn channel<T>(ctx: &egui::Context) -> (UiSender<T>, mpsc::Receiver<T>) {
+    let (sender, receiver) = mpsc::channel();
+    (UiSender { sender, ctx: ctx.clone() }, receiver)
+}